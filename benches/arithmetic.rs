@@ -0,0 +1,55 @@
+// Counts heap allocations and time for arithmetic-heavy loops.
+// Run with `cargo bench --bench arithmetic`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use codecrafters_interpreter::Interpreter;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const ITERATIONS: usize = 200_000;
+
+const BENCHMARKS: &[(&str, &str)] = &[
+    ("while loop sum", "var sum = 0; var i = 0; while (i < N) { sum = sum + i * 2 - 1; i = i + 1; }"),
+    ("for loop compare", "var n = 0; for (var i = 0; i < N; i = i + 1) { if (i % 3 == 0 and i > 10) n = n + 1; }"),
+    ("float math", "var x = 0.5; var i = 0; while (i < N) { x = (x * 1.0001 + 0.25) / 1.5; i = i + 1; }"),
+];
+
+fn main() {
+    println!("{:<20} {:>12} {:>14} {:>10}", "benchmark", "allocations", "per iteration", "time");
+    for (name, source) in BENCHMARKS {
+        let source = source.replace('N', &ITERATIONS.to_string());
+        let mut interp = Interpreter::new();
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let start = Instant::now();
+        interp.eval_str(&source).expect("benchmark should run");
+        let elapsed = start.elapsed();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+        println!(
+            "{:<20} {:>12} {:>14.2} {:>9.1?}",
+            name,
+            allocations,
+            allocations as f64 / ITERATIONS as f64,
+            elapsed,
+        );
+    }
+}
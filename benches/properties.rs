@@ -0,0 +1,98 @@
+// Times object-heavy programs, whose field reads and writes and method calls go
+// through the shapes and inline caches, on both backends. The tree has no
+// implementation without the caches to compare with, so it only reports times.
+// Run with `cargo bench --bench properties`.
+
+use std::time::{Duration, Instant};
+use codecrafters_interpreter::{Backend, Interpreter};
+
+const BENCHMARKS: &[(&str, &str)] = &[
+    (
+        "binary trees",
+        "class Node {}
+        fun make(depth) {
+            var node = Node();
+            if (depth > 0) { node.left = make(depth - 1); node.right = make(depth - 1); } else { node.left = nil; node.right = nil; }
+            return node;
+        }
+        fun check(node) { if (node.left == nil) return 1; return 1 + check(node.left) + check(node.right); }
+        var total = 0;
+        for (var i = 0; i < 20; i = i + 1) total = total + check(make(12));",
+    ),
+    (
+        "field update loop",
+        "class Particle {}
+        fun run() {
+            var p = Particle();
+            p.x = 0; p.y = 0; p.dx = 1; p.dy = 2;
+            for (var i = 0; i < 300000; i = i + 1) { p.x = p.x + p.dx; p.y += p.dy; p.dx = p.dy - p.dx; }
+            return p.x;
+        }
+        run();",
+    ),
+    (
+        "two shapes at a site",
+        "class Point {}
+        fun make(i) { var p = Point(); if (i % 2 == 0) { p.x = i; p.y = 0; } else { p.y = 0; p.x = i; } return p; }
+        fun run() {
+            var a = make(0); var b = make(1); var sum = 0;
+            for (var i = 0; i < 300000; i = i + 1) { var p = i % 2 == 0 ? a : b; sum = sum + p.x + p.y; }
+            return sum;
+        }
+        run();",
+    ),
+    (
+        "method call loop",
+        "class Counter {
+            init() { this.n = 0; }
+            add(k) { this.n = this.n + k; }
+            get() { return this.n; }
+        }
+        fun run() {
+            var c = Counter();
+            for (var i = 0; i < 200000; i = i + 1) { c.add(i); c.add(c.get()); }
+            return c.get();
+        }
+        run();",
+    ),
+    (
+        "binary tree methods",
+        "class Tree {
+            init(depth) {
+                if (depth > 0) { this.left = Tree(depth - 1); this.right = Tree(depth - 1); } else { this.left = nil; this.right = nil; }
+            }
+            check() { if (this.left == nil) return 1; return 1 + this.left.check() + this.right.check(); }
+        }
+        var total = 0;
+        for (var i = 0; i < 10; i = i + 1) total = total + Tree(12).check();",
+    ),
+];
+
+// Each benchmark reports its fastest run, which is the least disturbed by other
+// work on the machine.
+const RUNS: usize = 5;
+
+fn fastest(source: &str, backend: Backend) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let mut interp = Interpreter::new();
+            interp.set_backend(backend);
+            let start = Instant::now();
+            interp.eval_str(source).expect("benchmark should run");
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    println!("{:<22} {:>12} {:>10}", "benchmark", "tree walker", "vm");
+    for (name, source) in BENCHMARKS {
+        println!(
+            "{:<22} {:>12.1?} {:>10.1?}",
+            name,
+            fastest(source, Backend::TreeWalker),
+            fastest(source, Backend::Vm),
+        );
+    }
+}
@@ -0,0 +1,60 @@
+// Times programs dominated by variable access on the tree-walking evaluator. Each
+// one runs twice: at the top level, where its variables are globals found by name
+// in a hash map as every variable was before locals were resolved to slots, and
+// inside a function, where they are locals in slots.
+// Run with `cargo bench --bench variables`.
+
+use std::time::{Duration, Instant};
+use codecrafters_interpreter::{Backend, Interpreter};
+
+// (name, source). The sources declare their variables up front, without `for`
+// initializers, so that none of them is a local in the top-level run.
+const BENCHMARKS: &[(&str, &str)] = &[
+    (
+        "nested loops",
+        "var total = 0; var i = 0; var j = 0;
+        while (i < 1000) { j = 0; while (j < 1000) { total = total + j; j = j + 1; } i = i + 1; }",
+    ),
+    (
+        "swap loop",
+        "var i = 0; var a = 1; var b = 2; var t = nil;
+        while (i < 1000000) { t = a; a = b; b = t; i = i + 1; }",
+    ),
+    (
+        "closure counter",
+        "var n = 0; var i = 0;
+        fun inc() { n = n + 1; return n; }
+        while (i < 500000) { inc(); i = i + 1; }",
+    ),
+];
+
+// How many times each variant runs; the fastest time is the one reported.
+const RUNS: usize = 5;
+
+fn fastest(source: &str) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let mut interp = Interpreter::new();
+            interp.set_backend(Backend::TreeWalker);
+            let start = Instant::now();
+            interp.eval_str(source).expect("benchmark should run");
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    println!("{:<20} {:>10} {:>10} {:>8}", "benchmark", "globals", "locals", "speedup");
+    for (name, source) in BENCHMARKS {
+        let globals = fastest(source);
+        let locals = fastest(&format!("fun run() {{ {} }} run();", source));
+        println!(
+            "{:<20} {:>10.1?} {:>10.1?} {:>7.2}x",
+            name,
+            globals,
+            locals,
+            globals.as_secs_f64() / locals.as_secs_f64(),
+        );
+    }
+}
@@ -1,5 +1,11 @@
 use std::any::Any;
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+use crate::common::output::Output;
 use crate::lexer::lexer::{Token};
+use crate::object::object::StringData;
+use crate::object::shape::PropertyCache;
 
 #[derive(Debug)]
 pub enum NodeType {
@@ -12,6 +18,38 @@ pub enum NodeType {
     PrefixExpression,
     InfixExpression,
     Nil,
+    Identifier,
+    TernaryExpression,
+    AssignExpression,
+    UpdateExpression,
+    GetExpression,
+    IndexExpression,
+    PrintStatement,
+    VarStatement,
+    BlockStatement,
+    IfStatement,
+    WhileStatement,
+    ForStatement,
+    BreakStatement,
+    ContinueStatement,
+    ListLiteral,
+    MapLiteral,
+    CallExpression,
+    ClassStatement,
+    MatchStatement,
+    MatchExpression,
+    LiteralPattern,
+    RangePattern,
+    WildcardPattern,
+    BindingPattern,
+    ListPattern,
+    MapPattern,
+    InstancePattern,
+    FunctionStatement,
+    ReturnStatement,
+    ThrowStatement,
+    TryStatement,
+    ImportStatement,
 }
 
 pub trait Node: Any {
@@ -24,6 +62,8 @@ pub trait Statement: Node {}
 
 pub trait Expression: Node {}
 
+pub trait Pattern: Node {}
+
 pub struct ExpressionStatement {
     pub expression: Box<dyn Expression>,
 }
@@ -49,10 +89,11 @@ pub struct Program {
 }
 
 impl Program {
-    pub fn print(&self) {
+    pub fn print(&self, out: &Output) -> io::Result<()> {
         for stmt in self.statements.iter() {
-            println!("{}", stmt.string());
+            out.write_line(&stmt.string())?;
         }
+        Ok(())
     }
 }
 
@@ -127,7 +168,7 @@ impl Node for NumberLiteral {
 impl Expression for NumberLiteral {}
 
 pub struct StringLiteral {
-    pub value: String,
+    pub value: Rc<StringData>,
 }
 
 impl Node for StringLiteral {
@@ -203,4 +244,815 @@ impl Node for InfixExpression {
     }
 }
 
-impl Expression for InfixExpression {}
\ No newline at end of file
+impl Expression for InfixExpression {}
+
+// Where a local variable lives at runtime: in the frame `depth` scopes out from the
+// innermost one, at index `index`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+// The variable a name refers to, as the resolver decided it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Resolution {
+    // Looked up by name in the global table when the code runs.
+    #[default]
+    Global,
+    // A local in `slot`. `shadowed` counts the scopes between the name and that
+    // local's scope that declare the same name further down, which the name
+    // doesn't refer to; the compiler uses it to find the same local.
+    Local { slot: Slot, shadowed: usize },
+}
+
+pub struct Identifier {
+    pub token: Token,
+    // Filled in by the resolver.
+    pub resolution: Cell<Resolution>,
+}
+
+impl Identifier {
+    pub fn new(token: Token) -> Self {
+        Self { token, resolution: Cell::new(Resolution::Global) }
+    }
+}
+
+impl Node for Identifier {
+    fn string(&self) -> String {
+        self.token.lexeme.to_string()
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::Identifier
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Expression for Identifier {}
+
+pub struct TernaryExpression {
+    pub condition: Box<dyn Expression>,
+    pub consequence: Box<dyn Expression>,
+    pub alternative: Box<dyn Expression>,
+}
+
+impl Node for TernaryExpression {
+    fn string(&self) -> String {
+        format!("(? {} {} {})", self.condition.string(), self.consequence.string(), self.alternative.string())
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::TernaryExpression
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Expression for TernaryExpression {}
+
+// `target = value` as well as the compound forms `target += value` etc.
+// `target` is always an Identifier, GetExpression or IndexExpression.
+pub struct AssignExpression {
+    pub operator: Token,
+    pub target: Box<dyn Expression>,
+    pub value: Box<dyn Expression>,
+}
+
+impl Node for AssignExpression {
+    fn string(&self) -> String {
+        format!("({} {} {})", self.operator.lexeme, self.target.string(), self.value.string())
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::AssignExpression
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Expression for AssignExpression {}
+
+// `++target`, `--target`, `target++` and `target--`.
+pub struct UpdateExpression {
+    pub operator: Token,
+    pub target: Box<dyn Expression>,
+    pub prefix: bool,
+}
+
+impl Node for UpdateExpression {
+    fn string(&self) -> String {
+        if self.prefix {
+            format!("({} {})", self.operator.lexeme, self.target.string())
+        } else {
+            format!("({} {})", self.target.string(), self.operator.lexeme)
+        }
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::UpdateExpression
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Expression for UpdateExpression {}
+
+pub struct GetExpression {
+    pub object: Box<dyn Expression>,
+    pub name: Token,
+    // Where the tree walker last found the field, for instances of the same shape.
+    pub cache: PropertyCache,
+}
+
+impl Node for GetExpression {
+    fn string(&self) -> String {
+        format!("(. {} {})", self.object.string(), self.name.lexeme)
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::GetExpression
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Expression for GetExpression {}
+
+pub struct IndexExpression {
+    pub token: Token,
+    pub left: Box<dyn Expression>,
+    pub index: Box<dyn Expression>,
+}
+
+impl Node for IndexExpression {
+    fn string(&self) -> String {
+        format!("([] {} {})", self.left.string(), self.index.string())
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::IndexExpression
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Expression for IndexExpression {}
+
+pub struct PrintStatement {
+    pub expression: Box<dyn Expression>,
+}
+
+impl Node for PrintStatement {
+    fn string(&self) -> String {
+        format!("(print {})", self.expression.string())
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::PrintStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for PrintStatement {}
+
+pub struct VarStatement {
+    pub name: Token,
+    pub initializer: Option<Box<dyn Expression>>,
+    // The variable's index in its scope's frame, set by the resolver. None for globals.
+    pub slot: Cell<Option<usize>>,
+}
+
+impl Node for VarStatement {
+    fn string(&self) -> String {
+        match &self.initializer {
+            Some(initializer) => format!("(var {} {})", self.name.lexeme, initializer.string()),
+            None => format!("(var {})", self.name.lexeme),
+        }
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::VarStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for VarStatement {}
+
+pub struct BlockStatement {
+    pub statements: Vec<Box<dyn Statement>>,
+}
+
+impl Node for BlockStatement {
+    fn string(&self) -> String {
+        let statements: Vec<String> = self.statements.iter().map(|stmt| stmt.string()).collect();
+        format!("(block {})", statements.join(" "))
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::BlockStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for BlockStatement {}
+
+pub struct IfStatement {
+    pub condition: Box<dyn Expression>,
+    pub consequence: Box<dyn Statement>,
+    pub alternative: Option<Box<dyn Statement>>,
+}
+
+impl Node for IfStatement {
+    fn string(&self) -> String {
+        match &self.alternative {
+            Some(alternative) => format!("(if {} {} {})", self.condition.string(), self.consequence.string(), alternative.string()),
+            None => format!("(if {} {})", self.condition.string(), self.consequence.string()),
+        }
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::IfStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for IfStatement {}
+
+pub struct WhileStatement {
+    pub label: Option<Token>,
+    pub condition: Box<dyn Expression>,
+    pub body: Box<dyn Statement>,
+}
+
+impl Node for WhileStatement {
+    fn string(&self) -> String {
+        format!("({}while {} {})", label_prefix(&self.label), self.condition.string(), self.body.string())
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::WhileStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for WhileStatement {}
+
+// Kept as its own node rather than desugared into a while loop so that `continue`
+// still runs the increment clause.
+pub struct ForStatement {
+    pub label: Option<Token>,
+    pub initializer: Option<Box<dyn Statement>>,
+    pub condition: Option<Box<dyn Expression>>,
+    pub increment: Option<Box<dyn Expression>>,
+    pub body: Box<dyn Statement>,
+}
+
+impl Node for ForStatement {
+    fn string(&self) -> String {
+        format!(
+            "({}for {} {} {} {})",
+            label_prefix(&self.label),
+            self.initializer.as_ref().map_or("nil".to_string(), |s| s.string()),
+            self.condition.as_ref().map_or("nil".to_string(), |e| e.string()),
+            self.increment.as_ref().map_or("nil".to_string(), |e| e.string()),
+            self.body.string(),
+        )
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::ForStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for ForStatement {}
+
+pub struct BreakStatement {
+    pub token: Token,
+    pub label: Option<Token>,
+}
+
+impl Node for BreakStatement {
+    fn string(&self) -> String {
+        match &self.label {
+            Some(label) => format!("(break {})", label.lexeme),
+            None => "(break)".to_string(),
+        }
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::BreakStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for BreakStatement {}
+
+pub struct ContinueStatement {
+    pub token: Token,
+    pub label: Option<Token>,
+}
+
+impl Node for ContinueStatement {
+    fn string(&self) -> String {
+        match &self.label {
+            Some(label) => format!("(continue {})", label.lexeme),
+            None => "(continue)".to_string(),
+        }
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::ContinueStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for ContinueStatement {}
+
+fn label_prefix(label: &Option<Token>) -> String {
+    match label {
+        Some(label) => format!("{}: ", label.lexeme),
+        None => String::new(),
+    }
+}
+
+pub struct ListLiteral {
+    pub elements: Vec<Box<dyn Expression>>,
+}
+
+impl Node for ListLiteral {
+    fn string(&self) -> String {
+        let elements: Vec<String> = self.elements.iter().map(|e| e.string()).collect();
+        format!("(list {})", elements.join(" "))
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::ListLiteral
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Expression for ListLiteral {}
+
+pub struct MapLiteral {
+    pub token: Token,
+    pub pairs: Vec<(Box<dyn Expression>, Box<dyn Expression>)>,
+}
+
+impl Node for MapLiteral {
+    fn string(&self) -> String {
+        let pairs: Vec<String> = self.pairs.iter().map(|(k, v)| format!("{} {}", k.string(), v.string())).collect();
+        format!("(map {})", pairs.join(" "))
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::MapLiteral
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Expression for MapLiteral {}
+
+pub struct CallExpression {
+    pub token: Token,
+    pub callee: Box<dyn Expression>,
+    pub arguments: Vec<Box<dyn Expression>>,
+}
+
+impl Node for CallExpression {
+    fn string(&self) -> String {
+        let mut parts = vec![self.callee.string()];
+        parts.extend(self.arguments.iter().map(|a| a.string()));
+        format!("(call {})", parts.join(" "))
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::CallExpression
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Expression for CallExpression {}
+
+pub struct ClassStatement {
+    pub name: Token,
+    pub methods: Vec<FunctionStatement>,
+    pub slot: Cell<Option<usize>>,
+}
+
+impl Node for ClassStatement {
+    fn string(&self) -> String {
+        let methods: Vec<String> = self.methods.iter().map(|method| format!(" {}", method.string())).collect();
+        format!("(class {}{})", self.name.lexeme, methods.concat())
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::ClassStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for ClassStatement {}
+
+// One `case` of a match. A match statement's arms run a statement; a match
+// expression's arms give the expression the match evaluates to.
+pub struct MatchArm<B: ?Sized = dyn Statement> {
+    pub pattern: Box<dyn Pattern>,
+    pub guard: Option<Box<dyn Expression>>,
+    pub body: Box<B>,
+}
+
+fn match_string<B: Node + ?Sized>(subject: &dyn Expression, arms: &[MatchArm<B>]) -> String {
+    let arms: Vec<String> = arms.iter().map(|arm| match &arm.guard {
+        Some(guard) => format!("(case {} (if {}) {})", arm.pattern.string(), guard.string(), arm.body.string()),
+        None => format!("(case {} {})", arm.pattern.string(), arm.body.string()),
+    }).collect();
+    format!("(match {} {})", subject.string(), arms.join(" "))
+}
+
+pub struct MatchStatement {
+    pub token: Token,
+    pub subject: Box<dyn Expression>,
+    pub arms: Vec<MatchArm>,
+}
+
+impl Node for MatchStatement {
+    fn string(&self) -> String {
+        match_string(self.subject.as_ref(), &self.arms)
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::MatchStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for MatchStatement {}
+
+// `match` where a value is expected, e.g. `var kind = match (x) { case 0 => "zero"; case _ => "other"; };`.
+pub struct MatchExpression {
+    pub token: Token,
+    pub subject: Box<dyn Expression>,
+    pub arms: Vec<MatchArm<dyn Expression>>,
+}
+
+impl Node for MatchExpression {
+    fn string(&self) -> String {
+        match_string(self.subject.as_ref(), &self.arms)
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::MatchExpression
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Expression for MatchExpression {}
+
+// A number, string, boolean or nil literal compared with `==`.
+pub struct LiteralPattern {
+    pub value: Box<dyn Expression>,
+}
+
+impl Node for LiteralPattern {
+    fn string(&self) -> String {
+        self.value.string()
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::LiteralPattern
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Pattern for LiteralPattern {}
+
+// `start..end` is half-open, `start..=end` includes `end`. Only numbers match.
+pub struct RangePattern {
+    pub start: f64,
+    pub end: f64,
+    pub inclusive: bool,
+}
+
+impl Node for RangePattern {
+    fn string(&self) -> String {
+        format!("{:?}{}{:?}", self.start, if self.inclusive { "..=" } else { ".." }, self.end)
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::RangePattern
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Pattern for RangePattern {}
+
+pub struct WildcardPattern {}
+
+impl Node for WildcardPattern {
+    fn string(&self) -> String {
+        "_".to_string()
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::WildcardPattern
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Pattern for WildcardPattern {}
+
+// Matches anything and binds it to `name` in the arm's scope.
+pub struct BindingPattern {
+    pub name: Token,
+}
+
+impl Node for BindingPattern {
+    fn string(&self) -> String {
+        self.name.lexeme.to_string()
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::BindingPattern
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Pattern for BindingPattern {}
+
+pub enum RestPattern {
+    // `[a, b]`: the list must have exactly as many elements as patterns.
+    None,
+    // `[a, ..]`: any remaining elements are ignored.
+    Ignore,
+    // `[a, ..rest]`: remaining elements are bound to `rest` as a new list.
+    Bind(Token),
+}
+
+pub struct ListPattern {
+    pub elements: Vec<Box<dyn Pattern>>,
+    pub rest: RestPattern,
+}
+
+impl Node for ListPattern {
+    fn string(&self) -> String {
+        let mut parts: Vec<String> = self.elements.iter().map(|e| e.string()).collect();
+        match &self.rest {
+            RestPattern::None => {}
+            RestPattern::Ignore => parts.push("..".to_string()),
+            RestPattern::Bind(name) => parts.push(format!("..{}", name.lexeme)),
+        }
+        format!("[{}]", parts.join(" "))
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::ListPattern
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Pattern for ListPattern {}
+
+// Matches maps containing every listed key; other keys are ignored.
+pub struct MapPattern {
+    pub entries: Vec<(Box<dyn Expression>, Box<dyn Pattern>)>,
+}
+
+impl Node for MapPattern {
+    fn string(&self) -> String {
+        let entries: Vec<String> = self.entries.iter().map(|(k, p)| format!("{}: {}", k.string(), p.string())).collect();
+        format!("{{{}}}", entries.join(" "))
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::MapPattern
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Pattern for MapPattern {}
+
+// `Point { x: 0, y }` matches instances of `Point` whose fields match; a bare
+// field name binds the field's value to a variable of the same name.
+pub struct InstancePattern {
+    pub class_name: Token,
+    // Resolved like an identifier, in the scope around the match arm.
+    pub class_resolution: Cell<Resolution>,
+    pub fields: Vec<(Token, Box<dyn Pattern>)>,
+}
+
+impl Node for InstancePattern {
+    fn string(&self) -> String {
+        let fields: Vec<String> = self.fields.iter().map(|(name, p)| format!("{}: {}", name.lexeme, p.string())).collect();
+        format!("{} {{{}}}", self.class_name.lexeme, fields.join(" "))
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::InstancePattern
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Pattern for InstancePattern {}
+
+// Parameters and body are reference counted so that function objects created at
+// runtime can share them with the AST.
+pub struct FunctionStatement {
+    pub name: Token,
+    pub params: Rc<Vec<Token>>,
+    pub body: Rc<BlockStatement>,
+    pub slot: Cell<Option<usize>>,
+}
+
+impl Node for FunctionStatement {
+    fn string(&self) -> String {
+        let params: Vec<String> = self.params.iter().map(|p| p.lexeme.to_string()).collect();
+        format!("(fun {} ({}) {})", self.name.lexeme, params.join(" "), self.body.string())
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::FunctionStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for FunctionStatement {}
+
+pub struct ReturnStatement {
+    pub token: Token,
+    pub value: Option<Box<dyn Expression>>,
+    // Set by the resolver when the value is a call that can replace the current
+    // one instead of nesting inside it: nothing in the function is left to run
+    // after it, such as a catch clause or finally block around the return.
+    pub tail_call: Cell<bool>,
+}
+
+impl Node for ReturnStatement {
+    fn string(&self) -> String {
+        match &self.value {
+            Some(value) => format!("(return {})", value.string()),
+            None => "(return)".to_string(),
+        }
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::ReturnStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for ReturnStatement {}
+
+pub struct ThrowStatement {
+    pub token: Token,
+    pub value: Box<dyn Expression>,
+}
+
+impl Node for ThrowStatement {
+    fn string(&self) -> String {
+        format!("(throw {})", self.value.string())
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::ThrowStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for ThrowStatement {}
+
+pub struct CatchClause {
+    pub name: Token,
+    pub body: BlockStatement,
+}
+
+// At least one of `catch_clause` and `finally_body` is present.
+pub struct TryStatement {
+    pub body: BlockStatement,
+    pub catch_clause: Option<CatchClause>,
+    pub finally_body: Option<BlockStatement>,
+}
+
+impl Node for TryStatement {
+    fn string(&self) -> String {
+        let mut parts = vec![self.body.string()];
+        if let Some(catch_clause) = &self.catch_clause {
+            parts.push(format!("(catch {} {})", catch_clause.name.lexeme, catch_clause.body.string()));
+        }
+        if let Some(finally_body) = &self.finally_body {
+            parts.push(format!("(finally {})", finally_body.string()));
+        }
+        format!("(try {})", parts.join(" "))
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::TryStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for TryStatement {}
+
+// One name in `import { name as alias } from "path";`.
+pub struct ImportName {
+    pub name: Token,
+    pub alias: Option<Token>,
+}
+
+impl ImportName {
+    // The name the import is bound to in the importing file.
+    pub fn binding(&self) -> &Token {
+        self.alias.as_ref().unwrap_or(&self.name)
+    }
+}
+
+// Either `import "path" as alias;` (`alias` set) or `import { a, b as c } from "path";`
+// (`names` non-empty).
+pub struct ImportStatement {
+    pub token: Token,
+    pub path: String,
+    pub alias: Option<Token>,
+    pub names: Vec<ImportName>,
+}
+
+impl Node for ImportStatement {
+    fn string(&self) -> String {
+        if let Some(alias) = &self.alias {
+            return format!("(import \"{}\" {})", self.path, alias.lexeme);
+        }
+        let names: Vec<String> = self.names.iter()
+            .map(|n| match &n.alias {
+                Some(alias) => format!("({} {})", n.name.lexeme, alias.lexeme),
+                None => n.name.lexeme.to_string(),
+            })
+            .collect();
+        format!("(import \"{}\" ({}))", self.path, names.join(" "))
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::ImportStatement
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl Statement for ImportStatement {}
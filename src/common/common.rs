@@ -1,10 +1,13 @@
+use std::io;
+use crate::common::output::Output;
+
 pub trait PrjString {
-    fn print_error(&self);
+    fn print_error(&self, diagnostics: &Output) -> io::Result<()>;
 }
 
 impl PrjString for String {
-    fn print_error(&self) {
-        eprintln!("{self}");
+    fn print_error(&self, diagnostics: &Output) -> io::Result<()> {
+        diagnostics.write_line(self)
     }
 }
 
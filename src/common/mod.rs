@@ -1 +1,4 @@
-pub mod common;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod common;
+pub mod output;
+pub mod symbol;
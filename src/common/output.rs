@@ -0,0 +1,72 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// A destination for text: program output, token and AST listings, or diagnostics.
+// Clones share the same writer, so output from several sources stays in order.
+#[derive(Clone)]
+pub struct Output {
+    writer: Rc<RefCell<dyn Write>>,
+}
+
+impl Output {
+    pub fn new(writer: impl Write + 'static) -> Self {
+        Self { writer: Rc::new(RefCell::new(writer)) }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+
+    // An output that collects everything written to it in the returned buffer.
+    pub fn capture() -> (Self, OutputBuffer) {
+        let buffer = OutputBuffer::default();
+        (Self::new(buffer.clone()), buffer)
+    }
+
+    // Writes `text` without ending the line, e.g. a prompt.
+    pub fn write(&self, text: &str) -> io::Result<()> {
+        write!(self.writer.borrow_mut(), "{}", text)
+    }
+
+    pub fn write_line(&self, line: &str) -> io::Result<()> {
+        writeln!(self.writer.borrow_mut(), "{}", line)
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.writer.borrow_mut().flush()
+    }
+}
+
+// Text captured by Output::capture.
+#[derive(Clone, Default)]
+pub struct OutputBuffer {
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl OutputBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.borrow()).to_string()
+    }
+
+    // Returns what was captured so far and empties the buffer.
+    pub fn take(&self) -> String {
+        let bytes = std::mem::take(&mut *self.bytes.borrow_mut());
+        String::from_utf8_lossy(&bytes).to_string()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+
+// A name: an identifier from source code, or one the interpreter or host uses.
+// Symbols an Interner made for the same name share one allocation, and symbols
+// are compared and hashed by that allocation, so neither looks at the text.
+// Names that are looked up must therefore all come from the same Interner.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+// Names the interpreter itself looks up. Every Interner starts with these, so
+// `Symbol::known` equals what the lexer makes for them.
+#[derive(Clone, Copy)]
+pub enum Known {
+    This,
+    Init,
+    Kind,
+    Message,
+    Line,
+    Stack,
+}
+
+const KNOWN_NAMES: [&str; 6] = ["this", "init", "kind", "message", "line", "stack"];
+
+thread_local! {
+    static KNOWN: Vec<Symbol> = KNOWN_NAMES.iter().map(|name| Symbol(Rc::from(*name))).collect();
+}
+
+impl Symbol {
+    // A symbol of its own, equal only to its clones. For names that are only
+    // shown, like "script" or hidden locals, never looked up.
+    pub fn new(name: &str) -> Symbol {
+        Symbol(Rc::from(name))
+    }
+
+    pub fn known(name: Known) -> Symbol {
+        KNOWN.with(|known| known[name as usize].clone())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as *const u8).hash(state);
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+// The names of the programs one interpreter has compiled and of the globals its
+// host defined, so that each name is allocated once however often it appears.
+// Clones share the table, which is freed with the last of them, so a long session
+// or an embedding host that creates many interpreters doesn't keep old names alive.
+#[derive(Clone)]
+pub struct Interner {
+    names: Rc<RefCell<HashSet<Rc<str>>>>,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        let names = KNOWN.with(|known| known.iter().map(|symbol| Rc::clone(&symbol.0)).collect());
+        Self { names: Rc::new(RefCell::new(names)) }
+    }
+}
+
+impl Interner {
+    pub fn intern(&self, name: &str) -> Symbol {
+        let mut names = self.names.borrow_mut();
+        if let Some(interned) = names.get(name) {
+            return Symbol(Rc::clone(interned));
+        }
+        let interned: Rc<str> = Rc::from(name);
+        names.insert(Rc::clone(&interned));
+        Symbol(interned)
+    }
+
+    // The symbol for `name` if it has been interned, without adding it.
+    pub fn lookup(&self, name: &str) -> Option<Symbol> {
+        self.names.borrow().get(name).map(|interned| Symbol(Rc::clone(interned)))
+    }
+
+    // How many distinct names have been interned, counting the known ones.
+    pub fn len(&self) -> usize {
+        self.names.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.borrow().is_empty()
+    }
+}
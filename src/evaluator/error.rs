@@ -0,0 +1,219 @@
+use std::fmt;
+use crate::lexer::lexer::Token;
+use crate::object::object::Value;
+
+// Broad category of a runtime error, visible to scripts as the `kind` field of
+// caught error objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    TypeError,
+    NameError,
+    PropertyError,
+    IndexError,
+    // An argument of the right type but an unusable value, like num("abc").
+    ValueError,
+    MatchError,
+    ImportError,
+    // A failure reported by a host function.
+    HostError,
+    // An I/O builtin was denied by the sandbox.
+    PermissionError,
+    // An I/O builtin failed, e.g. because a file doesn't exist.
+    IOError,
+    // `exit(status)` was called. Can't be caught.
+    Exit(i32),
+    // A value thrown by the script with `throw`.
+    Exception,
+    // Calls nested deeper than the call depth limit.
+    StackOverflow,
+    // Loaded bytecode broke an assumption the verifier doesn't prove, e.g. it reads
+    // a list element a pattern never checked for. Can't be caught.
+    BytecodeError,
+    // The remaining kinds come from execution limits and can't be caught.
+    StepLimitExceeded,
+    MemoryLimitExceeded,
+    Timeout,
+    // The host stopped the program through its interrupt handle.
+    Interrupted,
+}
+
+impl ErrorKind {
+    // Whether `catch` sees this error. Uncatchable errors also skip `finally` blocks,
+    // so scripts can't keep running after them.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            ErrorKind::Exit(_)
+                | ErrorKind::BytecodeError
+                | ErrorKind::StepLimitExceeded
+                | ErrorKind::MemoryLimitExceeded
+                | ErrorKind::Timeout
+                | ErrorKind::Interrupted
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::TypeError => "TypeError",
+            ErrorKind::NameError => "NameError",
+            ErrorKind::PropertyError => "PropertyError",
+            ErrorKind::IndexError => "IndexError",
+            ErrorKind::ValueError => "ValueError",
+            ErrorKind::MatchError => "MatchError",
+            ErrorKind::ImportError => "ImportError",
+            ErrorKind::HostError => "HostError",
+            ErrorKind::PermissionError => "PermissionError",
+            ErrorKind::IOError => "IOError",
+            ErrorKind::Exit(_) => "Exit",
+            ErrorKind::Exception => "Exception",
+            ErrorKind::StackOverflow => "StackOverflow",
+            ErrorKind::BytecodeError => "BytecodeError",
+            ErrorKind::StepLimitExceeded => "StepLimitExceeded",
+            ErrorKind::MemoryLimitExceeded => "MemoryLimitExceeded",
+            ErrorKind::Timeout => "Timeout",
+            ErrorKind::Interrupted => "Interrupted",
+        }
+    }
+}
+
+// One line of a stack trace: the line executing in `function` when the error passed through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub line: i32,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.function == "script" {
+            write!(f, "[line {}] in script", self.line)
+        } else if self.function.starts_with('<') {
+            // Not a function, like the top level of a module.
+            write!(f, "[line {}] in {}", self.line, self.function)
+        } else {
+            write!(f, "[line {}] in {}()", self.line, self.function)
+        }
+    }
+}
+
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub line: i32,
+    // Filled in by the evaluator the first time the error leaves a function call,
+    // reaches a `catch`, or reaches the top level, while the call stack still matches
+    // the point where it was raised.
+    pub trace: Option<Vec<Frame>>,
+    // The value given to `throw`; None for errors raised by the interpreter itself.
+    pub thrown: Option<Value>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: ErrorKind, message: &str, token: &Token) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+            line: token.line_number,
+            trace: None,
+            thrown: None,
+        }
+    }
+
+    // For errors raised away from any particular token, like an exceeded limit.
+    pub fn at_line(kind: ErrorKind, message: &str, line: i32) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+            line,
+            trace: None,
+            thrown: None,
+        }
+    }
+}
+
+// An error returned by a native function; the evaluator turns it into a
+// RuntimeError at the call site.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativeError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl NativeError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into() }
+    }
+}
+
+impl From<String> for NativeError {
+    fn from(message: String) -> Self {
+        NativeError::new(ErrorKind::HostError, message)
+    }
+}
+
+impl From<&str> for NativeError {
+    fn from(message: &str) -> Self {
+        NativeError::new(ErrorKind::HostError, message)
+    }
+}
+
+impl fmt::Display for NativeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl fmt::Debug for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RuntimeError")
+            .field("kind", &self.kind)
+            .field("message", &self.message)
+            .field("line", &self.line)
+            .field("trace", &self.trace)
+            .field("thrown", &self.thrown.as_ref().map(|value| value.inspect()))
+            .finish()
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+// The message followed by the stack trace, innermost frame first:
+//
+//     Operands must be numbers.
+//     [line 2] in fib()
+//     [line 5] in script
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        match &self.trace {
+            Some(trace) => trace_lines(trace).iter().try_for_each(|line| write!(f, "\n{}", line)),
+            None => write!(f, "\n[line {}]", self.line),
+        }
+    }
+}
+
+// How many times a frame repeated by recursion is printed before the rest of
+// the run is counted instead.
+const REPEATS_SHOWN: usize = 3;
+// A trace longer than twice this keeps only this many lines at each end.
+const TRACE_ENDS_SHOWN: usize = 20;
+
+// The lines a trace prints as. A run of identical frames, as direct recursion
+// leaves, shows its first few frames and then "... N more".
+fn trace_lines(trace: &[Frame]) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < trace.len() {
+        let run = trace[start..].iter().take_while(|frame| **frame == trace[start]).count();
+        lines.extend(trace[start..start + run.min(REPEATS_SHOWN)].iter().map(Frame::to_string));
+        if run > REPEATS_SHOWN {
+            lines.push(format!("... {} more", run - REPEATS_SHOWN));
+        }
+        start += run;
+    }
+    if lines.len() > 2 * TRACE_ENDS_SHOWN {
+        let omitted = lines.len() - 2 * TRACE_ENDS_SHOWN;
+        lines.splice(TRACE_ENDS_SHOWN..lines.len() - TRACE_ENDS_SHOWN, [format!("... {} more lines", omitted)]);
+    }
+    lines
+}
@@ -1,51 +1,946 @@
-use crate::ast::ast::{Expression, Node, Program, Statement, StringLiteral};
-use crate::object::object::{
-    Boolean as BooleanObject,
-    Nil as NilObject,
-    NumberLiteral as NumberLiteralObject,
-    StringLiteral as StringLiteralObject,
-    Object
-};
-use crate::ast::ast::{Boolean, NumberLiteral, Nil, ExpressionStatement};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::ast::ast::{AssignExpression, BindingPattern, BlockStatement, BreakStatement, CallExpression, ClassStatement, ContinueStatement, Expression, ForStatement, FunctionStatement, GetExpression, Grouping, Identifier, IfStatement, ImportStatement, IndexExpression, InfixExpression, InstancePattern, ListLiteral, ListPattern, LiteralPattern, MapLiteral, MapPattern, MatchArm, MatchExpression, MatchStatement, Node, Pattern, PrefixExpression, PrintStatement, Program, RangePattern, Resolution, RestPattern, ReturnStatement, Statement, StringLiteral, TernaryExpression, ThrowStatement, TryStatement, UpdateExpression, VarStatement, WhileStatement, WildcardPattern};
+use crate::common::output::Output;
+use crate::common::symbol::{Interner, Symbol};
+use crate::evaluator::error::{ErrorKind, Frame, NativeError, RuntimeError};
+use crate::evaluator::limits::{InterruptHandle, Limits, Meter};
+use crate::interpreter::permissions::Permissions;
+use crate::evaluator::modules;
+use crate::evaluator::operations::{apply_infix_operator, caught_value, check_arity, check_call_depth, check_native_arity, compound_operator, declared_name, index_place, instantiate, map_key, negate, no_match, not_a_class, not_callable, operand_not_number, property_place, read_place, thrown_error, unbound_method, undefined_variable, write_place, Name, Place};
+use crate::object::environment::Environment;
+use crate::object::heap::{GcConfig, GcStats, Heap};
+use crate::object::object::{ClassData, FunctionData, HashPair, MapData, ModuleData, NativeFunctionData, Value};
+use crate::object::shape::Lookup;
+use crate::ast::ast::{Boolean, NumberLiteral, ExpressionStatement};
+use crate::lexer::lexer::{Token, TokenType};
 
-pub fn eval(node: Box<dyn Node>) -> Box<dyn Object> {
-    if let Some(p) = node.as_any().downcast_ref::<Program>() {
-        for stmt in p.statements.iter() {
-            return eval_statement(stmt);
+// Why statement evaluation stopped early. `break` and `continue` unwind as values
+// through `?` up to the loop they target; the resolver guarantees such a loop exists.
+// A tail call unwinds to the function making it, which runs the callee in its place.
+pub enum Interrupt {
+    Error(RuntimeError),
+    Return(Value),
+    TailCall(Rc<FunctionData>, Vec<Value>, Token),
+    Break(Option<Symbol>),
+    Continue(Option<Symbol>),
+}
+
+impl From<RuntimeError> for Interrupt {
+    fn from(err: RuntimeError) -> Self {
+        Interrupt::Error(err)
+    }
+}
+
+struct CallFrame {
+    function: Symbol,
+    call_line: i32,
+}
+
+enum LoopAction {
+    Next,
+    Exit,
+}
+
+pub struct Evaluator {
+    call_stack: Vec<CallFrame>,
+    // Built-in class of the error objects that `catch` receives for runtime errors.
+    error_class: Rc<ClassData>,
+    // Modules already loaded, by canonical path, so each file runs only once.
+    modules: HashMap<PathBuf, Rc<ModuleData>>,
+    // Files currently executing, outermost first; imports resolve relative to the last one.
+    loading: Vec<PathBuf>,
+    // Which files outside the main script's directory may be imported.
+    permissions: Rc<RefCell<Permissions>>,
+    // Where the identifiers of imported modules are interned.
+    interner: Interner,
+    // Host functions, defined in every module's global scope as well as the main one.
+    natives: Vec<Rc<NativeFunctionData>>,
+    limits: Limits,
+    meter: Meter,
+    interrupt: InterruptHandle,
+    // Whether imported modules go through the optimizer.
+    optimize: bool,
+    // Heap objects allocated by the program, for the garbage collector and heap limits.
+    heap: Heap,
+    // The last source line evaluation reached, for errors raised between tokens.
+    line: i32,
+    // Where `print` writes.
+    output: Output,
+}
+
+impl Evaluator {
+    // Defines the built-in globals in `globals`.
+    pub fn new(globals: &Rc<RefCell<Environment>>) -> Self {
+        let evaluator = Self {
+            call_stack: Vec::new(),
+            error_class: Rc::new(ClassData::new(Symbol::new("Error"))),
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            permissions: Rc::default(),
+            interner: Interner::default(),
+            natives: Vec::new(),
+            limits: Limits::default(),
+            meter: Meter::default(),
+            interrupt: InterruptHandle::default(),
+            optimize: false,
+            heap: Heap::default(),
+            line: 0,
+            output: Output::stdout(),
+        };
+        evaluator.define_builtins(globals);
+        evaluator
+    }
+
+    fn define_builtins(&self, globals: &Rc<RefCell<Environment>>) {
+        globals.borrow_mut().define(self.error_class.name.clone(), Value::Class(Rc::clone(&self.error_class)));
+        for native in self.natives.iter() {
+            globals.borrow_mut().define(native.name.clone(), Value::NativeFunction(Rc::clone(native)));
         }
     }
-    if let Some(b) = node.as_any().downcast_ref::<Boolean>() {
-        return Box::new(BooleanObject { value: b.value });
+
+    // Makes a host function available in `globals` and in modules loaded from now on.
+    pub fn define_native(&mut self, globals: &Rc<RefCell<Environment>>, native: Rc<NativeFunctionData>) {
+        globals.borrow_mut().define(native.name.clone(), Value::NativeFunction(Rc::clone(&native)));
+        self.natives.retain(|existing| existing.name != native.name);
+        self.natives.push(native);
     }
-    if let Some(n) = node.as_any().downcast_ref::<NumberLiteral>() {
-        return Box::new(NumberLiteralObject { value: n.value, literal: n.literal.to_string() })
+
+    // Sets the file the program was read from, so that its imports resolve relative to it.
+    // Without one, imports resolve relative to the working directory.
+    pub fn set_script_path(&mut self, path: Option<&Path>) {
+        self.loading = modules::script_loading(path);
     }
-    if let Some(s) = node.as_any().downcast_ref::<StringLiteral>() {
-        return Box::new(StringLiteralObject { value: s.value.to_string() })
+
+    // The class of the error objects `catch` receives, shared with the VM.
+    pub fn error_class(&self) -> Rc<ClassData> {
+        Rc::clone(&self.error_class)
     }
-    if let Some(_) = node.as_any().downcast_ref::<Nil>() {
-        Box::new(NilObject {})
-    } else {
-        Box::new(NilObject {})
+
+    // Shares the interpreter's permissions, which decide what may be imported.
+    pub fn set_permissions(&mut self, permissions: Rc<RefCell<Permissions>>) {
+        self.permissions = permissions;
     }
-}
 
-fn eval_statement(stmt: &Box<dyn Statement>) -> Box<dyn Object> {
-    if let Some(e) = stmt.as_any().downcast_ref::<ExpressionStatement>() {
-        return eval_expression(&e.expression);
+    // Shares the interpreter's interner with the modules this evaluator imports.
+    pub fn set_interner(&mut self, interner: Interner) {
+        self.interner = interner;
+    }
+
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    pub fn set_gc_config(&mut self, config: GcConfig) {
+        self.heap.set_config(config);
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats().clone()
+    }
+
+    // Runs the collector now; returns how many objects it freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.heap.collect()
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    // A handle other threads can use to stop the program this evaluator is running.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    pub fn eval(&mut self, node: &dyn Node, env: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        if let Some(p) = node.as_any().downcast_ref::<Program>() {
+            // Every run gets the full budget; an interrupt that arrived between runs is dropped.
+            self.meter = Meter::start(&self.limits);
+            self.interrupt.reset();
+            self.line = 1;
+            let mut result: Value = Value::Nil;
+            for stmt in p.statements.iter() {
+                result = match self.eval_statement(stmt.as_ref(), env) {
+                    Ok(obj) => obj,
+                    Err(Interrupt::Error(mut err)) => {
+                        self.record_trace(&mut err);
+                        return Err(err);
+                    }
+                    Err(Interrupt::Return(_) | Interrupt::TailCall(..) | Interrupt::Break(_) | Interrupt::Continue(_)) => Value::Nil,
+                };
+            }
+            return Ok(result);
+        }
+        if let Some(b) = node.as_any().downcast_ref::<Boolean>() {
+            return Ok(Value::Boolean(b.value));
+        }
+        if let Some(n) = node.as_any().downcast_ref::<NumberLiteral>() {
+            return Ok(Value::Number(n.value))
+        }
+        if let Some(s) = node.as_any().downcast_ref::<StringLiteral>() {
+            return Ok(Value::String(Rc::clone(&s.value)))
+        }
+        Ok(Value::Nil)
+    }
+
+    // Counts one step of execution and checks the step budget, the deadline and the
+    // interrupt flag.
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        self.meter.tick(&self.limits, &self.interrupt).map_err(|(kind, message)| self.limit_error(kind, &message))
+    }
+
+    fn limit_error(&self, kind: ErrorKind, message: &str) -> RuntimeError {
+        let mut err = RuntimeError::at_line(kind, message, self.line);
+        self.record_trace(&mut err);
+        err
+    }
+
+    // Registers a newly allocated object with the collector and against the heap limits.
+    fn track(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        self.heap.track(value);
+        if self.heap.should_collect() {
+            self.heap.collect();
+        }
+        self.heap.check_limits(&self.limits).map_err(|message| self.limit_error(ErrorKind::MemoryLimitExceeded, &message))
+    }
+
+    // Assigns `value` to `place`. A value stored in a container counts against the
+    // heap limits, since the container grows without a new allocation.
+    fn write(&mut self, place: Place, value: Value) -> Result<(), RuntimeError> {
+        let in_container = place.in_container();
+        if in_container {
+            self.heap.note_write(&value);
+        }
+        write_place(place, value)?;
+        if !in_container {
+            return Ok(());
+        }
+        self.heap.check_limits(&self.limits).map_err(|message| self.limit_error(ErrorKind::MemoryLimitExceeded, &message))
+    }
+
+    // A new scope inside `outer`. Scopes are registered with the heap because
+    // closures capture them, which can create cycles.
+    fn new_scope(&mut self, outer: &Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+        self.new_frame(outer, Vec::new())
+    }
+
+    // A new scope whose first slots hold `slots`.
+    fn new_frame(&mut self, outer: &Rc<RefCell<Environment>>, slots: Vec<Option<Value>>) -> Rc<RefCell<Environment>> {
+        let env = Environment::new_frame(outer, slots);
+        self.heap.track_environment(&env);
+        if self.heap.should_collect() {
+            self.heap.collect();
+        }
+        env
+    }
+
+    // Fails if the next call would nest deeper than the call depth limit.
+    fn check_call_depth(&self, token: &Token) -> Result<(), RuntimeError> {
+        check_call_depth(self.call_stack.len(), self.limits.max_call_depth, token.line_number)
+    }
+
+    // The stack as it is now, innermost frame first, for an error raised at `line`.
+    fn stack_trace(&self, line: i32) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut line = line;
+        for frame in self.call_stack.iter().rev() {
+            frames.push(Frame { function: frame.function.to_string(), line });
+            line = frame.call_line;
+        }
+        frames.push(Frame { function: "script".to_string(), line });
+        frames
+    }
+
+    fn record_trace(&self, err: &mut RuntimeError) {
+        if err.trace.is_none() {
+            err.trace = Some(self.stack_trace(err.line));
+        }
+    }
+
+    fn eval_throw_statement(&mut self, t: &ThrowStatement, env: &Rc<RefCell<Environment>>) -> Result<Value, Interrupt> {
+        let value = self.eval_expression(t.value.as_ref(), env)?;
+        Err(Interrupt::Error(thrown_error(value, &self.error_class, t.token.line_number)))
+    }
+
+    // `finally` runs unless an uncatchable error is unwinding. If it completes normally,
+    // the outcome of the try (or catch) block stands, including a pending return, break,
+    // continue or error; if it exits early itself, that replaces the pending outcome.
+    fn eval_try_statement(&mut self, t: &TryStatement, env: &Rc<RefCell<Environment>>) -> Result<Value, Interrupt> {
+        let body_env = self.new_scope(env);
+        let mut outcome = self.eval_block_statement(&t.body, &body_env);
+        if let Some(catch_clause) = &t.catch_clause {
+            outcome = match outcome {
+                Err(Interrupt::Error(mut err)) if err.kind.is_catchable() => {
+                    self.record_trace(&mut err);
+                    let value = caught_value(err, &self.error_class);
+                    // The caught error has a scope of its own around the block's.
+                    let catch_env = self.new_frame(env, vec![Some(value)]);
+                    self.eval_statement(&catch_clause.body, &catch_env)
+                }
+                outcome => outcome,
+            };
+        }
+        if let Err(Interrupt::Error(err)) = &outcome {
+            if !err.kind.is_catchable() {
+                return outcome;
+            }
+        }
+        if let Some(finally_body) = &t.finally_body {
+            let finally_env = self.new_scope(env);
+            self.eval_block_statement(finally_body, &finally_env)?;
+        }
+        outcome
+    }
+
+    fn eval_import_statement(&mut self, i: &ImportStatement, env: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        let path = modules::resolve_import(&self.loading, &self.permissions.borrow(), &i.path, i.token.line_number)?;
+        let module = match self.modules.get(&path) {
+            Some(module) => Rc::clone(module),
+            None => self.load_module(path, i)?,
+        };
+
+        if let Some(alias) = &i.alias {
+            env.borrow_mut().define(alias.lexeme.clone(), Value::Module(module));
+            return Ok(Value::Nil);
+        }
+        for name in i.names.iter() {
+            let value = read_place(&Place::Export(Rc::clone(&module), Name::from(&name.name)))?;
+            env.borrow_mut().define(name.binding().lexeme.clone(), value);
+        }
+        Ok(Value::Nil)
+    }
+
+    // Compiles and runs the module at `path` in a fresh global scope.
+    fn load_module(&mut self, path: PathBuf, i: &ImportStatement) -> Result<Rc<ModuleData>, RuntimeError> {
+        let program = modules::parse_module(&path, &i.path, i.token.line_number, self.optimize, &self.interner)?;
+        let globals = Environment::new();
+        self.define_builtins(&globals);
+        // The module's top-level code shows up in stack traces as a frame of its own.
+        let module_frame = CallFrame { function: Symbol::new(&modules::module_frame_name(&self.loading, &path)), call_line: i.token.line_number };
+        self.call_stack.push(module_frame);
+        self.loading.push(path.clone());
+        let mut outcome = Ok(());
+        for stmt in program.statements.iter() {
+            if let Err(Interrupt::Error(mut err)) = self.eval_statement(stmt.as_ref(), &globals) {
+                self.record_trace(&mut err);
+                outcome = Err(err);
+                break;
+            }
+        }
+        self.loading.pop();
+        self.call_stack.pop();
+        outcome?;
+
+        let module = Rc::new(ModuleData {
+            name: modules::module_name(&path, &i.path),
+            globals,
+            exports: program.statements.iter().filter_map(|stmt| declared_name(stmt.as_ref())).collect(),
+        });
+        self.modules.insert(path, Rc::clone(&module));
+        Ok(module)
+    }
+
+    fn call_native(&mut self, native: &NativeFunctionData, arguments: Vec<Value>, token: &Token) -> Result<Value, RuntimeError> {
+        check_native_arity(native, arguments.len(), token.line_number)?;
+        self.check_call_depth(token)?;
+        self.call_stack.push(CallFrame { function: native.name.clone(), call_line: token.line_number });
+        let outcome = (native.function)(arguments).map_err(|err: NativeError| {
+            let mut err = runtime_error(err.kind, &err.message, token);
+            self.record_trace(&mut err);
+            err
+        });
+        self.call_stack.pop();
+        let result = outcome?;
+        self.track(&result)?;
+        Ok(result)
+    }
+
+    fn call_function(&mut self, function: Rc<FunctionData>, arguments: Vec<Value>, token: &Token) -> Result<Value, RuntimeError> {
+        check_arity(function.params.len(), arguments.len(), token.line_number)?;
+        self.check_call_depth(token)?;
+        self.call_stack.push(CallFrame { function: function.name.clone(), call_line: token.line_number });
+        let outcome = self.run_function(function, arguments);
+        self.call_stack.pop();
+        outcome
+    }
+
+    // Runs the body of the function on top of the call stack, then that of each
+    // function it tail-calls in turn, so a chain of tail calls of any length uses
+    // one frame and no extra native stack.
+    fn run_function(&mut self, mut function: Rc<FunctionData>, mut arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        loop {
+            // Parameters are the frame's first slots.
+            let env = self.new_frame(&function.closure, arguments.into_iter().map(Some).collect());
+            let interrupt = function.body.statements.iter().find_map(|stmt| self.eval_statement(stmt.as_ref(), &env).err());
+            match interrupt {
+                Some(Interrupt::TailCall(callee, callee_arguments, token)) => {
+                    if let Err(mut err) = check_arity(callee.params.len(), callee_arguments.len(), token.line_number) {
+                        self.record_trace(&mut err);
+                        return Err(err);
+                    }
+                    // The callee takes over the frame, and returns straight to its caller.
+                    self.call_stack.last_mut().expect("a function is executing").function = callee.name.clone();
+                    function = callee;
+                    arguments = callee_arguments;
+                }
+                Some(Interrupt::Error(mut err)) => {
+                    self.record_trace(&mut err);
+                    return Err(err);
+                }
+                // An initializer returns the instance, found where `this` is.
+                _ if function.initializer => return Ok(function.closure.borrow().get_at(0, 0).expect("a method is bound")),
+                Some(Interrupt::Return(value)) => return Ok(value),
+                Some(Interrupt::Break(_) | Interrupt::Continue(_)) | None => return Ok(Value::Nil),
+            }
+        }
+    }
+
+    fn eval_statement(&mut self, stmt: &dyn Statement, env: &Rc<RefCell<Environment>>) -> Result<Value, Interrupt> {
+        self.step()?;
+        let any = stmt.as_any();
+        if let Some(e) = any.downcast_ref::<ExpressionStatement>() {
+            return Ok(self.eval_expression(e.expression.as_ref(), env)?);
+        }
+        if let Some(p) = any.downcast_ref::<PrintStatement>() {
+            let value = self.eval_expression(p.expression.as_ref(), env)?;
+            if let Err(err) = self.output.write_line(&value.inspect()) {
+                let message = format!("Could not write output: {}.", err);
+                return Err(Interrupt::Error(RuntimeError::at_line(ErrorKind::IOError, &message, self.line)));
+            }
+            return Ok(Value::Nil);
+        }
+        if let Some(v) = any.downcast_ref::<VarStatement>() {
+            self.line = v.name.line_number;
+            let value = match &v.initializer {
+                Some(initializer) => self.eval_expression(initializer.as_ref(), env)?,
+                None => Value::Nil,
+            };
+            declare(env, &v.name, v.slot.get(), value);
+            return Ok(Value::Nil);
+        }
+        if let Some(b) = any.downcast_ref::<BlockStatement>() {
+            let block_env = self.new_scope(env);
+            return self.eval_block_statement(b, &block_env);
+        }
+        if let Some(i) = any.downcast_ref::<IfStatement>() {
+            let condition = self.eval_expression(i.condition.as_ref(), env)?;
+            if condition.is_truthy() {
+                return self.eval_statement(i.consequence.as_ref(), env);
+            }
+            if let Some(alternative) = &i.alternative {
+                return self.eval_statement(alternative.as_ref(), env);
+            }
+            return Ok(Value::Nil);
+        }
+        if let Some(w) = any.downcast_ref::<WhileStatement>() {
+            return self.eval_while_statement(w, env);
+        }
+        if let Some(f) = any.downcast_ref::<ForStatement>() {
+            let loop_env = self.new_scope(env);
+            return self.eval_for_statement(f, &loop_env);
+        }
+        if let Some(c) = any.downcast_ref::<ClassStatement>() {
+            let mut methods = HashMap::new();
+            for method in c.methods.iter() {
+                let function = Value::Function(Rc::new(FunctionData {
+                    name: method.name.lexeme.clone(),
+                    params: Rc::clone(&method.params),
+                    body: Rc::clone(&method.body),
+                    closure: Rc::clone(env),
+                    initializer: method.name.lexeme == "init",
+                }));
+                self.track(&function)?;
+                methods.insert(method.name.lexeme.clone(), function);
+            }
+            let class = Value::Class(Rc::new(ClassData::with_methods(c.name.lexeme.clone(), methods)));
+            self.track(&class)?;
+            declare(env, &c.name, c.slot.get(), class);
+            return Ok(Value::Nil);
+        }
+        if let Some(m) = any.downcast_ref::<MatchStatement>() {
+            return self.eval_match_statement(m, env);
+        }
+        if let Some(f) = any.downcast_ref::<FunctionStatement>() {
+            let function = Value::Function(Rc::new(FunctionData {
+                name: f.name.lexeme.clone(),
+                params: Rc::clone(&f.params),
+                body: Rc::clone(&f.body),
+                closure: Rc::clone(env),
+                initializer: false,
+            }));
+            self.track(&function)?;
+            declare(env, &f.name, f.slot.get(), function);
+            return Ok(Value::Nil);
+        }
+        if let Some(r) = any.downcast_ref::<ReturnStatement>() {
+            if r.tail_call.get() {
+                if let Some(c) = r.value.as_ref().and_then(|value| value.as_any().downcast_ref::<CallExpression>()) {
+                    return Err(self.eval_tail_call(c, env)?);
+                }
+            }
+            let value = match &r.value {
+                Some(value) => self.eval_expression(value.as_ref(), env)?,
+                None => Value::Nil,
+            };
+            return Err(Interrupt::Return(value));
+        }
+        if let Some(t) = any.downcast_ref::<ThrowStatement>() {
+            return self.eval_throw_statement(t, env);
+        }
+        if let Some(t) = any.downcast_ref::<TryStatement>() {
+            return self.eval_try_statement(t, env);
+        }
+        if let Some(i) = any.downcast_ref::<ImportStatement>() {
+            return Ok(self.eval_import_statement(i, env)?);
+        }
+        if let Some(b) = any.downcast_ref::<BreakStatement>() {
+            return Err(Interrupt::Break(b.label.as_ref().map(|l| l.lexeme.clone())));
+        }
+        if let Some(c) = any.downcast_ref::<ContinueStatement>() {
+            return Err(Interrupt::Continue(c.label.as_ref().map(|l| l.lexeme.clone())));
+        }
+        Ok(Value::Nil)
+    }
+
+    fn eval_block_statement(&mut self, block: &BlockStatement, env: &Rc<RefCell<Environment>>) -> Result<Value, Interrupt> {
+        for stmt in block.statements.iter() {
+            self.eval_statement(stmt.as_ref(), env)?;
+        }
+        Ok(Value::Nil)
+    }
+
+    fn eval_while_statement(&mut self, w: &WhileStatement, env: &Rc<RefCell<Environment>>) -> Result<Value, Interrupt> {
+        while self.eval_expression(w.condition.as_ref(), env)?.is_truthy() {
+            if let LoopAction::Exit = loop_action(self.eval_statement(w.body.as_ref(), env), &w.label)? {
+                break;
+            }
+        }
+        Ok(Value::Nil)
+    }
+
+    fn eval_for_statement(&mut self, f: &ForStatement, env: &Rc<RefCell<Environment>>) -> Result<Value, Interrupt> {
+        if let Some(initializer) = &f.initializer {
+            self.eval_statement(initializer.as_ref(), env)?;
+        }
+        loop {
+            if let Some(condition) = &f.condition {
+                if !self.eval_expression(condition.as_ref(), env)?.is_truthy() {
+                    break;
+                }
+            }
+            if let LoopAction::Exit = loop_action(self.eval_statement(f.body.as_ref(), env), &f.label)? {
+                break;
+            }
+            if let Some(increment) = &f.increment {
+                self.eval_expression(increment.as_ref(), env)?;
+            }
+        }
+        Ok(Value::Nil)
+    }
+
+    fn eval_match_statement(&mut self, m: &MatchStatement, env: &Rc<RefCell<Environment>>) -> Result<Value, Interrupt> {
+        self.eval_match(m.subject.as_ref(), &m.arms, m.token.line_number, env, |evaluator, body, arm_env| evaluator.eval_statement(body, arm_env))
+    }
+
+    fn eval_match_expression(&mut self, m: &MatchExpression, env: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        self.eval_match(m.subject.as_ref(), &m.arms, m.token.line_number, env, |evaluator, body, arm_env| evaluator.eval_expression(body, arm_env))
+    }
+
+    // Runs the body of the first arm whose pattern matches and whose guard
+    // (evaluated with the pattern's bindings in scope) is truthy. No matching arm
+    // is a runtime error.
+    fn eval_match<B: ?Sized, E: From<RuntimeError>>(
+        &mut self,
+        subject: &dyn Expression,
+        arms: &[MatchArm<B>],
+        line: i32,
+        env: &Rc<RefCell<Environment>>,
+        run: impl FnOnce(&mut Self, &B, &Rc<RefCell<Environment>>) -> Result<Value, E>,
+    ) -> Result<Value, E> {
+        let subject = self.eval_expression(subject, env)?;
+        for arm in arms.iter() {
+            let mut bindings = Vec::new();
+            if !self.match_pattern(arm.pattern.as_ref(), &subject, &mut bindings, env)? {
+                continue;
+            }
+
+            let arm_env = self.new_frame(env, bindings.into_iter().map(Some).collect());
+            if let Some(guard) = &arm.guard {
+                if !self.eval_expression(guard.as_ref(), &arm_env)?.is_truthy() {
+                    continue;
+                }
+            }
+            return run(self, arm.body.as_ref(), &arm_env);
+        }
+        Err(no_match(&subject, line).into())
+    }
+
+    // Collects the values of the pattern's bindings in `bindings`, in the order the
+    // resolver numbered their slots.
+    fn match_pattern(&mut self, pattern: &dyn Pattern, value: &Value, bindings: &mut Vec<Value>, env: &Rc<RefCell<Environment>>) -> Result<bool, RuntimeError> {
+        let any = pattern.as_any();
+        if any.is::<WildcardPattern>() {
+            return Ok(true);
+        }
+        if any.is::<BindingPattern>() {
+            bindings.push(value.clone());
+            return Ok(true);
+        }
+        if let Some(l) = any.downcast_ref::<LiteralPattern>() {
+            let literal = self.eval_expression(l.value.as_ref(), env)?;
+            return Ok(literal == *value);
+        }
+        if let Some(r) = any.downcast_ref::<RangePattern>() {
+            return Ok(match value {
+                Value::Number(n) if r.inclusive => r.start <= *n && *n <= r.end,
+                Value::Number(n) => r.start <= *n && *n < r.end,
+                _ => false,
+            });
+        }
+        if let Some(l) = any.downcast_ref::<ListPattern>() {
+            let elements: Vec<Value> = match value {
+                Value::List(elements) => elements.borrow().clone(),
+                _ => return Ok(false),
+            };
+            let length_matches = match l.rest {
+                RestPattern::None => elements.len() == l.elements.len(),
+                RestPattern::Ignore | RestPattern::Bind(_) => elements.len() >= l.elements.len(),
+            };
+            if !length_matches {
+                return Ok(false);
+            }
+            for (pattern, element) in l.elements.iter().zip(elements.iter()) {
+                if !self.match_pattern(pattern.as_ref(), element, bindings, env)? {
+                    return Ok(false);
+                }
+            }
+            if let RestPattern::Bind(_) = &l.rest {
+                bindings.push(Value::list(elements[l.elements.len()..].to_vec()));
+            }
+            return Ok(true);
+        }
+        if let Some(m) = any.downcast_ref::<MapPattern>() {
+            let map = match value {
+                Value::Map(map) => map,
+                _ => return Ok(false),
+            };
+            for (key, pattern) in m.entries.iter() {
+                let key = self.eval_expression(key.as_ref(), env)?;
+                let entry = match key.hash_key().and_then(|k| map.borrow().get(&k).map(|pair| pair.value.clone())) {
+                    Some(entry) => entry,
+                    None => return Ok(false),
+                };
+                if !self.match_pattern(pattern.as_ref(), &entry, bindings, env)? {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        }
+        if let Some(i) = any.downcast_ref::<InstancePattern>() {
+            let class = match read_variable(&i.class_name, i.class_resolution.get(), env)? {
+                Value::Class(class) => class,
+                _ => return Err(not_a_class(Name::from(&i.class_name))),
+            };
+            let instance = match value {
+                Value::Instance(instance) if Rc::ptr_eq(&instance.borrow().class, &class) => Rc::clone(instance),
+                _ => return Ok(false),
+            };
+            for (name, pattern) in i.fields.iter() {
+                let field = match instance.borrow().get(&name.lexeme) {
+                    Some(field) => field.clone(),
+                    None => return Ok(false),
+                };
+                if !self.match_pattern(pattern.as_ref(), &field, bindings, env)? {
+                    return Ok(false);
+                }
+            }
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn eval_expression(&mut self, exp: &dyn Expression, env: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        self.step()?;
+        if let Some(b) = exp.as_any().downcast_ref::<Boolean>() {
+            return Ok(Value::Boolean(b.value))
+        }
+        if let Some(n) = exp.as_any().downcast_ref::<NumberLiteral>() {
+            return Ok(Value::Number(n.value))
+        }
+        if let Some(s) = exp.as_any().downcast_ref::<StringLiteral>() {
+            return Ok(Value::String(Rc::clone(&s.value)))
+        }
+        if let Some(g) = exp.as_any().downcast_ref::<Grouping>() {
+            return self.eval_expression(g.expression.as_ref(), env);
+        }
+        if let Some(p) = exp.as_any().downcast_ref::<PrefixExpression>() {
+            return self.eval_prefix_expression(p, env);
+        }
+        if let Some(i) = exp.as_any().downcast_ref::<InfixExpression>() {
+            return self.eval_infix_expression(i, env);
+        }
+        if let Some(t) = exp.as_any().downcast_ref::<TernaryExpression>() {
+            let condition = self.eval_expression(t.condition.as_ref(), env)?;
+            return if condition.is_truthy() {
+                self.eval_expression(t.consequence.as_ref(), env)
+            } else {
+                self.eval_expression(t.alternative.as_ref(), env)
+            };
+        }
+        if let Some(i) = exp.as_any().downcast_ref::<Identifier>() {
+            self.line = i.token.line_number;
+            return read_variable(&i.token, i.resolution.get(), env);
+        }
+        if let Some(a) = exp.as_any().downcast_ref::<AssignExpression>() {
+            return self.eval_assign_expression(a, env);
+        }
+        if let Some(u) = exp.as_any().downcast_ref::<UpdateExpression>() {
+            return self.eval_update_expression(u, env);
+        }
+        if let Some(g) = exp.as_any().downcast_ref::<GetExpression>() {
+            let object = self.eval_expression(g.object.as_ref(), env)?;
+            if let Value::Instance(instance) = &object {
+                let lookup = g.cache.lookup(&instance.borrow(), &g.name.lexeme);
+                match lookup {
+                    Some(Lookup::Field(slot)) => return Ok(instance.borrow().field(slot).clone()),
+                    Some(Lookup::Method(method)) => return Ok(Value::Function(self.bind(&method, object.clone())?)),
+                    None => {}
+                }
+            }
+            let place = property_place(object, Name::from(&g.name))?;
+            return self.read_property(&place);
+        }
+        if let Some(i) = exp.as_any().downcast_ref::<IndexExpression>() {
+            let place = self.eval_index_place(i, env)?;
+            return read_place(&place);
+        }
+        if let Some(l) = exp.as_any().downcast_ref::<ListLiteral>() {
+            let mut elements = Vec::with_capacity(l.elements.len());
+            for element in l.elements.iter() {
+                elements.push(self.eval_expression(element.as_ref(), env)?);
+            }
+            let list = Value::list(elements);
+            self.track(&list)?;
+            return Ok(list);
+        }
+        if let Some(m) = exp.as_any().downcast_ref::<MapLiteral>() {
+            let mut data = MapData::default();
+            for (key, value) in m.pairs.iter() {
+                let key = self.eval_expression(key.as_ref(), env)?;
+                let hash_key = map_key(&key, m.token.line_number)?;
+                let value = self.eval_expression(value.as_ref(), env)?;
+                data.insert(hash_key, HashPair { key, value });
+            }
+            let map = Value::Map(Rc::new(RefCell::new(data)));
+            self.track(&map)?;
+            return Ok(map);
+        }
+        if let Some(c) = exp.as_any().downcast_ref::<CallExpression>() {
+            return self.eval_call_expression(c, env);
+        }
+        if let Some(m) = exp.as_any().downcast_ref::<MatchExpression>() {
+            return self.eval_match_expression(m, env);
+        }
+        Ok(Value::Nil)
+    }
+
+    fn eval_call_expression(&mut self, c: &CallExpression, env: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        let (callee, arguments) = self.eval_call_operands(c, env)?;
+        self.call(callee, arguments, &c.token)
+    }
+
+    // Evaluates the call in a `return` the resolver marked as a tail call. Calls
+    // to Lox functions unwind to the function returning, which makes them in its
+    // own frame; anything else is called here.
+    fn eval_tail_call(&mut self, c: &CallExpression, env: &Rc<RefCell<Environment>>) -> Result<Interrupt, RuntimeError> {
+        match self.eval_call_operands(c, env)? {
+            (Value::Function(function), arguments) => Ok(Interrupt::TailCall(function, arguments, c.token.clone())),
+            (callee, arguments) => Ok(Interrupt::Return(self.call(callee, arguments, &c.token)?)),
+        }
+    }
+
+    fn eval_call_operands(&mut self, c: &CallExpression, env: &Rc<RefCell<Environment>>) -> Result<(Value, Vec<Value>), RuntimeError> {
+        let callee = self.eval_expression(c.callee.as_ref(), env)?;
+        self.line = c.token.line_number;
+        let mut arguments = Vec::with_capacity(c.arguments.len());
+        for argument in c.arguments.iter() {
+            arguments.push(self.eval_expression(argument.as_ref(), env)?);
+        }
+        Ok((callee, arguments))
+    }
+
+    fn call(&mut self, callee: Value, arguments: Vec<Value>, token: &Token) -> Result<Value, RuntimeError> {
+        match callee {
+            Value::Function(function) => self.call_function(function, arguments, token),
+            Value::NativeFunction(native) => self.call_native(&native, arguments, token),
+            Value::Class(class) => {
+                let (instance, initializer) = instantiate(class, arguments.len(), token.line_number)?;
+                self.track(&instance)?;
+                if let Some(Value::Function(initializer)) = initializer {
+                    let initializer = self.bind(&initializer, instance.clone())?;
+                    self.call_function(initializer, arguments, token)?;
+                }
+                Ok(instance)
+            }
+            _ => Err(not_callable(token.line_number)),
+        }
+    }
+
+    // Reads `place`; a method of the instance's class comes bound to the instance.
+    fn read_property(&mut self, place: &Place) -> Result<Value, RuntimeError> {
+        match unbound_method(place) {
+            Some((Value::Function(method), instance)) => Ok(Value::Function(self.bind(&method, instance)?)),
+            _ => read_place(place),
+        }
+    }
+
+    // A copy of `method` whose body sees `instance` as `this`, in a scope of its own
+    // between the method's closure and its parameters.
+    fn bind(&mut self, method: &Rc<FunctionData>, instance: Value) -> Result<Rc<FunctionData>, RuntimeError> {
+        let this = self.new_frame(&method.closure, vec![Some(instance)]);
+        let bound = Rc::new(FunctionData {
+            name: method.name.clone(),
+            params: Rc::clone(&method.params),
+            body: Rc::clone(&method.body),
+            closure: this,
+            initializer: method.initializer,
+        });
+        self.track(&Value::Function(Rc::clone(&bound)))?;
+        Ok(bound)
+    }
+
+    fn eval_place(&mut self, target: &dyn Expression, env: &Rc<RefCell<Environment>>) -> Result<Place, RuntimeError> {
+        if let Some(i) = target.as_any().downcast_ref::<Identifier>() {
+            return Ok(match i.resolution.get() {
+                Resolution::Local { slot, .. } => Place::Slot(Environment::ancestor(env, slot.depth), slot.index, Name::from(&i.token)),
+                Resolution::Global => Place::Variable(Name::from(&i.token), Rc::clone(env)),
+            });
+        }
+        if let Some(g) = target.as_any().downcast_ref::<GetExpression>() {
+            return self.eval_get_place(g, env);
+        }
+        if let Some(i) = target.as_any().downcast_ref::<IndexExpression>() {
+            return self.eval_index_place(i, env);
+        }
+        unreachable!("the parser only produces assignable targets")
+    }
+
+    // An instance field found through the expression's inline cache is a place by
+    // slot, so `p.x += 1` looks the field up once; anything else goes by name.
+    fn eval_get_place(&mut self, g: &GetExpression, env: &Rc<RefCell<Environment>>) -> Result<Place, RuntimeError> {
+        let object = self.eval_expression(g.object.as_ref(), env)?;
+        if let Value::Instance(instance) = &object {
+            let slot = g.cache.slot(&instance.borrow(), &g.name.lexeme);
+            if let Some(slot) = slot {
+                return Ok(Place::FieldSlot(Rc::clone(instance), slot));
+            }
+        }
+        property_place(object, Name::from(&g.name))
+    }
+
+    fn eval_index_place(&mut self, i: &IndexExpression, env: &Rc<RefCell<Environment>>) -> Result<Place, RuntimeError> {
+        let left = self.eval_expression(i.left.as_ref(), env)?;
+        let index = self.eval_expression(i.index.as_ref(), env)?;
+        index_place(left, index, i.token.line_number)
+    }
+
+    fn eval_assign_expression(&mut self, a: &AssignExpression, env: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        let place = self.eval_place(a.target.as_ref(), env)?;
+        let value = match compound_operator(&a.operator.token_type) {
+            Some(operator) => {
+                let current = self.read_property(&place)?;
+                let right = self.eval_expression(a.value.as_ref(), env)?;
+                apply_infix_operator(&operator, &current, &right, a.operator.line_number)?
+            }
+            None => self.eval_expression(a.value.as_ref(), env)?,
+        };
+        self.write(place, value.clone())?;
+        Ok(value)
+    }
+
+    fn eval_update_expression(&mut self, u: &UpdateExpression, env: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        let place = self.eval_place(u.target.as_ref(), env)?;
+        let current = self.read_property(&place)?;
+        let old = match current {
+            Value::Number(n) => n,
+            _ => return Err(operand_not_number(u.operator.line_number)),
+        };
+        let new = if u.operator.token_type == TokenType::PlusPlus { old + 1.0 } else { old - 1.0 };
+        self.write(place, Value::Number(new))?;
+        Ok(Value::Number(if u.prefix { new } else { old }))
+    }
+
+    fn eval_prefix_expression(&mut self, p: &PrefixExpression, env: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        let right = self.eval_expression(p.right.as_ref(), env)?;
+        match p.operator.token_type {
+            TokenType::Bang => Ok(Value::Boolean(!right.is_truthy())),
+            TokenType::Minus => negate(&right, p.operator.line_number),
+            _ => Err(runtime_error(ErrorKind::TypeError, &format!("Unknown prefix operator '{}'.", p.operator.lexeme), &p.operator)),
+        }
     }
-    Box::new(NilObject {})
-}
 
-fn eval_expression(exp: &Box<dyn Expression>) -> Box<dyn Object> {
-    if let Some(b) = exp.as_any().downcast_ref::<Boolean>() {
-        return Box::new(BooleanObject { value: b.value })
+    fn eval_infix_expression(&mut self, i: &InfixExpression, env: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        let left = self.eval_expression(i.left.as_ref(), env)?;
+        match i.token.token_type {
+            TokenType::Or if left.is_truthy() => return Ok(left),
+            TokenType::And if !left.is_truthy() => return Ok(left),
+            TokenType::Or | TokenType::And => return self.eval_expression(i.right.as_ref(), env),
+            _ => {}
+        }
+        let right = self.eval_expression(i.right.as_ref(), env)?;
+        self.line = i.token.line_number;
+        let result = apply_infix_operator(&i.token.token_type, &left, &right, i.token.line_number)?;
+        if let (Some(max), Value::String(s)) = (self.limits.max_heap_bytes, &result) {
+            if s.len() > max {
+                return Err(self.limit_error(ErrorKind::MemoryLimitExceeded, &format!("Heap limit of {} bytes exceeded.", max)));
+            }
+        }
+        Ok(result)
     }
-    if let Some(n) = exp.as_any().downcast_ref::<NumberLiteral>() {
-        return Box::new(NumberLiteralObject { value: n.value, literal: n.literal.to_string() })
+}
+
+// Decides what a loop labeled `label` does with the outcome of one iteration of its body.
+// Unlabeled break/continue target the innermost loop; labeled ones pass through until
+// they reach the loop carrying that label.
+fn loop_action(outcome: Result<Value, Interrupt>, label: &Option<Token>) -> Result<LoopAction, Interrupt> {
+    let targets_this_loop = |target: &Option<Symbol>| match target {
+        Some(target) => label.as_ref().is_some_and(|l| l.lexeme == *target),
+        None => true,
+    };
+    match outcome {
+        Ok(_) => Ok(LoopAction::Next),
+        Err(Interrupt::Break(target)) if targets_this_loop(&target) => Ok(LoopAction::Exit),
+        Err(Interrupt::Continue(target)) if targets_this_loop(&target) => Ok(LoopAction::Next),
+        Err(interrupt) => Err(interrupt),
     }
-    if let Some(s) = exp.as_any().downcast_ref::<StringLiteral>() {
-        return Box::new(StringLiteralObject { value: s.value.to_string() })
+}
+
+// Reads the variable the resolver bound `name` to. A local whose declaration
+// hasn't run yet is undefined, like a global that was never defined.
+fn read_variable(name: &Token, resolution: Resolution, env: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let env = env.borrow();
+    let value = match resolution {
+        Resolution::Local { slot, .. } => env.get_at(slot.depth, slot.index),
+        Resolution::Global => env.get(&name.lexeme),
+    };
+    value.ok_or_else(|| undefined_variable(Name::from(name)))
+}
+
+// Declares a variable in the innermost scope: in its slot, or by name at the top level.
+fn declare(env: &Rc<RefCell<Environment>>, name: &Token, slot: Option<usize>, value: Value) {
+    match slot {
+        Some(index) => env.borrow_mut().define_at(index, value),
+        None => env.borrow_mut().define(name.lexeme.clone(), value),
     }
-    Box::new(NilObject {})
+}
+
+fn runtime_error(kind: ErrorKind, message: &str, token: &Token) -> RuntimeError {
+    RuntimeError::new(kind, message, token)
 }
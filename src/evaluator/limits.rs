@@ -0,0 +1,94 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::evaluator::error::ErrorKind;
+
+// Bounds on what one run of a program may use. Each limit that is hit ends the
+// run with its own error kind; `None` means unlimited.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    // Statements and expressions evaluated.
+    pub max_steps: Option<u64>,
+    // Lists, maps, instances and functions alive at once.
+    pub max_heap_objects: Option<usize>,
+    // Estimated size of the live heap objects, and of any single string.
+    pub max_heap_bytes: Option<usize>,
+    // Nested function calls. Every level takes native stack (up to ~20KB in debug
+    // builds, ~2KB in release builds), so the thread running the interpreter needs
+    // enough stack for the depth allowed here.
+    pub max_call_depth: Option<usize>,
+    // Wall-clock time from the start of the run.
+    pub timeout: Option<Duration>,
+}
+
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            max_heap_objects: None,
+            max_heap_bytes: None,
+            max_call_depth: Some(DEFAULT_MAX_CALL_DEPTH),
+            timeout: None,
+        }
+    }
+}
+
+// Stops a running program from another thread. The program ends with an
+// `Interrupted` error at its next step.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    flag: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn reset(&self) {
+        self.flag.store(false, Ordering::Relaxed);
+    }
+}
+
+// How often, in steps, the wall clock is checked against the deadline.
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+// Measures one run against the step budget and the deadline.
+#[derive(Debug, Default)]
+pub(crate) struct Meter {
+    // Steps taken since the run started.
+    steps: u64,
+    deadline: Option<Instant>,
+}
+
+impl Meter {
+    pub(crate) fn start(limits: &Limits) -> Meter {
+        Meter { steps: 0, deadline: limits.timeout.map(|timeout| Instant::now() + timeout) }
+    }
+
+    // Counts one step and checks the step budget, the interrupt flag and, every
+    // DEADLINE_CHECK_INTERVAL steps, the deadline.
+    pub(crate) fn tick(&mut self, limits: &Limits, interrupt: &InterruptHandle) -> Result<(), (ErrorKind, String)> {
+        self.steps += 1;
+        if let Some(max) = limits.max_steps {
+            if self.steps > max {
+                return Err((ErrorKind::StepLimitExceeded, format!("Step limit of {} exceeded.", max)));
+            }
+        }
+        if interrupt.is_interrupted() {
+            return Err((ErrorKind::Interrupted, "Execution interrupted.".to_string()));
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, limits.timeout) {
+            if self.steps % DEADLINE_CHECK_INTERVAL == 0 && Instant::now() >= deadline {
+                return Err((ErrorKind::Timeout, format!("Execution timed out after {}s.", timeout.as_secs_f64())));
+            }
+        }
+        Ok(())
+    }
+}
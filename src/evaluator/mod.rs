@@ -1 +1,6 @@
-pub mod evaluator;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod evaluator;
+pub mod error;
+pub mod limits;
+pub mod modules;
+pub mod operations;
@@ -0,0 +1,90 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::ast::ast::Program;
+use crate::common::symbol::Interner;
+use crate::evaluator::error::{ErrorKind, RuntimeError};
+use crate::interpreter::permissions::Permissions;
+use crate::lexer::lexer::Lexer;
+use crate::optimizer::optimizer;
+use crate::parser::parser::Parser;
+use crate::resolver::resolver::Resolver;
+
+// Finding and compiling imported files, for both backends. `loading` is the stack
+// of files currently executing, outermost (the main script) first.
+
+// The files executing at the start of a run: just the script, if it came from one.
+pub fn script_loading(path: Option<&Path>) -> Vec<PathBuf> {
+    path.and_then(|path| fs::canonicalize(path).ok()).into_iter().collect()
+}
+
+// The canonical path of the module `import "path"` refers to. Imports resolve
+// relative to the importing file, or to the working directory without one. A
+// module must lie in the main script's directory or one `permissions` lets
+// scripts read; otherwise importing it is a PermissionError.
+pub fn resolve_import(loading: &[PathBuf], permissions: &Permissions, path: &str, line: i32) -> Result<PathBuf, RuntimeError> {
+    let base = match loading.last().and_then(|file| file.parent()) {
+        Some(dir) => dir.to_path_buf(),
+        None => PathBuf::from("."),
+    };
+    let resolved = match fs::canonicalize(base.join(path)) {
+        Ok(resolved) => resolved,
+        Err(_) => return Err(RuntimeError::at_line(ErrorKind::ImportError, &format!("Could not find module '{}'.", path), line)),
+    };
+    let in_project = loading.first().and_then(|file| file.parent()).is_some_and(|root| resolved.starts_with(root));
+    if !in_project && permissions.readable(&resolved).is_none() {
+        let message = format!("Can't import '{}': read access to it is not allowed.", path);
+        return Err(RuntimeError::at_line(ErrorKind::PermissionError, &message, line));
+    }
+    if let Some(start) = loading.iter().position(|file| *file == resolved) {
+        let cycle: Vec<String> = loading[start..].iter()
+            .chain(std::iter::once(&resolved))
+            .map(|file| display_path(loading, file))
+            .collect();
+        return Err(RuntimeError::at_line(ErrorKind::ImportError, &format!("Circular import: {}.", cycle.join(" -> ")), line));
+    }
+    Ok(resolved)
+}
+
+// Reads, parses and resolves the module at `resolved`, interning its identifiers
+// in `interner`, then optimizes it if `optimize` is set.
+pub fn parse_module(resolved: &Path, path: &str, line: i32, optimize: bool, interner: &Interner) -> Result<Program, RuntimeError> {
+    let source = match fs::read_to_string(resolved) {
+        Ok(source) => source,
+        Err(_) => return Err(RuntimeError::at_line(ErrorKind::ImportError, &format!("Could not read module '{}'.", path), line)),
+    };
+    let mut l = Lexer::with_interner(source, interner.clone());
+    l.tokenize();
+    let mut errors = l.errors.clone();
+    let mut p = Parser::new(l);
+    let program = p.parse_program();
+    errors.append(&mut p.errors);
+    if errors.is_empty() {
+        let mut r = Resolver::new();
+        r.resolve_program(&program);
+        errors = r.errors;
+    }
+    if !errors.is_empty() {
+        let message = format!("Could not compile module '{}':\n{}", path, errors.join("\n"));
+        return Err(RuntimeError::at_line(ErrorKind::ImportError, &message, line));
+    }
+    Ok(if optimize { optimizer::optimize(&program) } else { program })
+}
+
+// The name a module is known by: its file name without the extension.
+pub fn module_name(resolved: &Path, path: &str) -> String {
+    resolved.file_stem().map_or(path.to_string(), |stem| stem.to_string_lossy().to_string())
+}
+
+// How the top level of a module appears in stack traces.
+pub fn module_frame_name(loading: &[PathBuf], resolved: &Path) -> String {
+    format!("<module {}>", display_path(loading, resolved))
+}
+
+// How a module path appears in diagnostics: relative to the main script's directory when possible.
+pub fn display_path(loading: &[PathBuf], path: &Path) -> String {
+    let root = loading.first().and_then(|file| file.parent());
+    match root.and_then(|root| path.strip_prefix(root).ok()) {
+        Some(relative) => relative.display().to_string(),
+        None => path.display().to_string(),
+    }
+}
@@ -0,0 +1,360 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::ast::ast::{ClassStatement, FunctionStatement, Statement, VarStatement};
+use crate::common::symbol::{Known, Symbol};
+use crate::evaluator::error::{ErrorKind, NativeError, RuntimeError};
+use crate::interpreter::host::HostObject;
+use crate::interpreter::native::Args;
+use crate::interpreter::value::LoxValue;
+use crate::lexer::lexer::{Token, TokenType};
+use crate::object::environment::Environment;
+use crate::object::object::{ClassData, HashKey, HashPair, InstanceData, MapData, ModuleData, NativeFunctionData, Value};
+
+// The runtime semantics both backends share: reading and writing variables,
+// properties and elements, applying operators and checking calls. Keeping them in
+// one place means the tree walker and the VM fail with the same errors.
+
+// A name as it appears at one place in the source.
+#[derive(Clone)]
+pub struct Name {
+    pub symbol: Symbol,
+    pub line: i32,
+}
+
+impl From<&Token> for Name {
+    fn from(token: &Token) -> Self {
+        Name { symbol: token.lexeme.clone(), line: token.line_number }
+    }
+}
+
+// Something that can be read and assigned: the target of `=`, `+=`, `++` etc.
+// Resolving a place evaluates its sub-expressions once, so `a[f()] += 1` calls `f` once.
+pub enum Place {
+    // A global.
+    Variable(Name, Rc<RefCell<Environment>>),
+    // A local: a slot in a frame, undefined until its declaration runs.
+    Slot(Rc<RefCell<Environment>>, usize, Name),
+    Field(Rc<RefCell<InstanceData>>, Name),
+    // A field the instance has, at its slot in the instance's shape.
+    FieldSlot(Rc<RefCell<InstanceData>>, usize),
+    Export(Rc<ModuleData>, Name),
+    HostProperty(Rc<RefCell<dyn HostObject>>, Name),
+    Element(Rc<RefCell<Vec<Value>>>, usize),
+    Entry(Rc<RefCell<MapData>>, HashKey, Value),
+}
+
+impl Place {
+    // Whether the place is in a list, map or instance, where what is written
+    // counts against the heap limits.
+    pub fn in_container(&self) -> bool {
+        matches!(self, Place::Field(..) | Place::FieldSlot(..) | Place::Element(..) | Place::Entry(..))
+    }
+}
+
+// The property `name` of `object`, which must be an instance, host object or module.
+pub fn property_place(object: Value, name: Name) -> Result<Place, RuntimeError> {
+    match object {
+        Value::Module(module) => Ok(Place::Export(module, name)),
+        Value::Host(host) => Ok(Place::HostProperty(host, name)),
+        Value::Instance(instance) => Ok(Place::Field(instance, name)),
+        _ => Err(RuntimeError::at_line(ErrorKind::TypeError, "Only instances have properties.", name.line)),
+    }
+}
+
+// Element `index` of a list, which must exist, or the entry for `index` in a map.
+pub fn index_place(left: Value, index: Value, line: i32) -> Result<Place, RuntimeError> {
+    match left {
+        Value::List(elements) => {
+            let len = elements.borrow().len();
+            let position = match index {
+                Value::Number(n) if n.fract() == 0.0 => n,
+                _ => return Err(RuntimeError::at_line(ErrorKind::TypeError, "List index must be an integer.", line)),
+            };
+            if position < 0.0 || position >= len as f64 {
+                let message = format!("List index {} out of range for length {}.", position, len);
+                return Err(RuntimeError::at_line(ErrorKind::IndexError, &message, line));
+            }
+            Ok(Place::Element(elements, position as usize))
+        }
+        Value::Map(data) => {
+            let hash_key = map_key(&index, line)?;
+            Ok(Place::Entry(data, hash_key, index))
+        }
+        _ => Err(RuntimeError::at_line(ErrorKind::TypeError, "Only lists and maps can be indexed.", line)),
+    }
+}
+
+pub fn read_place(place: &Place) -> Result<Value, RuntimeError> {
+    match place {
+        Place::Variable(name, env) => match env.borrow().get(&name.symbol) {
+            Some(value) => Ok(value),
+            None => Err(undefined_variable(name.clone())),
+        },
+        Place::Slot(frame, index, name) => frame.borrow().get_at(0, *index).ok_or_else(|| undefined_variable(name.clone())),
+        Place::Field(instance, name) => match instance.borrow().get(&name.symbol) {
+            Some(value) => Ok(value.clone()),
+            None => Err(undefined_property(name.clone())),
+        },
+        Place::FieldSlot(instance, slot) => Ok(instance.borrow().field(*slot).clone()),
+        Place::Export(module, name) => match module.globals.borrow().get(&name.symbol) {
+            Some(value) if module.exports.contains(&name.symbol) => Ok(value),
+            _ => {
+                let message = format!("Module '{}' has no export '{}'.", module.name, name.symbol);
+                Err(RuntimeError::at_line(ErrorKind::ImportError, &message, name.line))
+            }
+        },
+        Place::HostProperty(host, name) => read_host_property(host, name.clone()),
+        Place::Element(elements, i) => Ok(elements.borrow()[*i].clone()),
+        // Reading a missing key yields nil rather than an error.
+        Place::Entry(data, key, _) => match data.borrow().get(key) {
+            Some(pair) => Ok(pair.value.clone()),
+            None => Ok(Value::Nil),
+        },
+    }
+}
+
+// A property of a host object, or one of its methods bound to it as a native function.
+fn read_host_property(host: &Rc<RefCell<dyn HostObject>>, name: Name) -> Result<Value, RuntimeError> {
+    let data = host.borrow();
+    if let Some(value) = data.get_property(&name.symbol) {
+        return value.into_value().map_err(|message| RuntimeError::at_line(ErrorKind::TypeError, &message, name.line));
+    }
+    let arity = match data.method_arity(&name.symbol) {
+        Some(arity) => arity,
+        None => return Err(undefined_property(name)),
+    };
+    let receiver = Rc::clone(host);
+    let method = name.symbol.clone();
+    let function = move |arguments: Vec<Value>| {
+        let args = Args {
+            name: method.to_string(),
+            values: arguments,
+        };
+        let result = receiver.borrow_mut().call_method(&method, &args)?;
+        result.into_value().map_err(|message| NativeError::new(ErrorKind::TypeError, message))
+    };
+    Ok(Value::NativeFunction(Rc::new(NativeFunctionData {
+        name: name.symbol.clone(),
+        arity,
+        variadic: false,
+        function: Box::new(function),
+    })))
+}
+
+pub fn write_place(place: Place, value: Value) -> Result<(), RuntimeError> {
+    match place {
+        Place::Variable(name, env) => {
+            if !env.borrow_mut().assign(&name.symbol, value) {
+                return Err(undefined_variable(name));
+            }
+        }
+        Place::Slot(frame, index, name) => {
+            if !frame.borrow_mut().assign_at(index, value) {
+                return Err(undefined_variable(name));
+            }
+        }
+        Place::Field(instance, name) => {
+            instance.borrow_mut().set(name.symbol, value);
+        }
+        Place::FieldSlot(instance, slot) => instance.borrow_mut().set_field(slot, value),
+        Place::Export(module, name) => {
+            let message = format!("Can't assign to export '{}' of module '{}'.", name.symbol, module.name);
+            return Err(RuntimeError::at_line(ErrorKind::TypeError, &message, name.line));
+        }
+        Place::HostProperty(host, name) => {
+            let value = LoxValue::from_value(&value);
+            let outcome = host.borrow_mut().set_property(&name.symbol, value);
+            outcome.map_err(|err| RuntimeError::at_line(err.kind, &err.message, name.line))?;
+        }
+        Place::Element(elements, i) => elements.borrow_mut()[i] = value,
+        Place::Entry(data, hash_key, key) => data.borrow_mut().insert(hash_key, HashPair { key, value }),
+    }
+    Ok(())
+}
+
+pub fn map_key(key: &Value, line: i32) -> Result<HashKey, RuntimeError> {
+    match key.hash_key() {
+        Some(hash_key) => Ok(hash_key),
+        None => Err(RuntimeError::at_line(ErrorKind::TypeError, "Map keys must be nil, booleans, numbers or strings.", line)),
+    }
+}
+
+// The binary operator a compound assignment applies, e.g. `+` for `+=`.
+pub fn compound_operator(operator: &TokenType) -> Option<TokenType> {
+    match operator {
+        TokenType::PlusAssign | TokenType::PlusPlus => Some(TokenType::Plus),
+        TokenType::MinusAssign | TokenType::MinusMinus => Some(TokenType::Minus),
+        TokenType::AsteriskAssign => Some(TokenType::Asterisk),
+        TokenType::SlashAssign => Some(TokenType::Slash),
+        TokenType::PercentAssign => Some(TokenType::Percent),
+        _ => None,
+    }
+}
+
+// Applies a binary operator other than `and` and `or`. Errors are reported at
+// `line`, where the operator (or the compound assignment it came from) is.
+pub fn apply_infix_operator(operator: &TokenType, left: &Value, right: &Value, line: i32) -> Result<Value, RuntimeError> {
+    match operator {
+        TokenType::Equal => return Ok(Value::Boolean(left == right)),
+        TokenType::NotEqual => return Ok(Value::Boolean(left != right)),
+        TokenType::Plus => {
+            if let (Value::String(l), Value::String(r)) = (left, right) {
+                return Ok(Value::String(Rc::new(format!("{}{}", l, r).into_boxed_str())));
+            }
+        }
+        _ => {}
+    }
+
+    let (l, r) = match (left, right) {
+        (Value::Number(l), Value::Number(r)) => (*l, *r),
+        _ if *operator == TokenType::Plus => {
+            return Err(RuntimeError::at_line(ErrorKind::TypeError, "Operands must be two numbers or two strings.", line));
+        }
+        _ => return Err(RuntimeError::at_line(ErrorKind::TypeError, "Operands must be numbers.", line)),
+    };
+
+    Ok(match operator {
+        TokenType::Plus => Value::Number(l + r),
+        TokenType::Minus => Value::Number(l - r),
+        TokenType::Asterisk => Value::Number(l * r),
+        TokenType::Slash => Value::Number(l / r),
+        // Remainder truncates toward zero and takes the sign of the dividend, like C's fmod.
+        TokenType::Percent => Value::Number(l % r),
+        TokenType::StarStar => Value::Number(l.powf(r)),
+        TokenType::Less => Value::Boolean(l < r),
+        TokenType::LessEqual => Value::Boolean(l <= r),
+        TokenType::Greater => Value::Boolean(l > r),
+        TokenType::GreaterEqual => Value::Boolean(l >= r),
+        _ => return Err(RuntimeError::at_line(ErrorKind::TypeError, &format!("Unknown infix operator {:?}.", operator), line)),
+    })
+}
+
+pub fn negate(value: &Value, line: i32) -> Result<Value, RuntimeError> {
+    match value {
+        Value::Number(n) => Ok(Value::Number(-n)),
+        _ => Err(operand_not_number(line)),
+    }
+}
+
+pub fn operand_not_number(line: i32) -> RuntimeError {
+    RuntimeError::at_line(ErrorKind::TypeError, "Operand must be a number.", line)
+}
+
+pub fn check_arity(expected: usize, got: usize, line: i32) -> Result<(), RuntimeError> {
+    if expected != got {
+        return Err(RuntimeError::at_line(ErrorKind::TypeError, &format!("Expected {} arguments but got {}.", expected, got), line));
+    }
+    Ok(())
+}
+
+pub fn check_native_arity(native: &NativeFunctionData, got: usize, line: i32) -> Result<(), RuntimeError> {
+    if native.variadic && got < native.arity {
+        let message = format!("Expected at least {} arguments but got {}.", native.arity, got);
+        return Err(RuntimeError::at_line(ErrorKind::TypeError, &message, line));
+    }
+    if !native.variadic {
+        check_arity(native.arity, got, line)?;
+    }
+    Ok(())
+}
+
+pub fn not_callable(line: i32) -> RuntimeError {
+    RuntimeError::at_line(ErrorKind::TypeError, "Can only call functions and classes.", line)
+}
+
+// Calling a class makes an empty instance of it. Returns the instance and the
+// class's `init` method, which the caller then runs on it with the call's
+// arguments; a class without one takes no arguments.
+pub fn instantiate(class: Rc<ClassData>, arguments: usize, line: i32) -> Result<(Value, Option<Value>), RuntimeError> {
+    let initializer = class.method(&Symbol::known(Known::Init)).cloned();
+    if initializer.is_none() {
+        check_arity(0, arguments, line)?;
+    }
+    Ok((Value::Instance(Rc::new(RefCell::new(InstanceData::new(class)))), initializer))
+}
+
+// The method a read of `place` finds when it is a field the instance doesn't have
+// but its class has a method of that name, with the instance to bind it to.
+pub fn unbound_method(place: &Place) -> Option<(Value, Value)> {
+    let Place::Field(instance, name) = place else {
+        return None;
+    };
+    let data = instance.borrow();
+    if data.has(&name.symbol) {
+        return None;
+    }
+    let method = data.class.method(&name.symbol)?.clone();
+    Some((method, Value::Instance(Rc::clone(instance))))
+}
+
+// Fails if a call `depth` levels deep would nest deeper than the call depth limit.
+pub fn check_call_depth(depth: usize, max: Option<usize>, line: i32) -> Result<(), RuntimeError> {
+    match max {
+        Some(max) if depth >= max => {
+            let message = format!("Stack overflow: maximum call depth of {} exceeded.", max);
+            Err(RuntimeError::at_line(ErrorKind::StackOverflow, &message, line))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn undefined_variable(name: Name) -> RuntimeError {
+    RuntimeError::at_line(ErrorKind::NameError, &format!("Undefined variable '{}'.", name.symbol), name.line)
+}
+
+pub fn not_a_class(name: Name) -> RuntimeError {
+    RuntimeError::at_line(ErrorKind::TypeError, &format!("'{}' is not a class.", name.symbol), name.line)
+}
+
+fn undefined_property(name: Name) -> RuntimeError {
+    RuntimeError::at_line(ErrorKind::PropertyError, &format!("Undefined property '{}'.", name.symbol), name.line)
+}
+
+// The error `throw value` raises. Rethrowing a caught error object keeps its
+// message for the uncaught-error report.
+pub fn thrown_error(value: Value, error_class: &Rc<ClassData>, line: i32) -> RuntimeError {
+    let message = match &value {
+        Value::Instance(instance) if Rc::ptr_eq(&instance.borrow().class, error_class) => {
+            instance.borrow().get(&Symbol::known(Known::Message)).map_or("Error".to_string(), |m| m.inspect())
+        }
+        _ => format!("Uncaught exception: {}", value.inspect()),
+    };
+    let mut err = RuntimeError::at_line(ErrorKind::Exception, &message, line);
+    err.thrown = Some(value);
+    err
+}
+
+// The value a `catch` clause binds: whatever was thrown, or an `Error` instance
+// with `kind`, `message`, `line` and `stack` fields for interpreter errors. The
+// error's trace must already be recorded.
+pub fn caught_value(err: RuntimeError, error_class: &Rc<ClassData>) -> Value {
+    if let Some(thrown) = err.thrown {
+        return thrown;
+    }
+    let stack = err.trace.unwrap_or_default().iter().map(|frame| Value::string(&frame.to_string())).collect();
+    let mut error = InstanceData::new(Rc::clone(error_class));
+    error.set(Symbol::known(Known::Kind), Value::string(err.kind.name()));
+    error.set(Symbol::known(Known::Message), Value::string(&err.message));
+    error.set(Symbol::known(Known::Line), Value::Number(err.line as f64));
+    error.set(Symbol::known(Known::Stack), Value::list(stack));
+    Value::Instance(Rc::new(RefCell::new(error)))
+}
+
+pub fn no_match(subject: &Value, line: i32) -> RuntimeError {
+    RuntimeError::at_line(ErrorKind::MatchError, &format!("No match case for value {}.", subject.inspect()), line)
+}
+
+// The name a top-level statement declares, which a module exports.
+pub fn declared_name(stmt: &dyn Statement) -> Option<Symbol> {
+    let any = stmt.as_any();
+    if let Some(v) = any.downcast_ref::<VarStatement>() {
+        return Some(v.name.lexeme.clone());
+    }
+    if let Some(f) = any.downcast_ref::<FunctionStatement>() {
+        return Some(f.name.lexeme.clone());
+    }
+    if let Some(c) = any.downcast_ref::<ClassStatement>() {
+        return Some(c.name.lexeme.clone());
+    }
+    None
+}
@@ -0,0 +1,291 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::evaluator::error::{ErrorKind, NativeError};
+use crate::interpreter::interpreter::Interpreter;
+use crate::interpreter::native::Args;
+use crate::interpreter::permissions::Permissions;
+use crate::interpreter::value::LoxValue;
+
+// Defines the functions every interpreter starts with. Besides the errors listed
+// for each function, passing an argument of the wrong type is a TypeError
+// ("Argument 1 to 'upper' must be a string but got number.") and so is calling a
+// function with the wrong number of arguments.
+pub fn register(interp: &mut Interpreter) {
+    // Seconds since the Unix epoch.
+    interp.register_fn("clock", 0, |_| {
+        Ok(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |elapsed| elapsed.as_secs_f64()))
+    });
+
+    register_strings(interp);
+    register_math(interp);
+    register_conversions(interp);
+    register_io(interp);
+}
+
+// String positions and lengths count characters, not bytes.
+fn register_strings(interp: &mut Interpreter) {
+    // len(value): the number of characters in a string, elements in a list or entries in a map.
+    // TypeError for any other value.
+    interp.register_fn("len", 1, |args| {
+        let value = args.arg(0);
+        value.length().ok_or_else(|| NativeError::new(
+            ErrorKind::TypeError,
+            format!("Argument 1 to 'len' must be a string, list or map but got {}.", value.type_name()),
+        ))
+    });
+
+    // substr(s, start, length): `length` characters of `s` from index `start`.
+    // TypeError if `start` or `length` isn't an integer; IndexError if the range
+    // doesn't lie within `s`.
+    interp.register_fn("substr", 3, |args| {
+        let s: String = args.get(0)?;
+        let (start, length) = (args.get_integer(1)?, args.get_integer(2)?);
+        let len = s.chars().count() as i64;
+        // Huge arguments saturate, so the end is checked without adding them.
+        if start < 0 || length < 0 || start > len || length > len - start {
+            return Err(args.error(
+                ErrorKind::IndexError,
+                format!("range {}..{} is out of bounds for a string of length {}.", start, start.saturating_add(length), len),
+            ));
+        }
+        Ok(s.chars().skip(start as usize).take(length as usize).collect::<String>())
+    });
+
+    // split(s, separator): the pieces of `s` between occurrences of `separator`, or
+    // its individual characters if `separator` is "".
+    interp.register_fn("split", 2, |args| {
+        let (s, separator): (String, String) = (args.get(0)?, args.get(1)?);
+        if separator.is_empty() {
+            return Ok(s.chars().map(String::from).collect::<Vec<_>>());
+        }
+        Ok(s.split(separator.as_str()).map(String::from).collect::<Vec<_>>())
+    });
+
+    // join(list, separator): the elements of `list`, converted as by str(), with
+    // `separator` between them.
+    interp.register_fn("join", 2, |args| {
+        let (values, separator): (Vec<LoxValue>, String) = (args.get(0)?, args.get(1)?);
+        Ok(values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(&separator))
+    });
+
+    // trim(s), upper(s), lower(s).
+    interp.register_fn("trim", 1, |args| Ok(args.get::<String>(0)?.trim().to_string()));
+    interp.register_fn("upper", 1, |args| Ok(args.get::<String>(0)?.to_uppercase()));
+    interp.register_fn("lower", 1, |args| Ok(args.get::<String>(0)?.to_lowercase()));
+
+    // find(s, needle): the index of the first occurrence of `needle` in `s`, or -1.
+    interp.register_fn("find", 2, |args| {
+        let (s, needle): (String, String) = (args.get(0)?, args.get(1)?);
+        Ok(s.find(needle.as_str()).map_or(-1.0, |byte| s[..byte].chars().count() as f64))
+    });
+
+    // replace(s, from, to): `s` with every occurrence of `from` replaced by `to`.
+    // ValueError if `from` is "".
+    interp.register_fn("replace", 3, |args| {
+        let (s, from, to): (String, String, String) = (args.get(0)?, args.get(1)?, args.get(2)?);
+        if from.is_empty() {
+            return Err(args.error(ErrorKind::ValueError, "the string to replace must not be empty."));
+        }
+        Ok(s.replace(from.as_str(), &to))
+    });
+
+    // format(template, ...values): `template` with each "{}" replaced by the next
+    // value, converted as by str(); "{{" and "}}" stand for literal braces.
+    // ValueError if the number of placeholders and values differ or a brace is unmatched.
+    interp.register_variadic_fn("format", 1, |args| {
+        let template: String = args.get(0)?;
+        let values = args.len() - 1;
+        let mut out = String::new();
+        let mut used = 0;
+        let mut chars = template.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match (ch, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    chars.next();
+                    out.push(ch);
+                }
+                ('{', Some('}')) => {
+                    chars.next();
+                    if used < values {
+                        out.push_str(&args.arg(1 + used).to_string());
+                    }
+                    used += 1;
+                }
+                ('{', _) | ('}', _) => {
+                    return Err(args.error(ErrorKind::ValueError, format!("unmatched '{}' in template.", ch)));
+                }
+                _ => out.push(ch),
+            }
+        }
+        if used != values {
+            return Err(args.error(
+                ErrorKind::ValueError,
+                format!("template has {} placeholders but got {} values.", used, values),
+            ));
+        }
+        Ok(out)
+    });
+}
+
+type MathFn = fn(f64) -> f64;
+
+// Math functions follow IEEE 754 semantics, so sqrt(-1) is NaN rather than an error.
+fn register_math(interp: &mut Interpreter) {
+    let unary: [(&str, MathFn); 10] = [
+        ("floor", f64::floor),
+        ("ceil", f64::ceil),
+        ("sqrt", f64::sqrt),
+        ("abs", f64::abs),
+        ("sin", f64::sin),
+        ("cos", f64::cos),
+        ("tan", f64::tan),
+        ("asin", f64::asin),
+        ("acos", f64::acos),
+        ("atan", f64::atan),
+    ];
+    for (name, function) in unary {
+        interp.register_fn(name, 1, move |args| Ok(function(args.get(0)?)));
+    }
+
+    // pow(base, exponent) and atan2(y, x).
+    interp.register_fn("pow", 2, |args| Ok(args.get::<f64>(0)?.powf(args.get(1)?)));
+    interp.register_fn("atan2", 2, |args| Ok(args.get::<f64>(0)?.atan2(args.get(1)?)));
+
+    // min(x, ...) and max(x, ...): the smallest or largest of one or more numbers.
+    interp.register_variadic_fn("min", 1, |args| fold_numbers(args, f64::min));
+    interp.register_variadic_fn("max", 1, |args| fold_numbers(args, f64::max));
+}
+
+fn fold_numbers(args: &Args, f: fn(f64, f64) -> f64) -> Result<f64, NativeError> {
+    let mut result: f64 = args.get(0)?;
+    for i in 1..args.len() {
+        result = f(result, args.get(i)?);
+    }
+    Ok(result)
+}
+
+fn register_conversions(interp: &mut Interpreter) {
+    // num(value): a number unchanged, or a string such as "3.5", "-2" or "1e3"
+    // (surrounding whitespace allowed) parsed as a number.
+    // ValueError if the string isn't a number; TypeError for other values.
+    interp.register_fn("num", 1, |args| {
+        let value = args.arg(0);
+        if let Some(n) = value.as_number() {
+            return Ok(n);
+        }
+        let Some(s) = value.as_str() else {
+            return Err(NativeError::new(
+                ErrorKind::TypeError,
+                format!("Argument 1 to 'num' must be a number or string but got {}.", value.type_name()),
+            ));
+        };
+        let text = s.trim();
+        let numeric = !text.is_empty() && text.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'));
+        match text.parse::<f64>() {
+            Ok(n) if numeric => Ok(n),
+            _ => Err(args.error(ErrorKind::ValueError, format!("can't convert \"{}\" to a number.", s))),
+        }
+    });
+
+    // str(value): the text `print` would show for `value`.
+    interp.register_fn("str", 1, |args| Ok(args.arg(0).to_string()));
+
+    // type(value): one of "nil", "boolean", "number", "string", "list", "map",
+    // "function", "class", "instance" or "module".
+    interp.register_fn("type", 1, |args| Ok(args.arg(0).type_name()));
+}
+
+// I/O goes through the interpreter's Permissions. A denied operation is a
+// PermissionError and a failed one an IOError; both can be caught.
+fn register_io(interp: &mut Interpreter) {
+    // input(): the next line of standard input without its line ending, or nil at
+    // the end of input. Needs stdin permission.
+    let permissions = Rc::clone(&interp.permissions);
+    interp.register_fn("input", 0, move |args| {
+        if !permissions.borrow().stdin {
+            return Err(args.error(ErrorKind::PermissionError, "reading standard input is not allowed."));
+        }
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line.trim_end_matches(['\n', '\r']).to_string())),
+            Err(err) => Err(args.error(ErrorKind::IOError, format!("can't read standard input: {}.", err))),
+        }
+    });
+
+    // readFile(path): the contents of a UTF-8 text file. Needs read permission for `path`.
+    let permissions = Rc::clone(&interp.permissions);
+    interp.register_fn("readFile", 1, move |args| {
+        let path: String = args.get(0)?;
+        let approved = check_access(args, &permissions, &path, Access::Read)?;
+        fs::read_to_string(approved).map_err(|err| args.error(ErrorKind::IOError, format!("can't read '{}': {}.", path, err)))
+    });
+
+    // writeFile(path, contents): creates or overwrites a file. Needs write permission for `path`.
+    let permissions = Rc::clone(&interp.permissions);
+    interp.register_fn("writeFile", 2, move |args| {
+        let (path, contents): (String, String) = (args.get(0)?, args.get(1)?);
+        let approved = check_access(args, &permissions, &path, Access::Write)?;
+        fs::write(approved, contents).map_err(|err| args.error(ErrorKind::IOError, format!("can't write '{}': {}.", path, err)))
+    });
+
+    // listDir(path): the sorted names of the entries in a directory. Needs read permission for `path`.
+    let permissions = Rc::clone(&interp.permissions);
+    interp.register_fn("listDir", 1, move |args| {
+        let path: String = args.get(0)?;
+        let approved = check_access(args, &permissions, &path, Access::Read)?;
+        let io_error = |err: io::Error| args.error(ErrorKind::IOError, format!("can't list '{}': {}.", path, err));
+        let mut names = Vec::new();
+        for entry in fs::read_dir(approved).map_err(io_error)? {
+            names.push(entry.map_err(io_error)?.file_name().to_string_lossy().to_string());
+        }
+        names.sort();
+        Ok(names)
+    });
+
+    // env(name): the value of an environment variable, or nil if it isn't set. Needs env permission.
+    let permissions = Rc::clone(&interp.permissions);
+    interp.register_fn("env", 1, move |args| {
+        let name: String = args.get(0)?;
+        if !permissions.borrow().env {
+            return Err(args.error(ErrorKind::PermissionError, "reading environment variables is not allowed."));
+        }
+        Ok(std::env::var(&name).ok())
+    });
+
+    // exit([status]): stops the script with `status` (default 0), skipping `catch` and
+    // `finally` blocks. TypeError if `status` isn't an integer from 0 to 255 or more than
+    // one argument is given.
+    interp.register_variadic_fn("exit", 0, |args| {
+        if args.len() > 1 {
+            return Err(NativeError::new(ErrorKind::TypeError, format!("Expected at most 1 arguments but got {}.", args.len())));
+        }
+        let status = if args.is_empty() { 0 } else { args.get_integer(0)? };
+        if !(0..=255).contains(&status) {
+            return Err(args.error(ErrorKind::TypeError, format!("status {} is not between 0 and 255.", status)));
+        }
+        Err::<(), _>(NativeError::new(ErrorKind::Exit(status as i32), format!("Script exited with status {}.", status)))
+    });
+}
+
+enum Access {
+    Read,
+    Write,
+}
+
+// The canonical path that `path` may be opened through, which is the one that was
+// checked.
+fn check_access(args: &Args, permissions: &RefCell<Permissions>, path: &str, access: Access) -> Result<PathBuf, NativeError> {
+    let (approved, what) = match access {
+        Access::Read => (permissions.borrow().readable(Path::new(path)), "read"),
+        Access::Write => (permissions.borrow().writable(Path::new(path)), "write"),
+    };
+    if let Some(approved) = approved {
+        return Ok(approved);
+    }
+    Err(args.error(ErrorKind::PermissionError, format!("{} access to '{}' is not allowed.", what, path)))
+}
@@ -0,0 +1,49 @@
+use std::any::Any;
+use crate::evaluator::error::{ErrorKind, NativeError};
+use crate::interpreter::native::Args;
+use crate::interpreter::value::LoxValue;
+
+// Lets host objects be downcast back to their Rust type.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// A Rust value that scripts use like an instance of a class: `obj.field` reads a
+// property and `obj.method(args)` calls a method. Host objects compare by identity.
+pub trait HostObject: AsAny + 'static {
+    fn class_name(&self) -> &str;
+
+    // The arity of method `name`, or None if there is no such method.
+    fn method_arity(&self, _name: &str) -> Option<usize> {
+        None
+    }
+
+    // Only called for methods `method_arity` knows, with the argument count checked.
+    fn call_method(&mut self, name: &str, _args: &Args) -> Result<LoxValue, NativeError> {
+        Err(NativeError::new(ErrorKind::PropertyError, format!("Undefined property '{}'.", name)))
+    }
+
+    // None if there is no such property; methods are looked up after properties.
+    fn get_property(&self, _name: &str) -> Option<LoxValue> {
+        None
+    }
+
+    fn set_property(&mut self, name: &str, _value: LoxValue) -> Result<(), NativeError> {
+        Err(NativeError::new(ErrorKind::PropertyError, format!("Can't set property '{}' on {}.", name, self.class_name())))
+    }
+
+    // What `print` shows.
+    fn inspect(&self) -> String {
+        format!("{} instance", self.class_name())
+    }
+}
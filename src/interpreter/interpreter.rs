@@ -0,0 +1,338 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::ast::ast::Program;
+use crate::common::output::{Output, OutputBuffer};
+use crate::common::symbol::{Interner, Symbol};
+use crate::evaluator::error::{ErrorKind, Frame, NativeError, RuntimeError};
+use crate::evaluator::evaluator::Evaluator;
+use crate::evaluator::limits::{InterruptHandle, Limits};
+use crate::interpreter::builtins;
+use crate::interpreter::host::HostObject;
+use crate::interpreter::native::{Args, IntoLox};
+use crate::interpreter::permissions::Permissions;
+use crate::interpreter::value::LoxValue;
+use crate::lexer::lexer::Lexer;
+use crate::object::environment::Environment;
+use crate::object::heap::{GcConfig, GcStats};
+use crate::object::object::{NativeFunctionData, Value};
+use crate::optimizer::optimizer;
+use crate::parser::parser::Parser;
+use crate::resolver::resolver::Resolver;
+use crate::vm::chunk::FunctionProto;
+use crate::vm::{bytecode, compiler};
+use crate::vm::vm::Vm;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read file {}: {source}", path.display())]
+    Io { path: PathBuf, source: io::Error },
+    // Lexer, parser or resolver diagnostics, one "[line N] ..." entry each.
+    #[error("{}", .0.join("\n"))]
+    Compile(Vec<String>),
+    // A compiled bytecode file that is damaged, from another format version, or
+    // fails verification.
+    #[error("Invalid bytecode file {}: {message}.", path.display())]
+    Bytecode { path: PathBuf, message: String },
+    #[error("{0}")]
+    Runtime(RuntimeError),
+    // A Rust value with no Lox equivalent, such as a map keyed by a list.
+    #[error("{0}")]
+    Conversion(String),
+    // The script called `exit(status)`.
+    #[error("Script exited with status {0}.")]
+    Exit(i32),
+}
+
+impl Error {
+    // Where a runtime error happened, innermost frame first; None for other errors.
+    pub fn trace(&self) -> Option<&[Frame]> {
+        match self {
+            Error::Runtime(err) => err.trace.as_deref(),
+            _ => None,
+        }
+    }
+
+    // The exit status the CLI uses for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 66,
+            Error::Compile(_) | Error::Bytecode { .. } => 65,
+            Error::Runtime(_) | Error::Conversion(_) => 70,
+            Error::Exit(status) => *status,
+        }
+    }
+}
+
+// Which engine runs programs. Both produce the same output and errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    // Walks the syntax tree directly.
+    #[default]
+    TreeWalker,
+    // Compiles to bytecode and runs it on a stack machine.
+    Vm,
+}
+
+// A Lox interpreter whose global scope persists across calls, so a host can
+// define globals, run scripts and read back what they left behind.
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    evaluator: Evaluator,
+    vm: Vm,
+    backend: Backend,
+    // Whether programs go through the optimizer before they run.
+    optimize: bool,
+    // Shared with the I/O builtins, which check it on every call.
+    pub(crate) permissions: Rc<RefCell<Permissions>>,
+    // The identifiers of every program and module this interpreter compiles.
+    pub(crate) interner: Interner,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        let globals = Environment::new();
+        let permissions = Rc::new(RefCell::new(Permissions::default()));
+        let mut evaluator = Evaluator::new(&globals);
+        evaluator.set_permissions(Rc::clone(&permissions));
+        let mut vm = Vm::new(evaluator.error_class(), evaluator.interrupt_handle());
+        vm.set_permissions(Rc::clone(&permissions));
+        let interner = Interner::default();
+        evaluator.set_interner(interner.clone());
+        vm.set_interner(interner.clone());
+        let mut interp = Self { globals, evaluator, vm, backend: Backend::default(), optimize: false, permissions, interner };
+        builtins::register(&mut interp);
+        interp
+    }
+
+    // Lexes, parses and resolves `source` without running it, e.g. to save it as
+    // bytecode. Its names are interned apart from any interpreter's.
+    pub fn compile(source: &str) -> Result<Program, Error> {
+        Self::compile_with(source, &Interner::default())
+    }
+
+    // Like compile, interning identifiers in `interner`.
+    pub(crate) fn compile_with(source: &str, interner: &Interner) -> Result<Program, Error> {
+        let mut l = Lexer::with_interner(source.to_string(), interner.clone());
+        l.tokenize();
+        if !l.errors.is_empty() {
+            return Err(Error::Compile(l.errors));
+        }
+
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        if !p.errors.is_empty() {
+            return Err(Error::Compile(p.errors));
+        }
+
+        let mut r = Resolver::new();
+        r.resolve_program(&program);
+        if !r.errors.is_empty() {
+            return Err(Error::Compile(r.errors));
+        }
+        Ok(program)
+    }
+
+    // Compiles a program to bytecode in the format `run_bytecode_file` loads.
+    pub fn compile_bytecode(program: &Program) -> Vec<u8> {
+        bytecode::write(&compiler::compile(program, Symbol::new("script")))
+    }
+
+    // Reads and verifies the compiled script at `path`, e.g. to list it. Scripts to
+    // run go through `run_bytecode_file`, so their names are this interpreter's.
+    pub fn load_bytecode(path: impl AsRef<Path>) -> Result<Rc<FunctionProto>, Error> {
+        Self::load_bytecode_with(path.as_ref(), &Interner::default())
+    }
+
+    fn load_bytecode_with(path: &Path, interner: &Interner) -> Result<Rc<FunctionProto>, Error> {
+        let bytes = fs::read(path).map_err(|source| Error::Io { path: path.to_path_buf(), source })?;
+        bytecode::read(&bytes, interner).map_err(|message| Error::Bytecode { path: path.to_path_buf(), message })
+    }
+
+    // Runs `source` and returns the value of its last statement (nil unless it is an
+    // expression statement). Imports resolve relative to the working directory.
+    pub fn eval_str(&mut self, source: &str) -> Result<LoxValue, Error> {
+        self.eval_program(Self::compile_with(source, &self.interner)?)
+    }
+
+    // Like eval_str, for a program already compiled with this interpreter's
+    // interner, whose symbols are the ones its globals are defined under.
+    pub(crate) fn eval_program(&mut self, program: Program) -> Result<LoxValue, Error> {
+        self.evaluator.set_script_path(None);
+        self.vm.set_script_path(None);
+        self.execute(program)
+    }
+
+    // Runs the script at `path`; its imports resolve relative to it.
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<LoxValue, Error> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|source| Error::Io { path: path.to_path_buf(), source })?;
+        let program = Self::compile_with(&source, &self.interner)?;
+        self.evaluator.set_script_path(Some(path));
+        self.vm.set_script_path(Some(path));
+        self.execute(program)
+    }
+
+    // Runs the compiled script at `path` on the VM, whichever backend is chosen;
+    // its imports resolve relative to it.
+    pub fn run_bytecode_file(&mut self, path: impl AsRef<Path>) -> Result<LoxValue, Error> {
+        let path = path.as_ref();
+        let script = Self::load_bytecode_with(path, &self.interner)?;
+        self.vm.set_script_path(Some(path));
+        let outcome = self.vm.run(script, &self.globals);
+        Self::finish(outcome)
+    }
+
+    fn execute(&mut self, mut program: Program) -> Result<LoxValue, Error> {
+        if self.optimize {
+            program = optimizer::optimize(&program);
+        }
+        let outcome = match self.backend {
+            Backend::TreeWalker => self.evaluator.eval(&program, &self.globals),
+            Backend::Vm => self.vm.run(compiler::compile(&program, Symbol::new("script")), &self.globals),
+        };
+        Self::finish(outcome)
+    }
+
+    fn finish(outcome: Result<Value, RuntimeError>) -> Result<LoxValue, Error> {
+        match outcome {
+            Ok(value) => Ok(LoxValue::from_value(&value)),
+            Err(err) => match err.kind {
+                ErrorKind::Exit(status) => Err(Error::Exit(status)),
+                _ => Err(Error::Runtime(err)),
+            },
+        }
+    }
+
+    // Folds constants and drops unreachable code before running programs and the
+    // modules they import.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+        self.evaluator.set_optimize(optimize);
+        self.vm.set_optimize(optimize);
+    }
+
+    // Chooses the engine for later runs; globals carry over from one to the other.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    // Sends the output of `print` to `output` instead of standard output.
+    pub fn set_output(&mut self, output: Output) {
+        self.evaluator.set_output(output.clone());
+        self.vm.set_output(output);
+    }
+
+    // Collects the output of `print` from now on in the returned buffer.
+    pub fn capture_output(&mut self) -> OutputBuffer {
+        let (output, buffer) = Output::capture();
+        self.set_output(output);
+        buffer
+    }
+
+    // Bounds every later run; by default only the call depth is limited.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.evaluator.set_limits(limits.clone());
+        self.vm.set_limits(limits);
+    }
+
+    // Tunes when the garbage collector runs.
+    pub fn set_gc_config(&mut self, config: GcConfig) {
+        self.evaluator.set_gc_config(config.clone());
+        self.vm.set_gc_config(config);
+    }
+
+    // The collector's counters for the active backend.
+    pub fn gc_stats(&self) -> GcStats {
+        match self.backend {
+            Backend::TreeWalker => self.evaluator.gc_stats(),
+            Backend::Vm => self.vm.gc_stats(),
+        }
+    }
+
+    // Frees unreachable reference cycles now; returns how many objects were freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        match self.backend {
+            Backend::TreeWalker => self.evaluator.collect_garbage(),
+            Backend::Vm => self.vm.collect_garbage(),
+        }
+    }
+
+    // A handle that stops the running program from another thread. An interrupt
+    // only affects the run in progress; each new run starts uninterrupted.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.evaluator.interrupt_handle()
+    }
+
+    // Replaces what the I/O builtins may access; nothing is allowed by default.
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        *self.permissions.borrow_mut() = permissions;
+    }
+
+    pub fn set_global(&mut self, name: &str, value: impl Into<LoxValue>) -> Result<(), Error> {
+        let value = value.into().into_value().map_err(Error::Conversion)?;
+        self.globals.borrow_mut().define(self.interner.intern(name), value);
+        Ok(())
+    }
+
+    // Defines a global Lox function implemented by `function`. Calls with the wrong
+    // number of arguments fail before `function` runs; errors it returns become Lox
+    // runtime errors at the call site.
+    pub fn register_fn<F, R>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&Args) -> Result<R, NativeError> + 'static,
+        R: IntoLox,
+    {
+        self.register_native(name, arity, false, function);
+    }
+
+    // Like register_fn, for a function taking `min_arity` or more arguments.
+    pub fn register_variadic_fn<F, R>(&mut self, name: &str, min_arity: usize, function: F)
+    where
+        F: Fn(&Args) -> Result<R, NativeError> + 'static,
+        R: IntoLox,
+    {
+        self.register_native(name, min_arity, true, function);
+    }
+
+    fn register_native<F, R>(&mut self, name: &str, arity: usize, variadic: bool, function: F)
+    where
+        F: Fn(&Args) -> Result<R, NativeError> + 'static,
+        R: IntoLox,
+    {
+        let fn_name = name.to_string();
+        let native = move |arguments: Vec<Value>| {
+            let args = Args {
+                name: fn_name.to_string(),
+                values: arguments,
+            };
+            function(&args)?.into_lox().into_value().map_err(|message| NativeError::new(ErrorKind::TypeError, message))
+        };
+        let native = Rc::new(NativeFunctionData { name: self.interner.intern(name), arity, variadic, function: Box::new(native) });
+        self.vm.define_native(Rc::clone(&native));
+        self.evaluator.define_native(&self.globals, native);
+    }
+
+    // Defines `name` as a constructor for host objects of type T, so scripts can
+    // create them with `name(args)`.
+    pub fn register_class<T, F>(&mut self, name: &str, arity: usize, constructor: F)
+    where
+        T: HostObject,
+        F: Fn(&Args) -> Result<T, NativeError> + 'static,
+    {
+        self.register_fn(name, arity, move |args| Ok(LoxValue::host(constructor(args)?)));
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<LoxValue> {
+        let value = self.globals.borrow().get(&self.interner.lookup(name)?);
+        value.map(|value| LoxValue::from_value(&value))
+    }
+}
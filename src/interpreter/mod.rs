@@ -0,0 +1,7 @@
+#[allow(clippy::module_inception)]
+pub mod interpreter;
+pub mod value;
+pub mod native;
+pub mod host;
+pub mod permissions;
+pub mod builtins;
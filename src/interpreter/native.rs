@@ -0,0 +1,202 @@
+use std::fmt;
+use crate::evaluator::error::{ErrorKind, NativeError};
+use crate::interpreter::value::LoxValue;
+use crate::object::object::Value;
+
+// A Rust type that native function arguments can be converted to.
+pub trait FromLox: Sized {
+    // None if `value` doesn't have this type.
+    fn from_lox(value: Arg) -> Option<Self>;
+    // What the type is called in argument errors, e.g. "a number".
+    fn expected() -> String;
+}
+
+// A Rust type that native functions can return to Lox.
+pub trait IntoLox {
+    fn into_lox(self) -> LoxValue;
+}
+
+impl<T: Into<LoxValue>> IntoLox for T {
+    fn into_lox(self) -> LoxValue {
+        self.into()
+    }
+}
+
+// Copies the argument, including every list and map it holds.
+impl FromLox for LoxValue {
+    fn from_lox(value: Arg) -> Option<Self> {
+        Some(value.to_lox())
+    }
+    fn expected() -> String {
+        "any value".to_string()
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: Arg) -> Option<Self> {
+        value.as_number()
+    }
+    fn expected() -> String {
+        "a number".to_string()
+    }
+}
+
+impl FromLox for bool {
+    fn from_lox(value: Arg) -> Option<Self> {
+        match value.0 {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+    fn expected() -> String {
+        "a boolean".to_string()
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(value: Arg) -> Option<Self> {
+        value.as_str().map(str::to_string)
+    }
+    fn expected() -> String {
+        "a string".to_string()
+    }
+}
+
+impl FromLox for () {
+    fn from_lox(value: Arg) -> Option<Self> {
+        match value.0 {
+            Value::Nil => Some(()),
+            _ => None,
+        }
+    }
+    fn expected() -> String {
+        "nil".to_string()
+    }
+}
+
+impl<T: FromLox> FromLox for Vec<T> {
+    fn from_lox(value: Arg) -> Option<Self> {
+        match value.0 {
+            Value::List(values) => values.borrow().iter().map(|value| T::from_lox(Arg(value))).collect(),
+            _ => None,
+        }
+    }
+    fn expected() -> String {
+        format!("a list of {}", T::expected())
+    }
+}
+
+// nil converts to None.
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: Arg) -> Option<Self> {
+        match value.0 {
+            Value::Nil => Some(None),
+            _ => T::from_lox(value).map(Some),
+        }
+    }
+    fn expected() -> String {
+        format!("{} or nil", T::expected())
+    }
+}
+
+// One argument of a native function call. It refers to the value the script
+// passed, so looking at a list or map argument doesn't copy it.
+#[derive(Clone, Copy)]
+pub struct Arg<'a>(&'a Value);
+
+impl<'a> Arg<'a> {
+    // The name of the value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        self.0.obj_type().name()
+    }
+
+    // The number of characters in a string, elements in a list or entries in a
+    // map; None for other values.
+    pub fn length(&self) -> Option<usize> {
+        match self.0 {
+            Value::String(s) => Some(s.chars().count()),
+            Value::List(values) => Some(values.borrow().len()),
+            Value::Map(data) => Some(data.borrow().pairs.len()),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self.0 {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self.0 {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    // A copy of the value, as `get::<LoxValue>` returns it.
+    pub fn to_lox(&self) -> LoxValue {
+        LoxValue::from_value(self.0)
+    }
+}
+
+// Formats the value the way `print` would.
+impl fmt::Display for Arg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.inspect())
+    }
+}
+
+// Missing arguments of a variadic function read as nil.
+const NIL: &Value = &Value::Nil;
+
+// The arguments of a native function call, already checked against its arity.
+// They are only converted when the function asks for them.
+pub struct Args {
+    pub(crate) name: String,
+    pub(crate) values: Vec<Value>,
+}
+
+impl Args {
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // Argument `index`, or nil if there are fewer arguments.
+    pub fn arg(&self, index: usize) -> Arg<'_> {
+        Arg(self.values.get(index).unwrap_or(NIL))
+    }
+
+    // Converts argument `index`, failing with a TypeError that names the function.
+    pub fn get<T: FromLox>(&self, index: usize) -> Result<T, NativeError> {
+        let value = self.arg(index);
+        T::from_lox(value).ok_or_else(|| {
+            NativeError::new(
+                ErrorKind::TypeError,
+                format!("Argument {} to '{}' must be {} but got {}.", index + 1, self.name, T::expected(), value.type_name()),
+            )
+        })
+    }
+
+    // Like get::<f64>, but the number must also be a whole number.
+    pub fn get_integer(&self, index: usize) -> Result<i64, NativeError> {
+        let value = self.get::<f64>(index)?;
+        if value.fract() != 0.0 || !value.is_finite() {
+            return Err(NativeError::new(
+                ErrorKind::TypeError,
+                format!("Argument {} to '{}' must be an integer but got {}.", index + 1, self.name, value),
+            ));
+        }
+        Ok(value as i64)
+    }
+
+    // An error about this call, prefixed with the function's name.
+    pub fn error(&self, kind: ErrorKind, message: impl Into<String>) -> NativeError {
+        NativeError::new(kind, format!("{}: {}", self.name, message.into()))
+    }
+}
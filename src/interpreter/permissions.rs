@@ -0,0 +1,56 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// What the I/O builtins may touch. The default allows nothing, so untrusted
+// scripts can't read files, write files, see the environment or read stdin.
+#[derive(Debug, Clone, Default)]
+pub struct Permissions {
+    // Directories whose files and subdirectories `readFile` and `listDir` may
+    // read, and `import` may load. Modules in the main script's own directory
+    // can always be imported.
+    pub read: Vec<PathBuf>,
+    // Directories in which `writeFile` may create and overwrite files.
+    pub write: Vec<PathBuf>,
+    // Whether `env` may read environment variables.
+    pub env: bool,
+    // Whether `input` may read standard input.
+    pub stdin: bool,
+}
+
+impl Permissions {
+    // The canonical form of `path` if scripts may read it. Callers open that rather
+    // than `path`, so a symlink swapped in after the check isn't followed.
+    pub fn readable(&self, path: &Path) -> Option<PathBuf> {
+        within(path, &self.read)
+    }
+
+    // Like `readable`, for writing.
+    pub fn writable(&self, path: &Path) -> Option<PathBuf> {
+        within(path, &self.write)
+    }
+}
+
+// Compares canonical paths, so `..` and symlinks can't escape an allowed directory.
+fn within(path: &Path, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let path = canonical(path).ok()?;
+    dirs.iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| path.starts_with(dir))
+        .then_some(path)
+}
+
+// The canonical form of `path`, which may name a file that doesn't exist yet
+// as long as its directory does. A symlink is always resolved, so one that
+// dangles fails rather than being checked as the name it has here.
+fn canonical(path: &Path) -> io::Result<PathBuf> {
+    if fs::symlink_metadata(path).is_ok() {
+        return path.canonicalize();
+    }
+    let name = path.file_name().ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Ok(parent.canonicalize()?.join(name))
+}
@@ -0,0 +1,208 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use crate::interpreter::host::HostObject;
+use crate::object::object::{HashPair, MapData, Value};
+
+// A Lox value as seen from Rust. Lists and maps are copied in and out of the
+// interpreter; everything else (functions, classes, instances, modules) is passed
+// around as an opaque handle to the live object. So is a list or map where it
+// occurs inside itself.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoxValue {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<LoxValue>),
+    // Entries in insertion order.
+    Map(Vec<(LoxValue, LoxValue)>),
+    Object(ObjectHandle),
+}
+
+#[derive(Clone)]
+pub struct ObjectHandle(Value);
+
+impl ObjectHandle {
+    pub fn inspect(&self) -> String {
+        self.0.inspect()
+    }
+
+    // Runs `f` on the wrapped host object if it is a `T`.
+    pub fn with_host<T: HostObject, R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let host = match &self.0 {
+            Value::Host(host) => host,
+            _ => return None,
+        };
+        let mut data = host.borrow_mut();
+        data.as_any_mut().downcast_mut::<T>().map(f)
+    }
+}
+
+impl PartialEq for ObjectHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl fmt::Debug for ObjectHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ObjectHandle({})", self.0.inspect())
+    }
+}
+
+impl LoxValue {
+    // The name of the value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            LoxValue::Nil => "nil",
+            LoxValue::Bool(_) => "boolean",
+            LoxValue::Number(_) => "number",
+            LoxValue::String(_) => "string",
+            LoxValue::List(_) => "list",
+            LoxValue::Map(_) => "map",
+            LoxValue::Object(handle) => handle.0.obj_type().name(),
+        }
+    }
+
+    // Wraps a Rust value so scripts can call its methods and read its properties.
+    pub fn host(obj: impl HostObject) -> LoxValue {
+        LoxValue::Object(ObjectHandle(Value::Host(Rc::new(RefCell::new(obj)))))
+    }
+
+    pub fn from_value(value: &Value) -> LoxValue {
+        LoxValue::copy_within(value, &mut Vec::new())
+    }
+
+    // `enclosing` holds the lists and maps being copied around this value. One
+    // that contains itself is passed as a handle where it recurs, since a copy
+    // would never end.
+    fn copy_within(value: &Value, enclosing: &mut Vec<*const ()>) -> LoxValue {
+        let pointer = match value {
+            Value::List(elements) => Rc::as_ptr(elements) as *const (),
+            Value::Map(data) => Rc::as_ptr(data) as *const (),
+            _ => std::ptr::null(),
+        };
+        if enclosing.contains(&pointer) {
+            return LoxValue::Object(ObjectHandle(value.clone()));
+        }
+        match value {
+            Value::Nil => LoxValue::Nil,
+            Value::Boolean(b) => LoxValue::Bool(*b),
+            Value::Number(n) => LoxValue::Number(*n),
+            Value::String(s) => LoxValue::String(s.to_string()),
+            Value::List(elements) => {
+                enclosing.push(pointer);
+                let values = elements.borrow().iter().map(|e| LoxValue::copy_within(e, enclosing)).collect();
+                enclosing.pop();
+                LoxValue::List(values)
+            }
+            Value::Map(data) => {
+                enclosing.push(pointer);
+                let entries = data.borrow().pairs.iter()
+                    .map(|pair| (LoxValue::copy_within(&pair.key, enclosing), LoxValue::copy_within(&pair.value, enclosing)))
+                    .collect();
+                enclosing.pop();
+                LoxValue::Map(entries)
+            }
+            value => LoxValue::Object(ObjectHandle(value.clone())),
+        }
+    }
+
+    // Fails only for a map with a key Lox can't hash (a list, map or object).
+    pub fn into_value(self) -> Result<Value, String> {
+        Ok(match self {
+            LoxValue::Nil => Value::Nil,
+            LoxValue::Bool(value) => Value::Boolean(value),
+            LoxValue::Number(value) => Value::Number(value),
+            LoxValue::String(value) => Value::String(Rc::new(value.into_boxed_str())),
+            LoxValue::List(values) => Value::list(values.into_iter().map(LoxValue::into_value).collect::<Result<_, _>>()?),
+            LoxValue::Map(entries) => {
+                let mut data = MapData::default();
+                for (key, value) in entries {
+                    let key = key.into_value()?;
+                    let hash_key = key.hash_key()
+                        .ok_or_else(|| "Map keys must be nil, booleans, numbers or strings.".to_string())?;
+                    data.insert(hash_key, HashPair { key, value: value.into_value()? });
+                }
+                Value::Map(Rc::new(RefCell::new(data)))
+            }
+            LoxValue::Object(handle) => handle.0,
+        })
+    }
+}
+
+// Formats the value the way `print` would.
+impl fmt::Display for LoxValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoxValue::Nil => write!(f, "nil"),
+            LoxValue::Bool(value) => write!(f, "{}", value),
+            LoxValue::Number(value) => write!(f, "{}", value),
+            LoxValue::String(value) => write!(f, "{}", value),
+            LoxValue::List(values) => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+            LoxValue::Map(entries) => {
+                let entries: Vec<String> = entries.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{{{}}}", entries.join(", "))
+            }
+            LoxValue::Object(handle) => write!(f, "{}", handle.inspect()),
+        }
+    }
+}
+
+impl From<()> for LoxValue {
+    fn from(_: ()) -> Self {
+        LoxValue::Nil
+    }
+}
+
+impl From<bool> for LoxValue {
+    fn from(value: bool) -> Self {
+        LoxValue::Bool(value)
+    }
+}
+
+impl From<f64> for LoxValue {
+    fn from(value: f64) -> Self {
+        LoxValue::Number(value)
+    }
+}
+
+impl From<usize> for LoxValue {
+    fn from(value: usize) -> Self {
+        LoxValue::Number(value as f64)
+    }
+}
+
+impl From<i32> for LoxValue {
+    fn from(value: i32) -> Self {
+        LoxValue::Number(value as f64)
+    }
+}
+
+impl From<&str> for LoxValue {
+    fn from(value: &str) -> Self {
+        LoxValue::String(value.to_string())
+    }
+}
+
+impl From<String> for LoxValue {
+    fn from(value: String) -> Self {
+        LoxValue::String(value)
+    }
+}
+
+impl<T: Into<LoxValue>> From<Vec<T>> for LoxValue {
+    fn from(values: Vec<T>) -> Self {
+        LoxValue::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<LoxValue>> From<Option<T>> for LoxValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(LoxValue::Nil, Into::into)
+    }
+}
@@ -1,5 +1,8 @@
 use std::collections::HashMap;
+use std::io;
 use crate::common::common::PrjChar;
+use crate::common::output::Output;
+use crate::common::symbol::{Interner, Symbol};
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
 pub enum TokenType {
@@ -7,14 +10,30 @@ pub enum TokenType {
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
     Comma,
     Dot,
+    DotDot,
+    DotDotEqual,
+    FatArrow,
     Minus,
     Plus,
     Semicolon,
     Asterisk,
     Slash,
+    Percent,
+    StarStar,
+    Question,
+    Colon,
     Assign,
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    PercentAssign,
+    PlusPlus,
+    MinusMinus,
     Equal,
     Bang,
     NotEqual,
@@ -26,48 +45,87 @@ pub enum TokenType {
     Number,
     Identifier,
     And,
+    Break,
+    Case,
+    Catch,
     Class,
+    Continue,
     Else,
     False,
+    Finally,
     For,
     Fun,
     If,
+    Import,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
     EOF,
 }
 
+pub const PREFIX_PRECEDENCE: i32 = 9;
+pub const POSTFIX_PRECEDENCE: i32 = 11;
+
 impl TokenType {
+    // Binding power used by the Pratt parser. Unary operators bind at PREFIX_PRECEDENCE,
+    // so `**` binds tighter than unary minus (`-2 ** 2` is `-(2 ** 2)`) while `*`, `/`
+    // and `%` bind looser (`-2 * 3` is `(-2) * 3`).
     pub fn precedence(&self) -> i32 {
         match self {
-            TokenType::LParen => 0,
-            TokenType::Equal | TokenType::NotEqual => 1,
-            TokenType::Less | TokenType::LessEqual | TokenType::Greater | TokenType::GreaterEqual => 2,
-            TokenType::Plus | TokenType::Minus => 3,
-            TokenType::Asterisk | TokenType::Slash => 4,
+            TokenType::Assign
+            | TokenType::PlusAssign
+            | TokenType::MinusAssign
+            | TokenType::AsteriskAssign
+            | TokenType::SlashAssign
+            | TokenType::PercentAssign => 1,
+            TokenType::Question => 2,
+            TokenType::Or => 3,
+            TokenType::And => 4,
+            TokenType::Equal | TokenType::NotEqual => 5,
+            TokenType::Less | TokenType::LessEqual | TokenType::Greater | TokenType::GreaterEqual => 6,
+            TokenType::Plus | TokenType::Minus => 7,
+            TokenType::Asterisk | TokenType::Slash | TokenType::Percent => 8,
+            TokenType::StarStar => 10,
+            TokenType::Dot | TokenType::LBracket | TokenType::LParen | TokenType::PlusPlus | TokenType::MinusMinus => POSTFIX_PRECEDENCE,
             _ => 0
         }
     }
+
+    pub fn is_right_associative(&self) -> bool {
+        matches!(
+            self,
+            TokenType::StarStar
+                | TokenType::Question
+                | TokenType::Assign
+                | TokenType::PlusAssign
+                | TokenType::MinusAssign
+                | TokenType::AsteriskAssign
+                | TokenType::SlashAssign
+                | TokenType::PercentAssign
+        )
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexeme: String,
-    pub literal: String,
+    pub lexeme: Symbol,
+    pub literal: Symbol,
     pub line_number: i32,
 }
 
 impl Token {
-    pub fn print(&self) {
-        println!("{} {} {}", self.token_type_to_print(), self.lexeme, self.literal);
+    pub fn print(&self, out: &Output) -> io::Result<()> {
+        out.write_line(&format!("{} {} {}", self.token_type_to_print(), self.lexeme, self.literal))
     }
 
     fn token_type_to_print(&self) -> &str {
@@ -76,14 +134,30 @@ impl Token {
             TokenType::RParen => "RIGHT_PAREN",
             TokenType::LBrace => "LEFT_BRACE",
             TokenType::RBrace => "RIGHT_BRACE",
+            TokenType::LBracket => "LEFT_BRACKET",
+            TokenType::RBracket => "RIGHT_BRACKET",
             TokenType::Comma => "COMMA",
             TokenType::Dot => "DOT",
+            TokenType::DotDot => "DOT_DOT",
+            TokenType::DotDotEqual => "DOT_DOT_EQUAL",
+            TokenType::FatArrow => "FAT_ARROW",
             TokenType::Minus => "MINUS",
             TokenType::Plus => "PLUS",
             TokenType::Semicolon => "SEMICOLON",
             TokenType::Asterisk => "STAR",
             TokenType::Slash => "SLASH",
+            TokenType::Percent => "PERCENT",
+            TokenType::StarStar => "STAR_STAR",
+            TokenType::Question => "QUESTION",
+            TokenType::Colon => "COLON",
             TokenType::Assign => "EQUAL",
+            TokenType::PlusAssign => "PLUS_EQUAL",
+            TokenType::MinusAssign => "MINUS_EQUAL",
+            TokenType::AsteriskAssign => "STAR_EQUAL",
+            TokenType::SlashAssign => "SLASH_EQUAL",
+            TokenType::PercentAssign => "PERCENT_EQUAL",
+            TokenType::PlusPlus => "PLUS_PLUS",
+            TokenType::MinusMinus => "MINUS_MINUS",
             TokenType::Equal => "EQUAL_EQUAL",
             TokenType::Bang => "BANG",
             TokenType::NotEqual => "BANG_EQUAL",
@@ -95,19 +169,28 @@ impl Token {
             TokenType::Number => "NUMBER",
             TokenType::Identifier => "IDENTIFIER",
             TokenType::And => "AND",
+            TokenType::Break => "BREAK",
+            TokenType::Case => "CASE",
+            TokenType::Catch => "CATCH",
             TokenType::Class => "CLASS",
+            TokenType::Continue => "CONTINUE",
             TokenType::Else => "ELSE",
             TokenType::False => "FALSE",
+            TokenType::Finally => "FINALLY",
             TokenType::For => "FOR",
             TokenType::Fun => "FUN",
             TokenType::If => "IF",
+            TokenType::Import => "IMPORT",
+            TokenType::Match => "MATCH",
             TokenType::Nil => "NIL",
             TokenType::Or => "OR",
             TokenType::Print => "PRINT",
             TokenType::Return => "RETURN",
             TokenType::Super => "SUPER",
             TokenType::This => "THIS",
+            TokenType::Throw => "THROW",
             TokenType::True => "TRUE",
+            TokenType::Try => "TRY",
             TokenType::Var => "VAR",
             TokenType::While => "WHILE",
             TokenType::EOF => "EOF",
@@ -121,16 +204,29 @@ pub struct Lexer {
     pub tokens: Vec<Token>,
     pub errors: Vec<String>,
     keywords: HashMap<String, TokenType>,
+    // Where identifiers and keywords get their symbols. Other lexemes aren't
+    // interned, since literals could make the table grow without bound.
+    interner: Interner,
+    // The literal of every token but strings and numbers.
+    null: Symbol,
 }
 
 impl Lexer {
     pub fn new(input: String) -> Self {
+        Self::with_interner(input, Interner::default())
+    }
+
+    // A lexer that interns identifiers in `interner`, to share them with other code
+    // the same interpreter compiles.
+    pub fn with_interner(input: String, interner: Interner) -> Self {
         let mut lexer = Self {
             input: input.chars().collect(),
             position: 0,
             tokens: Vec::new(),
             errors: Vec::new(),
             keywords: HashMap::new(),
+            interner,
+            null: Symbol::new("null"),
         };
 
         lexer.init_keywords();
@@ -139,19 +235,28 @@ impl Lexer {
 
     fn init_keywords(&mut self) {
         self.keywords.insert("and".to_string(), TokenType::And);
+        self.keywords.insert("break".to_string(), TokenType::Break);
+        self.keywords.insert("case".to_string(), TokenType::Case);
+        self.keywords.insert("catch".to_string(), TokenType::Catch);
         self.keywords.insert("class".to_string(), TokenType::Class);
+        self.keywords.insert("continue".to_string(), TokenType::Continue);
         self.keywords.insert("else".to_string(), TokenType::Else);
         self.keywords.insert("false".to_string(), TokenType::False);
+        self.keywords.insert("finally".to_string(), TokenType::Finally);
         self.keywords.insert("for".to_string(), TokenType::For);
         self.keywords.insert("fun".to_string(), TokenType::Fun);
         self.keywords.insert("if".to_string(), TokenType::If);
+        self.keywords.insert("import".to_string(), TokenType::Import);
+        self.keywords.insert("match".to_string(), TokenType::Match);
         self.keywords.insert("nil".to_string(), TokenType::Nil);
         self.keywords.insert("or".to_string(), TokenType::Or);
         self.keywords.insert("print".to_string(), TokenType::Print);
         self.keywords.insert("return".to_string(), TokenType::Return);
         self.keywords.insert("super".to_string(), TokenType::Super);
         self.keywords.insert("this".to_string(), TokenType::This);
+        self.keywords.insert("throw".to_string(), TokenType::Throw);
         self.keywords.insert("true".to_string(), TokenType::True);
+        self.keywords.insert("try".to_string(), TokenType::Try);
         self.keywords.insert("var".to_string(), TokenType::Var);
         self.keywords.insert("while".to_string(), TokenType::While);
     }
@@ -184,10 +289,11 @@ impl Lexer {
     }
 
     fn add_token(&mut self, token_type: TokenType, lexeme: &'static str, literal: &'static str, line_number: i32) {
+        let literal = if literal == "null" { self.null.clone() } else { Symbol::new(literal) };
         self.tokens.push(Token {
             token_type,
-            lexeme: lexeme.to_string(),
-            literal: literal.to_string(),
+            lexeme: Symbol::new(lexeme),
+            literal,
             line_number,
         });
     }
@@ -195,8 +301,8 @@ impl Lexer {
     fn add_token_string(&mut self, token_type: TokenType, lexeme: String, literal: String, line_number: i32) {
         self.tokens.push(Token {
             token_type,
-            lexeme,
-            literal,
+            lexeme: Symbol::new(&lexeme),
+            literal: Symbol::new(&literal),
             line_number,
         });
     }
@@ -207,15 +313,15 @@ impl Lexer {
         }
     }
 
+    // Strings may span lines; one that is never closed runs to the end of the input.
     fn read_string(&mut self) -> Option<String> {
         let mut str= String::new();
-        while self.input[self.position] != '"' {
-            let ch = self.input[self.position];
-            str.push(ch);
+        while self.position < self.input.len() && !self.current_token_is('"') && !self.current_token_is('\0') {
+            str.push(self.input[self.position]);
             self.position += 1;
-            if self.position >= self.input.len() || self.current_token_is('\n') || self.current_token_is('\0') {
-                return None;
-            }
+        }
+        if !self.current_token_is('"') {
+            return None;
         }
         self.position += 1;
         Some(str)
@@ -232,7 +338,9 @@ impl Lexer {
             lexeme.push(self.input[self.position]);
             self.position += 1;
         }
-        if self.position < self.input.len() && self.input[self.position] == '.' {
+        // A '.' only belongs to the number when a digit follows, so `1..10` lexes as a range.
+        let fraction_follows = self.position + 1 < self.input.len() && self.input[self.position + 1].is_ascii_digit();
+        if self.position < self.input.len() && self.input[self.position] == '.' && fraction_follows {
             literal.push('.');
             lexeme.push('.');
             self.position += 1;
@@ -245,20 +353,19 @@ impl Lexer {
                 literal = literal.trim_end_matches('0').to_string();
             }
             if literal.ends_with(".") {
-                literal.push_str("0");
+                literal.push('0');
             }
         } else {
             literal.push_str(".0");
         }
-        return (lexeme, literal);
+        (lexeme, literal)
     }
 
+    // Keywords are interned too, since `this` is looked up like a variable.
     fn add_token_identifier(&mut self, ident: String, line_number: i32) {
-        if let Some(token_type) = self.keywords.get(&ident) {
-            self.add_token_string(token_type.clone(), ident, "null".to_string(), line_number);
-        } else {
-            self.add_token_string(TokenType::Identifier, ident, "null".to_string(), line_number);
-        }
+        let token_type = self.keywords.get(&ident).cloned().unwrap_or(TokenType::Identifier);
+        let lexeme = self.interner.intern(&ident);
+        self.tokens.push(Token { token_type, lexeme, literal: self.null.clone(), line_number });
     }
 
     fn read_identifier(&mut self, first: char) -> String {
@@ -288,15 +395,62 @@ impl Lexer {
                 ')' => self.add_token(TokenType::RParen, ")", "null", line_number),
                 '{' => self.add_token(TokenType::LBrace, "{", "null", line_number),
                 '}' => self.add_token(TokenType::RBrace, "}", "null", line_number),
+                '[' => self.add_token(TokenType::LBracket, "[", "null", line_number),
+                ']' => self.add_token(TokenType::RBracket, "]", "null", line_number),
                 ',' => self.add_token(TokenType::Comma, ",", "null", line_number),
-                '.' => self.add_token(TokenType::Dot, ".", "null", line_number),
-                '-' => self.add_token(TokenType::Minus, "-", "null", line_number),
-                '+' => self.add_token(TokenType::Plus, "+", "null", line_number),
+                '.' => {
+                    if self.expect_current_token('.') {
+                        if self.expect_current_token('=') {
+                            self.add_token(TokenType::DotDotEqual, "..=", "null", line_number);
+                        } else {
+                            self.add_token(TokenType::DotDot, "..", "null", line_number);
+                        }
+                    } else {
+                        self.add_token(TokenType::Dot, ".", "null", line_number);
+                    }
+                }
+                '?' => self.add_token(TokenType::Question, "?", "null", line_number),
+                ':' => self.add_token(TokenType::Colon, ":", "null", line_number),
+                '-' => {
+                    if self.expect_current_token('-') {
+                        self.add_token(TokenType::MinusMinus, "--", "null", line_number);
+                    } else if self.expect_current_token('=') {
+                        self.add_token(TokenType::MinusAssign, "-=", "null", line_number);
+                    } else {
+                        self.add_token(TokenType::Minus, "-", "null", line_number);
+                    }
+                }
+                '+' => {
+                    if self.expect_current_token('+') {
+                        self.add_token(TokenType::PlusPlus, "++", "null", line_number);
+                    } else if self.expect_current_token('=') {
+                        self.add_token(TokenType::PlusAssign, "+=", "null", line_number);
+                    } else {
+                        self.add_token(TokenType::Plus, "+", "null", line_number);
+                    }
+                }
                 ';' => self.add_token(TokenType::Semicolon, ";", "null", line_number),
-                '*' => self.add_token(TokenType::Asterisk, "*", "null", line_number),
+                '*' => {
+                    if self.expect_current_token('*') {
+                        self.add_token(TokenType::StarStar, "**", "null", line_number);
+                    } else if self.expect_current_token('=') {
+                        self.add_token(TokenType::AsteriskAssign, "*=", "null", line_number);
+                    } else {
+                        self.add_token(TokenType::Asterisk, "*", "null", line_number);
+                    }
+                }
+                '%' => {
+                    if self.expect_current_token('=') {
+                        self.add_token(TokenType::PercentAssign, "%=", "null", line_number);
+                    } else {
+                        self.add_token(TokenType::Percent, "%", "null", line_number);
+                    }
+                }
                 '/' => {
                     if self.expect_current_token('/') {
                         self.skip_line();
+                    } else if self.expect_current_token('=') {
+                        self.add_token(TokenType::SlashAssign, "/=", "null", line_number);
                     } else {
                         self.add_token(TokenType::Slash, "/", "null", line_number)
                     }
@@ -304,6 +458,8 @@ impl Lexer {
                 '=' => {
                     if self.expect_current_token('=') {
                         self.add_token(TokenType::Equal, "==", "null", line_number);
+                    } else if self.expect_current_token('>') {
+                        self.add_token(TokenType::FatArrow, "=>", "null", line_number);
                     } else {
                         self.add_token(TokenType::Assign, "=", "null", line_number);
                     }
@@ -333,9 +489,13 @@ impl Lexer {
                     line_number += 1;
                     continue;
                 }
-                '"' => match self.read_string() {
+                '"' => {
+                    let start = self.position;
+                    match self.read_string() {
                         Some(str) =>  self.add_token_string(TokenType::String, format!("\"{}\"", str.clone()), str.clone(), line_number),
                         None => self.errors.push(format!("[line {line_number}] Error: Unterminated string.")),
+                    }
+                    line_number += self.input[start..self.position].iter().filter(|&&ch| ch == '\n').count() as i32;
                 }
                 '0'..='9' => {
                     let (lexeme, literal) = self.read_number_str(ch);
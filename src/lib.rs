@@ -0,0 +1,22 @@
+pub mod lexer;
+pub mod common;
+pub mod parser;
+pub mod ast;
+pub mod object;
+pub mod evaluator;
+pub mod resolver;
+pub mod optimizer;
+pub mod interpreter;
+pub mod vm;
+pub mod repl;
+
+pub use crate::interpreter::interpreter::{Backend, Error, Interpreter};
+pub use crate::common::output::{Output, OutputBuffer};
+pub use crate::evaluator::error::{ErrorKind, Frame, NativeError, RuntimeError};
+pub use crate::evaluator::limits::{InterruptHandle, Limits};
+pub use crate::interpreter::host::HostObject;
+pub use crate::interpreter::native::{Args, FromLox, IntoLox};
+pub use crate::interpreter::permissions::Permissions;
+pub use crate::object::heap::{GcConfig, GcStats};
+pub use crate::interpreter::value::{LoxValue, ObjectHandle};
+pub use crate::repl::repl::Repl;
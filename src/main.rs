@@ -1,33 +1,191 @@
-mod lexer;
-mod common;
-mod parser;
-mod ast;
-mod object;
-mod evaluator;
-
-use std::{env, process};
+use std::{env, process, thread};
 use std::fs;
-use std::io::{self, Write};
-use crate::common::common::PrjString;
-use crate::parser::parser::Parser;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use codecrafters_interpreter::common::common::PrjString;
+use codecrafters_interpreter::ast::ast::Program;
+use codecrafters_interpreter::lexer;
+use codecrafters_interpreter::optimizer::optimizer;
+use codecrafters_interpreter::parser::parser::Parser;
+use codecrafters_interpreter::common::symbol::Symbol;
+use codecrafters_interpreter::vm::{compiler, disassembler};
+use codecrafters_interpreter::{Backend, Error, GcConfig, Interpreter, Limits, Output, Permissions, Repl};
 
+// Command-line flags; they may appear before or after the file name.
+struct Options {
+    // Every command but `repl` needs one.
+    filename: Option<String>,
+    permissions: Permissions,
+    limits: Limits,
+    gc: GcConfig,
+    // Print collector statistics to standard error after the run.
+    gc_stats: bool,
+    backend: Backend,
+    // Print the compiled bytecode to standard error before running.
+    dump_bytecode: Option<ListingFormat>,
+    // How `disassemble` prints the bytecode.
+    format: ListingFormat,
+    // Where `compile` writes the bytecode.
+    output: Option<PathBuf>,
+    // Fold constants and drop unreachable code before printing, compiling or running.
+    optimize: bool,
+}
+
+#[derive(Clone, Copy)]
+enum ListingFormat {
+    Text,
+    Json,
+}
+
+fn parse_format(flag: &str, value: Option<&str>) -> Result<ListingFormat, String> {
+    match value {
+        None | Some("text") => Ok(ListingFormat::Text),
+        Some("json") => Ok(ListingFormat::Json),
+        Some(value) => Err(format!("Invalid value for {}: {}", flag, value)),
+    }
+}
+
+// Native stack reserved per level of Lox calls; debug builds need the most.
+const STACK_PER_CALL: usize = 32 * 1024;
+// Stack for everything besides the calls themselves.
+const BASE_STACK: usize = 16 * 1024 * 1024;
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut filename = None;
+        // Scripts may always read standard input from the command line.
+        let mut permissions = Permissions { stdin: true, ..Permissions::default() };
+        let mut limits = Limits::default();
+        let mut gc = GcConfig::default();
+        let mut gc_stats = false;
+        let mut backend = Backend::default();
+        let mut dump_bytecode = None;
+        let mut format = ListingFormat::Text;
+        let mut output = None;
+        let mut optimize = false;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "-o" {
+                let path = args.next().ok_or("-o needs a file name.")?;
+                output = Some(PathBuf::from(path));
+                continue;
+            }
+            if !arg.starts_with("--") {
+                if filename.replace(arg.to_string()).is_some() {
+                    return Err(format!("Unexpected argument: {}", arg));
+                }
+                continue;
+            }
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value)),
+                None => (arg.as_str(), None),
+            };
+            match (flag, value) {
+                ("--allow-read", Some(dir)) if !dir.is_empty() => permissions.read.push(PathBuf::from(dir)),
+                ("--allow-write", Some(dir)) if !dir.is_empty() => permissions.write.push(PathBuf::from(dir)),
+                ("--allow-read" | "--allow-write", _) => return Err(format!("{} needs a directory, e.g. {}=.", flag, flag)),
+                ("--allow-env", None) => permissions.env = true,
+                ("--max-steps", Some(n)) => limits.max_steps = Some(parse_number(flag, n)?),
+                ("--max-heap-objects", Some(n)) => limits.max_heap_objects = Some(parse_number(flag, n)?),
+                ("--max-heap-bytes", Some(n)) => limits.max_heap_bytes = Some(parse_number(flag, n)?),
+                ("--max-call-depth", Some(n)) => limits.max_call_depth = Some(parse_number(flag, n)?),
+                ("--timeout", Some(seconds)) => {
+                    let seconds: f64 = parse_number(flag, seconds)?;
+                    limits.timeout = Some(Duration::try_from_secs_f64(seconds).map_err(|_| format!("Invalid value for {}: {}", flag, seconds))?);
+                }
+                ("--gc-stress", None) => gc.stress = true,
+                ("--gc-stats", None) => gc_stats = true,
+                ("--backend", Some("tree")) => backend = Backend::TreeWalker,
+                ("--backend", Some("vm")) => backend = Backend::Vm,
+                ("--backend", _) => return Err("--backend must be tree or vm.".to_string()),
+                ("--dump-bytecode", value) => dump_bytecode = Some(parse_format(flag, value)?),
+                ("--format", Some(value)) => format = parse_format(flag, Some(value))?,
+                ("--output", Some(path)) if !path.is_empty() => output = Some(PathBuf::from(path)),
+                ("--optimize", None) => optimize = true,
+                ("--gc-threshold", Some(n)) => gc.initial_threshold = parse_number(flag, n)?,
+                ("--gc-growth", Some(factor)) => {
+                    gc.growth_factor = parse_number(flag, factor)?;
+                    if gc.growth_factor.is_nan() || gc.growth_factor < 1.0 {
+                        return Err(format!("Invalid value for {}: {}", flag, factor));
+                    }
+                }
+                _ => return Err(format!("Unknown option: {}", arg)),
+            }
+        }
+        Ok(Options { filename, permissions, limits, gc, gc_stats, backend, dump_bytecode, format, output, optimize })
+    }
+
+    // An interpreter set up as the flags ask.
+    fn interpreter(&self, out: &Output) -> Interpreter {
+        let mut interp = Interpreter::new();
+        interp.set_output(out.clone());
+        interp.set_permissions(self.permissions.clone());
+        interp.set_limits(self.limits.clone());
+        interp.set_gc_config(self.gc.clone());
+        interp.set_backend(self.backend);
+        interp.set_optimize(self.optimize);
+        interp
+    }
+}
+
+fn parse_number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+// Deep recursion in a script recurses in the evaluator too, so the interpreter runs
+// on a thread with enough stack for the call depth limit.
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        writeln!(io::stderr(), "Usage: {} tokenize <filename>", args[0]).unwrap();
-        return;
+    let depth = args.iter()
+        .find_map(|arg| arg.strip_prefix("--max-call-depth="))
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(Limits::default().max_call_depth.unwrap_or_default());
+    let stack_size = BASE_STACK.saturating_add(depth.saturating_mul(STACK_PER_CALL));
+    let interpreter = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(move || run(args))
+        .unwrap_or_else(|err| {
+            check_written(Output::stderr().write_line(&format!("Failed to start the interpreter: {}", err)));
+            process::exit(70);
+        });
+    if interpreter.join().is_err() {
+        process::exit(70);
     }
+}
 
-    let command = &args[1];
-    let filename = &args[2];
+fn run(args: Vec<String>) {
+    // Without a command, the interpreter starts a REPL; flags may still be given.
+    let (command, flags) = match args.get(1) {
+        Some(command) if !command.starts_with("--") => (command.as_str(), args.get(2..).unwrap_or_default()),
+        _ => ("repl", args.get(1..).unwrap_or_default()),
+    };
+    let out = Output::stdout();
+    let diagnostics = Output::stderr();
+    let usage = |message: &str| -> ! {
+        check_written(diagnostics.write_line(message));
+        check_written(diagnostics.write_line(&format!("Usage: {} <tokenize|parse|evaluate|run|compile|disassemble> <filename> [--allow-read=dir] [--allow-write=dir] [--allow-env] [--max-steps=N] [--max-heap-objects=N] [--max-heap-bytes=N] [--max-call-depth=N] [--timeout=SECONDS] [--gc-stress] [--gc-stats] [--gc-threshold=N] [--gc-growth=F] [--backend=tree|vm] [--dump-bytecode[=json]] [--format=text|json] [-o FILE] [--optimize]", args[0])));
+        check_written(diagnostics.write_line(&format!("       {} [repl] [options]", args[0])));
+        process::exit(64);
+    };
+    let options = Options::parse(flags).unwrap_or_else(|message| usage(&message));
+    if command == "repl" {
+        if let Some(filename) = &options.filename {
+            usage(&format!("Unexpected argument: {}", filename));
+        }
+        repl(options, out, diagnostics.clone());
+        return;
+    }
+    let filename = options.filename.as_deref().unwrap_or_else(|| usage("Missing file name."));
 
-    match command.as_str() {
+    match command {
         "tokenize" => {
             // You can use print statements as follows for debugging, they'll be visible when running tests.
-            writeln!(io::stderr(), "Logs from your program will appear here!").unwrap();
+            check_written(diagnostics.write_line("Logs from your program will appear here!"));
 
             let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
+                check_written(diagnostics.write_line(&format!("Failed to read file {}", filename)));
                 String::new()
             });
 
@@ -36,23 +194,23 @@ fn main() {
                 l.tokenize();
 
                 for err in l.errors.iter() {
-                    err.print_error();
+                    check_written(err.print_error(&diagnostics));
                 }
 
                 for token in l.tokens.iter() {
-                    token.print();
+                    check_written(token.print(&out));
                 }
 
-                if l.errors.len() > 0 {
+                if !l.errors.is_empty() {
                     process::exit(65);
                 }
             } else {
-                println!("EOF  null"); // Placeholder, remove this line when implementing the scanner
+                check_written(out.write_line("EOF  null")); // Placeholder, remove this line when implementing the scanner
             }
         }
         "parse" => {
             let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
+                check_written(diagnostics.write_line(&format!("Failed to read file {}", filename)));
                 String::new()
             });
 
@@ -60,7 +218,7 @@ fn main() {
                 let mut l = lexer::lexer::Lexer::new(file_contents);
                 l.tokenize();
 
-                if l.errors.len() > 0 {
+                if !l.errors.is_empty() {
                     process::exit(65);
                 }
 
@@ -68,48 +226,161 @@ fn main() {
                 let pg = p.parse_program();
 
                 for err in p.errors.iter() {
-                   err.print_error();
+                   check_written(err.print_error(&diagnostics));
                 }
 
-                if p.errors.len() > 0 {
+                if !p.errors.is_empty() {
                     process::exit(65);
                 }
 
-                pg.print();
+                let pg = if options.optimize { optimizer::optimize(&pg) } else { pg };
+                check_written(pg.print(&out));
             } else {
-                println!("EOF  null"); // Placeholder, remove this line when implementing the scanner
+                check_written(out.write_line("EOF  null")); // Placeholder, remove this line when implementing the scanner
             }
         }
         "evaluate" => {
             let file_contents = fs::read_to_string(filename).unwrap_or_else(|_| {
-                writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
+                check_written(diagnostics.write_line(&format!("Failed to read file {}", filename)));
                 String::new()
             });
 
             if !file_contents.is_empty() {
-                let mut l = lexer::lexer::Lexer::new(file_contents);
-                l.tokenize();
-
-                if l.errors.len() > 0 {
-                    process::exit(65);
+                let mut interp = options.interpreter(&out);
+                if let Some(format) = options.dump_bytecode {
+                    dump_bytecode(filename, format, options.optimize, &diagnostics, &diagnostics);
                 }
-
-                let mut p = Parser::new(l);
-                let pg = p.parse_program();
-
-                if p.errors.len() > 0 {
-                    process::exit(65);
+                let result = interp.eval_str(&file_contents);
+                if options.gc_stats && !matches!(result, Err(Error::Compile(_))) {
+                    print_gc_stats(&interp, &diagnostics);
+                }
+                match result {
+                    Ok(value) => check_written(out.write_line(&value.to_string())),
+                    Err(err) => exit_with(err, &diagnostics),
                 }
-
-                let obj = evaluator::evaluator::eval(Box::new(pg));
-                println!("{}", obj.inspect());
             } else {
-                println!("EOF  null"); // Placeholder, remove this line when implementing the scanner
+                check_written(out.write_line("EOF  null")); // Placeholder, remove this line when implementing the scanner
+            }
+        }
+        "run" => {
+            let mut interp = options.interpreter(&out);
+            if let Some(format) = options.dump_bytecode {
+                dump_bytecode(filename, format, options.optimize, &diagnostics, &diagnostics);
+            }
+            let result = if is_bytecode(filename) {
+                interp.run_bytecode_file(filename)
+            } else {
+                interp.run_file(filename)
+            };
+            // Statistics are printed even when the script fails at runtime.
+            if options.gc_stats && !matches!(result, Err(Error::Compile(_) | Error::Io { .. })) {
+                print_gc_stats(&interp, &diagnostics);
+            }
+            if let Err(err) = result {
+                exit_with(err, &diagnostics);
             }
         }
+        "compile" => {
+            let bytes = Interpreter::compile_bytecode(&compile_file(filename, options.optimize, &diagnostics));
+            let output = options.output.unwrap_or_else(|| Path::new(filename).with_extension("loxc"));
+            if let Err(err) = fs::write(&output, bytes) {
+                check_written(diagnostics.write_line(&format!("Failed to write file {}: {}", output.display(), err)));
+                process::exit(73);
+            }
+        }
+        "disassemble" => dump_bytecode(filename, options.format, options.optimize, &out, &diagnostics),
         _ => {
-            writeln!(io::stderr(), "Unknown command: {}", command).unwrap();
-            return;
+            check_written(diagnostics.write_line(&format!("Unknown command: {}", command)));
+        }
+    }
+}
+
+// Reads entries from standard input until it ends, `:quit` or a call to `exit`.
+// Prompts are only shown when a person is typing.
+fn repl(options: Options, out: Output, diagnostics: Output) {
+    let interactive = io::stdin().is_terminal();
+    let interpreter_out = out.clone();
+    let mut repl = Repl::new(move || options.interpreter(&interpreter_out), out.clone(), diagnostics);
+    if interactive {
+        check_written(out.write_line("Lox REPL. Type :help for commands."));
+    }
+    let mut line = String::new();
+    let status = loop {
+        if interactive {
+            check_written(out.write(repl.prompt()));
+            check_written(out.flush());
+        }
+        line.clear();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break repl.finish(),
+            Ok(_) => {}
         }
+        if let Some(status) = repl.feed(line.trim_end_matches(['\n', '\r'])) {
+            break Some(status);
+        }
+    };
+    check_written(out.flush());
+    if let Some(status) = status {
+        process::exit(status);
+    }
+}
+
+// Compiled bytecode files are run and listed as they are, without a source file.
+fn is_bytecode(filename: &str) -> bool {
+    Path::new(filename).extension().is_some_and(|extension| extension == "loxc")
+}
+
+// Reads, compiles and, if asked, optimizes the script at `filename`; scripts that
+// can't be read or don't compile end the program with their diagnostics.
+fn compile_file(filename: &str, optimize: bool, diagnostics: &Output) -> Program {
+    let source = fs::read_to_string(filename).unwrap_or_else(|source| {
+        exit_with(Error::Io { path: PathBuf::from(filename), source }, diagnostics);
+    });
+    let program = Interpreter::compile(&source).unwrap_or_else(|err| exit_with(err, diagnostics));
+    if optimize { optimizer::optimize(&program) } else { program }
+}
+
+// Lists the bytecode of the script at `filename`, compiling it first unless it is a
+// bytecode file, which is listed as it was saved.
+fn dump_bytecode(filename: &str, format: ListingFormat, optimize: bool, out: &Output, diagnostics: &Output) {
+    let script = if is_bytecode(filename) {
+        Interpreter::load_bytecode(filename).unwrap_or_else(|err| exit_with(err, diagnostics))
+    } else {
+        compiler::compile(&compile_file(filename, optimize, diagnostics), Symbol::new("script"))
+    };
+    check_written(match format {
+        ListingFormat::Text => disassembler::print(&script, out),
+        ListingFormat::Json => disassembler::print_json(&script, out),
+    });
+}
+
+// Output that can't be written, e.g. to a closed pipe, ends the program quietly.
+fn check_written(result: io::Result<()>) {
+    if result.is_err() {
+        process::exit(74);
+    }
+}
+
+fn print_gc_stats(interp: &Interpreter, diagnostics: &Output) {
+    let stats = interp.gc_stats();
+    let lines = [
+        "GC statistics:".to_string(),
+        format!("  collections:        {}", stats.collections),
+        format!("  objects allocated:  {}", stats.allocated),
+        format!("  freed by refcount:  {}", stats.freed_by_refcount),
+        format!("  freed by collector: {}", stats.freed_by_collector),
+        format!("  live objects:       {}", stats.live),
+        format!("  next collection at: {}", stats.next_collection),
+        format!("  time collecting:    {:.3}ms", stats.time.as_secs_f64() * 1000.0),
+    ];
+    for line in lines {
+        check_written(diagnostics.write_line(&line));
+    }
+}
+
+fn exit_with(err: Error, diagnostics: &Output) -> ! {
+    if !matches!(err, Error::Exit(_)) {
+        check_written(diagnostics.write_line(&err.to_string()));
     }
+    process::exit(err.exit_code());
 }
@@ -0,0 +1,116 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::common::symbol::Symbol;
+use crate::object::object::Value;
+
+// A scope at runtime. The global scope binds variables by name, so code can refer
+// to globals that are defined later. Local scopes are frames of slots, numbered by
+// the resolver; a slot is None until its declaration runs.
+pub struct Environment {
+    store: HashMap<Symbol, Value>,
+    slots: Vec<Option<Value>>,
+    outer: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            store: HashMap::new(),
+            slots: Vec::new(),
+            outer: None,
+        }))
+    }
+
+    // A local scope inside `outer` whose first slots hold `slots`.
+    pub fn new_frame(outer: &Rc<RefCell<Environment>>, slots: Vec<Option<Value>>) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            store: HashMap::new(),
+            slots,
+            outer: Some(Rc::clone(outer)),
+        }))
+    }
+
+    pub fn get(&self, name: &Symbol) -> Option<Value> {
+        match self.store.get(name) {
+            Some(value) => Some(value.clone()),
+            None => match &self.outer {
+                Some(outer) => outer.borrow().get(name),
+                None => None,
+            },
+        }
+    }
+
+    // The value in slot `index` of the frame `depth` scopes out, if its declaration has run.
+    pub fn get_at(&self, depth: usize, index: usize) -> Option<Value> {
+        if depth == 0 {
+            return self.slots.get(index).cloned().flatten();
+        }
+        match &self.outer {
+            Some(outer) => outer.borrow().get_at(depth - 1, index),
+            None => None,
+        }
+    }
+
+    // The frame `depth` scopes out from `env`.
+    pub fn ancestor(env: &Rc<RefCell<Environment>>, depth: usize) -> Rc<RefCell<Environment>> {
+        let mut frame = Rc::clone(env);
+        for _ in 0..depth {
+            let outer = Rc::clone(frame.borrow().outer.as_ref().expect("the resolver counts only existing scopes"));
+            frame = outer;
+        }
+        frame
+    }
+
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        self.store.insert(name, value);
+    }
+
+    // Stores `value` in slot `index` of this frame, declaring it if it wasn't already.
+    pub fn define_at(&mut self, index: usize, value: Value) {
+        if index >= self.slots.len() {
+            self.slots.resize(index + 1, None);
+        }
+        self.slots[index] = Some(value);
+    }
+
+    // Stores `value` in slot `index` of this frame; returns false if the slot's
+    // declaration hasn't run.
+    pub fn assign_at(&mut self, index: usize, value: Value) -> bool {
+        match self.slots.get_mut(index) {
+            Some(slot @ Some(_)) => {
+                *slot = Some(value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.store.values().chain(self.slots.iter().flatten())
+    }
+
+    pub fn outer(&self) -> Option<&Rc<RefCell<Environment>>> {
+        self.outer.as_ref()
+    }
+
+    // Drops every binding and the link to the enclosing scope. The garbage collector
+    // uses this to break reference cycles through unreachable scopes.
+    pub fn clear(&mut self) {
+        self.store.clear();
+        self.slots.clear();
+        self.outer = None;
+    }
+
+    // Returns false if `name` is not defined in this or any enclosing scope.
+    pub fn assign(&mut self, name: &Symbol, value: Value) -> bool {
+        if let Some(slot) = self.store.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+}
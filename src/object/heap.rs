@@ -0,0 +1,505 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+use crate::evaluator::limits::Limits;
+use crate::object::environment::Environment;
+use crate::object::object::{ClassData, ClosureData, FunctionData, HashKey, InstanceData, MapData, Upvalue, Value};
+
+// The cycle collector marks from roots and sweeps what it didn't reach, but it
+// doesn't take the roots from the environment chain and the call stack. The tree
+// walker keeps values in Rust locals while it works: the left operand while the
+// right one is evaluated, arguments before a call, a native's result. The host can
+// also hold values through the API. None of those are in a scope or on the VM's
+// stack, so a collection that only marked from there would clear objects that are
+// still in use. Any reference from outside the tracked heap shows up in an
+// object's reference count, though. So the roots are the objects whose count is
+// higher than the references other tracked objects hold to them, and neither
+// backend has to report them. Reference counting frees everything that isn't part
+// of a cycle, so this only has to find the unreachable cycles.
+
+// A weak reference to a heap object, kept to tell whether it is still alive.
+enum HeapRef {
+    List(Weak<RefCell<Vec<Value>>>),
+    Map(Weak<RefCell<MapData>>),
+    Instance(Weak<RefCell<InstanceData>>),
+    Class(Weak<ClassData>),
+    Function(Weak<FunctionData>),
+    Closure(Weak<ClosureData>),
+    Environment(Weak<RefCell<Environment>>),
+    Upvalue(Weak<RefCell<Upvalue>>),
+}
+
+impl HeapRef {
+    fn upgrade(&self) -> Option<Node> {
+        match self {
+            HeapRef::List(weak) => weak.upgrade().map(Node::List),
+            HeapRef::Map(weak) => weak.upgrade().map(Node::Map),
+            HeapRef::Instance(weak) => weak.upgrade().map(Node::Instance),
+            HeapRef::Class(weak) => weak.upgrade().map(Node::Class),
+            HeapRef::Function(weak) => weak.upgrade().map(Node::Function),
+            HeapRef::Closure(weak) => weak.upgrade().map(Node::Closure),
+            HeapRef::Environment(weak) => weak.upgrade().map(Node::Environment),
+            HeapRef::Upvalue(weak) => weak.upgrade().map(Node::Upvalue),
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        match self {
+            HeapRef::List(weak) => weak.strong_count() > 0,
+            HeapRef::Map(weak) => weak.strong_count() > 0,
+            HeapRef::Instance(weak) => weak.strong_count() > 0,
+            HeapRef::Class(weak) => weak.strong_count() > 0,
+            HeapRef::Function(weak) => weak.strong_count() > 0,
+            HeapRef::Closure(weak) => weak.strong_count() > 0,
+            HeapRef::Environment(weak) => weak.strong_count() > 0,
+            HeapRef::Upvalue(weak) => weak.strong_count() > 0,
+        }
+    }
+
+    // Scopes and captured variables don't count against heap limits.
+    fn is_scope(&self) -> bool {
+        matches!(self, HeapRef::Environment(_) | HeapRef::Upvalue(_))
+    }
+}
+
+// A tracked object held strongly for the duration of a collection.
+enum Node {
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<MapData>>),
+    Instance(Rc<RefCell<InstanceData>>),
+    Class(Rc<ClassData>),
+    Function(Rc<FunctionData>),
+    Closure(Rc<ClosureData>),
+    Environment(Rc<RefCell<Environment>>),
+    Upvalue(Rc<RefCell<Upvalue>>),
+}
+
+impl Node {
+    fn id(&self) -> *const () {
+        match self {
+            Node::List(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Map(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Instance(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Class(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Function(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Closure(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Environment(rc) => Rc::as_ptr(rc) as *const (),
+            Node::Upvalue(rc) => Rc::as_ptr(rc) as *const (),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::List(rc) => Rc::strong_count(rc),
+            Node::Map(rc) => Rc::strong_count(rc),
+            Node::Instance(rc) => Rc::strong_count(rc),
+            Node::Class(rc) => Rc::strong_count(rc),
+            Node::Function(rc) => Rc::strong_count(rc),
+            Node::Closure(rc) => Rc::strong_count(rc),
+            Node::Environment(rc) => Rc::strong_count(rc),
+            Node::Upvalue(rc) => Rc::strong_count(rc),
+        }
+    }
+
+    // Calls `visit` with the id of every object this one holds a strong reference
+    // to. Returns false, without visiting anything, if the object is borrowed.
+    fn for_each_child(&self, visit: &mut impl FnMut(*const ())) -> bool {
+        let mut visit_value = |value: &Value| {
+            if let Some(id) = value_id(value) {
+                visit(id);
+            }
+        };
+        match self {
+            Node::List(rc) => match rc.try_borrow() {
+                Ok(elements) => elements.iter().for_each(visit_value),
+                Err(_) => return false,
+            },
+            Node::Map(rc) => match rc.try_borrow() {
+                Ok(data) => data.pairs.iter().for_each(|pair| {
+                    visit_value(&pair.key);
+                    visit_value(&pair.value);
+                }),
+                Err(_) => return false,
+            },
+            Node::Instance(rc) => match rc.try_borrow() {
+                Ok(data) => data.values().for_each(visit_value),
+                Err(_) => return false,
+            },
+            Node::Class(rc) => rc.methods.values().for_each(visit_value),
+            Node::Function(rc) => visit(Rc::as_ptr(&rc.closure) as *const ()),
+            Node::Closure(rc) => {
+                rc.upvalues.iter().for_each(|upvalue| visit(Rc::as_ptr(upvalue) as *const ()));
+                if let Some(id) = rc.receiver.as_ref().and_then(value_id) {
+                    visit(id);
+                }
+            }
+            Node::Environment(rc) => match rc.try_borrow() {
+                Ok(env) => {
+                    env.values().for_each(visit_value);
+                    if let Some(outer) = env.outer() {
+                        visit(Rc::as_ptr(outer) as *const ());
+                    }
+                }
+                Err(_) => return false,
+            },
+            // An open upvalue refers to a stack slot, which is a root anyway.
+            Node::Upvalue(rc) => match rc.try_borrow() {
+                Ok(upvalue) => {
+                    if let Upvalue::Closed(value) = &*upvalue {
+                        visit_value(value);
+                    }
+                }
+                Err(_) => return false,
+            },
+        }
+        true
+    }
+
+    // Drops the references this object holds, which frees the cycle it is part of.
+    // Functions hold nothing but their closure, which is cleared as an environment;
+    // closures hold their upvalues and the instance they are bound to, which are
+    // cleared on their own; classes hold nothing but their methods.
+    fn clear(&self) {
+        match self {
+            Node::List(rc) => {
+                if let Ok(mut elements) = rc.try_borrow_mut() {
+                    elements.clear();
+                }
+            }
+            Node::Map(rc) => {
+                if let Ok(mut data) = rc.try_borrow_mut() {
+                    data.pairs.clear();
+                    data.index.clear();
+                }
+            }
+            Node::Instance(rc) => {
+                if let Ok(mut data) = rc.try_borrow_mut() {
+                    data.clear();
+                }
+            }
+            Node::Class(_) | Node::Function(_) | Node::Closure(_) => {}
+            Node::Environment(rc) => {
+                if let Ok(mut env) = rc.try_borrow_mut() {
+                    env.clear();
+                }
+            }
+            Node::Upvalue(rc) => {
+                if let Ok(mut upvalue) = rc.try_borrow_mut() {
+                    *upvalue = Upvalue::Closed(Value::Nil);
+                }
+            }
+        }
+    }
+}
+
+fn value_id(value: &Value) -> Option<*const ()> {
+    match value {
+        Value::List(rc) => Some(Rc::as_ptr(rc) as *const ()),
+        Value::Map(rc) => Some(Rc::as_ptr(rc) as *const ()),
+        Value::Instance(rc) => Some(Rc::as_ptr(rc) as *const ()),
+        Value::Class(rc) => Some(Rc::as_ptr(rc) as *const ()),
+        Value::Function(rc) => Some(Rc::as_ptr(rc) as *const ()),
+        Value::Closure(rc) => Some(Rc::as_ptr(rc) as *const ()),
+        _ => None,
+    }
+}
+
+// How many tracked allocations may happen between two heap measurements.
+const CENSUS_INTERVAL: usize = 1024;
+
+// When the collector runs.
+#[derive(Debug, Clone)]
+pub struct GcConfig {
+    // Tracked objects at which the first collection happens.
+    pub initial_threshold: usize,
+    // After a collection, the next one happens once the heap has grown to this
+    // multiple of the objects that survived.
+    pub growth_factor: f64,
+    // Collect on every allocation, to shake out bugs that depend on timing.
+    pub stress: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self { initial_threshold: 1024, growth_factor: 2.0, stress: false }
+    }
+}
+
+// Counters describing the collector's work so far.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GcStats {
+    pub collections: usize,
+    // Objects registered with the heap, including scopes.
+    pub allocated: usize,
+    // Objects freed by reference counting, noticed during a collection.
+    pub freed_by_refcount: usize,
+    // Objects in unreachable cycles that the collector freed.
+    pub freed_by_collector: usize,
+    // Objects alive after the last collection.
+    pub live: usize,
+    // Tracked objects at which the next collection happens.
+    pub next_collection: usize,
+    pub time: Duration,
+}
+
+// What the live heap objects add up to.
+pub struct Census {
+    pub objects: usize,
+    pub bytes: usize,
+}
+
+// Keeps track of the heap objects a program allocates: lists, maps, instances,
+// classes, functions and scopes (or, in the VM, captured variables). Reference counting frees most of them; the collector
+// frees the rest, which keep each other alive in cycles, such as an instance
+// holding a closure that captures the instance.
+pub struct Heap {
+    objects: Vec<HeapRef>,
+    // How many of `objects` are scopes.
+    scopes: usize,
+    allocated_since_census: usize,
+    // Bytes stored into lists, maps and instances since the last census, and
+    // what that census measured. Together they bound the heap's size without
+    // measuring it, so a container that grows without new allocations is still
+    // measured once it could have passed the byte limit.
+    written_since_census: usize,
+    census_bytes: usize,
+    config: GcConfig,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        let config = GcConfig::default();
+        let stats = GcStats { next_collection: config.initial_threshold, ..GcStats::default() };
+        Self { objects: Vec::new(), scopes: 0, allocated_since_census: 0, written_since_census: 0, census_bytes: 0, config, stats }
+    }
+}
+
+impl Heap {
+    pub fn set_config(&mut self, config: GcConfig) {
+        self.stats.next_collection = config.initial_threshold;
+        self.config = config;
+    }
+
+    pub fn stats(&self) -> &GcStats {
+        &self.stats
+    }
+
+    // Registers `value` if it is a list, map, instance, class or function; other values
+    // can't be part of a cycle.
+    pub fn track(&mut self, value: &Value) {
+        let heap_ref = match value {
+            Value::List(elements) => HeapRef::List(Rc::downgrade(elements)),
+            Value::Map(data) => HeapRef::Map(Rc::downgrade(data)),
+            Value::Instance(data) => HeapRef::Instance(Rc::downgrade(data)),
+            Value::Class(data) => HeapRef::Class(Rc::downgrade(data)),
+            Value::Function(data) => HeapRef::Function(Rc::downgrade(data)),
+            Value::Closure(data) => HeapRef::Closure(Rc::downgrade(data)),
+            _ => return,
+        };
+        self.objects.push(heap_ref);
+        self.allocated_since_census += 1;
+        self.stats.allocated += 1;
+    }
+
+    // Scopes are tracked because closures capture them, but they don't count
+    // against heap limits.
+    pub fn track_environment(&mut self, env: &Rc<RefCell<Environment>>) {
+        self.objects.push(HeapRef::Environment(Rc::downgrade(env)));
+        self.scopes += 1;
+        self.stats.allocated += 1;
+    }
+
+    // Variables captured by VM closures are tracked like scopes.
+    pub fn track_upvalue(&mut self, upvalue: &Rc<RefCell<Upvalue>>) {
+        self.objects.push(HeapRef::Upvalue(Rc::downgrade(upvalue)));
+        self.scopes += 1;
+        self.stats.allocated += 1;
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.config.stress || self.objects.len() >= self.stats.next_collection
+    }
+
+    // Frees unreachable cycles and returns how many objects that freed.
+    //
+    // The roots are the objects referenced from outside the tracked heap: the
+    // scopes of the calls in progress, the VM's stack, the globals and anything the
+    // host holds (see the top of this file). Everything reachable from a root is
+    // kept; the rest is only alive because of a cycle.
+    pub fn collect(&mut self) -> usize {
+        let start = Instant::now();
+        let mut nodes = Vec::new();
+        let mut index = HashMap::new();
+        let mut dead = 0;
+        self.objects.retain(|heap_ref| match heap_ref.upgrade() {
+            // An object can be registered twice, e.g. when a native returns one it was given.
+            Some(node) if !index.contains_key(&node.id()) => {
+                index.insert(node.id(), nodes.len());
+                nodes.push(node);
+                true
+            }
+            Some(_) => false,
+            None => {
+                dead += 1;
+                false
+            }
+        });
+
+        // References from outside the heap; the collector's own reference doesn't count.
+        let mut external: Vec<usize> = nodes.iter().map(|node| node.strong_count() - 1).collect();
+        let mut roots = Vec::new();
+        for (i, node) in nodes.iter().enumerate() {
+            let visited = node.for_each_child(&mut |child| {
+                if let Some(&j) = index.get(&child) {
+                    external[j] = external[j].saturating_sub(1);
+                }
+            });
+            // A borrowed object is in use, so it is a root.
+            if !visited {
+                roots.push(i);
+            }
+        }
+        roots.extend((0..nodes.len()).filter(|&i| external[i] > 0));
+
+        let mut marked = vec![false; nodes.len()];
+        while let Some(i) = roots.pop() {
+            if marked[i] {
+                continue;
+            }
+            marked[i] = true;
+            nodes[i].for_each_child(&mut |child| {
+                if let Some(&j) = index.get(&child) {
+                    if !marked[j] {
+                        roots.push(j);
+                    }
+                }
+            });
+        }
+
+        let mut freed = 0;
+        for (node, marked) in nodes.iter().zip(marked.iter()) {
+            if !marked {
+                node.clear();
+                freed += 1;
+            }
+        }
+        drop(nodes);
+        self.prune();
+
+        self.stats.collections += 1;
+        self.stats.freed_by_refcount += dead;
+        self.stats.freed_by_collector += freed;
+        self.stats.live = self.objects.len();
+        let grown = (self.objects.len() as f64 * self.config.growth_factor) as usize;
+        self.stats.next_collection = grown.max(self.config.initial_threshold);
+        self.stats.time += start.elapsed();
+        freed
+    }
+
+    fn prune(&mut self) {
+        self.objects.retain(HeapRef::is_alive);
+        self.scopes = self.objects.iter().filter(|heap_ref| heap_ref.is_scope()).count();
+    }
+
+    // Tracked objects other than scopes, some of which may have been freed since
+    // the last census.
+    pub fn len(&self) -> usize {
+        self.objects.len() - self.scopes
+    }
+
+    // Records `value` being stored into a list, map or instance.
+    pub fn note_write(&mut self, value: &Value) {
+        self.written_since_census += slot_size(value);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn allocated_since_census(&self) -> usize {
+        self.allocated_since_census
+    }
+
+    // Checks the live heap against the heap limits after an allocation or a write
+    // into a container. It is measured every CENSUS_INTERVAL allocations, or sooner
+    // once the tracked count passes the object limit or the bytes written since the
+    // last census could have taken it past the byte limit, so freed objects and
+    // overwritten values don't count against it.
+    pub fn check_limits(&mut self, limits: &Limits) -> Result<(), String> {
+        let (max_objects, max_bytes) = (limits.max_heap_objects, limits.max_heap_bytes);
+        if max_objects.is_none() && max_bytes.is_none() {
+            return Ok(());
+        }
+        let over_count = max_objects.is_some_and(|max| self.len() > max);
+        let over_bytes = max_bytes.is_some_and(|max| self.census_bytes + self.written_since_census > max);
+        if !over_count && !over_bytes && self.allocated_since_census < CENSUS_INTERVAL {
+            return Ok(());
+        }
+        // Unreachable cycles don't count against the limit.
+        if over_count {
+            self.collect();
+        }
+        let census = self.census();
+        if let Some(max) = max_objects {
+            if census.objects > max {
+                return Err(format!("Heap limit of {} objects exceeded.", max));
+            }
+        }
+        if let Some(max) = max_bytes {
+            if census.bytes > max {
+                return Err(format!("Heap limit of {} bytes exceeded.", max));
+            }
+        }
+        Ok(())
+    }
+
+    // Forgets freed objects and measures the rest, leaving out scopes. Sizes are
+    // estimates: each object counts its own storage and the values stored directly in it.
+    pub fn census(&mut self) -> Census {
+        self.allocated_since_census = 0;
+        self.written_since_census = 0;
+        self.prune();
+        let mut objects = 0;
+        let mut bytes = 0;
+        for heap_ref in self.objects.iter() {
+            match heap_ref.upgrade() {
+                Some(Node::List(elements)) => {
+                    bytes += size_of::<Vec<Value>>() + elements.borrow().iter().map(slot_size).sum::<usize>();
+                }
+                Some(Node::Map(data)) => {
+                    let data = data.borrow();
+                    bytes += size_of::<MapData>() + data.pairs.iter()
+                        .map(|pair| slot_size(&pair.key) + slot_size(&pair.value) + size_of::<(HashKey, usize)>())
+                        .sum::<usize>();
+                }
+                Some(Node::Instance(data)) => {
+                    // Field names live in the shape, which instances share.
+                    bytes += size_of::<InstanceData>() + data.borrow().values().map(slot_size).sum::<usize>();
+                }
+                Some(Node::Class(class)) => {
+                    bytes += size_of::<ClassData>() + class.methods.values().map(slot_size).sum::<usize>();
+                }
+                Some(Node::Function(_)) => bytes += size_of::<FunctionData>(),
+                Some(Node::Closure(closure)) => {
+                    bytes += size_of::<ClosureData>() + closure.upvalues.len() * size_of::<Rc<RefCell<Upvalue>>>();
+                }
+                Some(Node::Environment(_) | Node::Upvalue(_)) | None => continue,
+            }
+            objects += 1;
+        }
+        self.census_bytes = bytes;
+        Census { objects, bytes }
+    }
+}
+
+// The size of one value held by a container, including string contents.
+fn slot_size(value: &Value) -> usize {
+    let contents = match value {
+        Value::String(s) => s.len(),
+        _ => 0,
+    };
+    size_of::<Value>() + contents
+}
@@ -1 +1,7 @@
-pub mod object;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+pub mod object;
+pub mod environment;
+pub mod heap;
+pub mod shape;
+#[cfg(feature = "nan-boxing")]
+pub mod nanbox;
@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::mem::{align_of, size_of, ManuallyDrop};
+use std::rc::Rc;
+use crate::object::object::{ClassData, ClosureData, FunctionData, InstanceData, MapData, ModuleData, NativeFunctionData, StringData, Value};
+
+#[cfg(not(target_pointer_width = "64"))]
+compile_error!("the nan-boxing feature needs 64-bit pointers");
+
+// A value packed into the 64 bits of an f64, for the VM's stack.
+//
+// Numbers are stored as themselves, with every NaN stored as the canonical one.
+// Everything else hides in the NaNs that leaves unused: the exponent bits plus the
+// top two mantissa bits all set. The low 48 bits then hold an `Rc` pointer, or
+// zero for nil, false and true, which the bottom two bits tell apart. Since every
+// object is 8-aligned, a pointer's bottom three bits and the sign bit together say
+// what it points at. Host objects, whose `Rc` is a fat pointer, are boxed in an
+// `Rc<Value>` first; they are made by the embedding program, not in a script's
+// inner loops.
+//
+// A NanBox owns one strong reference to its object, exactly as the Value it was
+// made from did, so the collector sees the same counts either way.
+pub struct NanBox(u64);
+
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+const SIGN: u64 = 0x8000_0000_0000_0000;
+// The NaN arithmetic produces, which stays clear of the tagged space.
+const CANONICAL_NAN: u64 = 0x7ff8_0000_0000_0000;
+
+const NIL: u64 = QNAN | 1;
+const FALSE: u64 = QNAN | 2;
+const TRUE: u64 = QNAN | 3;
+
+const POINTER: u64 = 0x0000_ffff_ffff_fff8;
+const KIND: u64 = 0x7;
+
+// Kinds 0 to 7 have the sign bit set; from 8 on, it is clear.
+const LIST: u64 = 0;
+const MAP: u64 = 1;
+const CLASS: u64 = 2;
+const INSTANCE: u64 = 3;
+const FUNCTION: u64 = 4;
+const CLOSURE: u64 = 5;
+const NATIVE_FUNCTION: u64 = 6;
+const STRING: u64 = 7;
+const MODULE: u64 = 8;
+const BOXED: u64 = 9;
+
+const _: () = assert!(size_of::<NanBox>() == 8);
+const _: () = assert!(
+    align_of::<RefCell<Vec<Value>>>() >= 8
+        && align_of::<RefCell<MapData>>() >= 8
+        && align_of::<ClassData>() >= 8
+        && align_of::<RefCell<InstanceData>>() >= 8
+        && align_of::<FunctionData>() >= 8
+        && align_of::<ClosureData>() >= 8
+        && align_of::<NativeFunctionData>() >= 8
+        && align_of::<StringData>() >= 8
+        && align_of::<ModuleData>() >= 8
+        && align_of::<Value>() >= 8
+);
+
+impl NanBox {
+    pub fn number(n: f64) -> Self {
+        if n.is_nan() {
+            NanBox(CANONICAL_NAN)
+        } else {
+            NanBox(n.to_bits())
+        }
+    }
+
+    fn object<T>(pointer: *const T, kind: u64) -> Self {
+        let address = pointer as usize as u64;
+        assert!(address & !POINTER == 0, "object pointers fit in 48 bits");
+        let sign = if kind < 8 { SIGN } else { 0 };
+        NanBox(sign | QNAN | address | kind & KIND)
+    }
+
+    fn kind(&self) -> u64 {
+        let high = if self.0 & SIGN == 0 { 8 } else { 0 };
+        high | self.0 & KIND
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        if self.0 & QNAN != QNAN {
+            Some(f64::from_bits(self.0))
+        } else {
+            None
+        }
+    }
+
+    pub fn is_nil(&self) -> bool {
+        self.0 == NIL
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        self.0 != NIL && self.0 != FALSE
+    }
+
+    fn is_object(&self) -> bool {
+        self.0 & QNAN == QNAN && self.0 & POINTER != 0
+    }
+
+    fn immediate(&self) -> Value {
+        match self.0 {
+            NIL => Value::Nil,
+            FALSE => Value::Boolean(false),
+            TRUE => Value::Boolean(true),
+            bits => Value::Number(f64::from_bits(bits)),
+        }
+    }
+
+    // Adds a strong reference to the object. Only for object boxes.
+    unsafe fn increment(&self) {
+        let pointer = (self.0 & POINTER) as usize;
+        match self.kind() {
+            LIST => Rc::increment_strong_count(pointer as *const RefCell<Vec<Value>>),
+            MAP => Rc::increment_strong_count(pointer as *const RefCell<MapData>),
+            CLASS => Rc::increment_strong_count(pointer as *const ClassData),
+            INSTANCE => Rc::increment_strong_count(pointer as *const RefCell<InstanceData>),
+            FUNCTION => Rc::increment_strong_count(pointer as *const FunctionData),
+            CLOSURE => Rc::increment_strong_count(pointer as *const ClosureData),
+            NATIVE_FUNCTION => Rc::increment_strong_count(pointer as *const NativeFunctionData),
+            STRING => Rc::increment_strong_count(pointer as *const StringData),
+            MODULE => Rc::increment_strong_count(pointer as *const ModuleData),
+            _ => Rc::increment_strong_count(pointer as *const Value),
+        }
+    }
+
+    // The object as a Value, consuming one of the strong references the box
+    // accounts for. Only for object boxes.
+    unsafe fn take(&self) -> Value {
+        let pointer = (self.0 & POINTER) as usize;
+        match self.kind() {
+            LIST => Value::List(Rc::from_raw(pointer as *const RefCell<Vec<Value>>)),
+            MAP => Value::Map(Rc::from_raw(pointer as *const RefCell<MapData>)),
+            CLASS => Value::Class(Rc::from_raw(pointer as *const ClassData)),
+            INSTANCE => Value::Instance(Rc::from_raw(pointer as *const RefCell<InstanceData>)),
+            FUNCTION => Value::Function(Rc::from_raw(pointer as *const FunctionData)),
+            CLOSURE => Value::Closure(Rc::from_raw(pointer as *const ClosureData)),
+            NATIVE_FUNCTION => Value::NativeFunction(Rc::from_raw(pointer as *const NativeFunctionData)),
+            STRING => Value::String(Rc::from_raw(pointer as *const StringData)),
+            MODULE => Value::Module(Rc::from_raw(pointer as *const ModuleData)),
+            _ => Rc::unwrap_or_clone(Rc::from_raw(pointer as *const Value)),
+        }
+    }
+}
+
+impl From<Value> for NanBox {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Nil => NanBox(NIL),
+            Value::Boolean(false) => NanBox(FALSE),
+            Value::Boolean(true) => NanBox(TRUE),
+            Value::Number(n) => NanBox::number(n),
+            Value::List(list) => NanBox::object(Rc::into_raw(list), LIST),
+            Value::Map(map) => NanBox::object(Rc::into_raw(map), MAP),
+            Value::Class(class) => NanBox::object(Rc::into_raw(class), CLASS),
+            Value::Instance(instance) => NanBox::object(Rc::into_raw(instance), INSTANCE),
+            Value::Function(function) => NanBox::object(Rc::into_raw(function), FUNCTION),
+            Value::Closure(closure) => NanBox::object(Rc::into_raw(closure), CLOSURE),
+            Value::NativeFunction(native) => NanBox::object(Rc::into_raw(native), NATIVE_FUNCTION),
+            Value::String(string) => NanBox::object(Rc::into_raw(string), STRING),
+            Value::Module(module) => NanBox::object(Rc::into_raw(module), MODULE),
+            value @ Value::Host(_) => NanBox::object(Rc::into_raw(Rc::new(value)), BOXED),
+        }
+    }
+}
+
+impl From<&NanBox> for Value {
+    fn from(boxed: &NanBox) -> Self {
+        if !boxed.is_object() {
+            return boxed.immediate();
+        }
+        // SAFETY: the extra reference is the one `take` hands to the result.
+        unsafe {
+            boxed.increment();
+            boxed.take()
+        }
+    }
+}
+
+impl From<NanBox> for Value {
+    fn from(boxed: NanBox) -> Self {
+        let boxed = ManuallyDrop::new(boxed);
+        if !boxed.is_object() {
+            return boxed.immediate();
+        }
+        // SAFETY: the box is never dropped, so its reference moves to the result.
+        unsafe { boxed.take() }
+    }
+}
+
+impl Clone for NanBox {
+    fn clone(&self) -> Self {
+        if self.is_object() {
+            // SAFETY: the new box accounts for the new reference.
+            unsafe { self.increment() };
+        }
+        NanBox(self.0)
+    }
+}
+
+impl Drop for NanBox {
+    fn drop(&mut self) {
+        if self.is_object() {
+            // SAFETY: releases the reference this box accounted for.
+            drop(unsafe { self.take() });
+        }
+    }
+}
+
+impl fmt::Debug for NanBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NanBox({})", Value::from(self).inspect())
+    }
+}
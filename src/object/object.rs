@@ -1,62 +1,351 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use crate::ast::ast::BlockStatement;
+use crate::common::symbol::Symbol;
+use crate::evaluator::error::NativeError;
+use crate::interpreter::host::HostObject;
+use crate::lexer::lexer::Token;
+use crate::object::environment::Environment;
+use crate::object::shape::Shape;
+use crate::vm::chunk::FunctionProto;
+
 pub enum ObjectType {
     BooleanObj,
     StringLiteralObj,
     NumberLiteralObj,
     NilObj,
+    ListObj,
+    MapObj,
+    ClassObj,
+    InstanceObj,
+    FunctionObj,
+    ModuleObj,
+    NativeFunctionObj,
+    HostObj,
 }
 
-pub trait Object {
-    fn obj_type(&self) -> ObjectType;
-    fn inspect(&self) -> String;
+impl ObjectType {
+    // The type name scripts see in error messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ObjectType::BooleanObj => "boolean",
+            ObjectType::StringLiteralObj => "string",
+            ObjectType::NumberLiteralObj => "number",
+            ObjectType::NilObj => "nil",
+            ObjectType::ListObj => "list",
+            ObjectType::MapObj => "map",
+            ObjectType::ClassObj => "class",
+            ObjectType::InstanceObj | ObjectType::HostObj => "instance",
+            ObjectType::FunctionObj | ObjectType::NativeFunctionObj => "function",
+            ObjectType::ModuleObj => "module",
+        }
+    }
 }
 
-pub struct Boolean {
-    pub value: bool,
+// A string's text. Boxing it makes `Rc<StringData>` a thin pointer, which a
+// NaN-boxed stack slot can hold as it is.
+pub type StringData = Box<str>;
+
+// A Lox value. Nil, booleans and numbers are stored inline; everything else is a
+// reference-counted pointer, so cloning a value never copies the object behind it.
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Rc<StringData>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<MapData>>),
+    Class(Rc<ClassData>),
+    Instance(Rc<RefCell<InstanceData>>),
+    Function(Rc<FunctionData>),
+    // A function compiled for the bytecode VM.
+    Closure(Rc<ClosureData>),
+    Module(Rc<ModuleData>),
+    NativeFunction(Rc<NativeFunctionData>),
+    Host(Rc<RefCell<dyn HostObject>>),
+}
+
+impl Value {
+    pub fn string(value: &str) -> Value {
+        Value::String(Rc::new(Box::from(value)))
+    }
+
+    pub fn list(elements: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn obj_type(&self) -> ObjectType {
+        match self {
+            Value::Nil => ObjectType::NilObj,
+            Value::Boolean(_) => ObjectType::BooleanObj,
+            Value::Number(_) => ObjectType::NumberLiteralObj,
+            Value::String(_) => ObjectType::StringLiteralObj,
+            Value::List(_) => ObjectType::ListObj,
+            Value::Map(_) => ObjectType::MapObj,
+            Value::Class(_) => ObjectType::ClassObj,
+            Value::Instance(_) => ObjectType::InstanceObj,
+            Value::Function(_) | Value::Closure(_) => ObjectType::FunctionObj,
+            Value::Module(_) => ObjectType::ModuleObj,
+            Value::NativeFunction(_) => ObjectType::NativeFunctionObj,
+            Value::Host(_) => ObjectType::HostObj,
+        }
+    }
+
+    // What `print` shows.
+    pub fn inspect(&self) -> String {
+        self.inspect_within(&mut Vec::new())
+    }
+
+    // `enclosing` holds the lists and maps being printed around this value. One
+    // that contains itself prints as `[...]` or `{...}` where it recurs.
+    fn inspect_within(&self, enclosing: &mut Vec<*const ()>) -> String {
+        match self {
+            Value::Nil => "nil".to_string(),
+            Value::Boolean(value) => value.to_string(),
+            Value::Number(value) => value.to_string(),
+            Value::String(value) => value.to_string(),
+            Value::List(elements) => {
+                let pointer = Rc::as_ptr(elements) as *const ();
+                if enclosing.contains(&pointer) {
+                    return "[...]".to_string();
+                }
+                enclosing.push(pointer);
+                let elements: Vec<String> = elements.borrow().iter().map(|e| e.inspect_within(enclosing)).collect();
+                enclosing.pop();
+                format!("[{}]", elements.join(", "))
+            }
+            Value::Map(data) => {
+                let pointer = Rc::as_ptr(data) as *const ();
+                if enclosing.contains(&pointer) {
+                    return "{...}".to_string();
+                }
+                enclosing.push(pointer);
+                let pairs: Vec<String> = data.borrow().pairs.iter()
+                    .map(|pair| format!("{}: {}", pair.key.inspect_within(enclosing), pair.value.inspect_within(enclosing)))
+                    .collect();
+                enclosing.pop();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            Value::Class(class) => class.name.to_string(),
+            Value::Instance(instance) => format!("{} instance", instance.borrow().class.name),
+            Value::Function(function) => format!("<fn {}>", function.name),
+            Value::Closure(closure) => format!("<fn {}>", closure.function.name),
+            Value::Module(module) => format!("<module {}>", module.name),
+            Value::NativeFunction(_) => "<native fn>".to_string(),
+            // The object is already borrowed if one of its own methods prints it.
+            Value::Host(host) => match host.try_borrow() {
+                Ok(host) => host.inspect(),
+                Err(_) => "<host object>".to_string(),
+            },
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    // The key this value is stored under in a map; None if it can't be a map key.
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Value::Nil => Some(HashKey::Nil),
+            Value::Boolean(b) => Some(HashKey::Boolean(*b)),
+            // -0 and 0 compare equal, so they must hash the same.
+            Value::Number(n) => Some(HashKey::Number(if *n == 0.0 { 0.0f64 } else { *n }.to_bits())),
+            Value::String(s) => Some(HashKey::String(Rc::clone(s))),
+            _ => None,
+        }
+    }
 }
 
-impl Object for Boolean {
-    fn obj_type(&self) -> ObjectType {
-        ObjectType::BooleanObj
+// Nil, booleans, numbers and strings compare by value; everything else by identity.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Number(l), Value::Number(r)) => l == r,
+            // Strings from the same literal share an allocation.
+            (Value::String(l), Value::String(r)) => Rc::ptr_eq(l, r) || l == r,
+            (Value::List(l), Value::List(r)) => Rc::ptr_eq(l, r),
+            (Value::Map(l), Value::Map(r)) => Rc::ptr_eq(l, r),
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            (Value::Closure(l), Value::Closure(r)) => Rc::ptr_eq(l, r),
+            (Value::Module(l), Value::Module(r)) => Rc::ptr_eq(l, r),
+            (Value::NativeFunction(l), Value::NativeFunction(r)) => Rc::ptr_eq(l, r),
+            (Value::Host(l), Value::Host(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
     }
-    fn inspect(&self) -> String {
-        self.value.to_string()
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.inspect())
     }
 }
 
-pub struct StringLiteral {
-    pub value: String,
+// Only nil, booleans, numbers and strings can be used as map keys.
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub enum HashKey {
+    Nil,
+    Boolean(bool),
+    Number(u64),
+    String(Rc<StringData>),
+}
+
+pub struct HashPair {
+    pub key: Value,
+    pub value: Value,
+}
+
+// Pairs are kept in insertion order so that printing a map is deterministic.
+#[derive(Default)]
+pub struct MapData {
+    pub pairs: Vec<HashPair>,
+    pub index: HashMap<HashKey, usize>,
 }
 
-impl Object for StringLiteral {
-    fn obj_type(&self) -> ObjectType {
-        ObjectType::StringLiteralObj
+impl MapData {
+    pub fn get(&self, key: &HashKey) -> Option<&HashPair> {
+        self.index.get(key).map(|&i| &self.pairs[i])
     }
-    fn inspect(&self) -> String {
-        self.value.to_string()
+
+    pub fn insert(&mut self, key: HashKey, pair: HashPair) {
+        match self.index.get(&key) {
+            Some(&i) => self.pairs[i] = pair,
+            None => {
+                self.index.insert(key, self.pairs.len());
+                self.pairs.push(pair);
+            }
+        }
     }
 }
 
-pub struct NumberLiteral {
-    pub value: f64,
-    pub literal: String,
+pub struct ClassData {
+    pub name: Symbol,
+    // The shape of the class's instances before they have any fields.
+    pub shape: Rc<Shape>,
+    // Functions in the tree walker, closures in the VM. An instance's fields
+    // shadow its methods.
+    pub methods: HashMap<Symbol, Value>,
 }
 
-impl Object for NumberLiteral {
-    fn obj_type(&self) -> ObjectType {
-        ObjectType::NumberLiteralObj
+impl ClassData {
+    pub fn new(name: Symbol) -> Self {
+        Self::with_methods(name, HashMap::new())
     }
-    fn inspect(&self) -> String {
-        self.literal.to_string()
+
+    pub fn with_methods(name: Symbol, methods: HashMap<Symbol, Value>) -> Self {
+        Self { name, shape: Shape::root(), methods }
+    }
+
+    pub fn method(&self, name: &Symbol) -> Option<&Value> {
+        self.methods.get(name)
     }
 }
 
-pub struct Nil {}
+// An instance keeps its field values in the slots its shape assigns them.
+pub struct InstanceData {
+    pub class: Rc<ClassData>,
+    shape: Rc<Shape>,
+    fields: Vec<Value>,
+}
+
+impl InstanceData {
+    pub fn new(class: Rc<ClassData>) -> Self {
+        let shape = Rc::clone(&class.shape);
+        Self { class, shape, fields: Vec::new() }
+    }
+
+    pub fn shape(&self) -> &Rc<Shape> {
+        &self.shape
+    }
+
+    pub fn get(&self, name: &Symbol) -> Option<&Value> {
+        self.shape.slot(name).map(|slot| &self.fields[slot])
+    }
+
+    pub fn has(&self, name: &Symbol) -> bool {
+        self.shape.slot(name).is_some()
+    }
+
+    // Sets the field `name`, adding it if the instance doesn't have it yet.
+    pub fn set(&mut self, name: Symbol, value: Value) {
+        match self.shape.slot(&name) {
+            Some(slot) => self.fields[slot] = value,
+            None => {
+                self.shape = self.shape.with_field(name);
+                self.fields.push(value);
+            }
+        }
+    }
+
+    // The field in `slot` of the instance's shape.
+    pub fn field(&self, slot: usize) -> &Value {
+        &self.fields[slot]
+    }
+
+    pub fn set_field(&mut self, slot: usize, value: Value) {
+        self.fields[slot] = value;
+    }
 
-impl Object for Nil {
-    fn obj_type(&self) -> ObjectType {
-        ObjectType::NilObj
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.fields.iter()
     }
-    fn inspect(&self) -> String {
-        "nil".to_string()
+
+    // Drops every field. The garbage collector uses this to break reference cycles.
+    pub fn clear(&mut self) {
+        self.shape = Rc::clone(&self.class.shape);
+        self.fields.clear();
     }
-}
\ No newline at end of file
+}
+
+pub struct FunctionData {
+    pub name: Symbol,
+    pub params: Rc<Vec<Token>>,
+    pub body: Rc<BlockStatement>,
+    pub closure: Rc<RefCell<Environment>>,
+    // An `init` method, which returns the instance it initialized.
+    pub initializer: bool,
+}
+
+// A function value in the VM: compiled code plus the variables it captured from
+// enclosing functions. Top-level names resolve in `globals`, the global scope of
+// the script or module that defined it. A method read from an instance is bound
+// to it: the instance is its `receiver`, passed in the slot before the arguments.
+pub struct ClosureData {
+    pub function: Rc<FunctionProto>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    pub globals: Rc<RefCell<Environment>>,
+    pub receiver: Option<Value>,
+}
+
+// A captured variable. It stays on the VM stack, shared by every closure that
+// captured it, until the scope that declared it ends; then it moves in here.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+// A loaded module: its own global scope plus the names of its top-level declarations.
+pub struct ModuleData {
+    pub name: String,
+    pub globals: Rc<RefCell<Environment>>,
+    pub exports: Vec<Symbol>,
+}
+
+pub type NativeFn = dyn Fn(Vec<Value>) -> Result<Value, NativeError>;
+
+// A function implemented in Rust by the host.
+pub struct NativeFunctionData {
+    pub name: Symbol,
+    pub arity: usize,
+    // Accepts `arity` or more arguments.
+    pub variadic: bool,
+    pub function: Box<NativeFn>,
+}
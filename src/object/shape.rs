@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::common::symbol::Symbol;
+use crate::object::object::{FunctionData, InstanceData, Value};
+
+static NEXT_SHAPE_ID: AtomicU64 = AtomicU64::new(0);
+
+// The layout of an instance's fields: the slot that holds each one. Instances of a
+// class that gain the same fields in the same order share a shape, so a property
+// expression can remember where it found its field and skip the lookup the next
+// time it sees an instance of that shape.
+//
+// Shapes never change. An instance that gains a field moves to the shape that
+// extends its current one with that field, which is created on first use and
+// shared from then on. Each class starts its instances at a root shape of its own.
+pub struct Shape {
+    // Unique for the life of the process, unlike an address, so a cache can't
+    // mistake a new shape for a freed one.
+    id: u64,
+    slots: HashMap<Symbol, usize>,
+    transitions: RefCell<HashMap<Symbol, Rc<Shape>>>,
+}
+
+impl Shape {
+    pub fn root() -> Rc<Shape> {
+        Rc::new(Self::with_slots(HashMap::new()))
+    }
+
+    fn with_slots(slots: HashMap<Symbol, usize>) -> Self {
+        Self {
+            id: NEXT_SHAPE_ID.fetch_add(1, Ordering::Relaxed),
+            slots,
+            transitions: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn slot(&self, name: &Symbol) -> Option<usize> {
+        self.slots.get(name).copied()
+    }
+
+    // The shape of an instance of this shape once it gains the field `name`, which
+    // goes in the next slot.
+    pub fn with_field(&self, name: Symbol) -> Rc<Shape> {
+        let mut transitions = self.transitions.borrow_mut();
+        let next = transitions.entry(name.clone()).or_insert_with(|| {
+            let mut slots = self.slots.clone();
+            slots.insert(name, self.slots.len());
+            Rc::new(Self::with_slots(slots))
+        });
+        Rc::clone(next)
+    }
+}
+
+// How many shapes a property expression remembers. Most expressions only ever
+// see one; a few see instances built in different orders.
+const CACHE_ENTRIES: usize = 4;
+
+// What a property expression found on an instance of a given shape.
+pub enum Lookup {
+    // The instance's field in this slot.
+    Field(usize),
+    // A method of the instance's class, which has no field of that name.
+    Method(Rc<FunctionData>),
+}
+
+enum Entry {
+    Field(usize),
+    // Weak so the cache doesn't keep a dropped class's methods alive. An instance
+    // of the shape holds its class, so on a hit the method is still there.
+    Method(Weak<FunctionData>),
+}
+
+// An inline cache for one property expression: the shapes of the instances it
+// last looked up its name on, most recent first, with what it found on each.
+//
+// Shapes never change and each class has its own root shape, so a shape settles
+// both which slot holds a field and which class the instance belongs to. Classes
+// can't be changed once declared, so the only thing that can make an entry wrong
+// is the instance gaining a field, and that moves it to another shape: setting a
+// field named after a method invalidates the method's entries for that instance
+// without the cache having to be told.
+#[derive(Default)]
+pub struct PropertyCache(RefCell<[Option<(u64, Entry)>; CACHE_ENTRIES]>);
+
+impl PropertyCache {
+    // The field or method `name` of `instance`, if it has one.
+    pub fn lookup(&self, instance: &InstanceData, name: &Symbol) -> Option<Lookup> {
+        let shape = instance.shape();
+        let mut entries = self.0.borrow_mut();
+        for entry in entries.iter() {
+            match entry {
+                Some((id, Entry::Field(slot))) if *id == shape.id() => return Some(Lookup::Field(*slot)),
+                Some((id, Entry::Method(method))) if *id == shape.id() => {
+                    if let Some(method) = method.upgrade() {
+                        return Some(Lookup::Method(method));
+                    }
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        let (entry, lookup) = match shape.slot(name) {
+            Some(slot) => (Entry::Field(slot), Lookup::Field(slot)),
+            None => match instance.class.method(name)? {
+                Value::Function(method) => (Entry::Method(Rc::downgrade(method)), Lookup::Method(Rc::clone(method))),
+                _ => return None,
+            },
+        };
+        // Evicts the least recently added shape.
+        entries.rotate_right(1);
+        entries[0] = Some((shape.id(), entry));
+        Some(lookup)
+    }
+
+    // The slot of the field `name` in `instance`, if it has one.
+    pub fn slot(&self, instance: &InstanceData, name: &Symbol) -> Option<usize> {
+        match self.lookup(instance, name)? {
+            Lookup::Field(slot) => Some(slot),
+            Lookup::Method(_) => None,
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use crate::ast::ast::{AssignExpression, BindingPattern, BlockStatement, Boolean, BreakStatement, CallExpression, CatchClause, ClassStatement, ContinueStatement, Expression, ExpressionStatement, ForStatement, FunctionStatement, GetExpression, Grouping, Identifier, IfStatement, ImportName, ImportStatement, IndexExpression, InfixExpression, InstancePattern, ListLiteral, ListPattern, LiteralPattern, MapLiteral, MapPattern, MatchArm, MatchExpression, MatchStatement, Nil, NumberLiteral, Pattern, PrefixExpression, PrintStatement, Program, RangePattern, Resolution, RestPattern, ReturnStatement, Statement, StringLiteral, TernaryExpression, ThrowStatement, TryStatement, UpdateExpression, VarStatement, WhileStatement, WildcardPattern};
+use crate::evaluator::operations::{apply_infix_operator, negate};
+use crate::lexer::lexer::TokenType;
+use crate::object::object::Value;
+use crate::object::shape::PropertyCache;
+use crate::resolver::resolver::Resolver;
+
+// An optional pass between resolving and running a program. It folds operators
+// whose operands are literals and drops code that can never run: untaken `if`
+// branches, `while (false)` loops and statements after a return, throw, break or
+// continue.
+//
+// Folding goes through the same operations the backends use, so results follow
+// their float semantics exactly. Operations that would fail at runtime, like
+// `-"a"`, are left in place to fail there. Run it after the resolver: it may drop
+// code the resolver would report errors in.
+//
+// AST nodes can't be moved out of their boxes, so the pass builds a new tree.
+pub fn optimize(program: &Program) -> Program {
+    let mut statements = Vec::new();
+    // The program's value is that of its last statement, so a last statement that
+    // is dropped is replaced by one whose value is nil.
+    let mut last_dropped = false;
+    for stmt in program.statements.iter() {
+        let optimized = statement(stmt.as_ref());
+        last_dropped = optimized.is_none();
+        statements.extend(optimized);
+        if ends_with_jump(&statements) {
+            break;
+        }
+    }
+    if last_dropped {
+        statements.push(empty_block());
+    }
+    // The new tree's variables need their slots assigned again. Removing code can't
+    // introduce resolver errors, so there are none to report.
+    let program = Program { statements };
+    Resolver::new().resolve_program(&program);
+    program
+}
+
+fn statement_list(statements: &[Box<dyn Statement>]) -> Vec<Box<dyn Statement>> {
+    let mut optimized = Vec::new();
+    for stmt in statements.iter() {
+        if let Some(stmt) = statement(stmt.as_ref()) {
+            optimized.push(stmt);
+        }
+        if ends_with_jump(&optimized) {
+            break;
+        }
+    }
+    optimized
+}
+
+// Whether the last statement always leaves the enclosing block.
+fn ends_with_jump(statements: &[Box<dyn Statement>]) -> bool {
+    statements.last().is_some_and(|stmt| {
+        let any = stmt.as_any();
+        any.is::<ReturnStatement>() || any.is::<ThrowStatement>() || any.is::<BreakStatement>() || any.is::<ContinueStatement>()
+    })
+}
+
+fn empty_block() -> Box<dyn Statement> {
+    Box::new(BlockStatement { statements: Vec::new() })
+}
+
+fn block(b: &BlockStatement) -> BlockStatement {
+    BlockStatement { statements: statement_list(&b.statements) }
+}
+
+fn function(f: &FunctionStatement) -> FunctionStatement {
+    FunctionStatement { name: f.name.clone(), params: Rc::clone(&f.params), body: Rc::new(block(&f.body)), slot: Cell::new(None) }
+}
+
+// A statement where exactly one is required, like the body of a loop.
+fn required_statement(stmt: &dyn Statement) -> Box<dyn Statement> {
+    statement(stmt).unwrap_or_else(empty_block)
+}
+
+// The optimized statement, or None when it does nothing.
+fn statement(stmt: &dyn Statement) -> Option<Box<dyn Statement>> {
+    let any = stmt.as_any();
+    if let Some(e) = any.downcast_ref::<ExpressionStatement>() {
+        return Some(Box::new(ExpressionStatement { expression: expression(e.expression.as_ref()) }));
+    }
+    if let Some(p) = any.downcast_ref::<PrintStatement>() {
+        return Some(Box::new(PrintStatement { expression: expression(p.expression.as_ref()) }));
+    }
+    if let Some(v) = any.downcast_ref::<VarStatement>() {
+        let initializer = v.initializer.as_ref().map(|initializer| expression(initializer.as_ref()));
+        return Some(Box::new(VarStatement { name: v.name.clone(), initializer, slot: Cell::new(None) }));
+    }
+    if let Some(b) = any.downcast_ref::<BlockStatement>() {
+        return Some(Box::new(block(b)));
+    }
+    if let Some(i) = any.downcast_ref::<IfStatement>() {
+        let condition = expression(i.condition.as_ref());
+        // The branch taken runs in the enclosing scope either way.
+        return match literal_value(condition.as_ref()) {
+            Some(value) if value.is_truthy() => statement(i.consequence.as_ref()),
+            Some(_) => i.alternative.as_ref().and_then(|alternative| statement(alternative.as_ref())),
+            None => Some(Box::new(IfStatement {
+                condition,
+                consequence: required_statement(i.consequence.as_ref()),
+                alternative: i.alternative.as_ref().and_then(|alternative| statement(alternative.as_ref())),
+            })),
+        };
+    }
+    if let Some(w) = any.downcast_ref::<WhileStatement>() {
+        let condition = expression(w.condition.as_ref());
+        if literal_value(condition.as_ref()).is_some_and(|value| !value.is_truthy()) {
+            return None;
+        }
+        return Some(Box::new(WhileStatement { label: w.label.clone(), condition, body: required_statement(w.body.as_ref()) }));
+    }
+    if let Some(f) = any.downcast_ref::<ForStatement>() {
+        let initializer = f.initializer.as_ref().and_then(|initializer| statement(initializer.as_ref()));
+        let condition = f.condition.as_ref().map(|condition| expression(condition.as_ref()));
+        if condition.as_ref().is_some_and(|condition| literal_value(condition.as_ref()).is_some_and(|value| !value.is_truthy())) {
+            // Only the initializer runs, still in a scope of its own.
+            return initializer.map(|initializer| Box::new(BlockStatement { statements: vec![initializer] }) as Box<dyn Statement>);
+        }
+        return Some(Box::new(ForStatement {
+            label: f.label.clone(),
+            initializer,
+            condition,
+            increment: f.increment.as_ref().map(|increment| expression(increment.as_ref())),
+            body: required_statement(f.body.as_ref()),
+        }));
+    }
+    if let Some(b) = any.downcast_ref::<BreakStatement>() {
+        return Some(Box::new(BreakStatement { token: b.token.clone(), label: b.label.clone() }));
+    }
+    if let Some(c) = any.downcast_ref::<ContinueStatement>() {
+        return Some(Box::new(ContinueStatement { token: c.token.clone(), label: c.label.clone() }));
+    }
+    if let Some(c) = any.downcast_ref::<ClassStatement>() {
+        let methods = c.methods.iter().map(function).collect();
+        return Some(Box::new(ClassStatement { name: c.name.clone(), methods, slot: Cell::new(None) }));
+    }
+    if let Some(m) = any.downcast_ref::<MatchStatement>() {
+        let arms = m.arms.iter()
+            .map(|arm| MatchArm {
+                pattern: pattern(arm.pattern.as_ref()),
+                guard: arm.guard.as_ref().map(|guard| expression(guard.as_ref())),
+                body: required_statement(arm.body.as_ref()),
+            })
+            .collect();
+        return Some(Box::new(MatchStatement { token: m.token.clone(), subject: expression(m.subject.as_ref()), arms }));
+    }
+    if let Some(f) = any.downcast_ref::<FunctionStatement>() {
+        return Some(Box::new(function(f)));
+    }
+    if let Some(r) = any.downcast_ref::<ReturnStatement>() {
+        let value = r.value.as_ref().map(|value| expression(value.as_ref()));
+        return Some(Box::new(ReturnStatement { token: r.token.clone(), value, tail_call: Cell::new(false) }));
+    }
+    if let Some(t) = any.downcast_ref::<ThrowStatement>() {
+        return Some(Box::new(ThrowStatement { token: t.token.clone(), value: expression(t.value.as_ref()) }));
+    }
+    if let Some(t) = any.downcast_ref::<TryStatement>() {
+        return Some(Box::new(TryStatement {
+            body: block(&t.body),
+            catch_clause: t.catch_clause.as_ref().map(|c| CatchClause { name: c.name.clone(), body: block(&c.body) }),
+            finally_body: t.finally_body.as_ref().map(block),
+        }));
+    }
+    if let Some(i) = any.downcast_ref::<ImportStatement>() {
+        let names = i.names.iter().map(|n| ImportName { name: n.name.clone(), alias: n.alias.clone() }).collect();
+        return Some(Box::new(ImportStatement { token: i.token.clone(), path: i.path.clone(), alias: i.alias.clone(), names }));
+    }
+    unreachable!("unknown statement {}", stmt.string())
+}
+
+fn pattern(p: &dyn Pattern) -> Box<dyn Pattern> {
+    let any = p.as_any();
+    if let Some(l) = any.downcast_ref::<LiteralPattern>() {
+        return Box::new(LiteralPattern { value: expression(l.value.as_ref()) });
+    }
+    if let Some(r) = any.downcast_ref::<RangePattern>() {
+        return Box::new(RangePattern { start: r.start, end: r.end, inclusive: r.inclusive });
+    }
+    if any.is::<WildcardPattern>() {
+        return Box::new(WildcardPattern {});
+    }
+    if let Some(b) = any.downcast_ref::<BindingPattern>() {
+        return Box::new(BindingPattern { name: b.name.clone() });
+    }
+    if let Some(l) = any.downcast_ref::<ListPattern>() {
+        let rest = match &l.rest {
+            RestPattern::None => RestPattern::None,
+            RestPattern::Ignore => RestPattern::Ignore,
+            RestPattern::Bind(name) => RestPattern::Bind(name.clone()),
+        };
+        return Box::new(ListPattern { elements: l.elements.iter().map(|e| pattern(e.as_ref())).collect(), rest });
+    }
+    if let Some(m) = any.downcast_ref::<MapPattern>() {
+        let entries = m.entries.iter().map(|(key, p)| (expression(key.as_ref()), pattern(p.as_ref()))).collect();
+        return Box::new(MapPattern { entries });
+    }
+    if let Some(i) = any.downcast_ref::<InstancePattern>() {
+        let fields = i.fields.iter().map(|(name, p)| (name.clone(), pattern(p.as_ref()))).collect();
+        return Box::new(InstancePattern { class_name: i.class_name.clone(), class_resolution: Cell::new(Resolution::Global), fields });
+    }
+    unreachable!("unknown pattern {}", p.string())
+}
+
+// The value of a literal, which is what folding reduces expressions to.
+fn literal_value(expr: &dyn Expression) -> Option<Value> {
+    let any = expr.as_any();
+    if let Some(b) = any.downcast_ref::<Boolean>() {
+        return Some(Value::Boolean(b.value));
+    }
+    if let Some(n) = any.downcast_ref::<NumberLiteral>() {
+        return Some(Value::Number(n.value));
+    }
+    if let Some(s) = any.downcast_ref::<StringLiteral>() {
+        return Some(Value::String(Rc::clone(&s.value)));
+    }
+    if any.is::<Nil>() {
+        return Some(Value::Nil);
+    }
+    None
+}
+
+fn literal(value: Value) -> Option<Box<dyn Expression>> {
+    match value {
+        Value::Boolean(value) => Some(Box::new(Boolean { value })),
+        // Written the way the lexer writes number literals.
+        Value::Number(value) => Some(Box::new(NumberLiteral { value, literal: format!("{:?}", value) })),
+        Value::String(value) => Some(Box::new(StringLiteral { value })),
+        Value::Nil => Some(Box::new(Nil {})),
+        _ => None,
+    }
+}
+
+fn expression(expr: &dyn Expression) -> Box<dyn Expression> {
+    let any = expr.as_any();
+    if let Some(n) = any.downcast_ref::<NumberLiteral>() {
+        return Box::new(NumberLiteral { value: n.value, literal: n.literal.clone() });
+    }
+    if let Some(value) = literal_value(expr) {
+        return literal(value).expect("literals fold to literals");
+    }
+    if let Some(g) = any.downcast_ref::<Grouping>() {
+        let inner = expression(g.expression.as_ref());
+        if literal_value(inner.as_ref()).is_some() {
+            return inner;
+        }
+        return Box::new(Grouping { expression: inner });
+    }
+    if let Some(p) = any.downcast_ref::<PrefixExpression>() {
+        let right = expression(p.right.as_ref());
+        let folded = literal_value(right.as_ref()).and_then(|value| match p.operator.token_type {
+            TokenType::Bang => Some(Value::Boolean(!value.is_truthy())),
+            TokenType::Minus => negate(&value, p.operator.line_number).ok(),
+            _ => None,
+        });
+        return match folded.and_then(literal) {
+            Some(folded) => folded,
+            None => Box::new(PrefixExpression { operator: p.operator.clone(), right }),
+        };
+    }
+    if let Some(i) = any.downcast_ref::<InfixExpression>() {
+        return infix(i);
+    }
+    if let Some(t) = any.downcast_ref::<TernaryExpression>() {
+        let condition = expression(t.condition.as_ref());
+        return match literal_value(condition.as_ref()) {
+            Some(value) if value.is_truthy() => expression(t.consequence.as_ref()),
+            Some(_) => expression(t.alternative.as_ref()),
+            None => Box::new(TernaryExpression {
+                condition,
+                consequence: expression(t.consequence.as_ref()),
+                alternative: expression(t.alternative.as_ref()),
+            }),
+        };
+    }
+    if let Some(i) = any.downcast_ref::<Identifier>() {
+        return Box::new(Identifier::new(i.token.clone()));
+    }
+    if let Some(a) = any.downcast_ref::<AssignExpression>() {
+        return Box::new(AssignExpression { operator: a.operator.clone(), target: expression(a.target.as_ref()), value: expression(a.value.as_ref()) });
+    }
+    if let Some(u) = any.downcast_ref::<UpdateExpression>() {
+        return Box::new(UpdateExpression { operator: u.operator.clone(), target: expression(u.target.as_ref()), prefix: u.prefix });
+    }
+    if let Some(g) = any.downcast_ref::<GetExpression>() {
+        return Box::new(GetExpression { object: expression(g.object.as_ref()), name: g.name.clone(), cache: PropertyCache::default() });
+    }
+    if let Some(i) = any.downcast_ref::<IndexExpression>() {
+        return Box::new(IndexExpression { token: i.token.clone(), left: expression(i.left.as_ref()), index: expression(i.index.as_ref()) });
+    }
+    if let Some(l) = any.downcast_ref::<ListLiteral>() {
+        return Box::new(ListLiteral { elements: l.elements.iter().map(|e| expression(e.as_ref())).collect() });
+    }
+    if let Some(m) = any.downcast_ref::<MapLiteral>() {
+        let pairs = m.pairs.iter().map(|(key, value)| (expression(key.as_ref()), expression(value.as_ref()))).collect();
+        return Box::new(MapLiteral { token: m.token.clone(), pairs });
+    }
+    if let Some(c) = any.downcast_ref::<CallExpression>() {
+        let arguments = c.arguments.iter().map(|argument| expression(argument.as_ref())).collect();
+        return Box::new(CallExpression { token: c.token.clone(), callee: expression(c.callee.as_ref()), arguments });
+    }
+    if let Some(m) = any.downcast_ref::<MatchExpression>() {
+        let arms = m.arms.iter()
+            .map(|arm| MatchArm {
+                pattern: pattern(arm.pattern.as_ref()),
+                guard: arm.guard.as_ref().map(|guard| expression(guard.as_ref())),
+                body: expression(arm.body.as_ref()),
+            })
+            .collect();
+        return Box::new(MatchExpression { token: m.token.clone(), subject: expression(m.subject.as_ref()), arms });
+    }
+    unreachable!("unknown expression {}", expr.string())
+}
+
+fn infix(i: &InfixExpression) -> Box<dyn Expression> {
+    let left = expression(i.left.as_ref());
+    let left_value = literal_value(left.as_ref());
+    // `and` and `or` produce one of their operands, so a literal on the left decides which.
+    if matches!(i.token.token_type, TokenType::And | TokenType::Or) {
+        return match left_value {
+            Some(value) if value.is_truthy() == (i.token.token_type == TokenType::Or) => left,
+            Some(_) => expression(i.right.as_ref()),
+            None => Box::new(InfixExpression { token: i.token.clone(), left, right: expression(i.right.as_ref()) }),
+        };
+    }
+    let right = expression(i.right.as_ref());
+    let folded = match (left_value, literal_value(right.as_ref())) {
+        (Some(l), Some(r)) => apply_infix_operator(&i.token.token_type, &l, &r, i.token.line_number).ok().and_then(literal),
+        _ => None,
+    };
+    folded.unwrap_or_else(|| Box::new(InfixExpression { token: i.token.clone(), left, right }))
+}
@@ -1,11 +1,19 @@
-use crate::ast::ast::{Boolean, Expression, ExpressionStatement, Grouping, InfixExpression, Nil, NumberLiteral, PrefixExpression, Program, Statement, StringLiteral};
-use crate::lexer::lexer::{Lexer, Token, TokenType};
-use crate::lexer::lexer::TokenType::{Asterisk, Slash, Plus, Minus, Less, LessEqual, Greater, GreaterEqual, EOF, Equal, NotEqual};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::ast::ast::{AssignExpression, BindingPattern, BlockStatement, Boolean, BreakStatement, CallExpression, CatchClause, ClassStatement, ContinueStatement, Expression, ExpressionStatement, ForStatement, FunctionStatement, GetExpression, Grouping, Identifier, IfStatement, ImportName, ImportStatement, IndexExpression, InfixExpression, InstancePattern, ListLiteral, ListPattern, LiteralPattern, MapLiteral, MapPattern, MatchArm, MatchExpression, MatchStatement, Nil, NumberLiteral, Pattern, PrefixExpression, PrintStatement, Program, RangePattern, Resolution, RestPattern, ReturnStatement, Statement, StringLiteral, TernaryExpression, ThrowStatement, TryStatement, UpdateExpression, VarStatement, WhileStatement, WildcardPattern};
+use crate::object::object::StringData;
+use crate::object::shape::PropertyCache;
+use crate::lexer::lexer::{Lexer, Token, TokenType, POSTFIX_PRECEDENCE, PREFIX_PRECEDENCE};
+use crate::lexer::lexer::TokenType::{Asterisk, Slash, Percent, StarStar, Plus, Minus, Less, LessEqual, Greater, GreaterEqual, EOF, Equal, NotEqual, And, Or};
 
 pub struct Parser {
     lexer: Lexer,
     off: usize,
     pub errors: Vec<String>,
+    // One shared string per distinct string literal, so evaluating a literal
+    // doesn't allocate and equal literals compare by pointer.
+    constants: HashMap<String, Rc<StringData>>,
 }
 
 impl Parser {
@@ -14,6 +22,7 @@ impl Parser {
             lexer,
             off: 0,
             errors: Vec::new(),
+            constants: HashMap::new(),
         }
     }
 
@@ -34,7 +43,8 @@ impl Parser {
     fn current_token_line_number(&self) -> i32 {
         match self.current_token() {
             Some(token) => token.line_number,
-            None => 0,
+            // Past the end of the input: the line of the EOF token.
+            None => self.lexer.tokens.last().map_or(0, |token| token.line_number),
         }
     }
 
@@ -65,6 +75,47 @@ impl Parser {
         }
     }
 
+    fn current_token_type_is(&self, target: TokenType) -> bool {
+        match self.current_token_type() {
+            Some(token_type) => token_type.clone() == target,
+            None => false,
+        }
+    }
+
+    // Advances onto the peek token if it has the expected type, otherwise records `message`.
+    fn expect_peek(&mut self, target: TokenType, message: &str) -> bool {
+        if self.peek_token_type_is(target) {
+            self.next_token();
+            return true;
+        }
+        self.peek_error(message);
+        false
+    }
+
+    fn peek_error(&mut self, message: &str) {
+        let line_number = match self.peek_token() {
+            Some(token) => token.line_number,
+            None => self.current_token_line_number(),
+        };
+        self.errors.push(format!("[line {}] {}", line_number, message));
+    }
+
+    // Skips to the end of the statement in error so that one mistake doesn't cascade.
+    fn synchronize(&mut self) {
+        while let Some(token_type) = self.current_token_type() {
+            match token_type {
+                TokenType::Semicolon | EOF => return,
+                _ => {}
+            }
+            match self.peek_token_type() {
+                Some(TokenType::Class | TokenType::Fun | TokenType::Var | TokenType::For | TokenType::If
+                     | TokenType::While | TokenType::Print | TokenType::Return | TokenType::Throw
+                     | TokenType::Try | TokenType::RBrace) => return,
+                _ => self.next_token(),
+            }
+        }
+    }
+
     fn peek_precedence(&self) -> i32 {
         match self.peek_token_type() {
             Some(token_type) => token_type.clone().precedence(),
@@ -79,7 +130,11 @@ impl Parser {
                 break;
             }
 
+            let errors = self.errors.len();
             let stmt = self.parse_statement();
+            if self.errors.len() > errors {
+                self.synchronize();
+            }
             program.statements.push(stmt);
             self.next_token();
         }
@@ -88,14 +143,227 @@ impl Parser {
 
     fn parse_statement(&mut self) -> Box<dyn Statement> {
         match self.current_token_type() {
+            Some(TokenType::Print) => self.parse_print_statement(),
+            Some(TokenType::Var) => self.parse_var_statement(),
+            Some(TokenType::LBrace) => Box::new(self.parse_block_statement()),
+            Some(TokenType::If) => self.parse_if_statement(),
+            Some(TokenType::While) => self.parse_while_statement(None),
+            Some(TokenType::For) => self.parse_for_statement(None),
+            Some(TokenType::Fun) => self.parse_function_statement(),
+            Some(TokenType::Return) => self.parse_return_statement(),
+            Some(TokenType::Throw) => self.parse_throw_statement(),
+            Some(TokenType::Try) => self.parse_try_statement(),
+            Some(TokenType::Class) => self.parse_class_statement(),
+            Some(TokenType::Import) => self.parse_import_statement(),
+            Some(TokenType::Match) => self.parse_match_statement(),
+            Some(TokenType::Break) => self.parse_break_statement(),
+            Some(TokenType::Continue) => self.parse_continue_statement(),
+            Some(TokenType::Identifier) if self.peek_token_type_is(TokenType::Colon) => self.parse_labeled_statement(),
             _ => Box::new(self.parse_expression_statement()),
         }
     }
 
+    // A trailing expression without ';' is accepted at the end of the input so that
+    // `parse` and `evaluate` keep working on bare expressions.
     fn parse_expression_statement(&mut self) -> ExpressionStatement {
+        let expression = self.parse_expression(0);
+        if !self.peek_token_type_is(EOF) {
+            self.expect_peek(TokenType::Semicolon, "Expect ';' after expression.");
+        }
         ExpressionStatement {
-            expression: self.parse_expression(0),
+            expression,
+        }
+    }
+
+    fn parse_print_statement(&mut self) -> Box<dyn Statement> {
+        self.next_token();
+        let expression = self.parse_expression(0);
+        self.expect_peek(TokenType::Semicolon, "Expect ';' after value.");
+        Box::new(PrintStatement {
+            expression,
+        })
+    }
+
+    fn parse_var_statement(&mut self) -> Box<dyn Statement> {
+        if !self.expect_peek(TokenType::Identifier, "Expect variable name.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        let name = self.current_token().cloned().unwrap();
+
+        let mut initializer = None;
+        if self.peek_token_type_is(TokenType::Assign) {
+            self.next_token();
+            self.next_token();
+            initializer = Some(self.parse_expression(0));
+        }
+        self.expect_peek(TokenType::Semicolon, "Expect ';' after variable declaration.");
+        Box::new(VarStatement {
+            name,
+            initializer,
+            slot: Cell::new(None),
+        })
+    }
+
+    fn parse_block_statement(&mut self) -> BlockStatement {
+        let mut statements = Vec::new();
+        self.next_token();
+        while !self.current_token_type_is(TokenType::RBrace) {
+            if self.current_token_type_is(EOF) || self.current_token().is_none() {
+                self.errors.push(format!("[line {}] Expect '}}' after block.", self.current_token_line_number()));
+                break;
+            }
+            let errors = self.errors.len();
+            statements.push(self.parse_statement());
+            if self.errors.len() > errors {
+                self.synchronize();
+            }
+            self.next_token();
+        }
+        BlockStatement {
+            statements,
+        }
+    }
+
+    fn parse_if_statement(&mut self) -> Box<dyn Statement> {
+        if !self.expect_peek(TokenType::LParen, "Expect '(' after 'if'.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        self.next_token();
+        let condition = self.parse_expression(0);
+        if !self.expect_peek(TokenType::RParen, "Expect ')' after if condition.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        self.next_token();
+        let consequence = self.parse_statement();
+
+        let mut alternative = None;
+        if self.peek_token_type_is(TokenType::Else) {
+            self.next_token();
+            self.next_token();
+            alternative = Some(self.parse_statement());
+        }
+        Box::new(IfStatement {
+            condition,
+            consequence,
+            alternative,
+        })
+    }
+
+    // `label: while (...)` or `label: for (...)`.
+    fn parse_labeled_statement(&mut self) -> Box<dyn Statement> {
+        let label = self.current_token().cloned().unwrap();
+        self.next_token();
+        match self.peek_token_type() {
+            Some(TokenType::While) => {
+                self.next_token();
+                self.parse_while_statement(Some(label))
+            }
+            Some(TokenType::For) => {
+                self.next_token();
+                self.parse_for_statement(Some(label))
+            }
+            _ => {
+                self.errors.push(format!("[line {}] Expect 'while' or 'for' after label '{}'.", label.line_number, label.lexeme));
+                Box::new(ExpressionStatement { expression: self.parse_nil_expression() })
+            }
+        }
+    }
+
+    fn parse_while_statement(&mut self, label: Option<Token>) -> Box<dyn Statement> {
+        if !self.expect_peek(TokenType::LParen, "Expect '(' after 'while'.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        self.next_token();
+        let condition = self.parse_expression(0);
+        if !self.expect_peek(TokenType::RParen, "Expect ')' after condition.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        self.next_token();
+        let body = self.parse_statement();
+        Box::new(WhileStatement {
+            label,
+            condition,
+            body,
+        })
+    }
+
+    fn parse_for_statement(&mut self, label: Option<Token>) -> Box<dyn Statement> {
+        if !self.expect_peek(TokenType::LParen, "Expect '(' after 'for'.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+
+        let initializer: Option<Box<dyn Statement>> = match self.peek_token_type() {
+            Some(TokenType::Semicolon) => {
+                self.next_token();
+                None
+            }
+            Some(TokenType::Var) => {
+                self.next_token();
+                Some(self.parse_var_statement())
+            }
+            _ => {
+                self.next_token();
+                let expression = self.parse_expression(0);
+                self.expect_peek(TokenType::Semicolon, "Expect ';' after loop initializer.");
+                Some(Box::new(ExpressionStatement { expression }))
+            }
+        };
+
+        let mut condition = None;
+        if !self.peek_token_type_is(TokenType::Semicolon) {
+            self.next_token();
+            condition = Some(self.parse_expression(0));
+        }
+        if !self.expect_peek(TokenType::Semicolon, "Expect ';' after loop condition.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+
+        let mut increment = None;
+        if !self.peek_token_type_is(TokenType::RParen) {
+            self.next_token();
+            increment = Some(self.parse_expression(0));
+        }
+        if !self.expect_peek(TokenType::RParen, "Expect ')' after for clauses.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        self.next_token();
+        let body = self.parse_statement();
+
+        Box::new(ForStatement {
+            label,
+            initializer,
+            condition,
+            increment,
+            body,
+        })
+    }
+
+    fn parse_loop_label(&mut self) -> Option<Token> {
+        if self.peek_token_type_is(TokenType::Identifier) {
+            self.next_token();
+            return self.current_token().cloned();
         }
+        None
+    }
+
+    fn parse_break_statement(&mut self) -> Box<dyn Statement> {
+        let token = self.current_token().cloned().unwrap();
+        let label = self.parse_loop_label();
+        self.expect_peek(TokenType::Semicolon, "Expect ';' after 'break'.");
+        Box::new(BreakStatement {
+            token,
+            label,
+        })
+    }
+
+    fn parse_continue_statement(&mut self) -> Box<dyn Statement> {
+        let token = self.current_token().cloned().unwrap();
+        let label = self.parse_loop_label();
+        self.expect_peek(TokenType::Semicolon, "Expect ';' after 'continue'.");
+        Box::new(ContinueStatement {
+            token,
+            label,
+        })
     }
 
     fn parse_expression(&mut self, precedence: i32) -> Box<dyn Expression> {
@@ -105,10 +373,19 @@ impl Parser {
             Some(TokenType::String) => self.parse_string_expression(),
             Some(TokenType::LParen) => self.parse_grouping_expression(),
             Some(TokenType::Bang | Minus) => self.parse_prefix_expression(),
+            Some(TokenType::PlusPlus | TokenType::MinusMinus) => self.parse_prefix_update_expression(),
             Some(TokenType::Nil) => self.parse_nil_expression(),
+            Some(TokenType::Identifier | TokenType::This) => self.parse_identifier(),
+            Some(TokenType::LBracket) => self.parse_list_literal(),
+            Some(TokenType::LBrace) => self.parse_map_literal(),
+            Some(TokenType::Match) => self.parse_match_expression(),
             _ => {
-                let token = self.current_token().unwrap();
-                self.errors.push(format!("[line {}] Invalid token type {:?}", token.clone().line_number, token.clone().token_type));
+                let message = match self.current_token() {
+                    Some(token) => format!("[line {}] Invalid token type {:?}", token.line_number, token.token_type),
+                    // Past the end of the input, after an error that skipped over EOF.
+                    None => format!("[line {}] Expect expression.", self.current_token_line_number()),
+                };
+                self.errors.push(message);
                 self.parse_nil_expression()
             }
         };
@@ -116,10 +393,34 @@ impl Parser {
 
         while precedence < self.peek_precedence() {
             match self.peek_token_type() {
-                Some(Asterisk | Slash | Plus | Minus | Less | LessEqual | Greater | GreaterEqual | Equal | NotEqual) => {
+                Some(Asterisk | Slash | Percent | StarStar | Plus | Minus | Less | LessEqual | Greater | GreaterEqual | Equal | NotEqual | And | Or) => {
                     self.next_token();
                     left = self.parse_infix_expression(left);
                 }
+                Some(TokenType::Question) => {
+                    self.next_token();
+                    left = self.parse_ternary_expression(left);
+                }
+                Some(TokenType::Assign | TokenType::PlusAssign | TokenType::MinusAssign | TokenType::AsteriskAssign | TokenType::SlashAssign | TokenType::PercentAssign) => {
+                    self.next_token();
+                    left = self.parse_assign_expression(left);
+                }
+                Some(TokenType::PlusPlus | TokenType::MinusMinus) => {
+                    self.next_token();
+                    left = self.parse_postfix_update_expression(left);
+                }
+                Some(TokenType::Dot) => {
+                    self.next_token();
+                    left = self.parse_get_expression(left);
+                }
+                Some(TokenType::LBracket) => {
+                    self.next_token();
+                    left = self.parse_index_expression(left);
+                }
+                Some(TokenType::LParen) => {
+                    self.next_token();
+                    left = self.parse_call_expression(left);
+                }
                 _ => {
                     return left;
                 }
@@ -131,26 +432,25 @@ impl Parser {
 
     fn parse_boolean_expression(&mut self) -> Box<dyn Expression> {
         Box::new(Boolean {
-            value: match self.current_token_type() {
-                Some(TokenType::True) => true,
-                _ => false,
-            }
+            value: matches!(self.current_token_type(), Some(TokenType::True)),
         })
     }
 
     fn parse_number_expression(&mut self) -> Box<dyn Expression> {
         match self.current_token().cloned() {
             Some(token) => {
-                Box::new(NumberLiteral { value: token.literal.parse().unwrap(), literal: token.literal})
+                Box::new(NumberLiteral { value: token.literal.parse().unwrap(), literal: token.literal.to_string()})
             }
             None => Box::new(Nil {}),
         }
     }
 
     fn parse_string_expression(&mut self) -> Box<dyn Expression> {
-        match self.current_token().cloned() {
-            Some(token) => {
-                Box::new(StringLiteral { value: token.literal })
+        match self.current_token().map(|token| token.literal.clone()) {
+            Some(literal) => {
+                // Keyed by text, since a literal's symbol is its own.
+                let value = self.constants.entry(literal.to_string()).or_insert_with(|| Rc::new(Box::from(literal.as_str())));
+                Box::new(StringLiteral { value: Rc::clone(value) })
             }
             None => Box::new(Nil {}),
         }
@@ -179,7 +479,7 @@ impl Parser {
         match self.current_token().cloned() {
             Some(token) => {
                 self.next_token();
-                let right = self.parse_expression(6);
+                let right = self.parse_expression(PREFIX_PRECEDENCE);
                 Box::new( PrefixExpression {
                     operator: token.clone(),
                     right,
@@ -192,7 +492,7 @@ impl Parser {
     fn parse_infix_expression(&mut self, left: Box<dyn Expression>) -> Box<dyn Expression> {
         match self.current_token().cloned() {
             Some(token) => {
-                let precedence = token.token_type.precedence();
+                let precedence = self.right_binding_precedence(&token.token_type);
                 self.next_token();
                 let right = self.parse_expression(precedence);
                 Box::new(InfixExpression {
@@ -204,4 +504,585 @@ impl Parser {
             None => self.parse_nil_expression(),
         }
     }
-}
\ No newline at end of file
+
+    // Right-associative operators parse their right operand one level looser so that
+    // another operator of the same precedence nests to the right: `a ** b ** c` is
+    // `a ** (b ** c)`, while `a - b - c` stays `(a - b) - c`.
+    fn right_binding_precedence(&self, token_type: &TokenType) -> i32 {
+        if token_type.is_right_associative() {
+            token_type.precedence() - 1
+        } else {
+            token_type.precedence()
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Box<dyn Expression> {
+        match self.current_token().cloned() {
+            Some(token) => Box::new(Identifier::new(token)),
+            None => self.parse_nil_expression(),
+        }
+    }
+
+    fn parse_ternary_expression(&mut self, condition: Box<dyn Expression>) -> Box<dyn Expression> {
+        let token = match self.current_token().cloned() {
+            Some(token) => token,
+            None => return self.parse_nil_expression(),
+        };
+        self.next_token();
+        let consequence = self.parse_expression(0);
+
+        if !self.peek_token_type_is(TokenType::Colon) {
+            self.errors.push(format!("[line {}] Expect ':' after then branch of conditional expression.", self.current_token_line_number()));
+            return self.parse_nil_expression();
+        }
+        self.next_token();
+        self.next_token();
+
+        let alternative = self.parse_expression(self.right_binding_precedence(&token.token_type));
+        Box::new(TernaryExpression {
+            condition,
+            consequence,
+            alternative,
+        })
+    }
+
+    fn is_assignable(target: &dyn Expression) -> bool {
+        let any = target.as_any();
+        let this = any.downcast_ref::<Identifier>().is_some_and(|i| i.token.token_type == TokenType::This);
+        (any.is::<Identifier>() && !this) || any.is::<GetExpression>() || any.is::<IndexExpression>()
+    }
+
+    fn parse_assign_expression(&mut self, target: Box<dyn Expression>) -> Box<dyn Expression> {
+        let operator = match self.current_token().cloned() {
+            Some(token) => token,
+            None => return self.parse_nil_expression(),
+        };
+        self.next_token();
+        let value = self.parse_expression(self.right_binding_precedence(&operator.token_type));
+
+        if !Self::is_assignable(target.as_ref()) {
+            self.errors.push(format!("[line {}] Invalid assignment target.", operator.line_number));
+            return self.parse_nil_expression();
+        }
+        Box::new(AssignExpression {
+            operator,
+            target,
+            value,
+        })
+    }
+
+    fn parse_prefix_update_expression(&mut self) -> Box<dyn Expression> {
+        let operator = match self.current_token().cloned() {
+            Some(token) => token,
+            None => return self.parse_nil_expression(),
+        };
+        self.next_token();
+        // Only property access and indexing bind tighter than a prefix update.
+        let target = self.parse_expression(POSTFIX_PRECEDENCE - 1);
+        if !Self::is_assignable(target.as_ref()) {
+            self.errors.push(format!("[line {}] Invalid {} target.", operator.line_number, operator.lexeme));
+            return self.parse_nil_expression();
+        }
+
+        Box::new(UpdateExpression {
+            operator,
+            target,
+            prefix: true,
+        })
+    }
+
+    fn parse_postfix_update_expression(&mut self, target: Box<dyn Expression>) -> Box<dyn Expression> {
+        let operator = match self.current_token().cloned() {
+            Some(token) => token,
+            None => return self.parse_nil_expression(),
+        };
+        if !Self::is_assignable(target.as_ref()) {
+            self.errors.push(format!("[line {}] Invalid {} target.", operator.line_number, operator.lexeme));
+            return self.parse_nil_expression();
+        }
+
+        Box::new(UpdateExpression {
+            operator,
+            target,
+            prefix: false,
+        })
+    }
+
+    fn parse_get_expression(&mut self, object: Box<dyn Expression>) -> Box<dyn Expression> {
+        if !self.peek_token_type_is(TokenType::Identifier) {
+            self.errors.push(format!("[line {}] Expect property name after '.'.", self.current_token_line_number()));
+            return self.parse_nil_expression();
+        }
+        self.next_token();
+
+        match self.current_token().cloned() {
+            Some(name) => Box::new(GetExpression { object, name, cache: PropertyCache::default() }),
+            None => self.parse_nil_expression(),
+        }
+    }
+
+    fn parse_index_expression(&mut self, left: Box<dyn Expression>) -> Box<dyn Expression> {
+        let token = match self.current_token().cloned() {
+            Some(token) => token,
+            None => return self.parse_nil_expression(),
+        };
+        self.next_token();
+        let index = self.parse_expression(0);
+
+        if !self.peek_token_type_is(TokenType::RBracket) {
+            self.errors.push(format!("[line {}] Expect ']' after index.", self.current_token_line_number()));
+            return self.parse_nil_expression();
+        }
+        self.next_token();
+
+        Box::new(IndexExpression {
+            token,
+            left,
+            index,
+        })
+    }
+
+    // Parses `item (, item)* end` where the current token is the opening delimiter,
+    // leaving the closing delimiter as the current token.
+    fn parse_delimited<T>(&mut self, end: TokenType, message: &str, mut item: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let mut items = Vec::new();
+        if self.peek_token_type_is(end.clone()) {
+            self.next_token();
+            return Some(items);
+        }
+        loop {
+            self.next_token();
+            items.push(item(self)?);
+            if !self.peek_token_type_is(TokenType::Comma) {
+                break;
+            }
+            self.next_token();
+        }
+        if !self.expect_peek(end, message) {
+            return None;
+        }
+        Some(items)
+    }
+
+    fn parse_list_literal(&mut self) -> Box<dyn Expression> {
+        match self.parse_delimited(TokenType::RBracket, "Expect ']' after list elements.", |p| Some(p.parse_expression(0))) {
+            Some(elements) => Box::new(ListLiteral { elements }),
+            None => self.parse_nil_expression(),
+        }
+    }
+
+    fn parse_map_literal(&mut self) -> Box<dyn Expression> {
+        let token = self.current_token().cloned().unwrap();
+        let pairs = self.parse_delimited(TokenType::RBrace, "Expect '}' after map entries.", |p| {
+            let key = p.parse_expression(0);
+            if !p.expect_peek(TokenType::Colon, "Expect ':' after map key.") {
+                return None;
+            }
+            if p.peek_token_type_is(EOF) {
+                p.peek_error("Expect expression.");
+                return None;
+            }
+            p.next_token();
+            Some((key, p.parse_expression(0)))
+        });
+        match pairs {
+            Some(pairs) => Box::new(MapLiteral { token, pairs }),
+            None => self.parse_nil_expression(),
+        }
+    }
+
+    fn parse_call_expression(&mut self, callee: Box<dyn Expression>) -> Box<dyn Expression> {
+        let token = self.current_token().cloned().unwrap();
+        match self.parse_delimited(TokenType::RParen, "Expect ')' after arguments.", |p| Some(p.parse_expression(0))) {
+            Some(arguments) => {
+                if arguments.len() > 255 {
+                    self.errors.push(format!("[line {}] Can't have more than 255 arguments.", token.line_number));
+                }
+                Box::new(CallExpression { token, callee, arguments })
+            }
+            None => self.parse_nil_expression(),
+        }
+    }
+
+    fn parse_function_statement(&mut self) -> Box<dyn Statement> {
+        if !self.expect_peek(TokenType::Identifier, "Expect function name.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        match self.parse_function("function") {
+            Some(function) => Box::new(function),
+            None => Box::new(ExpressionStatement { expression: self.parse_nil_expression() }),
+        }
+    }
+
+    // The parameters and body of a function or method whose name is the current token.
+    fn parse_function(&mut self, kind: &str) -> Option<FunctionStatement> {
+        let name = self.current_token().cloned().unwrap();
+        if !self.expect_peek(TokenType::LParen, &format!("Expect '(' after {} name.", kind)) {
+            return None;
+        }
+        let params = self.parse_delimited(TokenType::RParen, "Expect ')' after parameters.", |p| {
+            if !p.current_token_type_is(TokenType::Identifier) {
+                p.errors.push(format!("[line {}] Expect parameter name.", p.current_token_line_number()));
+                return None;
+            }
+            p.current_token().cloned()
+        })?;
+        if params.len() > 255 {
+            self.errors.push(format!("[line {}] Can't have more than 255 parameters.", name.line_number));
+        }
+        if !self.expect_peek(TokenType::LBrace, &format!("Expect '{{' before {} body.", kind)) {
+            return None;
+        }
+        let body = self.parse_block_statement();
+        Some(FunctionStatement {
+            name,
+            params: Rc::new(params),
+            body: Rc::new(body),
+            slot: Cell::new(None),
+        })
+    }
+
+    fn parse_return_statement(&mut self) -> Box<dyn Statement> {
+        let token = self.current_token().cloned().unwrap();
+        let mut value = None;
+        if !self.peek_token_type_is(TokenType::Semicolon) {
+            self.next_token();
+            value = Some(self.parse_expression(0));
+        }
+        self.expect_peek(TokenType::Semicolon, "Expect ';' after return value.");
+        Box::new(ReturnStatement {
+            token,
+            value,
+            tail_call: Cell::new(false),
+        })
+    }
+
+    fn parse_throw_statement(&mut self) -> Box<dyn Statement> {
+        let token = self.current_token().cloned().unwrap();
+        self.next_token();
+        let value = self.parse_expression(0);
+        self.expect_peek(TokenType::Semicolon, "Expect ';' after thrown value.");
+        Box::new(ThrowStatement {
+            token,
+            value,
+        })
+    }
+
+    // try { ... } [catch (name) { ... }] [finally { ... }]
+    fn parse_try_statement(&mut self) -> Box<dyn Statement> {
+        if !self.expect_peek(TokenType::LBrace, "Expect '{' after 'try'.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        let body = self.parse_block_statement();
+
+        let mut catch_clause = None;
+        if self.peek_token_type_is(TokenType::Catch) {
+            self.next_token();
+            if !self.expect_peek(TokenType::LParen, "Expect '(' after 'catch'.")
+                || !self.expect_peek(TokenType::Identifier, "Expect exception variable name.") {
+                return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+            }
+            let name = self.current_token().cloned().unwrap();
+            if !self.expect_peek(TokenType::RParen, "Expect ')' after exception variable.")
+                || !self.expect_peek(TokenType::LBrace, "Expect '{' before catch body.") {
+                return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+            }
+            catch_clause = Some(CatchClause { name, body: self.parse_block_statement() });
+        }
+
+        let mut finally_body = None;
+        if self.peek_token_type_is(TokenType::Finally) {
+            self.next_token();
+            if !self.expect_peek(TokenType::LBrace, "Expect '{' after 'finally'.") {
+                return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+            }
+            finally_body = Some(self.parse_block_statement());
+        }
+
+        if catch_clause.is_none() && finally_body.is_none() {
+            self.errors.push(format!("[line {}] Expect 'catch' or 'finally' after try block.", self.current_token_line_number()));
+        }
+        Box::new(TryStatement {
+            body,
+            catch_clause,
+            finally_body,
+        })
+    }
+
+    // `as` and `from` are only keywords inside an import, so they stay usable as names.
+    fn peek_word_is(&self, word: &str) -> bool {
+        matches!(self.peek_token(), Some(token) if token.token_type == TokenType::Identifier && token.lexeme == word)
+    }
+
+    fn expect_peek_word(&mut self, word: &str, message: &str) -> bool {
+        if self.peek_word_is(word) {
+            self.next_token();
+            return true;
+        }
+        self.peek_error(message);
+        false
+    }
+
+    // import "path" as name;
+    // import { name [as alias], ... } from "path";
+    fn parse_import_statement(&mut self) -> Box<dyn Statement> {
+        let token = self.current_token().cloned().unwrap();
+        let mut alias = None;
+        let mut names = Vec::new();
+        if self.peek_token_type_is(TokenType::LBrace) {
+            self.next_token();
+            let parsed = self.parse_delimited(TokenType::RBrace, "Expect '}' after imported names.", |p| {
+                if !p.current_token_type_is(TokenType::Identifier) {
+                    p.errors.push(format!("[line {}] Expect name to import.", p.current_token_line_number()));
+                    return None;
+                }
+                let name = p.current_token().cloned().unwrap();
+                let mut alias = None;
+                if p.peek_word_is("as") {
+                    p.next_token();
+                    if !p.expect_peek(TokenType::Identifier, "Expect name after 'as'.") {
+                        return None;
+                    }
+                    alias = p.current_token().cloned();
+                }
+                Some(ImportName { name, alias })
+            });
+            match parsed {
+                Some(parsed) if !parsed.is_empty() => names = parsed,
+                Some(_) => {
+                    self.errors.push(format!("[line {}] Expect name to import.", self.current_token_line_number()));
+                    return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+                }
+                None => return Box::new(ExpressionStatement { expression: self.parse_nil_expression() }),
+            }
+            if !self.expect_peek_word("from", "Expect 'from' after imported names.") {
+                return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+            }
+        }
+        if !self.expect_peek(TokenType::String, "Expect module path string.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        let path = self.current_token().unwrap().literal.to_string();
+        if names.is_empty() {
+            if !self.expect_peek_word("as", "Expect 'as' after module path.")
+                || !self.expect_peek(TokenType::Identifier, "Expect module name after 'as'.") {
+                return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+            }
+            alias = self.current_token().cloned();
+        }
+        self.expect_peek(TokenType::Semicolon, "Expect ';' after import.");
+        Box::new(ImportStatement {
+            token,
+            path,
+            alias,
+            names,
+        })
+    }
+
+    fn parse_class_statement(&mut self) -> Box<dyn Statement> {
+        if !self.expect_peek(TokenType::Identifier, "Expect class name.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        let name = self.current_token().cloned().unwrap();
+        if !self.expect_peek(TokenType::LBrace, "Expect '{' before class body.") {
+            return Box::new(ExpressionStatement { expression: self.parse_nil_expression() });
+        }
+        let mut methods = Vec::new();
+        while self.peek_token_type_is(TokenType::Identifier) {
+            self.next_token();
+            match self.parse_function("method") {
+                Some(method) => methods.push(method),
+                None => return Box::new(ExpressionStatement { expression: self.parse_nil_expression() }),
+            }
+        }
+        self.expect_peek(TokenType::RBrace, "Expect '}' after class body.");
+        Box::new(ClassStatement { name, methods, slot: Cell::new(None) })
+    }
+
+    // match (subject) { case <pattern> [if <guard>] => <statement> ... }
+    fn parse_match_statement(&mut self) -> Box<dyn Statement> {
+        let token = self.current_token().cloned().unwrap();
+        let parsed = self.parse_match_subject().and_then(|subject| {
+            let arms = self.parse_match_arms(|p| Some(p.parse_statement()))?;
+            Some((subject, arms))
+        });
+        match parsed {
+            Some((subject, arms)) => Box::new(MatchStatement { token, subject, arms }),
+            None => Box::new(ExpressionStatement { expression: self.parse_nil_expression() }),
+        }
+    }
+
+    // Each arm of a match expression is an expression ended by ';', as the
+    // expression statements of a match statement's arms are.
+    fn parse_match_expression(&mut self) -> Box<dyn Expression> {
+        let token = self.current_token().cloned().unwrap();
+        let parsed = self.parse_match_subject().and_then(|subject| {
+            let arms = self.parse_match_arms(|p| {
+                let value = p.parse_expression(0);
+                p.expect_peek(TokenType::Semicolon, "Expect ';' after match arm.").then_some(value)
+            })?;
+            Some((subject, arms))
+        });
+        match parsed {
+            Some((subject, arms)) => Box::new(MatchExpression { token, subject, arms }),
+            None => self.parse_nil_expression(),
+        }
+    }
+
+    fn parse_match_subject(&mut self) -> Option<Box<dyn Expression>> {
+        if !self.expect_peek(TokenType::LParen, "Expect '(' after 'match'.") {
+            return None;
+        }
+        self.next_token();
+        let subject = self.parse_expression(0);
+        if !self.expect_peek(TokenType::RParen, "Expect ')' after match subject.")
+            || !self.expect_peek(TokenType::LBrace, "Expect '{' before match arms.") {
+            return None;
+        }
+        Some(subject)
+    }
+
+    // The arms up to the closing brace, each of whose bodies `body` parses.
+    fn parse_match_arms<B: ?Sized>(&mut self, mut body: impl FnMut(&mut Self) -> Option<Box<B>>) -> Option<Vec<MatchArm<B>>> {
+        let mut arms = Vec::new();
+        while self.peek_token_type_is(TokenType::Case) {
+            self.next_token();
+            self.next_token();
+            let pattern = self.parse_pattern()?;
+
+            let mut guard = None;
+            if self.peek_token_type_is(TokenType::If) {
+                self.next_token();
+                self.next_token();
+                guard = Some(self.parse_expression(0));
+            }
+            if !self.expect_peek(TokenType::FatArrow, "Expect '=>' after match pattern.") {
+                return None;
+            }
+            self.next_token();
+            let body = body(self)?;
+            arms.push(MatchArm { pattern, guard, body });
+        }
+        self.expect_peek(TokenType::RBrace, "Expect '}' after match arms.");
+        Some(arms)
+    }
+
+    fn parse_pattern(&mut self) -> Option<Box<dyn Pattern>> {
+        let token = self.current_token().cloned().unwrap();
+        match token.token_type {
+            TokenType::Identifier if token.lexeme == "_" => Some(Box::new(WildcardPattern {})),
+            TokenType::Identifier if self.peek_token_type_is(TokenType::LBrace) => self.parse_instance_pattern(),
+            TokenType::Identifier => Some(Box::new(BindingPattern { name: token })),
+            TokenType::Number | Minus => {
+                let start = self.parse_pattern_number()?;
+                match self.peek_token_type() {
+                    Some(TokenType::DotDot | TokenType::DotDotEqual) => {
+                        self.next_token();
+                        let inclusive = self.current_token_type_is(TokenType::DotDotEqual);
+                        self.next_token();
+                        let end = self.parse_pattern_number()?;
+                        Some(Box::new(RangePattern { start, end, inclusive }))
+                    }
+                    _ => Some(Box::new(LiteralPattern { value: Box::new(NumberLiteral { value: start, literal: format!("{:?}", start) }) })),
+                }
+            }
+            TokenType::String | TokenType::True | TokenType::False | TokenType::Nil => {
+                Some(Box::new(LiteralPattern { value: self.parse_literal() }))
+            }
+            TokenType::LBracket => self.parse_list_pattern(),
+            TokenType::LBrace => {
+                let entries = self.parse_delimited(TokenType::RBrace, "Expect '}' after map pattern.", |p| {
+                    let key = match p.current_token_type() {
+                        Some(TokenType::String | TokenType::Number | TokenType::True | TokenType::False | TokenType::Nil) => p.parse_literal(),
+                        _ => {
+                            p.errors.push(format!("[line {}] Map pattern keys must be literals.", p.current_token_line_number()));
+                            return None;
+                        }
+                    };
+                    if !p.expect_peek(TokenType::Colon, "Expect ':' after map pattern key.") {
+                        return None;
+                    }
+                    p.next_token();
+                    Some((key, p.parse_pattern()?))
+                })?;
+                Some(Box::new(MapPattern { entries }))
+            }
+            _ => {
+                self.errors.push(format!("[line {}] Expect pattern.", token.line_number));
+                None
+            }
+        }
+    }
+
+    fn parse_literal(&mut self) -> Box<dyn Expression> {
+        match self.current_token_type() {
+            Some(TokenType::True | TokenType::False) => self.parse_boolean_expression(),
+            Some(TokenType::Number) => self.parse_number_expression(),
+            Some(TokenType::String) => self.parse_string_expression(),
+            _ => self.parse_nil_expression(),
+        }
+    }
+
+    // A number literal, optionally negated, as used in literal and range patterns.
+    fn parse_pattern_number(&mut self) -> Option<f64> {
+        let negative = self.current_token_type_is(Minus);
+        if negative {
+            self.next_token();
+        }
+        match self.current_token().cloned() {
+            Some(token) if token.token_type == TokenType::Number => {
+                let value: f64 = token.literal.parse().unwrap();
+                Some(if negative { -value } else { value })
+            }
+            _ => {
+                self.errors.push(format!("[line {}] Expect number in pattern.", self.current_token_line_number()));
+                None
+            }
+        }
+    }
+
+    fn parse_list_pattern(&mut self) -> Option<Box<dyn Pattern>> {
+        let mut elements = Vec::new();
+        let mut rest = RestPattern::None;
+        while !self.peek_token_type_is(TokenType::RBracket) {
+            self.next_token();
+            if self.current_token_type_is(TokenType::DotDot) {
+                rest = RestPattern::Ignore;
+                if self.peek_token_type_is(TokenType::Identifier) {
+                    self.next_token();
+                    rest = RestPattern::Bind(self.current_token().cloned().unwrap());
+                }
+                break;
+            }
+            elements.push(self.parse_pattern()?);
+            if !self.peek_token_type_is(TokenType::Comma) {
+                break;
+            }
+            self.next_token();
+        }
+        if !self.expect_peek(TokenType::RBracket, "Expect ']' after list pattern.") {
+            return None;
+        }
+        Some(Box::new(ListPattern { elements, rest }))
+    }
+
+    fn parse_instance_pattern(&mut self) -> Option<Box<dyn Pattern>> {
+        let class_name = self.current_token().cloned().unwrap();
+        self.next_token();
+        let fields = self.parse_delimited(TokenType::RBrace, "Expect '}' after instance pattern.", |p| {
+            if !p.current_token_type_is(TokenType::Identifier) {
+                p.errors.push(format!("[line {}] Expect field name in instance pattern.", p.current_token_line_number()));
+                return None;
+            }
+            let name = p.current_token().cloned().unwrap();
+            if !p.peek_token_type_is(TokenType::Colon) {
+                let binding: Box<dyn Pattern> = Box::new(BindingPattern { name: name.clone() });
+                return Some((name, binding));
+            }
+            p.next_token();
+            p.next_token();
+            Some((name, p.parse_pattern()?))
+        })?;
+        Some(Box::new(InstancePattern { class_name, class_resolution: Cell::new(Resolution::Global), fields }))
+    }
+}
@@ -0,0 +1,187 @@
+use std::io;
+use crate::ast::ast::{ExpressionStatement, Program};
+use crate::common::output::Output;
+use crate::common::symbol::Interner;
+use crate::interpreter::interpreter::{Error, Interpreter};
+use crate::lexer::lexer::{Lexer, TokenType};
+use crate::parser::parser::Parser;
+
+const HELP: &[&str] = &[
+    "Enter Lox statements or expressions; the values of expressions are echoed.",
+    "A line with an open bracket, brace, parenthesis or string continues on the next.",
+    "Commands:",
+    "  :help          Show this message.",
+    "  :load <file>   Run a script in this session.",
+    "  :reset         Start a fresh session, forgetting every global.",
+    "  :ast <code>    Print the syntax tree of some code without running it.",
+    "  :tokens <code> Print the tokens of some code.",
+    "  :quit          End the session, as end of input does.",
+];
+
+// Exit status for output that can't be written, as the CLI uses.
+const OUTPUT_FAILED: i32 = 74;
+
+// An interactive session. Each entry, a line or several if it is left open, is
+// compiled and run against the same global scope, so later entries see what
+// earlier ones defined. Errors are reported and the session carries on.
+pub struct Repl {
+    interpreter: Interpreter,
+    // Builds the interpreter for a session; `:reset` calls it again.
+    new_interpreter: Box<dyn Fn() -> Interpreter>,
+    // The lines of an entry still waiting for a closing bracket or quote.
+    pending: String,
+    // Where echoed values and listings go. Programs print wherever the
+    // interpreter's output is set.
+    out: Output,
+    diagnostics: Output,
+}
+
+impl Repl {
+    pub fn new(new_interpreter: impl Fn() -> Interpreter + 'static, out: Output, diagnostics: Output) -> Self {
+        Self { interpreter: new_interpreter(), new_interpreter: Box::new(new_interpreter), pending: String::new(), out, diagnostics }
+    }
+
+    // What to show before reading the next line: it differs while an entry is open.
+    pub fn prompt(&self) -> &'static str {
+        if self.pending.is_empty() {
+            "> "
+        } else {
+            "... "
+        }
+    }
+
+    // Handles one line of input, without its line break. Returns the exit status
+    // once the session is over.
+    pub fn feed(&mut self, line: &str) -> Option<i32> {
+        if self.pending.is_empty() {
+            if let Some(command) = line.trim().strip_prefix(':') {
+                return self.command(command);
+            }
+        }
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        if is_incomplete(&self.pending) {
+            return None;
+        }
+        let entry = std::mem::take(&mut self.pending);
+        self.evaluate(&entry)
+    }
+
+    // Runs an entry left open when input ends, which reports what is missing.
+    pub fn finish(&mut self) -> Option<i32> {
+        let entry = std::mem::take(&mut self.pending);
+        self.evaluate(&entry)
+    }
+
+    fn evaluate(&mut self, source: &str) -> Option<i32> {
+        // Without the last line break, errors at the end point at the last line.
+        let source = source.trim_end();
+        if source.is_empty() {
+            return None;
+        }
+        let program = match compile_entry(source, &self.interpreter.interner) {
+            Ok(program) => program,
+            Err(err) => return self.report(err),
+        };
+        let echo = program.statements.last().is_some_and(|stmt| stmt.as_any().is::<ExpressionStatement>());
+        match self.interpreter.eval_program(program) {
+            Ok(value) if echo => written(self.out.write_line(&value.to_string())),
+            Ok(_) => None,
+            Err(err) => self.report(err),
+        }
+    }
+
+    fn command(&mut self, command: &str) -> Option<i32> {
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+        match (name, argument) {
+            ("help", _) => HELP.iter().find_map(|line| written(self.out.write_line(line))),
+            ("quit", _) => Some(0),
+            ("reset", _) => {
+                self.interpreter = (self.new_interpreter)();
+                None
+            }
+            ("load", path) if !path.is_empty() => match self.interpreter.run_file(path) {
+                Ok(_) => None,
+                Err(err) => self.report(err),
+            },
+            ("ast", code) if !code.is_empty() => match parse_entry(code) {
+                Ok(program) => written(program.print(&self.out)),
+                Err(err) => self.report(err),
+            },
+            ("tokens", code) if !code.is_empty() => {
+                let mut lexer = Lexer::new(code.to_string());
+                lexer.tokenize();
+                lexer.errors.iter()
+                    .find_map(|err| written(self.diagnostics.write_line(err)))
+                    .or_else(|| lexer.tokens.iter().find_map(|token| written(token.print(&self.out))))
+            }
+            ("load" | "ast" | "tokens", _) => {
+                let usage = format!("Usage: :{} <{}>", name, if name == "load" { "file" } else { "code" });
+                written(self.diagnostics.write_line(&usage))
+            }
+            _ => written(self.diagnostics.write_line(&format!("Unknown command ':{}'. Type :help for the list.", name))),
+        }
+    }
+
+    // Prints `err`, unless the script called `exit`, which ends the session.
+    fn report(&self, err: Error) -> Option<i32> {
+        match err {
+            Error::Exit(status) => Some(status),
+            err => written(self.diagnostics.write_line(&err.to_string())),
+        }
+    }
+}
+
+fn written(result: io::Result<()>) -> Option<i32> {
+    result.err().map(|_| OUTPUT_FAILED)
+}
+
+// Whether `source` stops inside a string or with brackets left open, so that the
+// entry goes on in the next line.
+fn is_incomplete(source: &str) -> bool {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.tokenize();
+    if lexer.errors.iter().any(|err| err.ends_with("Unterminated string.")) {
+        return true;
+    }
+    let depth: i32 = lexer.tokens.iter()
+        .map(|token| match token.token_type {
+            TokenType::LParen | TokenType::LBrace | TokenType::LBracket => 1,
+            TokenType::RParen | TokenType::RBrace | TokenType::RBracket => -1,
+            _ => 0,
+        })
+        .sum();
+    depth > 0
+}
+
+// Compiles an entry. A final statement may leave out its semicolon, so that
+// `1 + 2` works as well as `1 + 2;`.
+fn compile_entry(source: &str, interner: &Interner) -> Result<Program, Error> {
+    Interpreter::compile_with(source, interner).or_else(|err| Interpreter::compile_with(&with_semicolon(source), interner).map_err(|_| err))
+}
+
+// Like compile_entry, without resolving: `:ast` shows any code that parses.
+fn parse_entry(source: &str) -> Result<Program, Error> {
+    parse(source).or_else(|err| parse(&with_semicolon(source)).map_err(|_| err))
+}
+
+fn parse(source: &str) -> Result<Program, Error> {
+    let mut lexer = Lexer::new(source.to_string());
+    lexer.tokenize();
+    if !lexer.errors.is_empty() {
+        return Err(Error::Compile(lexer.errors));
+    }
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program();
+    if !parser.errors.is_empty() {
+        return Err(Error::Compile(parser.errors));
+    }
+    Ok(program)
+}
+
+fn with_semicolon(source: &str) -> String {
+    format!("{};", source.trim_end())
+}
@@ -0,0 +1,425 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use crate::ast::ast::{AssignExpression, BindingPattern, BlockStatement, BreakStatement, CallExpression, ClassStatement, ContinueStatement, Expression, ExpressionStatement, ForStatement, FunctionStatement, GetExpression, Grouping, Identifier, IfStatement, ImportStatement, IndexExpression, InfixExpression, InstancePattern, ListLiteral, ListPattern, MapLiteral, MapPattern, MatchExpression, MatchStatement, Pattern, PrefixExpression, PrintStatement, Program, Resolution, RestPattern, ReturnStatement, Slot, Statement, TernaryExpression, ThrowStatement, TryStatement, UpdateExpression, VarStatement, WhileStatement};
+use crate::common::symbol::{Known, Symbol};
+use crate::lexer::lexer::{Token, TokenType};
+
+// Static checks run between parsing and evaluation. Errors use the same
+// "[line N] message" format as the parser and exit with 65.
+//
+// The resolver also tells the evaluator where local variables live: each local
+// scope becomes a frame of slots at runtime, and every declaration and variable
+// reference is annotated with the slot it uses. Globals stay in a table looked up
+// by name, so code can refer to globals defined after it.
+//
+// Each reference is bound to one variable here, once: the innermost local
+// declared before it, else a global declared before it, else the innermost local
+// declared after it in an enclosing scope (so functions can use variables
+// declared later in their block), else a global defined at runtime.
+pub struct Resolver {
+    scopes: Vec<Scope>,
+    // Names declared at the top level so far.
+    globals: HashSet<Symbol>,
+    // Labels of the enclosing loops, innermost last. Unlabeled loops push None.
+    // Reset at function boundaries: loop control can't jump out of a function.
+    loops: Vec<Option<Symbol>>,
+    in_function: bool,
+    // Whether the code is in a method, or in a function nested in one, where `this`
+    // is the instance the method was called on.
+    in_class: bool,
+    // Whether the code is directly in an `init` method, which can't return a value.
+    in_initializer: bool,
+    // Whether the statement being resolved is in the body or catch clause of a try
+    // statement in the current function, where a call can't be a tail call.
+    in_try: bool,
+    pub errors: Vec<String>,
+}
+
+// A local scope. Every variable declared directly in the scope gets its slot when
+// the scope begins, so that a function can use a variable declared after it in
+// the enclosing block, as it could when scopes were searched by name.
+#[derive(Default)]
+struct Scope {
+    // Whether each variable declared so far has finished its initializer.
+    declared: HashMap<Symbol, bool>,
+    slots: HashMap<Symbol, usize>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            globals: HashSet::new(),
+            loops: Vec::new(),
+            in_function: false,
+            in_class: false,
+            in_initializer: false,
+            in_try: false,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve_program(&mut self, program: &Program) {
+        for stmt in program.statements.iter() {
+            self.resolve_statement(stmt.as_ref());
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(format!("[line {}] {}", token.line_number, message));
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            self.globals.insert(name.lexeme.clone());
+            return;
+        };
+        if scope.declared.contains_key(&name.lexeme) {
+            self.error(name, "Already a variable with this name in this scope.");
+            return;
+        }
+        scope.declared.insert(name.lexeme.clone(), false);
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.declared.insert(name.lexeme.clone(), true);
+        }
+    }
+
+    // The slot of `name` in the innermost scope, allocating the next free one if it
+    // has none yet. None at the top level, where variables are globals.
+    fn slot(&mut self, name: &Token) -> Option<usize> {
+        let scope = self.scopes.last_mut()?;
+        let next = scope.slots.len();
+        Some(*scope.slots.entry(name.lexeme.clone()).or_insert(next))
+    }
+
+    // Allocates slots for the variables `stmt` declares in the current scope,
+    // including those in branches and loop bodies that aren't blocks of their own.
+    fn hoist(&mut self, stmt: &dyn Statement) {
+        let any = stmt.as_any();
+        if let Some(v) = any.downcast_ref::<VarStatement>() {
+            self.slot(&v.name);
+        } else if let Some(f) = any.downcast_ref::<FunctionStatement>() {
+            self.slot(&f.name);
+        } else if let Some(c) = any.downcast_ref::<ClassStatement>() {
+            self.slot(&c.name);
+        } else if let Some(i) = any.downcast_ref::<IfStatement>() {
+            self.hoist(i.consequence.as_ref());
+            if let Some(alternative) = &i.alternative {
+                self.hoist(alternative.as_ref());
+            }
+        } else if let Some(w) = any.downcast_ref::<WhileStatement>() {
+            self.hoist(w.body.as_ref());
+        }
+    }
+
+    // Binds a reference to `name` made inside `scopes`, by the rule above.
+    fn resolve_local(&self, scopes: &[Scope], name: &Token, resolution: &Cell<Resolution>) {
+        let name = &name.lexeme;
+        let local = |depth: usize| {
+            let scope = &scopes[scopes.len() - 1 - depth];
+            let shadowed = scopes[scopes.len() - depth..].iter().filter(|inner| inner.slots.contains_key(name)).count();
+            Resolution::Local { slot: Slot { depth, index: scope.slots[name] }, shadowed }
+        };
+        let mut declared_later = None;
+        for (depth, scope) in scopes.iter().rev().enumerate() {
+            if scope.declared.contains_key(name) {
+                resolution.set(local(depth));
+                return;
+            }
+            if declared_later.is_none() && scope.slots.contains_key(name) {
+                declared_later = Some(depth);
+            }
+        }
+        resolution.set(match declared_later {
+            Some(depth) if !self.globals.contains(name) => local(depth),
+            _ => Resolution::Global,
+        });
+    }
+
+    fn resolve_statement(&mut self, stmt: &dyn Statement) {
+        let any = stmt.as_any();
+        if let Some(e) = any.downcast_ref::<ExpressionStatement>() {
+            self.resolve_expression(e.expression.as_ref());
+        } else if let Some(p) = any.downcast_ref::<PrintStatement>() {
+            self.resolve_expression(p.expression.as_ref());
+        } else if let Some(v) = any.downcast_ref::<VarStatement>() {
+            v.slot.set(self.slot(&v.name));
+            self.declare(&v.name);
+            if let Some(initializer) = &v.initializer {
+                self.resolve_expression(initializer.as_ref());
+            }
+            self.define(&v.name);
+        } else if let Some(b) = any.downcast_ref::<BlockStatement>() {
+            self.begin_scope();
+            for stmt in b.statements.iter() {
+                self.hoist(stmt.as_ref());
+            }
+            for stmt in b.statements.iter() {
+                self.resolve_statement(stmt.as_ref());
+            }
+            self.end_scope();
+        } else if let Some(i) = any.downcast_ref::<IfStatement>() {
+            self.resolve_expression(i.condition.as_ref());
+            self.resolve_statement(i.consequence.as_ref());
+            if let Some(alternative) = &i.alternative {
+                self.resolve_statement(alternative.as_ref());
+            }
+        } else if let Some(w) = any.downcast_ref::<WhileStatement>() {
+            self.resolve_expression(w.condition.as_ref());
+            self.begin_loop(&w.label);
+            self.resolve_statement(w.body.as_ref());
+            self.loops.pop();
+        } else if let Some(f) = any.downcast_ref::<ForStatement>() {
+            self.begin_scope();
+            if let Some(initializer) = &f.initializer {
+                self.hoist(initializer.as_ref());
+            }
+            self.hoist(f.body.as_ref());
+            if let Some(initializer) = &f.initializer {
+                self.resolve_statement(initializer.as_ref());
+            }
+            if let Some(condition) = &f.condition {
+                self.resolve_expression(condition.as_ref());
+            }
+            if let Some(increment) = &f.increment {
+                self.resolve_expression(increment.as_ref());
+            }
+            self.begin_loop(&f.label);
+            self.resolve_statement(f.body.as_ref());
+            self.loops.pop();
+            self.end_scope();
+        } else if let Some(f) = any.downcast_ref::<FunctionStatement>() {
+            f.slot.set(self.slot(&f.name));
+            self.declare(&f.name);
+            self.define(&f.name);
+            self.resolve_function(f, false);
+        } else if let Some(r) = any.downcast_ref::<ReturnStatement>() {
+            if !self.in_function {
+                self.error(&r.token, "Can't return from top-level code.");
+            }
+            if let Some(value) = &r.value {
+                if self.in_initializer {
+                    self.error(&r.token, "Can't return a value from an initializer.");
+                }
+                self.resolve_expression(value.as_ref());
+                r.tail_call.set(self.in_function && !self.in_try && value.as_any().is::<CallExpression>());
+            }
+        } else if let Some(t) = any.downcast_ref::<ThrowStatement>() {
+            self.resolve_expression(t.value.as_ref());
+        } else if let Some(t) = any.downcast_ref::<TryStatement>() {
+            let enclosing_in_try = std::mem::replace(&mut self.in_try, true);
+            self.resolve_statement(&t.body);
+            if let Some(catch_clause) = &t.catch_clause {
+                self.begin_scope();
+                self.slot(&catch_clause.name);
+                self.declare(&catch_clause.name);
+                self.define(&catch_clause.name);
+                self.resolve_statement(&catch_clause.body);
+                self.end_scope();
+            }
+            self.in_try = enclosing_in_try;
+            if let Some(finally_body) = &t.finally_body {
+                self.resolve_statement(finally_body);
+            }
+        } else if let Some(i) = any.downcast_ref::<ImportStatement>() {
+            if !self.scopes.is_empty() || self.in_function {
+                self.error(&i.token, "Can only import at top level.");
+            }
+            match &i.alias {
+                Some(alias) => self.declare(alias),
+                None => i.names.iter().for_each(|name| self.declare(name.binding())),
+            }
+        } else if let Some(c) = any.downcast_ref::<ClassStatement>() {
+            c.slot.set(self.slot(&c.name));
+            self.declare(&c.name);
+            self.define(&c.name);
+            self.resolve_class(c);
+        } else if let Some(m) = any.downcast_ref::<MatchStatement>() {
+            self.resolve_expression(m.subject.as_ref());
+            for arm in m.arms.iter() {
+                self.begin_scope();
+                self.resolve_pattern(arm.pattern.as_ref());
+                self.hoist(arm.body.as_ref());
+                if let Some(guard) = &arm.guard {
+                    self.resolve_expression(guard.as_ref());
+                }
+                self.resolve_statement(arm.body.as_ref());
+                self.end_scope();
+            }
+        } else if let Some(b) = any.downcast_ref::<BreakStatement>() {
+            self.resolve_loop_jump(&b.token, &b.label);
+        } else if let Some(c) = any.downcast_ref::<ContinueStatement>() {
+            self.resolve_loop_jump(&c.token, &c.label);
+        }
+    }
+
+    // Each method runs with `this` in the only slot of a scope of its own, around
+    // the scope of its parameters.
+    fn resolve_class(&mut self, class: &ClassStatement) {
+        let enclosing_in_class = std::mem::replace(&mut self.in_class, true);
+        for method in class.methods.iter() {
+            let mut scope = Scope::default();
+            scope.declared.insert(Symbol::known(Known::This), true);
+            scope.slots.insert(Symbol::known(Known::This), 0);
+            self.scopes.push(scope);
+            self.resolve_function(method, method.name.lexeme == "init");
+            self.end_scope();
+        }
+        self.in_class = enclosing_in_class;
+    }
+
+    fn resolve_function(&mut self, function: &FunctionStatement, initializer: bool) {
+        let enclosing_loops = std::mem::take(&mut self.loops);
+        let enclosing_in_function = std::mem::replace(&mut self.in_function, true);
+        let enclosing_in_initializer = std::mem::replace(&mut self.in_initializer, initializer);
+        let enclosing_in_try = std::mem::replace(&mut self.in_try, false);
+        // Parameters take the first slots, in order, and the body shares their scope.
+        self.begin_scope();
+        for param in function.params.iter() {
+            self.slot(param);
+            self.declare(param);
+            self.define(param);
+        }
+        for stmt in function.body.statements.iter() {
+            self.hoist(stmt.as_ref());
+        }
+        for stmt in function.body.statements.iter() {
+            self.resolve_statement(stmt.as_ref());
+        }
+        self.end_scope();
+        self.in_function = enclosing_in_function;
+        self.in_initializer = enclosing_in_initializer;
+        self.in_try = enclosing_in_try;
+        self.loops = enclosing_loops;
+    }
+
+    // Declares every variable a pattern binds in the current (arm) scope, in the
+    // order the evaluator binds them.
+    fn resolve_pattern(&mut self, pattern: &dyn Pattern) {
+        let any = pattern.as_any();
+        if let Some(b) = any.downcast_ref::<BindingPattern>() {
+            self.slot(&b.name);
+            self.declare(&b.name);
+            self.define(&b.name);
+        } else if let Some(l) = any.downcast_ref::<ListPattern>() {
+            for element in l.elements.iter() {
+                self.resolve_pattern(element.as_ref());
+            }
+            if let RestPattern::Bind(name) = &l.rest {
+                self.slot(name);
+                self.declare(name);
+                self.define(name);
+            }
+        } else if let Some(m) = any.downcast_ref::<MapPattern>() {
+            for (_, value) in m.entries.iter() {
+                self.resolve_pattern(value.as_ref());
+            }
+        } else if let Some(i) = any.downcast_ref::<InstancePattern>() {
+            // Patterns are matched before the arm's scope exists.
+            let enclosing = &self.scopes[..self.scopes.len() - 1];
+            self.resolve_local(enclosing, &i.class_name, &i.class_resolution);
+            for (_, field) in i.fields.iter() {
+                self.resolve_pattern(field.as_ref());
+            }
+        }
+    }
+
+    fn begin_loop(&mut self, label: &Option<Token>) {
+        if let Some(label) = label {
+            if self.loops.contains(&Some(label.lexeme.clone())) {
+                self.error(label, &format!("Label '{}' is already used by an enclosing loop.", label.lexeme));
+            }
+        }
+        self.loops.push(label.as_ref().map(|l| l.lexeme.clone()));
+    }
+
+    fn resolve_loop_jump(&mut self, keyword: &Token, label: &Option<Token>) {
+        if self.loops.is_empty() {
+            self.error(keyword, &format!("Can't use '{}' outside of a loop.", keyword.lexeme));
+            return;
+        }
+        if let Some(label) = label {
+            if !self.loops.contains(&Some(label.lexeme.clone())) {
+                self.error(label, &format!("No enclosing loop labeled '{}'.", label.lexeme));
+            }
+        }
+    }
+
+    fn resolve_expression(&mut self, exp: &dyn Expression) {
+        let any = exp.as_any();
+        if let Some(i) = any.downcast_ref::<Identifier>() {
+            if i.token.token_type == TokenType::This && !self.in_class {
+                self.error(&i.token, "Can't use 'this' outside of a class.");
+            }
+            if let Some(scope) = self.scopes.last() {
+                if scope.declared.get(&i.token.lexeme) == Some(&false) {
+                    self.error(&i.token, "Can't read local variable in its own initializer.");
+                }
+            }
+            self.resolve_local(&self.scopes, &i.token, &i.resolution);
+        } else if let Some(g) = any.downcast_ref::<Grouping>() {
+            self.resolve_expression(g.expression.as_ref());
+        } else if let Some(p) = any.downcast_ref::<PrefixExpression>() {
+            self.resolve_expression(p.right.as_ref());
+        } else if let Some(i) = any.downcast_ref::<InfixExpression>() {
+            self.resolve_expression(i.left.as_ref());
+            self.resolve_expression(i.right.as_ref());
+        } else if let Some(t) = any.downcast_ref::<TernaryExpression>() {
+            self.resolve_expression(t.condition.as_ref());
+            self.resolve_expression(t.consequence.as_ref());
+            self.resolve_expression(t.alternative.as_ref());
+        } else if let Some(a) = any.downcast_ref::<AssignExpression>() {
+            self.resolve_expression(a.value.as_ref());
+            self.resolve_expression(a.target.as_ref());
+        } else if let Some(u) = any.downcast_ref::<UpdateExpression>() {
+            self.resolve_expression(u.target.as_ref());
+        } else if let Some(g) = any.downcast_ref::<GetExpression>() {
+            self.resolve_expression(g.object.as_ref());
+        } else if let Some(i) = any.downcast_ref::<IndexExpression>() {
+            self.resolve_expression(i.left.as_ref());
+            self.resolve_expression(i.index.as_ref());
+        } else if let Some(l) = any.downcast_ref::<ListLiteral>() {
+            for element in l.elements.iter() {
+                self.resolve_expression(element.as_ref());
+            }
+        } else if let Some(m) = any.downcast_ref::<MapLiteral>() {
+            for (key, value) in m.pairs.iter() {
+                self.resolve_expression(key.as_ref());
+                self.resolve_expression(value.as_ref());
+            }
+        } else if let Some(c) = any.downcast_ref::<CallExpression>() {
+            self.resolve_expression(c.callee.as_ref());
+            for argument in c.arguments.iter() {
+                self.resolve_expression(argument.as_ref());
+            }
+        } else if let Some(m) = any.downcast_ref::<MatchExpression>() {
+            self.resolve_expression(m.subject.as_ref());
+            for arm in m.arms.iter() {
+                self.begin_scope();
+                self.resolve_pattern(arm.pattern.as_ref());
+                if let Some(guard) = &arm.guard {
+                    self.resolve_expression(guard.as_ref());
+                }
+                self.resolve_expression(arm.body.as_ref());
+                self.end_scope();
+            }
+        }
+    }
+}
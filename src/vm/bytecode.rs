@@ -0,0 +1,434 @@
+use std::rc::Rc;
+use bytes::{Buf, BufMut};
+use crate::common::symbol::Interner;
+use crate::object::object::Value;
+use crate::vm::chunk::{Chunk, FunctionProto, Op, RangeDesc, UpvalueDesc};
+use crate::vm::verifier;
+
+// Compiled scripts saved to disk, so they can run without being lexed, parsed and
+// compiled again. A file is:
+//
+//   "LOXC"  magic
+//   u16     format version
+//   u32     payload length
+//   ...     payload: the script's function, with nested functions inside it
+//   u32     FNV-1a checksum of everything before it
+//
+// All integers are little-endian. Files are verified before they are run, so a
+// damaged or hand-edited file is rejected instead of crashing the VM.
+
+const MAGIC: &[u8; 4] = b"LOXC";
+// Bumped whenever the encoding or the meaning of an instruction changes.
+pub const FORMAT_VERSION: u16 = 2;
+const HEADER_LEN: usize = 4 + 2 + 4;
+const CHECKSUM_LEN: usize = 4;
+// Deeper nesting than any real program needs; keeps hostile files from
+// exhausting the stack while they are read.
+const MAX_NESTING: usize = 1024;
+
+const NUMBER_CONSTANT: u8 = 0;
+const STRING_CONSTANT: u8 = 1;
+
+// Encodes a compiled script.
+pub fn write(script: &FunctionProto) -> Vec<u8> {
+    let mut payload = Vec::new();
+    write_function(&mut payload, script);
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len() + CHECKSUM_LEN);
+    bytes.put_slice(MAGIC);
+    bytes.put_u16_le(FORMAT_VERSION);
+    bytes.put_u32_le(payload.len() as u32);
+    bytes.put_slice(&payload);
+    bytes.put_u32_le(checksum(&bytes));
+    bytes
+}
+
+// Decodes and verifies a compiled script, interning its names in `interner`; the
+// error says what is wrong with it.
+pub fn read(bytes: &[u8], interner: &Interner) -> Result<Rc<FunctionProto>, String> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err("not a compiled Lox file".to_string());
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err("the file is truncated".to_string());
+    }
+    let mut header = &bytes[MAGIC.len()..HEADER_LEN];
+    let version = header.get_u16_le();
+    if version != FORMAT_VERSION {
+        return Err(format!("compiled for format version {}, but this interpreter reads version {}", version, FORMAT_VERSION));
+    }
+    let payload_len = header.get_u32_le() as usize;
+    let end = HEADER_LEN + payload_len;
+    if bytes.len() < end + CHECKSUM_LEN {
+        return Err("the file is truncated".to_string());
+    }
+    if bytes.len() > end + CHECKSUM_LEN {
+        return Err("unexpected data after the end of the file".to_string());
+    }
+    let expected = (&bytes[end..]).get_u32_le();
+    if checksum(&bytes[..end]) != expected {
+        return Err("checksum mismatch; the file is corrupted".to_string());
+    }
+
+    let mut reader = Reader { bytes: &bytes[HEADER_LEN..end], depth: 0, interner };
+    let script = reader.function()?;
+    if reader.bytes.has_remaining() {
+        return Err("unexpected data after the script".to_string());
+    }
+    if script.arity != 0 || script.method || !script.upvalues.is_empty() {
+        return Err("the script must take no parameters and capture no variables".to_string());
+    }
+    verifier::verify(&script)?;
+    Ok(script)
+}
+
+// 32-bit FNV-1a.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash: u32, byte| (hash ^ *byte as u32).wrapping_mul(0x01000193))
+}
+
+fn write_function(out: &mut Vec<u8>, function: &FunctionProto) {
+    write_string(out, &function.name);
+    out.put_u32_le(function.arity as u32);
+    out.put_u8(function.method as u8);
+    out.put_u32_le(function.upvalues.len() as u32);
+    for upvalue in function.upvalues.iter() {
+        out.put_u8(upvalue.is_local as u8);
+        out.put_u32_le(upvalue.index);
+        write_string(out, &upvalue.name);
+    }
+
+    let chunk = &function.chunk;
+    out.put_u32_le(chunk.code.len() as u32);
+    for op in chunk.code.iter() {
+        write_op(out, *op);
+    }
+    // Lines as runs of (line, number of instructions), since most lines compile
+    // to several instructions.
+    let mut runs: Vec<(i32, u32)> = Vec::new();
+    for line in chunk.lines.iter() {
+        match runs.last_mut() {
+            Some((last, count)) if last == line => *count += 1,
+            _ => runs.push((*line, 1)),
+        }
+    }
+    out.put_u32_le(runs.len() as u32);
+    for (line, count) in runs {
+        out.put_i32_le(line);
+        out.put_u32_le(count);
+    }
+
+    out.put_u32_le(chunk.constants.len() as u32);
+    for constant in chunk.constants.iter() {
+        match constant {
+            Value::Number(n) => {
+                out.put_u8(NUMBER_CONSTANT);
+                out.put_f64_le(*n);
+            }
+            Value::String(s) => {
+                out.put_u8(STRING_CONSTANT);
+                write_string(out, s);
+            }
+            _ => unreachable!("the compiler only makes number and string constants"),
+        }
+    }
+    out.put_u32_le(chunk.names.len() as u32);
+    for name in chunk.names.iter() {
+        write_string(out, name);
+    }
+    out.put_u32_le(chunk.ranges.len() as u32);
+    for range in chunk.ranges.iter() {
+        out.put_f64_le(range.start);
+        out.put_f64_le(range.end);
+        out.put_u8(range.inclusive as u8);
+    }
+    out.put_u32_le(chunk.functions.len() as u32);
+    for nested in chunk.functions.iter() {
+        write_function(out, nested);
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    out.put_u32_le(s.len() as u32);
+    out.put_slice(s.as_bytes());
+}
+
+// Each instruction is a one-byte opcode followed by its operands.
+fn write_op(out: &mut Vec<u8>, op: Op) {
+    let (code, operands): (u8, &[u32]) = match op {
+        Op::Constant(i) => (0, &[i]),
+        Op::Nil => (1, &[]),
+        Op::Undefined => (2, &[]),
+        Op::True => (3, &[]),
+        Op::False => (4, &[]),
+        Op::Pop => (5, &[]),
+        Op::Dup => (6, &[]),
+        Op::Dup2 => (7, &[]),
+        Op::Bury(n) => (8, &[n]),
+        Op::GetLocal(slot) => (9, &[slot]),
+        Op::SetLocal(slot) => (10, &[slot]),
+        Op::DefineLocal(slot) => (11, &[slot]),
+        Op::GetLocalChecked(slot, name) => (12, &[slot, name]),
+        Op::SetLocalChecked(slot, name) => (13, &[slot, name]),
+        Op::GetUpvalue(i) => (14, &[i]),
+        Op::SetUpvalue(i) => (15, &[i]),
+        Op::GetGlobal(i) => (16, &[i]),
+        Op::SetGlobal(i) => (17, &[i]),
+        Op::DefineGlobal(i) => (18, &[i]),
+        Op::PopScope(n) => (19, &[n]),
+        Op::Add => (20, &[]),
+        Op::Subtract => (21, &[]),
+        Op::Multiply => (22, &[]),
+        Op::Divide => (23, &[]),
+        Op::Modulo => (24, &[]),
+        Op::Power => (25, &[]),
+        Op::Less => (26, &[]),
+        Op::LessEqual => (27, &[]),
+        Op::Greater => (28, &[]),
+        Op::GreaterEqual => (29, &[]),
+        Op::Equal => (30, &[]),
+        Op::NotEqual => (31, &[]),
+        Op::Negate => (32, &[]),
+        Op::Not => (33, &[]),
+        Op::Increment => (34, &[]),
+        Op::Decrement => (35, &[]),
+        Op::Jump(target) => (36, &[target]),
+        Op::JumpIfFalse(target) => (37, &[target]),
+        Op::JumpIfTrue(target) => (38, &[target]),
+        Op::PopJumpIfFalse(target) => (39, &[target]),
+        Op::Call(n) => (40, &[n]),
+        Op::Closure(i) => (41, &[i]),
+        Op::Class(name, methods) => (42, &[name, methods]),
+        Op::Return => (43, &[]),
+        Op::SetReturn => (44, &[]),
+        Op::ReturnPending => (45, &[]),
+        Op::Print => (46, &[]),
+        Op::List(n) => (47, &[n]),
+        Op::Map(n) => (48, &[n]),
+        Op::CheckKey => (49, &[]),
+        Op::GetIndex => (50, &[]),
+        Op::SetIndex => (51, &[]),
+        Op::CheckIndex => (52, &[]),
+        Op::GetProperty(i) => (53, &[i]),
+        Op::SetProperty(i) => (54, &[i]),
+        Op::CheckProperty(i) => (55, &[i]),
+        Op::PushCatch(target) => (56, &[target]),
+        Op::PushFinally(target) => (57, &[target]),
+        Op::PopHandler => (58, &[]),
+        Op::Rethrow => (59, &[]),
+        Op::Throw => (60, &[]),
+        Op::InRange(i) => (61, &[i]),
+        Op::IsList(len, exact) => (62, &[len, exact as u32]),
+        Op::IsMap => (63, &[]),
+        Op::HasEntry => (64, &[]),
+        Op::IsInstance(i) => (65, &[i]),
+        Op::HasField(i) => (66, &[i]),
+        Op::ListElement(i) => (67, &[i]),
+        Op::ListRest(i) => (68, &[i]),
+        Op::MapEntry => (69, &[]),
+        Op::NoMatch => (70, &[]),
+        Op::Import(i) => (71, &[i]),
+        Op::TailCall(n) => (72, &[n]),
+    };
+    out.put_u8(code);
+    for operand in operands {
+        out.put_u32_le(*operand);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    // How many functions enclose the one being read.
+    depth: usize,
+    // Where the names get their symbols, so that they equal the ones the
+    // interpreter running the script looks up.
+    interner: &'a Interner,
+}
+
+impl<'a> Reader<'a> {
+    fn need(&self, len: usize) -> Result<(), String> {
+        if self.bytes.remaining() < len {
+            return Err("unexpected end of data".to_string());
+        }
+        Ok(())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        self.need(1)?;
+        Ok(self.bytes.get_u8())
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.need(4)?;
+        Ok(self.bytes.get_u32_le())
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        self.need(4)?;
+        Ok(self.bytes.get_i32_le())
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        self.need(8)?;
+        Ok(self.bytes.get_f64_le())
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(format!("invalid boolean {}", byte)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        self.need(len)?;
+        let (text, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        String::from_utf8(text.to_vec()).map_err(|_| "invalid UTF-8 in a string".to_string())
+    }
+
+    fn function(&mut self) -> Result<Rc<FunctionProto>, String> {
+        if self.depth == MAX_NESTING {
+            return Err("functions are nested too deeply".to_string());
+        }
+        let name = self.interner.intern(&self.string()?);
+        let arity = self.u32()? as usize;
+        let method = self.bool()?;
+        let mut upvalues = Vec::new();
+        for _ in 0..self.u32()? {
+            let is_local = self.bool()?;
+            let index = self.u32()?;
+            let name = self.interner.intern(&self.string()?);
+            upvalues.push(UpvalueDesc { is_local, index, name });
+        }
+
+        let mut chunk = Chunk::default();
+        for _ in 0..self.u32()? {
+            let op = self.op().map_err(|message| format!("in {} at {:04}: {}", name, chunk.code.len(), message))?;
+            chunk.code.push(op);
+        }
+        for _ in 0..self.u32()? {
+            let line = self.i32()?;
+            let count = self.u32()? as usize;
+            if count > chunk.code.len() - chunk.lines.len() {
+                return Err(format!("in {}: the line table covers more instructions than there are", name));
+            }
+            chunk.lines.extend(std::iter::repeat(line).take(count));
+        }
+        if chunk.lines.len() != chunk.code.len() {
+            return Err(format!("in {}: the line table doesn't cover every instruction", name));
+        }
+
+        for _ in 0..self.u32()? {
+            let constant = match self.u8()? {
+                NUMBER_CONSTANT => Value::Number(self.f64()?),
+                STRING_CONSTANT => Value::string(&self.string()?),
+                tag => return Err(format!("in {}: unknown constant type {}", name, tag)),
+            };
+            chunk.constants.push(constant);
+        }
+        for _ in 0..self.u32()? {
+            chunk.names.push(self.interner.intern(&self.string()?));
+        }
+        for _ in 0..self.u32()? {
+            let start = self.f64()?;
+            let end = self.f64()?;
+            let inclusive = self.bool()?;
+            chunk.ranges.push(RangeDesc { start, end, inclusive });
+        }
+        self.depth += 1;
+        for _ in 0..self.u32()? {
+            chunk.functions.push(self.function()?);
+        }
+        self.depth -= 1;
+        Ok(Rc::new(FunctionProto { name, arity, method, upvalues, chunk }))
+    }
+
+    fn op(&mut self) -> Result<Op, String> {
+        let code = self.u8()?;
+        Ok(match code {
+            0 => Op::Constant(self.u32()?),
+            1 => Op::Nil,
+            2 => Op::Undefined,
+            3 => Op::True,
+            4 => Op::False,
+            5 => Op::Pop,
+            6 => Op::Dup,
+            7 => Op::Dup2,
+            8 => Op::Bury(self.u32()?),
+            9 => Op::GetLocal(self.u32()?),
+            10 => Op::SetLocal(self.u32()?),
+            11 => Op::DefineLocal(self.u32()?),
+            12 => Op::GetLocalChecked(self.u32()?, self.u32()?),
+            13 => Op::SetLocalChecked(self.u32()?, self.u32()?),
+            14 => Op::GetUpvalue(self.u32()?),
+            15 => Op::SetUpvalue(self.u32()?),
+            16 => Op::GetGlobal(self.u32()?),
+            17 => Op::SetGlobal(self.u32()?),
+            18 => Op::DefineGlobal(self.u32()?),
+            19 => Op::PopScope(self.u32()?),
+            20 => Op::Add,
+            21 => Op::Subtract,
+            22 => Op::Multiply,
+            23 => Op::Divide,
+            24 => Op::Modulo,
+            25 => Op::Power,
+            26 => Op::Less,
+            27 => Op::LessEqual,
+            28 => Op::Greater,
+            29 => Op::GreaterEqual,
+            30 => Op::Equal,
+            31 => Op::NotEqual,
+            32 => Op::Negate,
+            33 => Op::Not,
+            34 => Op::Increment,
+            35 => Op::Decrement,
+            36 => Op::Jump(self.u32()?),
+            37 => Op::JumpIfFalse(self.u32()?),
+            38 => Op::JumpIfTrue(self.u32()?),
+            39 => Op::PopJumpIfFalse(self.u32()?),
+            40 => Op::Call(self.u32()?),
+            41 => Op::Closure(self.u32()?),
+            42 => Op::Class(self.u32()?, self.u32()?),
+            43 => Op::Return,
+            44 => Op::SetReturn,
+            45 => Op::ReturnPending,
+            46 => Op::Print,
+            47 => Op::List(self.u32()?),
+            48 => Op::Map(self.u32()?),
+            49 => Op::CheckKey,
+            50 => Op::GetIndex,
+            51 => Op::SetIndex,
+            52 => Op::CheckIndex,
+            53 => Op::GetProperty(self.u32()?),
+            54 => Op::SetProperty(self.u32()?),
+            55 => Op::CheckProperty(self.u32()?),
+            56 => Op::PushCatch(self.u32()?),
+            57 => Op::PushFinally(self.u32()?),
+            58 => Op::PopHandler,
+            59 => Op::Rethrow,
+            60 => Op::Throw,
+            61 => Op::InRange(self.u32()?),
+            62 => {
+                let len = self.u32()?;
+                match self.u32()? {
+                    0 => Op::IsList(len, false),
+                    1 => Op::IsList(len, true),
+                    flag => return Err(format!("invalid IS_LIST flag {}", flag)),
+                }
+            }
+            63 => Op::IsMap,
+            64 => Op::HasEntry,
+            65 => Op::IsInstance(self.u32()?),
+            66 => Op::HasField(self.u32()?),
+            67 => Op::ListElement(self.u32()?),
+            68 => Op::ListRest(self.u32()?),
+            69 => Op::MapEntry,
+            70 => Op::NoMatch,
+            71 => Op::Import(self.u32()?),
+            72 => Op::TailCall(self.u32()?),
+            code => return Err(format!("unknown opcode {}", code)),
+        })
+    }
+}
@@ -0,0 +1,243 @@
+use std::rc::Rc;
+use crate::common::symbol::Symbol;
+use crate::object::object::Value;
+
+// One VM instruction. Operands index the chunk's tables (constants, names,
+// functions, ranges) or the stack; jump targets are absolute positions in `code`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Constant(u32),
+    Nil,
+    // The contents of a local's slot before its declaration has run.
+    Undefined,
+    True,
+    False,
+    Pop,
+    Dup,
+    // Duplicates the top two values, keeping their order.
+    Dup2,
+    // Moves the top value below the `n` values under it.
+    Bury(u32),
+
+    GetLocal(u32),
+    SetLocal(u32),
+    // Pops the top value into a local as its declaration runs.
+    DefineLocal(u32),
+    // For locals that may still be undefined, like one declared in a branch that
+    // didn't run: using one that is fails with an error that gives the local's name.
+    GetLocalChecked(u32, u32),
+    SetLocalChecked(u32, u32),
+    GetUpvalue(u32),
+    SetUpvalue(u32),
+    GetGlobal(u32),
+    SetGlobal(u32),
+    DefineGlobal(u32),
+    // Ends a scope: pops its `n` locals, moving the captured ones into their upvalues.
+    PopScope(u32),
+
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    Negate,
+    Not,
+    // Adds 1 to or subtracts 1 from a number, for `++` and `--`.
+    Increment,
+    Decrement,
+
+    Jump(u32),
+    // Conditional jumps that leave the condition on the stack, for `and` and `or`.
+    JumpIfFalse(u32),
+    JumpIfTrue(u32),
+    PopJumpIfFalse(u32),
+
+    // Calls the value below the `n` arguments on top of the stack.
+    Call(u32),
+    // Like Call followed by Return, but a Lox function called this way takes over
+    // the returning function's frame instead of adding one.
+    TailCall(u32),
+    Closure(u32),
+    // Makes a class with the given name from the `n` method closures on top of
+    // the stack, each stored under its function's name.
+    Class(u32, u32),
+    Return,
+    // Stores the top value as the frame's result without returning, so `finally`
+    // blocks can run between a `return` and the actual return.
+    SetReturn,
+    ReturnPending,
+    Print,
+
+    List(u32),
+    Map(u32),
+    // Fails unless the top value can be a map key.
+    CheckKey,
+    GetIndex,
+    SetIndex,
+    // Fails unless the top two values are a container and an index that can be
+    // assigned, before the assigned value is evaluated.
+    CheckIndex,
+    GetProperty(u32),
+    SetProperty(u32),
+    CheckProperty(u32),
+
+    // Installs a handler for errors raised until the matching PopHandler: catch
+    // handlers receive the error value, finally handlers rethrow it when done.
+    PushCatch(u32),
+    PushFinally(u32),
+    PopHandler,
+    Rethrow,
+    Throw,
+
+    // Pattern tests: each pushes whether the value below it matches.
+    InRange(u32),
+    IsList(u32, bool),
+    IsMap,
+    HasEntry,
+    IsInstance(u32),
+    HasField(u32),
+    // Pattern destructuring.
+    ListElement(u32),
+    ListRest(u32),
+    MapEntry,
+    NoMatch,
+
+    // Loads the module whose path is the given constant and pushes it.
+    Import(u32),
+}
+
+impl Op {
+    // The instruction's name in listings.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Constant(..) => "CONSTANT",
+            Op::Nil => "NIL",
+            Op::Undefined => "UNDEFINED",
+            Op::True => "TRUE",
+            Op::False => "FALSE",
+            Op::Pop => "POP",
+            Op::Dup => "DUP",
+            Op::Dup2 => "DUP2",
+            Op::Bury(..) => "BURY",
+            Op::GetLocal(..) => "GET_LOCAL",
+            Op::SetLocal(..) => "SET_LOCAL",
+            Op::DefineLocal(..) => "DEFINE_LOCAL",
+            Op::GetLocalChecked(..) => "GET_LOCAL_CHECKED",
+            Op::SetLocalChecked(..) => "SET_LOCAL_CHECKED",
+            Op::GetUpvalue(..) => "GET_UPVALUE",
+            Op::SetUpvalue(..) => "SET_UPVALUE",
+            Op::GetGlobal(..) => "GET_GLOBAL",
+            Op::SetGlobal(..) => "SET_GLOBAL",
+            Op::DefineGlobal(..) => "DEFINE_GLOBAL",
+            Op::PopScope(..) => "POP_SCOPE",
+            Op::Add => "ADD",
+            Op::Subtract => "SUBTRACT",
+            Op::Multiply => "MULTIPLY",
+            Op::Divide => "DIVIDE",
+            Op::Modulo => "MODULO",
+            Op::Power => "POWER",
+            Op::Less => "LESS",
+            Op::LessEqual => "LESS_EQUAL",
+            Op::Greater => "GREATER",
+            Op::GreaterEqual => "GREATER_EQUAL",
+            Op::Equal => "EQUAL",
+            Op::NotEqual => "NOT_EQUAL",
+            Op::Negate => "NEGATE",
+            Op::Not => "NOT",
+            Op::Increment => "INCREMENT",
+            Op::Decrement => "DECREMENT",
+            Op::Jump(..) => "JUMP",
+            Op::JumpIfFalse(..) => "JUMP_IF_FALSE",
+            Op::JumpIfTrue(..) => "JUMP_IF_TRUE",
+            Op::PopJumpIfFalse(..) => "POP_JUMP_IF_FALSE",
+            Op::Call(..) => "CALL",
+            Op::TailCall(..) => "TAIL_CALL",
+            Op::Closure(..) => "CLOSURE",
+            Op::Class(..) => "CLASS",
+            Op::Return => "RETURN",
+            Op::SetReturn => "SET_RETURN",
+            Op::ReturnPending => "RETURN_PENDING",
+            Op::Print => "PRINT",
+            Op::List(..) => "LIST",
+            Op::Map(..) => "MAP",
+            Op::CheckKey => "CHECK_KEY",
+            Op::GetIndex => "GET_INDEX",
+            Op::SetIndex => "SET_INDEX",
+            Op::CheckIndex => "CHECK_INDEX",
+            Op::GetProperty(..) => "GET_PROPERTY",
+            Op::SetProperty(..) => "SET_PROPERTY",
+            Op::CheckProperty(..) => "CHECK_PROPERTY",
+            Op::PushCatch(..) => "PUSH_CATCH",
+            Op::PushFinally(..) => "PUSH_FINALLY",
+            Op::PopHandler => "POP_HANDLER",
+            Op::Rethrow => "RETHROW",
+            Op::Throw => "THROW",
+            Op::InRange(..) => "IN_RANGE",
+            Op::IsList(..) => "IS_LIST",
+            Op::IsMap => "IS_MAP",
+            Op::HasEntry => "HAS_ENTRY",
+            Op::IsInstance(..) => "IS_INSTANCE",
+            Op::HasField(..) => "HAS_FIELD",
+            Op::ListElement(..) => "LIST_ELEMENT",
+            Op::ListRest(..) => "LIST_REST",
+            Op::MapEntry => "MAP_ENTRY",
+            Op::NoMatch => "NO_MATCH",
+            Op::Import(..) => "IMPORT",
+        }
+    }
+}
+
+// A range pattern, `start..end` or `start..=end`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeDesc {
+    pub start: f64,
+    pub end: f64,
+    pub inclusive: bool,
+}
+
+// Where a closure gets one of its upvalues when it is created: a local of the
+// enclosing function, or one of the enclosing function's own upvalues.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpvalueDesc {
+    pub is_local: bool,
+    pub index: u32,
+    // The captured variable, for when it turns out to be undefined.
+    pub name: Symbol,
+}
+
+// The compiled code of one function, with `lines[i]` the source line of `code[i]`.
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub lines: Vec<i32>,
+    pub constants: Vec<Value>,
+    pub names: Vec<Symbol>,
+    pub functions: Vec<Rc<FunctionProto>>,
+    pub ranges: Vec<RangeDesc>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, op: Op, line: i32) -> usize {
+        self.code.push(op);
+        self.lines.push(line);
+        self.code.len() - 1
+    }
+}
+
+// A compiled function, shared by every closure made from it. The script itself
+// compiles to a function with no parameters. A method takes the instance it is
+// called on, `this`, in the slot before its parameters.
+pub struct FunctionProto {
+    pub name: Symbol,
+    pub arity: usize,
+    pub method: bool,
+    pub upvalues: Vec<UpvalueDesc>,
+    pub chunk: Chunk,
+}
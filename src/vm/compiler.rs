@@ -0,0 +1,1155 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::ast::ast::{AssignExpression, BindingPattern, BlockStatement, BreakStatement, CallExpression, ClassStatement, ContinueStatement, Expression, ExpressionStatement, ForStatement, FunctionStatement, GetExpression, Grouping, Identifier, IfStatement, ImportStatement, IndexExpression, InfixExpression, InstancePattern, ListLiteral, ListPattern, LiteralPattern, MapLiteral, MapPattern, MatchArm, MatchExpression, MatchStatement, Nil, Pattern, PrefixExpression, PrintStatement, Program, RangePattern, Resolution, RestPattern, ReturnStatement, Statement, StringLiteral, TernaryExpression, ThrowStatement, TryStatement, UpdateExpression, VarStatement, WhileStatement, WildcardPattern};
+use crate::ast::ast::{Boolean, NumberLiteral};
+use crate::common::symbol::{Known, Symbol};
+use crate::lexer::lexer::{Token, TokenType};
+use crate::object::object::{HashKey, Value};
+use crate::vm::chunk::{Chunk, FunctionProto, Op, RangeDesc, UpvalueDesc};
+
+// Compiles a resolved program to bytecode. `name` is what the top-level code is
+// called in stack traces.
+//
+// Locals live in stack slots. Every variable a scope declares gets its slot when
+// the scope is entered, so a declaration inside a branch (`if (c) var x = 1;`)
+// doesn't unbalance the stack. References resolve like the tree walker's scope
+// chain: a local is visible to the code of its own function from its declaration
+// on, and to nested functions, which capture it as an upvalue, from anywhere in
+// its scope. Top-level declarations go to the global scope by name.
+pub fn compile(program: &Program, name: Symbol) -> Rc<FunctionProto> {
+    let mut compiler = Compiler { functions: vec![FunctionCompiler::new(name, 0)], line: 1 };
+    for stmt in program.statements.iter() {
+        compiler.result_statement(stmt.as_ref());
+    }
+    compiler.emit(Op::ReturnPending);
+    let function = compiler.functions.pop().expect("the script is being compiled");
+    Rc::new(function.finish())
+}
+
+// Where a variable reference finds its variable.
+enum Variable {
+    Local(u32),
+    // A local that may not be declared yet when the code runs; the name is for the
+    // error if it isn't.
+    CheckedLocal(u32, u32),
+    Upvalue(u32),
+    Global(u32),
+}
+
+struct Local {
+    name: Symbol,
+    // Whether the declaration has been compiled yet.
+    declared: bool,
+    // Whether the declaration is in the body of an `if` or a loop, so it may not
+    // have run when later code reads the variable.
+    conditional: bool,
+}
+
+// A loop that break and continue can target.
+struct Loop {
+    label: Option<Symbol>,
+    // Locals and try statements in scope where the loop starts.
+    locals: usize,
+    tries: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+// A try statement whose body or catch clause is being compiled.
+struct Try<'a> {
+    // Locals in scope where the try statement starts.
+    locals: usize,
+    // Handlers it has installed that are still active.
+    handlers: usize,
+    finally_body: Option<&'a BlockStatement>,
+}
+
+struct FunctionCompiler<'a> {
+    name: Symbol,
+    arity: usize,
+    method: bool,
+    // An `init` method, which returns `this`.
+    initializer: bool,
+    chunk: Chunk,
+    upvalues: Vec<UpvalueDesc>,
+    locals: Vec<Local>,
+    // Where each enclosing scope's locals start.
+    scopes: Vec<usize>,
+    // Values on the stack above the locals, which the expression being compiled
+    // has pushed and not yet used, like the left operand while the right one is
+    // compiled.
+    temporaries: usize,
+    loops: Vec<Loop>,
+    tries: Vec<Try<'a>>,
+    constants: HashMap<HashKey, u32>,
+    names: HashMap<Symbol, u32>,
+}
+
+impl<'a> FunctionCompiler<'a> {
+    fn new(name: Symbol, arity: usize) -> Self {
+        Self {
+            name,
+            arity,
+            method: false,
+            initializer: false,
+            chunk: Chunk::default(),
+            upvalues: Vec::new(),
+            locals: Vec::new(),
+            scopes: Vec::new(),
+            temporaries: 0,
+            loops: Vec::new(),
+            tries: Vec::new(),
+            constants: HashMap::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    fn finish(self) -> FunctionProto {
+        FunctionProto { name: self.name, arity: self.arity, method: self.method, upvalues: self.upvalues, chunk: self.chunk }
+    }
+
+    fn add_upvalue(&mut self, is_local: bool, index: u32, name: Symbol) -> u32 {
+        let upvalue = UpvalueDesc { is_local, index, name };
+        match self.upvalues.iter().position(|existing| *existing == upvalue) {
+            Some(i) => i as u32,
+            None => {
+                self.upvalues.push(upvalue);
+                self.upvalues.len() as u32 - 1
+            }
+        }
+    }
+}
+
+// Hidden locals, named so that no identifier can refer to them.
+const MATCH_SUBJECT: &str = "<match subject>";
+const PENDING_ERROR: &str = "<pending error>";
+const TEMPORARY: &str = "<temporary>";
+
+struct Compiler<'a> {
+    // The function being compiled is last; the ones it is nested in come before it.
+    functions: Vec<FunctionCompiler<'a>>,
+    // The source line the next instruction is attributed to.
+    line: i32,
+}
+
+impl<'a> Compiler<'a> {
+    fn current(&mut self) -> &mut FunctionCompiler<'a> {
+        self.functions.last_mut().expect("a function is being compiled")
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let line = self.line;
+        self.current().chunk.write(op, line)
+    }
+
+    fn here(&mut self) -> usize {
+        self.current().chunk.code.len()
+    }
+
+    // Points the jump at `at` to `target`.
+    fn patch_to(&mut self, at: usize, target: usize) {
+        let target = target as u32;
+        let code = &mut self.current().chunk.code;
+        code[at] = match code[at] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            Op::JumpIfTrue(_) => Op::JumpIfTrue(target),
+            Op::PopJumpIfFalse(_) => Op::PopJumpIfFalse(target),
+            Op::PushCatch(_) => Op::PushCatch(target),
+            Op::PushFinally(_) => Op::PushFinally(target),
+            op => unreachable!("{:?} is not a jump", op),
+        };
+    }
+
+    // Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        self.patch_to(at, target);
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        let function = self.current();
+        let key = value.hash_key();
+        if let Some(&index) = key.as_ref().and_then(|key| function.constants.get(key)) {
+            return index;
+        }
+        function.chunk.constants.push(value);
+        let index = function.chunk.constants.len() as u32 - 1;
+        if let Some(key) = key {
+            function.constants.insert(key, index);
+        }
+        index
+    }
+
+    fn name(&mut self, name: Symbol) -> u32 {
+        let function = self.current();
+        if let Some(&index) = function.names.get(&name) {
+            return index;
+        }
+        function.chunk.names.push(name.clone());
+        let index = function.chunk.names.len() as u32 - 1;
+        function.names.insert(name, index);
+        index
+    }
+
+    // Declarations outside any block of the script define globals.
+    fn at_top_level(&self) -> bool {
+        self.functions.len() == 1 && self.functions[0].scopes.is_empty()
+    }
+
+    // Starts a scope declaring `names`, with their slots undefined.
+    fn begin_scope(&mut self, names: &[Hoisted]) {
+        let function = self.current();
+        function.scopes.push(function.locals.len());
+        self.hoist_locals(names);
+    }
+
+    fn hoist_locals(&mut self, names: &[Hoisted]) {
+        let function = self.current();
+        let start = *function.scopes.last().expect("a scope is open");
+        let mut count = 0;
+        for Hoisted { name, conditional } in names {
+            match function.locals[start..].iter_mut().find(|local| local.name == *name) {
+                Some(local) => local.conditional |= conditional,
+                None => {
+                    function.locals.push(Local { name: name.clone(), declared: false, conditional: *conditional });
+                    count += 1;
+                }
+            }
+        }
+        for _ in 0..count {
+            self.emit(Op::Undefined);
+        }
+    }
+
+    fn end_scope(&mut self) {
+        let count = self.forget_scope();
+        if count > 0 {
+            self.emit(Op::PopScope(count as u32));
+        }
+    }
+
+    // Drops the innermost scope's locals from the compiler's view without popping
+    // them, for code that has already left the scope.
+    fn forget_scope(&mut self) -> usize {
+        let function = self.current();
+        let start = function.scopes.pop().expect("a scope is open");
+        let count = function.locals.len() - start;
+        function.locals.truncate(start);
+        count
+    }
+
+    // A local for a value the VM or the preceding code put on the stack.
+    fn push_local(&mut self, name: Symbol) -> u32 {
+        let function = self.current();
+        function.locals.push(Local { name, declared: true, conditional: false });
+        function.locals.len() as u32 - 1
+    }
+
+    // The slot of `name` in the innermost scope.
+    fn scope_slot(&mut self, name: &Symbol) -> u32 {
+        let function = self.current();
+        let start = *function.scopes.last().expect("locals are declared in a scope");
+        let slot = start + function.locals[start..].iter().position(|local| local.name == *name).expect("declarations are hoisted");
+        slot as u32
+    }
+
+    // The slot of `name` in the innermost scope, marking it declared from here on.
+    fn declare(&mut self, name: &Symbol) -> u32 {
+        let slot = self.scope_slot(name);
+        self.current().locals[slot as usize].declared = true;
+        slot
+    }
+
+    // Stores the value on top of the stack in the variable `name` declares.
+    fn define_variable(&mut self, name: &Token) {
+        self.line = name.line_number;
+        if self.at_top_level() {
+            let index = self.name(name.lexeme.clone());
+            self.emit(Op::DefineGlobal(index));
+        } else {
+            let slot = self.declare(&name.lexeme);
+            self.emit(Op::DefineLocal(slot));
+        }
+    }
+
+    // The local the resolver bound `name` to: the declaration of it that comes
+    // after the `shadowed` nearest ones, searching out through the enclosing
+    // functions. Returns the function that declares it and its slot there.
+    fn find_local(&self, name: &Symbol, shadowed: usize) -> (usize, u32) {
+        self.functions.iter().enumerate().rev()
+            .flat_map(|(function, compiler)| compiler.locals.iter().enumerate().rev().map(move |(slot, local)| (function, slot, local)))
+            .filter(|(_, _, local)| local.name == *name)
+            .nth(shadowed)
+            .map(|(function, slot, _)| (function, slot as u32))
+            .expect("the resolver found a declaration in scope")
+    }
+
+    // The index of an upvalue of `function` that captures `slot` of the
+    // enclosing function `declaring`, adding upvalues along the way as needed.
+    fn capture(&mut self, function: usize, declaring: usize, slot: u32, name: &Symbol) -> u32 {
+        if function - 1 == declaring {
+            return self.functions[function].add_upvalue(true, slot, name.clone());
+        }
+        let index = self.capture(function - 1, declaring, slot, name);
+        self.functions[function].add_upvalue(false, index, name.clone())
+    }
+
+    fn variable(&mut self, token: &Token, resolution: Resolution) -> Variable {
+        self.line = token.line_number;
+        let Resolution::Local { shadowed, .. } = resolution else {
+            return Variable::Global(self.name(token.lexeme.clone()));
+        };
+        let (declaring, slot) = self.find_local(&token.lexeme, shadowed);
+        let function = self.functions.len() - 1;
+        if declaring < function {
+            return Variable::Upvalue(self.capture(function, declaring, slot, &token.lexeme));
+        }
+        // The slot holds a placeholder until the declaration has run.
+        let local = &self.current().locals[slot as usize];
+        if local.declared && !local.conditional {
+            Variable::Local(slot)
+        } else {
+            Variable::CheckedLocal(slot, self.name(token.lexeme.clone()))
+        }
+    }
+
+    fn get_variable(&mut self, token: &Token, resolution: Resolution) {
+        let op = match self.variable(token, resolution) {
+            Variable::Local(slot) => Op::GetLocal(slot),
+            Variable::CheckedLocal(slot, name) => Op::GetLocalChecked(slot, name),
+            Variable::Upvalue(index) => Op::GetUpvalue(index),
+            Variable::Global(index) => Op::GetGlobal(index),
+        };
+        self.emit(op);
+    }
+
+    // Assigns the value on top of the stack, leaving it there.
+    fn set_variable(&mut self, token: &Token, resolution: Resolution) {
+        let op = match self.variable(token, resolution) {
+            Variable::Local(slot) => Op::SetLocal(slot),
+            Variable::CheckedLocal(slot, name) => Op::SetLocalChecked(slot, name),
+            Variable::Upvalue(index) => Op::SetUpvalue(index),
+            Variable::Global(index) => Op::SetGlobal(index),
+        };
+        self.emit(op);
+    }
+
+    // A top-level statement, whose value becomes the result of the program.
+    fn result_statement(&mut self, stmt: &'a dyn Statement) {
+        let any = stmt.as_any();
+        if let Some(e) = any.downcast_ref::<ExpressionStatement>() {
+            self.expression(e.expression.as_ref());
+            self.emit(Op::SetReturn);
+        } else if let Some(i) = any.downcast_ref::<IfStatement>() {
+            self.expression(i.condition.as_ref());
+            let skip_consequence = self.emit(Op::PopJumpIfFalse(0));
+            self.result_statement(i.consequence.as_ref());
+            let skip_alternative = self.emit(Op::Jump(0));
+            self.patch(skip_consequence);
+            match &i.alternative {
+                Some(alternative) => self.result_statement(alternative.as_ref()),
+                None => {
+                    self.emit(Op::Nil);
+                    self.emit(Op::SetReturn);
+                }
+            }
+            self.patch(skip_alternative);
+        } else if let Some(m) = any.downcast_ref::<MatchStatement>() {
+            self.match_statement(m, true);
+        } else {
+            self.statement(stmt);
+            self.emit(Op::Nil);
+            self.emit(Op::SetReturn);
+        }
+    }
+
+    fn statement(&mut self, stmt: &'a dyn Statement) {
+        let any = stmt.as_any();
+        if let Some(e) = any.downcast_ref::<ExpressionStatement>() {
+            self.expression(e.expression.as_ref());
+            self.emit(Op::Pop);
+        } else if let Some(p) = any.downcast_ref::<PrintStatement>() {
+            self.expression(p.expression.as_ref());
+            self.emit(Op::Print);
+        } else if let Some(v) = any.downcast_ref::<VarStatement>() {
+            self.line = v.name.line_number;
+            match &v.initializer {
+                Some(initializer) => self.expression(initializer.as_ref()),
+                None => {
+                    self.emit(Op::Nil);
+                }
+            }
+            self.define_variable(&v.name);
+        } else if let Some(b) = any.downcast_ref::<BlockStatement>() {
+            self.block(b);
+        } else if let Some(i) = any.downcast_ref::<IfStatement>() {
+            self.expression(i.condition.as_ref());
+            let skip_consequence = self.emit(Op::PopJumpIfFalse(0));
+            self.statement(i.consequence.as_ref());
+            match &i.alternative {
+                Some(alternative) => {
+                    let skip_alternative = self.emit(Op::Jump(0));
+                    self.patch(skip_consequence);
+                    self.statement(alternative.as_ref());
+                    self.patch(skip_alternative);
+                }
+                None => self.patch(skip_consequence),
+            }
+        } else if let Some(w) = any.downcast_ref::<WhileStatement>() {
+            self.while_statement(w);
+        } else if let Some(f) = any.downcast_ref::<ForStatement>() {
+            self.for_statement(f);
+        } else if let Some(c) = any.downcast_ref::<ClassStatement>() {
+            for method in c.methods.iter() {
+                let index = self.function(method, true);
+                self.line = method.name.line_number;
+                self.emit(Op::Closure(index));
+            }
+            self.line = c.name.line_number;
+            let name = self.name(c.name.lexeme.clone());
+            self.emit(Op::Class(name, c.methods.len() as u32));
+            self.define_variable(&c.name);
+        } else if let Some(m) = any.downcast_ref::<MatchStatement>() {
+            self.match_statement(m, false);
+        } else if let Some(f) = any.downcast_ref::<FunctionStatement>() {
+            let index = self.function(f, false);
+            self.line = f.name.line_number;
+            self.emit(Op::Closure(index));
+            self.define_variable(&f.name);
+        } else if let Some(r) = any.downcast_ref::<ReturnStatement>() {
+            self.return_statement(r);
+        } else if let Some(t) = any.downcast_ref::<ThrowStatement>() {
+            self.expression(t.value.as_ref());
+            self.line = t.token.line_number;
+            self.emit(Op::Throw);
+        } else if let Some(t) = any.downcast_ref::<TryStatement>() {
+            self.try_statement(t);
+        } else if let Some(i) = any.downcast_ref::<ImportStatement>() {
+            self.import_statement(i);
+        } else if let Some(b) = any.downcast_ref::<BreakStatement>() {
+            self.line = b.token.line_number;
+            self.loop_jump(&b.label, true);
+        } else if let Some(c) = any.downcast_ref::<ContinueStatement>() {
+            self.line = c.token.line_number;
+            self.loop_jump(&c.label, false);
+        }
+    }
+
+    fn block(&mut self, block: &'a BlockStatement) {
+        let mut names = Vec::new();
+        for stmt in block.statements.iter() {
+            hoist(stmt.as_ref(), false, &mut names);
+        }
+        self.begin_scope(&names);
+        for stmt in block.statements.iter() {
+            self.statement(stmt.as_ref());
+        }
+        self.end_scope();
+    }
+
+    fn begin_loop(&mut self, label: &Option<Token>) {
+        let function = self.current();
+        let context = Loop {
+            label: label.as_ref().map(|label| label.lexeme.clone()),
+            locals: function.locals.len(),
+            tries: function.tries.len(),
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        };
+        function.loops.push(context);
+    }
+
+    // Ends the innermost loop, whose continue statements jump to `next`.
+    fn end_loop(&mut self, next: usize) {
+        let context = self.current().loops.pop().expect("a loop is being compiled");
+        for at in context.continues {
+            self.patch_to(at, next);
+        }
+        let exit = self.here();
+        for at in context.breaks {
+            self.patch_to(at, exit);
+        }
+    }
+
+    fn while_statement(&mut self, w: &'a WhileStatement) {
+        let start = self.here();
+        self.expression(w.condition.as_ref());
+        let exit = self.emit(Op::PopJumpIfFalse(0));
+        self.begin_loop(&w.label);
+        self.statement(w.body.as_ref());
+        self.emit(Op::Jump(start as u32));
+        self.patch(exit);
+        self.end_loop(start);
+    }
+
+    fn for_statement(&mut self, f: &'a ForStatement) {
+        let mut names = Vec::new();
+        if let Some(initializer) = &f.initializer {
+            hoist(initializer.as_ref(), false, &mut names);
+        }
+        hoist(f.body.as_ref(), true, &mut names);
+        self.begin_scope(&names);
+        if let Some(initializer) = &f.initializer {
+            self.statement(initializer.as_ref());
+        }
+        let start = self.here();
+        let exit = f.condition.as_ref().map(|condition| {
+            self.expression(condition.as_ref());
+            self.emit(Op::PopJumpIfFalse(0))
+        });
+        self.begin_loop(&f.label);
+        self.statement(f.body.as_ref());
+        let next = self.here();
+        if let Some(increment) = &f.increment {
+            self.expression(increment.as_ref());
+            self.emit(Op::Pop);
+        }
+        self.emit(Op::Jump(start as u32));
+        if let Some(exit) = exit {
+            self.patch(exit);
+        }
+        self.end_loop(next);
+        self.end_scope();
+    }
+
+    // Jumps out of the try statements between here and the loop `label` targets,
+    // running their finally blocks, then to the loop's exit or next iteration.
+    fn loop_jump(&mut self, label: &Option<Token>, is_break: bool) {
+        let function = self.current();
+        let target = match label {
+            Some(label) => function.loops.iter().rposition(|context| context.label.as_ref() == Some(&label.lexeme)),
+            None => function.loops.len().checked_sub(1),
+        };
+        let target = target.expect("the resolver checked that the loop exists");
+        let (locals, tries) = (function.loops[target].locals, function.loops[target].tries);
+        let mut depth = function.locals.len();
+        self.leave_tries(tries, &mut depth);
+        if depth > locals {
+            self.emit(Op::PopScope((depth - locals) as u32));
+        }
+        let jump = self.emit(Op::Jump(0));
+        let context = &mut self.current().loops[target];
+        if is_break {
+            context.breaks.push(jump);
+        } else {
+            context.continues.push(jump);
+        }
+    }
+
+    // Leaves the try statements from the innermost out to the one at `outermost`:
+    // pops their locals (`depth` counts the locals still on the stack), removes
+    // their handlers and runs their finally blocks.
+    fn leave_tries(&mut self, outermost: usize, depth: &mut usize) {
+        for index in (outermost..self.current().tries.len()).rev() {
+            let context = &self.current().tries[index];
+            let (locals, handlers, finally_body) = (context.locals, context.handlers, context.finally_body);
+            if *depth > locals {
+                self.emit(Op::PopScope((*depth - locals) as u32));
+                *depth = locals;
+            }
+            for _ in 0..handlers {
+                self.emit(Op::PopHandler);
+            }
+            if let Some(finally_body) = finally_body {
+                self.inline_finally(index, finally_body);
+            }
+        }
+    }
+
+    // Compiles a copy of the finally block of the try statement at `index`, as it
+    // runs when a jump leaves the statement: outside it, with only the locals, loops
+    // and try statements around the statement in scope.
+    fn inline_finally(&mut self, index: usize, finally_body: &'a BlockStatement) {
+        let function = self.current();
+        let locals = function.locals.split_off(function.tries[index].locals);
+        let inner_loop = function.loops.iter().position(|context| context.tries > index).unwrap_or(function.loops.len());
+        let loops = function.loops.split_off(inner_loop);
+        let tries = function.tries.split_off(index);
+        self.block(finally_body);
+        let function = self.current();
+        function.locals.extend(locals);
+        function.loops.extend(loops);
+        function.tries.extend(tries);
+    }
+
+    fn return_statement(&mut self, r: &'a ReturnStatement) {
+        if r.tail_call.get() {
+            if let Some(c) = r.value.as_ref().and_then(|value| value.as_any().downcast_ref::<CallExpression>()) {
+                self.operands(c);
+                self.emit(Op::TailCall(c.arguments.len() as u32));
+                return;
+            }
+        }
+        match &r.value {
+            Some(value) => self.expression(value.as_ref()),
+            None => self.implicit_return_value(),
+        }
+        self.line = r.token.line_number;
+        if self.current().tries.iter().all(|context| context.finally_body.is_none()) {
+            self.emit(Op::Return);
+            return;
+        }
+        self.emit(Op::SetReturn);
+        let mut depth = self.current().locals.len();
+        self.leave_tries(0, &mut depth);
+        self.emit(Op::ReturnPending);
+    }
+
+    // The body runs under a catch handler inside a finally handler. Leaving the body
+    // or the catch clause normally removes them; an error jumps to the innermost
+    // one. The finally block is compiled once for normal completion and once for
+    // errors, which rethrows the error afterwards; break, continue and return
+    // compile their own copies.
+    fn try_statement(&mut self, t: &'a TryStatement) {
+        let locals = self.current().locals.len();
+        let finally_handler = t.finally_body.as_ref().map(|_| self.emit(Op::PushFinally(0)));
+        let catch_handler = t.catch_clause.as_ref().map(|_| self.emit(Op::PushCatch(0)));
+        let handlers = finally_handler.iter().count() + catch_handler.iter().count();
+        self.current().tries.push(Try { locals, handlers, finally_body: t.finally_body.as_ref() });
+        self.block(&t.body);
+
+        if let (Some(catch_clause), Some(catch_handler)) = (&t.catch_clause, catch_handler) {
+            self.emit(Op::PopHandler);
+            self.current().tries.last_mut().expect("the try statement is open").handlers -= 1;
+            let skip_catch = self.emit(Op::Jump(0));
+            self.patch(catch_handler);
+            // The VM leaves the error value where the catch variable's slot goes.
+            let scope_start = self.current().locals.len();
+            self.current().scopes.push(scope_start);
+            self.push_local(catch_clause.name.lexeme.clone());
+            self.block(&catch_clause.body);
+            self.end_scope();
+            self.patch(skip_catch);
+        }
+        self.current().tries.pop();
+
+        if let (Some(finally_body), Some(finally_handler)) = (&t.finally_body, finally_handler) {
+            self.emit(Op::PopHandler);
+            self.block(finally_body);
+            let skip_finally = self.emit(Op::Jump(0));
+            self.patch(finally_handler);
+            // The VM leaves a placeholder for the pending error where this local goes.
+            let scope_start = self.current().locals.len();
+            self.current().scopes.push(scope_start);
+            self.push_local(Symbol::new(PENDING_ERROR));
+            self.block(finally_body);
+            self.emit(Op::Rethrow);
+            self.forget_scope();
+            self.patch(skip_finally);
+        }
+    }
+
+    fn import_statement(&mut self, i: &'a ImportStatement) {
+        self.line = i.token.line_number;
+        let path = self.constant(Value::string(&i.path));
+        self.emit(Op::Import(path));
+        if let Some(alias) = &i.alias {
+            self.line = alias.line_number;
+            let alias = self.name(alias.lexeme.clone());
+            self.emit(Op::DefineGlobal(alias));
+            return;
+        }
+        for name in i.names.iter() {
+            self.emit(Op::Dup);
+            self.line = name.name.line_number;
+            let export = self.name(name.name.lexeme.clone());
+            self.emit(Op::GetProperty(export));
+            let binding = self.name(name.binding().lexeme.clone());
+            self.emit(Op::DefineGlobal(binding));
+        }
+        self.emit(Op::Pop);
+    }
+
+    // Compiles `f` as a function or method nested in the current function; returns
+    // its index in the current chunk's functions.
+    fn function(&mut self, f: &'a FunctionStatement, method: bool) -> u32 {
+        self.line = f.name.line_number;
+        self.functions.push(FunctionCompiler::new(f.name.lexeme.clone(), f.params.len()));
+        self.current().scopes.push(0);
+        if method {
+            let function = self.current();
+            function.method = true;
+            function.initializer = f.name.lexeme == "init";
+            self.push_local(Symbol::known(Known::This));
+        }
+        for param in f.params.iter() {
+            self.push_local(param.lexeme.clone());
+        }
+        // Parameters and the body share a scope.
+        let mut names = Vec::new();
+        for stmt in f.body.statements.iter() {
+            hoist(stmt.as_ref(), false, &mut names);
+        }
+        self.hoist_locals(&names);
+        for stmt in f.body.statements.iter() {
+            self.statement(stmt.as_ref());
+        }
+        self.implicit_return_value();
+        self.emit(Op::Return);
+        let function = self.functions.pop().expect("the function is being compiled");
+        let chunk = &mut self.current().chunk;
+        chunk.functions.push(Rc::new(function.finish()));
+        chunk.functions.len() as u32 - 1
+    }
+
+    // What a function returns without a value: nil, or `this` from an initializer.
+    fn implicit_return_value(&mut self) {
+        if self.current().initializer {
+            self.emit(Op::GetLocal(0));
+        } else {
+            self.emit(Op::Nil);
+        }
+    }
+
+    fn match_statement(&mut self, m: &'a MatchStatement, result: bool) {
+        let declared = |body: &'a dyn Statement, names: &mut Vec<Hoisted>| hoist(body, false, names);
+        self.match_arms(m.subject.as_ref(), &m.arms, m.token.line_number, false, declared, |compiler, body| {
+            if result {
+                compiler.result_statement(body);
+            } else {
+                compiler.statement(body);
+            }
+        });
+    }
+
+    // Leaves the value of the arm that matches on the stack. The values waiting
+    // under it become nameless locals while it runs, so that the subject and the
+    // bindings get the slots they are actually in.
+    fn match_expression(&mut self, m: &'a MatchExpression) {
+        let temporaries = std::mem::take(&mut self.current().temporaries);
+        let start = self.current().locals.len();
+        self.current().scopes.push(start);
+        for _ in 0..temporaries {
+            self.push_local(Symbol::new(TEMPORARY));
+        }
+        self.match_arms(m.subject.as_ref(), &m.arms, m.token.line_number, true, |_, _| {}, |compiler, value| compiler.expression(value));
+        self.forget_scope();
+        self.current().temporaries = temporaries;
+    }
+
+    // The subject is kept in a hidden local. Each arm declares its bindings, and
+    // whatever `declared` finds its body declares, in a scope of its own and tests
+    // its pattern against a copy of the subject; a failed test jumps to a landing
+    // pad that pops whatever the test left on the stack. With `value`, each body
+    // leaves a value, which is kept when the scopes end.
+    fn match_arms<B: ?Sized>(
+        &mut self,
+        subject: &'a dyn Expression,
+        arms: &'a [MatchArm<B>],
+        line: i32,
+        value: bool,
+        declared: impl Fn(&'a B, &mut Vec<Hoisted>),
+        mut body: impl FnMut(&mut Self, &'a B),
+    ) {
+        self.expression(subject);
+        let scope_start = self.current().locals.len();
+        self.current().scopes.push(scope_start);
+        let subject = self.push_local(Symbol::new(MATCH_SUBJECT));
+        let mut exits = Vec::new();
+        for arm in arms.iter() {
+            let mut bindings = Vec::new();
+            pattern_bindings(arm.pattern.as_ref(), &mut bindings);
+            let mut names: Vec<Hoisted> = bindings.iter().map(|name| Hoisted { name: name.clone(), conditional: false }).collect();
+            declared(arm.body.as_ref(), &mut names);
+            self.begin_scope(&names);
+            self.emit(Op::GetLocal(subject));
+            let mut failures = Vec::new();
+            self.pattern(arm.pattern.as_ref(), 1, &mut failures);
+            for name in bindings {
+                self.declare(&name);
+            }
+            if let Some(guard) = &arm.guard {
+                self.expression(guard.as_ref());
+                failures.push((self.emit(Op::PopJumpIfFalse(0)), 0));
+            }
+            body(self, arm.body.as_ref());
+            let count = self.forget_scope() as u32;
+            if count > 0 {
+                if value {
+                    self.emit(Op::Bury(count));
+                }
+                self.emit(Op::PopScope(count));
+            }
+            exits.push(self.emit(Op::Jump(0)));
+
+            let deepest = failures.iter().map(|&(_, depth)| depth).max().unwrap_or(0);
+            let mut pads = vec![0; deepest + 1];
+            for depth in (1..=deepest).rev() {
+                pads[depth] = self.emit(Op::Pop);
+            }
+            pads[0] = self.here();
+            if count > 0 {
+                self.emit(Op::PopScope(count));
+            }
+            for (at, depth) in failures {
+                self.patch_to(at, pads[depth]);
+            }
+        }
+        self.line = line;
+        self.emit(Op::GetLocal(subject));
+        self.emit(Op::NoMatch);
+        for exit in exits {
+            self.patch(exit);
+        }
+        if value {
+            self.emit(Op::Bury(1));
+        }
+        self.end_scope();
+    }
+
+    // Tests the value on top of the stack against `pattern`, consuming it. `depth`
+    // counts the values the arm has on the stack, including this one; a failed test
+    // records its jump with the depth it leaves behind.
+    fn pattern(&mut self, pattern: &'a dyn Pattern, depth: usize, failures: &mut Vec<(usize, usize)>) {
+        let any = pattern.as_any();
+        if any.is::<WildcardPattern>() {
+            self.emit(Op::Pop);
+        } else if let Some(b) = any.downcast_ref::<BindingPattern>() {
+            let slot = self.scope_slot(&b.name.lexeme);
+            self.emit(Op::DefineLocal(slot));
+        } else if let Some(l) = any.downcast_ref::<LiteralPattern>() {
+            self.emit(Op::Dup);
+            self.expression(l.value.as_ref());
+            self.emit(Op::Equal);
+            failures.push((self.emit(Op::PopJumpIfFalse(0)), depth));
+            self.emit(Op::Pop);
+        } else if let Some(r) = any.downcast_ref::<RangePattern>() {
+            let chunk = &mut self.current().chunk;
+            chunk.ranges.push(RangeDesc { start: r.start, end: r.end, inclusive: r.inclusive });
+            let range = chunk.ranges.len() as u32 - 1;
+            self.emit(Op::InRange(range));
+            failures.push((self.emit(Op::PopJumpIfFalse(0)), depth));
+            self.emit(Op::Pop);
+        } else if let Some(l) = any.downcast_ref::<ListPattern>() {
+            let exact = matches!(l.rest, RestPattern::None);
+            self.emit(Op::IsList(l.elements.len() as u32, exact));
+            failures.push((self.emit(Op::PopJumpIfFalse(0)), depth));
+            for (i, element) in l.elements.iter().enumerate() {
+                self.emit(Op::ListElement(i as u32));
+                self.pattern(element.as_ref(), depth + 1, failures);
+            }
+            if let RestPattern::Bind(name) = &l.rest {
+                self.emit(Op::ListRest(l.elements.len() as u32));
+                let slot = self.scope_slot(&name.lexeme);
+                self.emit(Op::DefineLocal(slot));
+            }
+            self.emit(Op::Pop);
+        } else if let Some(m) = any.downcast_ref::<MapPattern>() {
+            self.emit(Op::IsMap);
+            failures.push((self.emit(Op::PopJumpIfFalse(0)), depth));
+            for (key, value) in m.entries.iter() {
+                self.expression(key.as_ref());
+                self.emit(Op::HasEntry);
+                failures.push((self.emit(Op::PopJumpIfFalse(0)), depth + 1));
+                self.emit(Op::MapEntry);
+                self.pattern(value.as_ref(), depth + 1, failures);
+            }
+            self.emit(Op::Pop);
+        } else if let Some(i) = any.downcast_ref::<InstancePattern>() {
+            // The class name is resolved outside the arm, whose scope has begun here.
+            let resolution = match i.class_resolution.get() {
+                Resolution::Local { slot, shadowed } => {
+                    let function = self.current();
+                    let arm = *function.scopes.last().expect("the arm's scope is open");
+                    let in_arm = function.locals[arm..].iter().filter(|local| local.name == i.class_name.lexeme).count();
+                    Resolution::Local { slot, shadowed: shadowed + in_arm }
+                }
+                Resolution::Global => Resolution::Global,
+            };
+            self.get_variable(&i.class_name, resolution);
+            let class_name = self.name(i.class_name.lexeme.clone());
+            self.emit(Op::IsInstance(class_name));
+            failures.push((self.emit(Op::PopJumpIfFalse(0)), depth));
+            for (name, field) in i.fields.iter() {
+                let name = self.name(name.lexeme.clone());
+                self.emit(Op::HasField(name));
+                failures.push((self.emit(Op::PopJumpIfFalse(0)), depth));
+                self.emit(Op::Dup);
+                self.emit(Op::GetProperty(name));
+                self.pattern(field.as_ref(), depth + 1, failures);
+            }
+            self.emit(Op::Pop);
+        } else {
+            failures.push((self.emit(Op::Jump(0)), depth));
+        }
+    }
+
+    fn expression(&mut self, exp: &'a dyn Expression) {
+        let any = exp.as_any();
+        if let Some(b) = any.downcast_ref::<Boolean>() {
+            self.emit(if b.value { Op::True } else { Op::False });
+        } else if let Some(n) = any.downcast_ref::<NumberLiteral>() {
+            let index = self.constant(Value::Number(n.value));
+            self.emit(Op::Constant(index));
+        } else if let Some(s) = any.downcast_ref::<StringLiteral>() {
+            let index = self.constant(Value::String(Rc::clone(&s.value)));
+            self.emit(Op::Constant(index));
+        } else if any.is::<Nil>() {
+            self.emit(Op::Nil);
+        } else if let Some(g) = any.downcast_ref::<Grouping>() {
+            self.expression(g.expression.as_ref());
+        } else if let Some(p) = any.downcast_ref::<PrefixExpression>() {
+            self.expression(p.right.as_ref());
+            self.line = p.operator.line_number;
+            match p.operator.token_type {
+                TokenType::Bang => self.emit(Op::Not),
+                _ => self.emit(Op::Negate),
+            };
+        } else if let Some(i) = any.downcast_ref::<InfixExpression>() {
+            self.infix_expression(i);
+        } else if let Some(t) = any.downcast_ref::<TernaryExpression>() {
+            self.expression(t.condition.as_ref());
+            let skip_consequence = self.emit(Op::PopJumpIfFalse(0));
+            self.expression(t.consequence.as_ref());
+            let skip_alternative = self.emit(Op::Jump(0));
+            self.patch(skip_consequence);
+            self.expression(t.alternative.as_ref());
+            self.patch(skip_alternative);
+        } else if let Some(i) = any.downcast_ref::<Identifier>() {
+            self.get_variable(&i.token, i.resolution.get());
+        } else if let Some(a) = any.downcast_ref::<AssignExpression>() {
+            self.assign_expression(a);
+        } else if let Some(u) = any.downcast_ref::<UpdateExpression>() {
+            self.update_expression(u);
+        } else if let Some(g) = any.downcast_ref::<GetExpression>() {
+            self.expression(g.object.as_ref());
+            self.line = g.name.line_number;
+            let name = self.name(g.name.lexeme.clone());
+            self.emit(Op::GetProperty(name));
+        } else if let Some(i) = any.downcast_ref::<IndexExpression>() {
+            self.expression(i.left.as_ref());
+            self.operand(i.index.as_ref(), 1);
+            self.line = i.token.line_number;
+            self.emit(Op::GetIndex);
+        } else if let Some(l) = any.downcast_ref::<ListLiteral>() {
+            for (i, element) in l.elements.iter().enumerate() {
+                self.operand(element.as_ref(), i);
+            }
+            self.emit(Op::List(l.elements.len() as u32));
+        } else if let Some(m) = any.downcast_ref::<MapLiteral>() {
+            for (i, (key, value)) in m.pairs.iter().enumerate() {
+                self.operand(key.as_ref(), 2 * i);
+                self.line = m.token.line_number;
+                self.emit(Op::CheckKey);
+                self.operand(value.as_ref(), 2 * i + 1);
+            }
+            self.line = m.token.line_number;
+            self.emit(Op::Map(m.pairs.len() as u32));
+        } else if let Some(c) = any.downcast_ref::<CallExpression>() {
+            self.call_expression(c);
+        } else if let Some(m) = any.downcast_ref::<MatchExpression>() {
+            self.match_expression(m);
+        } else {
+            self.emit(Op::Nil);
+        }
+    }
+
+    // Compiles `exp` with `below` values of the enclosing expression waiting under
+    // its result.
+    fn operand(&mut self, exp: &'a dyn Expression, below: usize) {
+        self.current().temporaries += below;
+        self.expression(exp);
+        self.current().temporaries -= below;
+    }
+
+    fn infix_expression(&mut self, i: &'a InfixExpression) {
+        self.expression(i.left.as_ref());
+        if matches!(i.token.token_type, TokenType::And | TokenType::Or) {
+            let short_circuit = match i.token.token_type {
+                TokenType::And => self.emit(Op::JumpIfFalse(0)),
+                _ => self.emit(Op::JumpIfTrue(0)),
+            };
+            self.emit(Op::Pop);
+            self.expression(i.right.as_ref());
+            self.patch(short_circuit);
+            return;
+        }
+        self.operand(i.right.as_ref(), 1);
+        self.line = i.token.line_number;
+        self.emit(binary_op(&i.token.token_type));
+    }
+
+    fn call_expression(&mut self, c: &'a CallExpression) {
+        self.operands(c);
+        self.emit(Op::Call(c.arguments.len() as u32));
+    }
+
+    // Pushes the callee and then the arguments of a call.
+    fn operands(&mut self, c: &'a CallExpression) {
+        self.expression(c.callee.as_ref());
+        for (i, argument) in c.arguments.iter().enumerate() {
+            self.operand(argument.as_ref(), i + 1);
+        }
+        self.line = c.token.line_number;
+    }
+
+    // Like the tree walker, an assignment first finds its target, checking that the
+    // object can have the property or element, then evaluates the value.
+    fn assign_expression(&mut self, a: &'a AssignExpression) {
+        let operator = compound_operator_op(&a.operator.token_type);
+        let target = a.target.as_any();
+        if let Some(i) = target.downcast_ref::<Identifier>() {
+            if let Some(operator) = operator {
+                self.get_variable(&i.token, i.resolution.get());
+                self.operand(a.value.as_ref(), 1);
+                self.line = a.operator.line_number;
+                self.emit(operator);
+            } else {
+                self.expression(a.value.as_ref());
+            }
+            self.set_variable(&i.token, i.resolution.get());
+        } else if let Some(g) = target.downcast_ref::<GetExpression>() {
+            self.expression(g.object.as_ref());
+            self.line = g.name.line_number;
+            let name = self.name(g.name.lexeme.clone());
+            self.emit(Op::CheckProperty(name));
+            if let Some(operator) = operator {
+                self.emit(Op::Dup);
+                self.emit(Op::GetProperty(name));
+                self.operand(a.value.as_ref(), 2);
+                self.line = a.operator.line_number;
+                self.emit(operator);
+            } else {
+                self.operand(a.value.as_ref(), 1);
+            }
+            self.line = g.name.line_number;
+            self.emit(Op::SetProperty(name));
+        } else if let Some(i) = target.downcast_ref::<IndexExpression>() {
+            self.expression(i.left.as_ref());
+            self.operand(i.index.as_ref(), 1);
+            self.line = i.token.line_number;
+            self.emit(Op::CheckIndex);
+            if let Some(operator) = operator {
+                self.emit(Op::Dup2);
+                self.emit(Op::GetIndex);
+                self.operand(a.value.as_ref(), 3);
+                self.line = a.operator.line_number;
+                self.emit(operator);
+            } else {
+                self.operand(a.value.as_ref(), 2);
+            }
+            self.line = i.token.line_number;
+            self.emit(Op::SetIndex);
+        }
+    }
+
+    // `x++` leaves the old value under the target while the new one is stored.
+    fn update_expression(&mut self, u: &'a UpdateExpression) {
+        let step = if u.operator.token_type == TokenType::PlusPlus { Op::Increment } else { Op::Decrement };
+        let target = u.target.as_any();
+        if let Some(i) = target.downcast_ref::<Identifier>() {
+            self.get_variable(&i.token, i.resolution.get());
+            if !u.prefix {
+                self.emit(Op::Dup);
+            }
+            self.line = u.operator.line_number;
+            self.emit(step);
+            self.set_variable(&i.token, i.resolution.get());
+        } else if let Some(g) = target.downcast_ref::<GetExpression>() {
+            self.expression(g.object.as_ref());
+            self.line = g.name.line_number;
+            let name = self.name(g.name.lexeme.clone());
+            self.emit(Op::CheckProperty(name));
+            self.emit(Op::Dup);
+            self.emit(Op::GetProperty(name));
+            if !u.prefix {
+                self.emit(Op::Dup);
+                self.emit(Op::Bury(2));
+            }
+            self.line = u.operator.line_number;
+            self.emit(step);
+            self.line = g.name.line_number;
+            self.emit(Op::SetProperty(name));
+        } else if let Some(i) = target.downcast_ref::<IndexExpression>() {
+            self.expression(i.left.as_ref());
+            self.operand(i.index.as_ref(), 1);
+            self.line = i.token.line_number;
+            self.emit(Op::CheckIndex);
+            self.emit(Op::Dup2);
+            self.emit(Op::GetIndex);
+            if !u.prefix {
+                self.emit(Op::Dup);
+                self.emit(Op::Bury(3));
+            }
+            self.line = u.operator.line_number;
+            self.emit(step);
+            self.line = i.token.line_number;
+            self.emit(Op::SetIndex);
+        }
+        if !u.prefix {
+            self.emit(Op::Pop);
+        }
+    }
+}
+
+fn binary_op(operator: &TokenType) -> Op {
+    match operator {
+        TokenType::Plus => Op::Add,
+        TokenType::Minus => Op::Subtract,
+        TokenType::Asterisk => Op::Multiply,
+        TokenType::Slash => Op::Divide,
+        TokenType::Percent => Op::Modulo,
+        TokenType::StarStar => Op::Power,
+        TokenType::Less => Op::Less,
+        TokenType::LessEqual => Op::LessEqual,
+        TokenType::Greater => Op::Greater,
+        TokenType::GreaterEqual => Op::GreaterEqual,
+        TokenType::Equal => Op::Equal,
+        _ => Op::NotEqual,
+    }
+}
+
+fn compound_operator_op(operator: &TokenType) -> Option<Op> {
+    crate::evaluator::operations::compound_operator(operator).map(|operator| binary_op(&operator))
+}
+
+// A variable declared in a scope, which gets its slot when the scope starts.
+#[derive(Clone)]
+struct Hoisted {
+    name: Symbol,
+    // Declared in the body of an `if` or a loop.
+    conditional: bool,
+}
+
+// Adds the names `stmt` declares in the scope it runs in, including through the
+// bodies of `if` and `while` statements that aren't blocks.
+fn hoist(stmt: &dyn Statement, conditional: bool, names: &mut Vec<Hoisted>) {
+    let any = stmt.as_any();
+    if let Some(v) = any.downcast_ref::<VarStatement>() {
+        names.push(Hoisted { name: v.name.lexeme.clone(), conditional });
+    } else if let Some(f) = any.downcast_ref::<FunctionStatement>() {
+        names.push(Hoisted { name: f.name.lexeme.clone(), conditional });
+    } else if let Some(c) = any.downcast_ref::<ClassStatement>() {
+        names.push(Hoisted { name: c.name.lexeme.clone(), conditional });
+    } else if let Some(i) = any.downcast_ref::<IfStatement>() {
+        hoist(i.consequence.as_ref(), true, names);
+        if let Some(alternative) = &i.alternative {
+            hoist(alternative.as_ref(), true, names);
+        }
+    } else if let Some(w) = any.downcast_ref::<WhileStatement>() {
+        hoist(w.body.as_ref(), true, names);
+    }
+}
+
+fn pattern_bindings(pattern: &dyn Pattern, names: &mut Vec<Symbol>) {
+    let any = pattern.as_any();
+    if let Some(b) = any.downcast_ref::<BindingPattern>() {
+        names.push(b.name.lexeme.clone());
+    } else if let Some(l) = any.downcast_ref::<ListPattern>() {
+        for element in l.elements.iter() {
+            pattern_bindings(element.as_ref(), names);
+        }
+        if let RestPattern::Bind(name) = &l.rest {
+            names.push(name.lexeme.clone());
+        }
+    } else if let Some(m) = any.downcast_ref::<MapPattern>() {
+        for (_, value) in m.entries.iter() {
+            pattern_bindings(value.as_ref(), names);
+        }
+    } else if let Some(i) = any.downcast_ref::<InstancePattern>() {
+        for (_, field) in i.fields.iter() {
+            pattern_bindings(field.as_ref(), names);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::io;
+use crate::common::output::Output;
+use crate::object::object::Value;
+use crate::vm::chunk::{Chunk, FunctionProto, Op};
+
+// Listings of compiled code, for checking what the compiler produced. Each
+// function is listed after the function it is nested in.
+
+// What an instruction's operands refer to.
+enum Operand {
+    Count(u32),
+    Slot(u32),
+    Constant(u32),
+    Name(u32),
+    Upvalue(u32),
+    Target(u32),
+    Function(u32),
+    Range(u32),
+    // A local that may be undefined, and its name for the error if it is.
+    CheckedSlot(u32, u32),
+    // A list pattern's element count and whether it must match exactly.
+    ListLength(u32, bool),
+    // A class's name and how many methods it takes from the stack.
+    Class(u32, u32),
+}
+
+fn operand(op: Op) -> Option<Operand> {
+    Some(match op {
+        Op::Constant(i) | Op::Import(i) => Operand::Constant(i),
+        Op::Bury(n) | Op::PopScope(n) | Op::Call(n) | Op::TailCall(n) | Op::List(n) | Op::Map(n) | Op::ListElement(n) | Op::ListRest(n) => Operand::Count(n),
+        Op::GetLocal(slot) | Op::SetLocal(slot) | Op::DefineLocal(slot) => Operand::Slot(slot),
+        Op::GetLocalChecked(slot, name) | Op::SetLocalChecked(slot, name) => Operand::CheckedSlot(slot, name),
+        Op::GetUpvalue(i) | Op::SetUpvalue(i) => Operand::Upvalue(i),
+        Op::GetGlobal(i) | Op::SetGlobal(i) | Op::DefineGlobal(i) | Op::GetProperty(i) | Op::SetProperty(i)
+        | Op::CheckProperty(i) | Op::IsInstance(i) | Op::HasField(i) => Operand::Name(i),
+        Op::Jump(target) | Op::JumpIfFalse(target) | Op::JumpIfTrue(target) | Op::PopJumpIfFalse(target)
+        | Op::PushCatch(target) | Op::PushFinally(target) => Operand::Target(target),
+        Op::Closure(i) => Operand::Function(i),
+        Op::InRange(i) => Operand::Range(i),
+        Op::IsList(len, exact) => Operand::ListLength(len, exact),
+        Op::Class(name, methods) => Operand::Class(name, methods),
+        _ => return None,
+    })
+}
+
+// Writes a human-readable listing of `function` and the functions nested in it.
+pub fn print(function: &FunctionProto, out: &Output) -> io::Result<()> {
+    let kind = if function.method { "method, " } else { "" };
+    let header = match function.arity {
+        1 => format!("== {} ({}1 parameter) ==", function.name, kind),
+        n => format!("== {} ({}{} parameters) ==", function.name, kind, n),
+    };
+    out.write_line(&header)?;
+    let chunk = &function.chunk;
+    for offset in 0..chunk.code.len() {
+        out.write_line(&instruction(function, offset))?;
+        if let Op::Closure(i) = chunk.code[offset] {
+            for upvalue in chunk.functions[i as usize].upvalues.iter() {
+                let source = if upvalue.is_local { "local" } else { "upvalue" };
+                out.write_line(&format!("{:04}    | {:18} {} {} '{}'", offset, "", source, upvalue.index, upvalue.name))?;
+            }
+        }
+    }
+    for nested in chunk.functions.iter() {
+        out.write_line("")?;
+        print(nested, out)?;
+    }
+    Ok(())
+}
+
+// One line of the listing: offset, source line ("|" when it is the line of the
+// previous instruction), name and operands.
+pub fn instruction(function: &FunctionProto, offset: usize) -> String {
+    let chunk = &function.chunk;
+    let op = chunk.code[offset];
+    let line = if offset > 0 && chunk.lines[offset] == chunk.lines[offset - 1] {
+        "   |".to_string()
+    } else {
+        format!("{:>4}", chunk.lines[offset])
+    };
+    let detail = match operand(op) {
+        None => String::new(),
+        Some(Operand::Count(n) | Operand::Slot(n)) => n.to_string(),
+        Some(Operand::Constant(i)) => format!("{} {}", i, constant_text(&chunk.constants[i as usize])),
+        Some(Operand::Name(i)) => format!("{} '{}'", i, chunk.names[i as usize]),
+        Some(Operand::Upvalue(i)) => format!("{} '{}'", i, function.upvalues[i as usize].name),
+        Some(Operand::Target(target)) => format!("-> {:04}", target),
+        Some(Operand::Function(i)) => format!("{} <fn {}>", i, chunk.functions[i as usize].name),
+        Some(Operand::Range(i)) => {
+            let range = chunk.ranges[i as usize];
+            let dots = if range.inclusive { "..=" } else { ".." };
+            format!("{} {}{}{}", i, Value::Number(range.start).inspect(), dots, Value::Number(range.end).inspect())
+        }
+        Some(Operand::CheckedSlot(slot, name)) => format!("{} '{}'", slot, chunk.names[name as usize]),
+        Some(Operand::ListLength(len, true)) => format!("{} exactly", len),
+        Some(Operand::ListLength(len, false)) => format!("{} or more", len),
+        Some(Operand::Class(name, 1)) => format!("{} '{}' 1 method", name, chunk.names[name as usize]),
+        Some(Operand::Class(name, methods)) => format!("{} '{}' {} methods", name, chunk.names[name as usize], methods),
+    };
+    format!("{:04} {} {:<18} {}", offset, line, op.name(), detail).trim_end().to_string()
+}
+
+fn constant_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => json_string(s),
+        value => value.inspect(),
+    }
+}
+
+// Writes `function` and the functions nested in it as one JSON object.
+pub fn print_json(function: &FunctionProto, out: &Output) -> io::Result<()> {
+    out.write_line(&function_json(function))
+}
+
+fn function_json(function: &FunctionProto) -> String {
+    let chunk = &function.chunk;
+    let upvalues: Vec<String> = function.upvalues.iter()
+        .map(|upvalue| format!("{{\"local\":{},\"index\":{},\"name\":{}}}", upvalue.is_local, upvalue.index, json_string(&upvalue.name)))
+        .collect();
+    let constants: Vec<String> = chunk.constants.iter().map(json_value).collect();
+    let names: Vec<String> = chunk.names.iter().map(|name| json_string(name)).collect();
+    let code: Vec<String> = (0..chunk.code.len()).map(|offset| instruction_json(function, offset)).collect();
+    let functions: Vec<String> = chunk.functions.iter().map(|nested| function_json(nested)).collect();
+    format!(
+        "{{\"name\":{},\"arity\":{},\"method\":{},\"upvalues\":[{}],\"constants\":[{}],\"names\":[{}],\"code\":[{}],\"functions\":[{}]}}",
+        json_string(&function.name),
+        function.arity,
+        function.method,
+        upvalues.join(","),
+        constants.join(","),
+        names.join(","),
+        code.join(","),
+        functions.join(","),
+    )
+}
+
+fn instruction_json(function: &FunctionProto, offset: usize) -> String {
+    let chunk: &Chunk = &function.chunk;
+    let op = chunk.code[offset];
+    let (operands, detail) = match operand(op) {
+        None => (Vec::new(), String::new()),
+        Some(Operand::Count(n) | Operand::Slot(n)) => (vec![n.to_string()], String::new()),
+        Some(Operand::Constant(i)) => (vec![i.to_string()], format!(",\"constant\":{}", json_value(&chunk.constants[i as usize]))),
+        Some(Operand::Name(i)) => (vec![i.to_string()], format!(",\"name\":{}", json_string(&chunk.names[i as usize]))),
+        Some(Operand::Upvalue(i)) => (vec![i.to_string()], format!(",\"name\":{}", json_string(&function.upvalues[i as usize].name))),
+        Some(Operand::Target(target)) => (vec![target.to_string()], format!(",\"target\":{}", target)),
+        Some(Operand::Function(i)) => (vec![i.to_string()], format!(",\"function\":{}", json_string(&chunk.functions[i as usize].name))),
+        Some(Operand::Range(i)) => {
+            let range = chunk.ranges[i as usize];
+            let detail = format!(",\"range\":{{\"start\":{},\"end\":{},\"inclusive\":{}}}", json_number(range.start), json_number(range.end), range.inclusive);
+            (vec![i.to_string()], detail)
+        }
+        Some(Operand::CheckedSlot(slot, name)) => (vec![slot.to_string(), name.to_string()], format!(",\"name\":{}", json_string(&chunk.names[name as usize]))),
+        Some(Operand::ListLength(len, exact)) => (vec![len.to_string(), exact.to_string()], String::new()),
+        Some(Operand::Class(name, methods)) => (vec![name.to_string(), methods.to_string()], format!(",\"name\":{}", json_string(&chunk.names[name as usize]))),
+    };
+    format!(
+        "{{\"offset\":{},\"line\":{},\"op\":\"{}\",\"operands\":[{}]{}}}",
+        offset,
+        chunk.lines[offset],
+        op.name(),
+        operands.join(","),
+        detail,
+    )
+}
+
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Nil => "null".to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Number(n) => json_number(*n),
+        Value::String(s) => json_string(s),
+        value => json_string(&value.inspect()),
+    }
+}
+
+// JSON has no infinities or NaN; those become null.
+fn json_number(n: f64) -> String {
+    if n.is_finite() {
+        n.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
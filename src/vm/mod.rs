@@ -0,0 +1,8 @@
+pub mod bytecode;
+pub mod chunk;
+pub mod compiler;
+pub mod disassembler;
+mod stack;
+pub mod verifier;
+#[allow(clippy::module_inception)]
+pub mod vm;
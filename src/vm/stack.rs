@@ -0,0 +1,44 @@
+use crate::object::object::Value;
+
+// What the VM's stack holds: a Value, or with the `nan-boxing` feature a NanBox,
+// which packs the same thing into 8 bytes. Both have `is_truthy`; everything else
+// the VM does with a slot goes through this trait, so it runs the same with either.
+#[cfg(feature = "nan-boxing")]
+pub type Slot = crate::object::nanbox::NanBox;
+#[cfg(not(feature = "nan-boxing"))]
+pub type Slot = Value;
+
+pub trait StackSlot: Clone {
+    fn from_value(value: Value) -> Self;
+    fn to_value(&self) -> Value;
+    fn into_value(self) -> Value;
+}
+
+impl StackSlot for Value {
+    fn from_value(value: Value) -> Self {
+        value
+    }
+
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+#[cfg(feature = "nan-boxing")]
+impl StackSlot for crate::object::nanbox::NanBox {
+    fn from_value(value: Value) -> Self {
+        Self::from(value)
+    }
+
+    fn to_value(&self) -> Value {
+        Value::from(self)
+    }
+
+    fn into_value(self) -> Value {
+        Value::from(self)
+    }
+}
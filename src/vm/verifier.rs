@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use crate::object::object::Value;
+use crate::vm::chunk::{FunctionProto, Op};
+
+// Checks that code loaded from outside the compiler is safe to run: every operand
+// indexes an existing constant, name, range, function, upvalue or stack slot,
+// every jump lands inside the code, execution can't run off the end, and no
+// instruction pops values its own frame didn't push.
+//
+// Stack heights are tracked per instruction, counting the frame's locals, and
+// must agree wherever paths meet, as they do in everything the compiler emits.
+//
+// What the verifier doesn't prove, the VM checks as it runs: that LIST_ELEMENT,
+// LIST_REST and MAP_ENTRY follow the IS_LIST or HAS_ENTRY that makes them safe,
+// and that handlers and pending errors are there when POP_HANDLER and RETHROW
+// expect them, and that CLASS gets method closures and only methods are called
+// with an instance. Code that fails those checks stops with a BytecodeError.
+pub fn verify(function: &FunctionProto) -> Result<(), String> {
+    verify_function(function).map_err(|(offset, message)| match offset {
+        Some(offset) => format!("in {} at {:04}: {}", function.name, offset, message),
+        None => format!("in {}: {}", function.name, message),
+    })?;
+    function.chunk.functions.iter().try_for_each(|nested| verify(nested))
+}
+
+// An error at an instruction, or about the function as a whole.
+type Failure = (Option<usize>, String);
+
+fn verify_function(function: &FunctionProto) -> Result<(), Failure> {
+    let chunk = &function.chunk;
+    if chunk.code.is_empty() {
+        return Err((None, "the function has no code".to_string()));
+    }
+    let mut heights: Vec<Option<usize>> = vec![None; chunk.code.len()];
+    let mut pending = vec![(0, function.arity + function.method as usize)];
+    // Heights at which finally handlers were installed; a RETHROW must pop the
+    // placeholder one of them pushed.
+    let mut finally_heights = HashSet::new();
+    let mut rethrows = Vec::new();
+
+    while let Some((offset, height)) = pending.pop() {
+        match heights[offset] {
+            Some(known) if known == height => continue,
+            Some(known) => return Err((Some(offset), format!("the stack holds {} values on one path here and {} on another", known, height))),
+            None => heights[offset] = Some(height),
+        }
+        let op = chunk.code[offset];
+        let at = |message: String| (Some(offset), message);
+        let (pops, pushes) = stack_effect(op);
+        if height < pops {
+            return Err(at(format!("{} needs {} values but the stack holds {}", op.name(), pops, height)));
+        }
+        let after = height - pops + pushes;
+        check_operands(function, op, height).map_err(at)?;
+
+        let mut successors = Vec::new();
+        match op {
+            Op::Jump(target) => successors.push((target, after)),
+            Op::JumpIfFalse(target) | Op::JumpIfTrue(target) | Op::PopJumpIfFalse(target) => {
+                successors.push((target, after));
+                successors.push((offset as u32 + 1, after));
+            }
+            // An error raised in the protected code arrives at the handler with the
+            // stack cut back to this height, plus the error or its placeholder.
+            Op::PushCatch(target) | Op::PushFinally(target) => {
+                if let Op::PushFinally(_) = op {
+                    finally_heights.insert(height);
+                }
+                successors.push((target, height + 1));
+                successors.push((offset as u32 + 1, after));
+            }
+            Op::Rethrow => rethrows.push((offset, height)),
+            Op::Return | Op::TailCall(_) | Op::ReturnPending | Op::Throw | Op::NoMatch => {}
+            _ => successors.push((offset as u32 + 1, after)),
+        }
+        for (target, height) in successors {
+            if target as usize >= chunk.code.len() {
+                return Err(at(format!("execution continues at {:04}, past the end of the code", target)));
+            }
+            pending.push((target as usize, height));
+        }
+    }
+
+    for (offset, height) in rethrows {
+        if !finally_heights.contains(&(height - 1)) {
+            return Err((Some(offset), "RETHROW outside a finally handler".to_string()));
+        }
+    }
+    Ok(())
+}
+
+// How many values `op` pops, and then pushes, on the paths that continue.
+fn stack_effect(op: Op) -> (usize, usize) {
+    let n = |count: u32| count as usize;
+    match op {
+        Op::Constant(_) | Op::Nil | Op::Undefined | Op::True | Op::False => (0, 1),
+        Op::Pop => (1, 0),
+        Op::Dup => (1, 2),
+        Op::Dup2 => (2, 4),
+        Op::Bury(depth) => (n(depth) + 1, n(depth) + 1),
+        Op::GetLocal(_) | Op::GetLocalChecked(..) | Op::GetUpvalue(_) | Op::GetGlobal(_) => (0, 1),
+        Op::SetLocal(_) | Op::SetLocalChecked(..) | Op::SetUpvalue(_) | Op::SetGlobal(_) => (1, 1),
+        Op::DefineLocal(_) | Op::DefineGlobal(_) => (1, 0),
+        Op::PopScope(count) => (n(count), 0),
+        Op::Add | Op::Subtract | Op::Multiply | Op::Divide | Op::Modulo | Op::Power
+        | Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual | Op::Equal | Op::NotEqual => (2, 1),
+        Op::Negate | Op::Not | Op::Increment | Op::Decrement => (1, 1),
+        Op::Jump(_) => (0, 0),
+        Op::JumpIfFalse(_) | Op::JumpIfTrue(_) => (1, 1),
+        Op::PopJumpIfFalse(_) => (1, 0),
+        Op::Call(count) => (n(count) + 1, 1),
+        Op::TailCall(count) => (n(count) + 1, 0),
+        Op::Closure(_) => (0, 1),
+        Op::Class(_, methods) => (n(methods), 1),
+        Op::Return | Op::SetReturn | Op::Print | Op::Rethrow | Op::Throw | Op::NoMatch => (1, 0),
+        Op::ReturnPending => (0, 0),
+        Op::List(count) => (n(count), 1),
+        Op::Map(count) => (2 * n(count), 1),
+        Op::CheckKey | Op::GetProperty(_) | Op::CheckProperty(_) => (1, 1),
+        Op::GetIndex | Op::SetProperty(_) => (2, 1),
+        Op::SetIndex => (3, 1),
+        Op::CheckIndex => (2, 2),
+        Op::PushCatch(_) | Op::PushFinally(_) | Op::PopHandler => (0, 0),
+        Op::InRange(_) | Op::IsList(..) | Op::IsMap | Op::HasField(_) => (1, 2),
+        Op::HasEntry => (2, 3),
+        Op::IsInstance(_) => (2, 2),
+        Op::ListElement(_) | Op::ListRest(_) => (1, 2),
+        Op::MapEntry => (2, 2),
+        Op::Import(_) => (0, 1),
+    }
+}
+
+// Checks the indexes in `op`, which runs with `height` values on the frame's stack.
+fn check_operands(function: &FunctionProto, op: Op, height: usize) -> Result<(), String> {
+    let chunk = &function.chunk;
+    let within = |index: u32, len: usize, what: &str| {
+        if (index as usize) < len {
+            Ok(())
+        } else {
+            Err(format!("{} refers to {} {}, but there are only {}", op.name(), what, index, len))
+        }
+    };
+    match op {
+        Op::Constant(i) => within(i, chunk.constants.len(), "constant"),
+        Op::Import(i) => {
+            within(i, chunk.constants.len(), "constant")?;
+            match chunk.constants[i as usize] {
+                Value::String(_) => Ok(()),
+                _ => Err("IMPORT needs a string constant".to_string()),
+            }
+        }
+        Op::GetLocal(slot) | Op::SetLocal(slot) => within(slot, height, "stack slot"),
+        // The value is popped before it is stored.
+        Op::DefineLocal(slot) => within(slot, height - 1, "stack slot"),
+        Op::GetLocalChecked(slot, name) | Op::SetLocalChecked(slot, name) => {
+            within(slot, height, "stack slot")?;
+            within(name, chunk.names.len(), "name")
+        }
+        Op::GetUpvalue(i) | Op::SetUpvalue(i) => within(i, function.upvalues.len(), "upvalue"),
+        Op::GetGlobal(i) | Op::SetGlobal(i) | Op::DefineGlobal(i) | Op::Class(i, _) | Op::GetProperty(i)
+        | Op::SetProperty(i) | Op::CheckProperty(i) | Op::IsInstance(i) | Op::HasField(i) => within(i, chunk.names.len(), "name"),
+        Op::InRange(i) => within(i, chunk.ranges.len(), "range"),
+        Op::Closure(i) => {
+            within(i, chunk.functions.len(), "function")?;
+            for upvalue in chunk.functions[i as usize].upvalues.iter() {
+                if upvalue.is_local {
+                    within(upvalue.index, height, "stack slot")?;
+                } else {
+                    within(upvalue.index, function.upvalues.len(), "upvalue")?;
+                }
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
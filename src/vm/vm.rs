@@ -0,0 +1,940 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use crate::common::output::Output;
+use crate::common::symbol::{Interner, Symbol};
+use crate::evaluator::error::{ErrorKind, Frame, NativeError, RuntimeError};
+use crate::evaluator::limits::{InterruptHandle, Limits, Meter};
+use crate::interpreter::permissions::Permissions;
+use crate::evaluator::modules;
+use crate::evaluator::operations::{apply_infix_operator, caught_value, check_arity, check_call_depth, check_native_arity, declared_name, index_place, instantiate, map_key, negate, no_match, not_a_class, not_callable, operand_not_number, property_place, read_place, thrown_error, unbound_method, undefined_variable, write_place, Name};
+use crate::lexer::lexer::TokenType;
+use crate::object::environment::Environment;
+use crate::object::heap::{GcConfig, GcStats, Heap};
+use crate::object::object::{ClassData, ClosureData, HashPair, MapData, ModuleData, NativeFunctionData, Upvalue, Value};
+use crate::vm::chunk::{FunctionProto, Op};
+use crate::vm::compiler;
+use crate::vm::stack::{Slot, StackSlot};
+
+struct CallFrame {
+    closure: Rc<ClosureData>,
+    ip: usize,
+    // Stack index of the frame's first local; the callee sits just below it.
+    base: usize,
+    call_line: i32,
+    // What the frame returns when it reaches ReturnPending: the value a `return`
+    // left while finally blocks run, or the value of the last top-level statement.
+    result: Value,
+    // Set for the top level of a module being imported.
+    module: Option<ModuleFrame>,
+}
+
+// A closure about to be called, and the instance it gets as `this` if it is a method.
+type Callee = (Rc<ClosureData>, Option<Value>);
+
+struct ModuleFrame {
+    path: PathBuf,
+    name: String,
+    exports: Vec<Symbol>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum HandlerKind {
+    Catch,
+    Finally,
+}
+
+// Where execution continues when an error is raised inside a try statement.
+struct Handler {
+    kind: HandlerKind,
+    frame: usize,
+    // The stack height to unwind to.
+    stack_len: usize,
+    target: usize,
+}
+
+// Executes compiled programs. Errors, output and limits behave as in the tree
+// walker; `Interpreter::set_backend` chooses between the two.
+pub struct Vm {
+    stack: Vec<Slot>,
+    frames: Vec<CallFrame>,
+    handlers: Vec<Handler>,
+    // Errors a finally block will rethrow when it completes, by the stack slot
+    // holding the block's placeholder for them.
+    pending_errors: Vec<(usize, RuntimeError)>,
+    // Captured variables still living on the stack, ordered by slot.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    error_class: Rc<ClassData>,
+    // What a local's slot holds until its declaration runs. Programs never see it:
+    // reading or assigning it is an undefined variable error.
+    undefined: Value,
+    // Modules already loaded, by canonical path, so each file runs only once.
+    modules: HashMap<PathBuf, Rc<ModuleData>>,
+    // Files currently executing, outermost first; imports resolve relative to the last one.
+    loading: Vec<PathBuf>,
+    // Which files outside the main script's directory may be imported.
+    permissions: Rc<RefCell<Permissions>>,
+    // Where the identifiers of imported modules are interned.
+    interner: Interner,
+    // Host functions, defined in every module's global scope.
+    natives: Vec<Rc<NativeFunctionData>>,
+    limits: Limits,
+    meter: Meter,
+    interrupt: InterruptHandle,
+    // Whether imported modules go through the optimizer.
+    optimize: bool,
+    heap: Heap,
+    output: Output,
+}
+
+impl Vm {
+    // Shares the error class and the interrupt handle with the tree walker, so that
+    // programs see the same `Error` whichever backend runs them.
+    pub fn new(error_class: Rc<ClassData>, interrupt: InterruptHandle) -> Self {
+        Self {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            handlers: Vec::new(),
+            pending_errors: Vec::new(),
+            open_upvalues: Vec::new(),
+            error_class,
+            undefined: Value::Class(Rc::new(ClassData::new(Symbol::new("<undefined>")))),
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            permissions: Rc::default(),
+            interner: Interner::default(),
+            natives: Vec::new(),
+            limits: Limits::default(),
+            meter: Meter::default(),
+            interrupt,
+            optimize: false,
+            heap: Heap::default(),
+            output: Output::stdout(),
+        }
+    }
+
+    fn define_builtins(&self, globals: &Rc<RefCell<Environment>>) {
+        globals.borrow_mut().define(self.error_class.name.clone(), Value::Class(Rc::clone(&self.error_class)));
+        for native in self.natives.iter() {
+            globals.borrow_mut().define(native.name.clone(), Value::NativeFunction(Rc::clone(native)));
+        }
+    }
+
+    // Makes a host function available in modules loaded from now on.
+    pub fn define_native(&mut self, native: Rc<NativeFunctionData>) {
+        self.natives.retain(|existing| existing.name != native.name);
+        self.natives.push(native);
+    }
+
+    pub fn set_script_path(&mut self, path: Option<&Path>) {
+        self.loading = modules::script_loading(path);
+    }
+
+    // Shares the interpreter's permissions, which decide what may be imported.
+    pub fn set_permissions(&mut self, permissions: Rc<RefCell<Permissions>>) {
+        self.permissions = permissions;
+    }
+
+    // Shares the interpreter's interner with the modules this VM imports.
+    pub fn set_interner(&mut self, interner: Interner) {
+        self.interner = interner;
+    }
+
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    pub fn set_gc_config(&mut self, config: GcConfig) {
+        self.heap.set_config(config);
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats().clone()
+    }
+
+    pub fn collect_garbage(&mut self) -> usize {
+        self.heap.collect()
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    // Runs a compiled script with `globals` as its global scope and returns the
+    // value of its last statement.
+    pub fn run(&mut self, script: Rc<FunctionProto>, globals: &Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+        self.meter = Meter::start(&self.limits);
+        self.interrupt.reset();
+        let loading = self.loading.len();
+        let closure = Rc::new(ClosureData { function: script, upvalues: Vec::new(), globals: Rc::clone(globals), receiver: None });
+        self.push(Value::Closure(Rc::clone(&closure)));
+        self.frames.push(CallFrame { closure, ip: 0, base: 1, call_line: 0, result: Value::Nil, module: None });
+        let outcome = self.execute();
+        self.pending_errors.clear();
+        if outcome.is_err() {
+            self.close_upvalues(0);
+            self.stack.clear();
+            self.frames.clear();
+            self.handlers.clear();
+            self.loading.truncate(loading);
+        }
+        outcome
+    }
+
+    fn execute(&mut self) -> Result<Value, RuntimeError> {
+        loop {
+            match self.dispatch() {
+                Ok(value) => return Ok(value),
+                Err(err) => self.unwind(err)?,
+            }
+        }
+    }
+
+    // Hands `err` to the innermost handler, unless it is uncatchable or there is
+    // none, in which case it ends the run.
+    fn unwind(&mut self, mut err: RuntimeError) -> Result<(), RuntimeError> {
+        if err.trace.is_none() {
+            err.trace = Some(self.stack_trace(err.line));
+        }
+        if !err.kind.is_catchable() {
+            return Err(err);
+        }
+        let handler = match self.handlers.pop() {
+            Some(handler) => handler,
+            None => return Err(err),
+        };
+        // The handler's code expects the stack it was installed with.
+        if self.stack.len() < handler.stack_len {
+            let mut invalid = self.invalid_code("the stack shrank below an exception handler");
+            invalid.trace = err.trace;
+            return Err(invalid);
+        }
+        while self.frames.len() > handler.frame + 1 {
+            if let Some(CallFrame { module: Some(_), .. }) = self.frames.pop() {
+                self.loading.pop();
+            }
+        }
+        self.close_upvalues(handler.stack_len);
+        self.stack.truncate(handler.stack_len);
+        self.frame_mut().ip = handler.target;
+        match handler.kind {
+            HandlerKind::Catch => self.push(caught_value(err, &self.error_class)),
+            HandlerKind::Finally => {
+                self.pending_errors.retain(|(slot, _)| *slot < handler.stack_len);
+                self.pending_errors.push((handler.stack_len, err));
+                self.push(Value::Nil);
+            }
+        }
+        Ok(())
+    }
+
+    // The stack as it is now, innermost frame first, for an error raised at `line`.
+    fn stack_trace(&self, line: i32) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut line = line;
+        for frame in self.frames.iter().rev() {
+            frames.push(Frame { function: frame.closure.function.name.to_string(), line });
+            line = frame.call_line;
+        }
+        frames
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("a frame is executing")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("a frame is executing")
+    }
+
+    // The source line of the instruction being executed.
+    fn line(&self) -> i32 {
+        let frame = self.frame();
+        frame.closure.function.chunk.lines[frame.ip - 1]
+    }
+
+    fn name(&self, index: u32) -> Name {
+        let frame = self.frame();
+        Name { symbol: frame.closure.function.chunk.names[index as usize].clone(), line: self.line() }
+    }
+
+    fn upvalue_name(&self, index: u32) -> Name {
+        let frame = self.frame();
+        Name { symbol: frame.closure.function.upvalues[index as usize].name.clone(), line: self.line() }
+    }
+
+    // An error for loaded code that did something compiled code never does.
+    fn invalid_code(&self, message: &str) -> RuntimeError {
+        self.error(ErrorKind::BytecodeError, &format!("Invalid bytecode: {}.", message))
+    }
+
+    fn error(&self, kind: ErrorKind, message: &str) -> RuntimeError {
+        RuntimeError::at_line(kind, message, self.line())
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(Slot::from_value(value));
+    }
+
+    fn pop(&mut self) -> Value {
+        self.pop_slot().into_value()
+    }
+
+    fn pop_slot(&mut self) -> Slot {
+        self.stack.pop().expect("the stack is not empty")
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.peek_slot(distance).to_value()
+    }
+
+    fn peek_slot(&self, distance: usize) -> &Slot {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn is_undefined(&self, value: &Value) -> bool {
+        match (value, &self.undefined) {
+            (Value::Class(class), Value::Class(undefined)) => Rc::ptr_eq(class, undefined),
+            _ => false,
+        }
+    }
+
+    fn push_bool(&mut self, value: bool) {
+        self.push(Value::Boolean(value));
+    }
+
+    // Registers a newly allocated object with the collector and against the heap limits.
+    fn track(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        self.heap.track(value);
+        if self.heap.should_collect() {
+            self.heap.collect();
+        }
+        self.heap.check_limits(&self.limits).map_err(|message| self.error(ErrorKind::MemoryLimitExceeded, &message))
+    }
+
+    // Counts a value stored in a container against the heap limits, since the
+    // container grows without a new allocation.
+    fn note_write(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        self.heap.note_write(value);
+        self.heap.check_limits(&self.limits).map_err(|message| self.error(ErrorKind::MemoryLimitExceeded, &message))
+    }
+
+    // Runs instructions until the outermost frame returns or an error is raised.
+    fn dispatch(&mut self) -> Result<Value, RuntimeError> {
+        loop {
+            let frame = self.frames.last_mut().expect("a frame is executing");
+            if let Err((kind, message)) = self.meter.tick(&self.limits, &self.interrupt) {
+                // Attributed to the instruction about to run.
+                return Err(RuntimeError::at_line(kind, &message, frame.closure.function.chunk.lines[frame.ip]));
+            }
+            let op = frame.closure.function.chunk.code[frame.ip];
+            frame.ip += 1;
+            match op {
+                Op::Constant(index) => {
+                    let value = self.frame().closure.function.chunk.constants[index as usize].clone();
+                    self.push(value);
+                }
+                Op::Nil => self.push(Value::Nil),
+                Op::Undefined => self.push(self.undefined.clone()),
+                Op::True => self.push_bool(true),
+                Op::False => self.push_bool(false),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Dup => self.stack.push(self.peek_slot(0).clone()),
+                Op::Dup2 => {
+                    self.stack.push(self.peek_slot(1).clone());
+                    self.stack.push(self.peek_slot(1).clone());
+                }
+                Op::Bury(depth) => {
+                    let value = self.pop_slot();
+                    let at = self.stack.len() - depth as usize;
+                    self.stack.insert(at, value);
+                }
+
+                Op::GetLocal(slot) => {
+                    let value = self.stack[self.frame().base + slot as usize].clone();
+                    self.stack.push(value);
+                }
+                Op::SetLocal(slot) => {
+                    let at = self.frame().base + slot as usize;
+                    self.stack[at] = self.peek_slot(0).clone();
+                }
+                Op::DefineLocal(slot) => {
+                    let at = self.frame().base + slot as usize;
+                    self.stack[at] = self.pop_slot();
+                }
+                Op::GetLocalChecked(slot, index) => {
+                    let value = self.stack[self.frame().base + slot as usize].to_value();
+                    if self.is_undefined(&value) {
+                        return Err(undefined_variable(self.name(index)));
+                    }
+                    self.push(value);
+                }
+                Op::SetLocalChecked(slot, index) => {
+                    let at = self.frame().base + slot as usize;
+                    if self.is_undefined(&self.stack[at].to_value()) {
+                        return Err(undefined_variable(self.name(index)));
+                    }
+                    self.stack[at] = self.peek_slot(0).clone();
+                }
+                Op::GetUpvalue(index) => {
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index as usize]);
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].to_value(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    if self.is_undefined(&value) {
+                        return Err(undefined_variable(self.upvalue_name(index)));
+                    }
+                    self.push(value);
+                }
+                Op::SetUpvalue(index) => {
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index as usize]);
+                    let value = self.peek(0);
+                    let current = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].to_value(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    if self.is_undefined(&current) {
+                        return Err(undefined_variable(self.upvalue_name(index)));
+                    }
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = Slot::from_value(value),
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                Op::GetGlobal(index) => {
+                    let name = self.name(index);
+                    let value = self.frame().closure.globals.borrow().get(&name.symbol);
+                    match value {
+                        Some(value) => self.push(value),
+                        None => return Err(undefined_variable(name)),
+                    }
+                }
+                Op::SetGlobal(index) => {
+                    let name = self.name(index);
+                    let value = self.peek(0);
+                    if !self.frame().closure.globals.borrow_mut().assign(&name.symbol, value) {
+                        return Err(undefined_variable(name));
+                    }
+                }
+                Op::DefineGlobal(index) => {
+                    let name = self.name(index);
+                    let value = self.pop();
+                    self.frame().closure.globals.borrow_mut().define(name.symbol, value);
+                }
+                Op::PopScope(count) => {
+                    let from = self.stack.len() - count as usize;
+                    self.close_upvalues(from);
+                    self.stack.truncate(from);
+                }
+
+                Op::Add => self.binary(TokenType::Plus)?,
+                Op::Subtract => self.binary(TokenType::Minus)?,
+                Op::Multiply => self.binary(TokenType::Asterisk)?,
+                Op::Divide => self.binary(TokenType::Slash)?,
+                Op::Modulo => self.binary(TokenType::Percent)?,
+                Op::Power => self.binary(TokenType::StarStar)?,
+                Op::Less => self.binary(TokenType::Less)?,
+                Op::LessEqual => self.binary(TokenType::LessEqual)?,
+                Op::Greater => self.binary(TokenType::Greater)?,
+                Op::GreaterEqual => self.binary(TokenType::GreaterEqual)?,
+                Op::Equal => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.push_bool(left == right);
+                }
+                Op::NotEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    self.push_bool(left != right);
+                }
+                Op::Negate => {
+                    let value = self.pop();
+                    let result = negate(&value, self.line())?;
+                    self.push(result);
+                }
+                Op::Not => {
+                    let value = self.pop();
+                    self.push_bool(!value.is_truthy());
+                }
+                Op::Increment | Op::Decrement => {
+                    let n = match self.pop() {
+                        Value::Number(n) => n,
+                        _ => return Err(operand_not_number(self.line())),
+                    };
+                    self.push(Value::Number(if op == Op::Increment { n + 1.0 } else { n - 1.0 }));
+                }
+
+                Op::Jump(target) => self.frame_mut().ip = target as usize,
+                Op::JumpIfFalse(target) => {
+                    if !self.peek_slot(0).is_truthy() {
+                        self.frame_mut().ip = target as usize;
+                    }
+                }
+                Op::JumpIfTrue(target) => {
+                    if self.peek_slot(0).is_truthy() {
+                        self.frame_mut().ip = target as usize;
+                    }
+                }
+                Op::PopJumpIfFalse(target) => {
+                    if !self.pop_slot().is_truthy() {
+                        self.frame_mut().ip = target as usize;
+                    }
+                }
+
+                Op::Call(count) => self.call(count as usize)?,
+                Op::TailCall(count) => {
+                    if let Some(value) = self.tail_call(count as usize)? {
+                        return Ok(value);
+                    }
+                }
+                Op::Closure(index) => {
+                    let function = Rc::clone(&self.frame().closure.function.chunk.functions[index as usize]);
+                    let mut upvalues = Vec::with_capacity(function.upvalues.len());
+                    for upvalue in function.upvalues.iter() {
+                        let captured = if upvalue.is_local {
+                            self.capture_upvalue(self.frame().base + upvalue.index as usize)
+                        } else {
+                            Rc::clone(&self.frame().closure.upvalues[upvalue.index as usize])
+                        };
+                        upvalues.push(captured);
+                    }
+                    let globals = Rc::clone(&self.frame().closure.globals);
+                    let closure = Value::Closure(Rc::new(ClosureData { function, upvalues, globals, receiver: None }));
+                    self.track(&closure)?;
+                    self.push(closure);
+                }
+                Op::Class(index, count) => {
+                    let name = self.name(index).symbol;
+                    let mut methods = HashMap::new();
+                    for method in self.stack.split_off(self.stack.len() - count as usize) {
+                        match method.into_value() {
+                            Value::Closure(method) if method.function.method => {
+                                methods.insert(method.function.name.clone(), Value::Closure(method));
+                            }
+                            _ => return Err(self.invalid_code("CLASS takes method closures")),
+                        }
+                    }
+                    let class = Value::Class(Rc::new(ClassData::with_methods(name, methods)));
+                    self.track(&class)?;
+                    self.push(class);
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    if let Some(value) = self.return_from_frame(value) {
+                        return Ok(value);
+                    }
+                }
+                Op::SetReturn => {
+                    let value = self.pop();
+                    self.frame_mut().result = value;
+                }
+                Op::ReturnPending => {
+                    let value = std::mem::replace(&mut self.frame_mut().result, Value::Nil);
+                    if let Some(value) = self.return_from_frame(value) {
+                        return Ok(value);
+                    }
+                }
+                Op::Print => {
+                    let value = self.pop();
+                    if let Err(err) = self.output.write_line(&value.inspect()) {
+                        return Err(self.error(ErrorKind::IOError, &format!("Could not write output: {}.", err)));
+                    }
+                }
+
+                Op::List(count) => {
+                    let elements = self.stack.split_off(self.stack.len() - count as usize);
+                    let list = Value::list(elements.into_iter().map(Slot::into_value).collect());
+                    self.track(&list)?;
+                    self.push(list);
+                }
+                Op::Map(count) => {
+                    let values = self.stack.split_off(self.stack.len() - 2 * count as usize);
+                    let mut data = MapData::default();
+                    let mut values = values.into_iter().map(Slot::into_value);
+                    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                        let hash_key = map_key(&key, self.line())?;
+                        data.insert(hash_key, HashPair { key, value });
+                    }
+                    let map = Value::Map(Rc::new(RefCell::new(data)));
+                    self.track(&map)?;
+                    self.push(map);
+                }
+                Op::CheckKey => {
+                    map_key(&self.peek(0), self.line())?;
+                }
+                Op::GetIndex => {
+                    let index = self.pop();
+                    let left = self.pop();
+                    let place = index_place(left, index, self.line())?;
+                    self.push(read_place(&place)?);
+                }
+                Op::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let left = self.pop();
+                    let place = index_place(left, index, self.line())?;
+                    write_place(place, value.clone())?;
+                    self.note_write(&value)?;
+                    self.push(value);
+                }
+                Op::CheckIndex => {
+                    index_place(self.peek(1), self.peek(0), self.line())?;
+                }
+                Op::GetProperty(index) => {
+                    let object = self.pop();
+                    let place = property_place(object, self.name(index))?;
+                    let value = match unbound_method(&place) {
+                        Some((Value::Closure(method), instance)) => self.bind(&method, instance)?,
+                        _ => read_place(&place)?,
+                    };
+                    self.push(value);
+                }
+                Op::SetProperty(index) => {
+                    let value = self.pop();
+                    let object = self.pop();
+                    let place = property_place(object, self.name(index))?;
+                    let in_container = place.in_container();
+                    write_place(place, value.clone())?;
+                    if in_container {
+                        self.note_write(&value)?;
+                    }
+                    self.push(value);
+                }
+                Op::CheckProperty(index) => {
+                    property_place(self.peek(0), self.name(index))?;
+                }
+
+                Op::PushCatch(target) => self.push_handler(HandlerKind::Catch, target),
+                Op::PushFinally(target) => self.push_handler(HandlerKind::Finally, target),
+                Op::PopHandler => {
+                    let frame = self.frames.len() - 1;
+                    if !self.handlers.last().is_some_and(|handler| handler.frame == frame) {
+                        return Err(self.invalid_code("POP_HANDLER without a handler in this function"));
+                    }
+                    self.handlers.pop();
+                }
+                Op::Rethrow => {
+                    self.pop();
+                    let slot = self.stack.len();
+                    let position = self.pending_errors.iter().rposition(|(pending, _)| *pending == slot);
+                    return Err(match position {
+                        Some(position) => self.pending_errors.remove(position).1,
+                        None => self.invalid_code("RETHROW without a pending error"),
+                    });
+                }
+                Op::Throw => {
+                    let value = self.pop();
+                    return Err(thrown_error(value, &self.error_class, self.line()));
+                }
+
+                Op::InRange(index) => {
+                    let range = self.frame().closure.function.chunk.ranges[index as usize];
+                    let matches = match self.peek(0) {
+                        Value::Number(n) if range.inclusive => range.start <= n && n <= range.end,
+                        Value::Number(n) => range.start <= n && n < range.end,
+                        _ => false,
+                    };
+                    self.push_bool(matches);
+                }
+                Op::IsList(len, exact) => {
+                    let matches = match self.peek(0) {
+                        Value::List(elements) if exact => elements.borrow().len() == len as usize,
+                        Value::List(elements) => elements.borrow().len() >= len as usize,
+                        _ => false,
+                    };
+                    self.push_bool(matches);
+                }
+                Op::IsMap => self.push_bool(matches!(self.peek(0), Value::Map(_))),
+                Op::HasEntry => {
+                    let matches = match (self.peek(1), self.peek(0).hash_key()) {
+                        (Value::Map(map), Some(key)) => map.borrow().get(&key).is_some(),
+                        _ => false,
+                    };
+                    self.push_bool(matches);
+                }
+                Op::IsInstance(index) => {
+                    let class = match self.pop() {
+                        Value::Class(class) => class,
+                        _ => return Err(not_a_class(self.name(index))),
+                    };
+                    let matches = match self.peek(0) {
+                        Value::Instance(instance) => Rc::ptr_eq(&instance.borrow().class, &class),
+                        _ => false,
+                    };
+                    self.push_bool(matches);
+                }
+                Op::HasField(index) => {
+                    let name = self.name(index).symbol;
+                    let matches = match self.peek(0) {
+                        Value::Instance(instance) => instance.borrow().has(&name),
+                        _ => false,
+                    };
+                    self.push_bool(matches);
+                }
+                // IS_LIST and HAS_ENTRY check these reads in compiled code; the
+                // verifier doesn't, so loaded code that skips the check fails here.
+                Op::ListElement(index) => {
+                    let element = match self.peek(0) {
+                        Value::List(elements) => elements.borrow().get(index as usize).cloned(),
+                        _ => None,
+                    };
+                    match element {
+                        Some(element) => self.push(element),
+                        None => return Err(self.invalid_code("LIST_ELEMENT without a matching IS_LIST")),
+                    }
+                }
+                Op::ListRest(start) => {
+                    let rest = match self.peek(0) {
+                        Value::List(elements) => elements.borrow().get(start as usize..).map(<[Value]>::to_vec),
+                        _ => None,
+                    };
+                    match rest {
+                        Some(rest) => self.push(Value::list(rest)),
+                        None => return Err(self.invalid_code("LIST_REST without a matching IS_LIST")),
+                    }
+                }
+                Op::MapEntry => {
+                    let key = self.pop();
+                    let entry = match (self.peek(0), key.hash_key()) {
+                        (Value::Map(map), Some(key)) => map.borrow().get(&key).map(|pair| pair.value.clone()),
+                        _ => None,
+                    };
+                    match entry {
+                        Some(entry) => self.push(entry),
+                        None => return Err(self.invalid_code("MAP_ENTRY without a matching HAS_ENTRY")),
+                    }
+                }
+                Op::NoMatch => {
+                    let subject = self.pop();
+                    return Err(no_match(&subject, self.line()));
+                }
+
+                Op::Import(index) => {
+                    let path = match &self.frame().closure.function.chunk.constants[index as usize] {
+                        Value::String(path) => Rc::clone(path),
+                        _ => unreachable!("import paths are strings"),
+                    };
+                    self.import(&path)?;
+                }
+            }
+        }
+    }
+
+    fn binary(&mut self, operator: TokenType) -> Result<(), RuntimeError> {
+        let right = self.pop();
+        let left = self.pop();
+        let result = apply_infix_operator(&operator, &left, &right, self.line())?;
+        if let (Some(max), Value::String(s)) = (self.limits.max_heap_bytes, &result) {
+            if s.len() > max {
+                return Err(self.error(ErrorKind::MemoryLimitExceeded, &format!("Heap limit of {} bytes exceeded.", max)));
+            }
+        }
+        self.push(result);
+        Ok(())
+    }
+
+    fn push_handler(&mut self, kind: HandlerKind, target: u32) {
+        let handler = Handler { kind, frame: self.frames.len() - 1, stack_len: self.stack.len(), target: target as usize };
+        self.handlers.push(handler);
+    }
+
+    // Calls the value below the top `count` values with them as arguments.
+    fn call(&mut self, count: usize) -> Result<(), RuntimeError> {
+        let Some((closure, receiver)) = self.callee(count)? else {
+            return Ok(());
+        };
+        let line = self.line();
+        let callee_slot = self.stack.len() - count - 1;
+        check_arity(closure.function.arity, count, line)?;
+        check_call_depth(self.frames.len() - 1, self.limits.max_call_depth, line)?;
+        self.pass_receiver(&closure, receiver, callee_slot)?;
+        self.frames.push(CallFrame { closure, ip: 0, base: callee_slot + 1, call_line: line, result: Value::Nil, module: None });
+        Ok(())
+    }
+
+    // The closure a call to the value below the top `count` values runs, with the
+    // instance it gets as `this` if it is a method: a bound method's, or a new one
+    // for a class's `init`. Natives, and classes without `init`, are called here
+    // instead; their result replaces the callee and the arguments.
+    fn callee(&mut self, count: usize) -> Result<Option<Callee>, RuntimeError> {
+        let line = self.line();
+        let callee_slot = self.stack.len() - count - 1;
+        match self.stack[callee_slot].to_value() {
+            Value::Closure(closure) => {
+                let receiver = closure.receiver.clone();
+                return Ok(Some((closure, receiver)));
+            }
+            Value::NativeFunction(native) => {
+                check_native_arity(&native, count, line)?;
+                check_call_depth(self.frames.len() - 1, self.limits.max_call_depth, line)?;
+                let arguments = self.stack.split_off(callee_slot + 1).into_iter().map(Slot::into_value).collect();
+                self.stack.pop();
+                let result = (native.function)(arguments).map_err(|err: NativeError| {
+                    let mut err = RuntimeError::at_line(err.kind, &err.message, line);
+                    let mut trace = vec![Frame { function: native.name.to_string(), line }];
+                    trace.extend(self.stack_trace(line));
+                    err.trace = Some(trace);
+                    err
+                })?;
+                self.track(&result)?;
+                self.push(result);
+            }
+            Value::Class(class) => {
+                let (instance, initializer) = instantiate(class, count, line)?;
+                self.track(&instance)?;
+                if let Some(Value::Closure(initializer)) = initializer {
+                    return Ok(Some((initializer, Some(instance))));
+                }
+                self.stack.truncate(callee_slot);
+                self.push(instance);
+            }
+            _ => return Err(not_callable(line)),
+        }
+        Ok(None)
+    }
+
+    // Puts the instance a method is called on in the slot before its arguments.
+    fn pass_receiver(&mut self, closure: &ClosureData, receiver: Option<Value>, callee_slot: usize) -> Result<(), RuntimeError> {
+        if !closure.function.method {
+            return Ok(());
+        }
+        match receiver {
+            Some(receiver) => {
+                self.stack.insert(callee_slot + 1, Slot::from_value(receiver));
+                Ok(())
+            }
+            None => Err(self.invalid_code("a method was called without an instance")),
+        }
+    }
+
+    // `method` bound to `instance`, which calls pass it as `this`.
+    fn bind(&mut self, method: &ClosureData, instance: Value) -> Result<Value, RuntimeError> {
+        let bound = Value::Closure(Rc::new(ClosureData {
+            function: Rc::clone(&method.function),
+            upvalues: method.upvalues.clone(),
+            globals: Rc::clone(&method.globals),
+            receiver: Some(instance),
+        }));
+        self.track(&bound)?;
+        Ok(bound)
+    }
+
+    // Calls the value below the top `count` values and returns what it returns. A
+    // closure reuses the current frame; anything else is called as usual, and the
+    // frame returns its result as Op::Return would.
+    fn tail_call(&mut self, count: usize) -> Result<Option<Value>, RuntimeError> {
+        let callee_slot = self.stack.len() - count - 1;
+        let Some((closure, receiver)) = self.callee(count)? else {
+            let value = self.pop();
+            return Ok(self.return_from_frame(value));
+        };
+        check_arity(closure.function.arity, count, self.line())?;
+        self.pass_receiver(&closure, receiver, callee_slot)?;
+        let depth = self.frames.len() - 1;
+        while self.handlers.last().is_some_and(|handler| handler.frame >= depth) {
+            self.handlers.pop();
+        }
+        // The callee and its arguments replace the returning function's.
+        let base = self.frame().base;
+        self.close_upvalues(base);
+        self.stack.drain(base - 1..callee_slot);
+        let frame = self.frame_mut();
+        frame.closure = closure;
+        frame.ip = 0;
+        frame.result = Value::Nil;
+        Ok(None)
+    }
+
+    // Pops the current frame and hands `value` to its caller; returns it instead if
+    // that was the outermost frame.
+    fn return_from_frame(&mut self, value: Value) -> Option<Value> {
+        let frame = self.frames.pop().expect("a frame is executing");
+        while self.handlers.last().is_some_and(|handler| handler.frame >= self.frames.len()) {
+            self.handlers.pop();
+        }
+        self.close_upvalues(frame.base);
+        self.stack.truncate(frame.base - 1);
+        if let Some(ModuleFrame { path, name, exports }) = frame.module {
+            self.loading.pop();
+            let module = Rc::new(ModuleData { name, globals: Rc::clone(&frame.closure.globals), exports });
+            self.modules.insert(path, Rc::clone(&module));
+            self.push(Value::Module(module));
+            return None;
+        }
+        if self.frames.is_empty() {
+            return Some(value);
+        }
+        self.push(value);
+        None
+    }
+
+    // Pushes the module at `path`, first running its top level in a frame of its
+    // own if it hasn't been loaded yet.
+    fn import(&mut self, path: &str) -> Result<(), RuntimeError> {
+        let line = self.line();
+        let resolved = modules::resolve_import(&self.loading, &self.permissions.borrow(), path, line)?;
+        if let Some(module) = self.modules.get(&resolved) {
+            self.push(Value::Module(Rc::clone(module)));
+            return Ok(());
+        }
+        let program = modules::parse_module(&resolved, path, line, self.optimize, &self.interner)?;
+        let exports = program.statements.iter().filter_map(|stmt| declared_name(stmt.as_ref())).collect();
+        let name = Symbol::new(&modules::module_frame_name(&self.loading, &resolved));
+        let function = compiler::compile(&program, name);
+        let globals = Environment::new();
+        self.define_builtins(&globals);
+        let closure = Rc::new(ClosureData { function, upvalues: Vec::new(), globals, receiver: None });
+        self.push(Value::Closure(Rc::clone(&closure)));
+        let module = ModuleFrame { path: resolved.clone(), name: modules::module_name(&resolved, path), exports };
+        self.frames.push(CallFrame { closure, ip: 0, base: self.stack.len(), call_line: line, result: Value::Nil, module: Some(module) });
+        self.loading.push(resolved);
+        Ok(())
+    }
+
+    // The upvalue for stack slot `slot`, shared by every closure capturing it.
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let position = self.open_upvalues.partition_point(|upvalue| open_slot(upvalue) < slot);
+        if let Some(upvalue) = self.open_upvalues.get(position) {
+            if open_slot(upvalue) == slot {
+                return Rc::clone(upvalue);
+            }
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.insert(position, Rc::clone(&upvalue));
+        self.heap.track_upvalue(&upvalue);
+        if self.heap.should_collect() {
+            self.heap.collect();
+        }
+        upvalue
+    }
+
+    // Moves the values of stack slots from `from` up that closures captured into
+    // their upvalues, before the slots are popped.
+    fn close_upvalues(&mut self, from: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let slot = open_slot(upvalue);
+            if slot < from {
+                break;
+            }
+            let value = self.stack.get(slot).map(Slot::to_value).unwrap_or(Value::Nil);
+            *upvalue.borrow_mut() = Upvalue::Closed(value);
+            self.open_upvalues.pop();
+        }
+    }
+}
+
+fn open_slot(upvalue: &Rc<RefCell<Upvalue>>) -> usize {
+    match *upvalue.borrow() {
+        Upvalue::Open(slot) => slot,
+        Upvalue::Closed(_) => unreachable!("closed upvalues are not on the open list"),
+    }
+}
@@ -0,0 +1,260 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::rc::Rc;
+use codecrafters_interpreter::common::symbol::{Interner, Symbol};
+use codecrafters_interpreter::object::object::Value;
+use codecrafters_interpreter::vm::{bytecode, compiler};
+use codecrafters_interpreter::vm::chunk::{Chunk, FunctionProto, Op, UpvalueDesc};
+use codecrafters_interpreter::{Error, Interpreter};
+use common::{run, stderr, stdout};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lox-bytecode-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn lox(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter")).args(args).output().unwrap()
+}
+
+// Compiles `source` with the CLI and returns the path of the bytecode file.
+fn compile(dir: &Path, source: &str) -> PathBuf {
+    fs::write(dir.join("main.lox"), source).unwrap();
+    let output = dir.join("main.loxc");
+    let compiled = lox(&[Path::new("compile"), &dir.join("main.lox"), Path::new("-o"), &output]);
+    assert!(compiled.status.success(), "{}", stderr(&compiled));
+    output
+}
+
+#[test]
+fn compiled_files_run_like_their_source() {
+    let source = r#"
+        fun counter() {
+            var n = 0;
+            fun inc() { n = n + 1; return n; }
+            return inc;
+        }
+        var c = counter();
+        c();
+        print c();
+        try {
+            try { throw "inner"; } finally { print "finally"; }
+        } catch (e) {
+            print e;
+        }
+        match ([1, [2, 3]]) {
+            case [a, [b, ..rest]] => print rest;
+        }
+        for (var i = 0; i < 3; i = i + 1) if (i == 1) print 0.5 * i; else print "é";
+        class Box {
+            init(value) { this.value = value; }
+            get() { return this.value; }
+        }
+        print Box("boxed").get();
+        fun fail() {
+            return -"a";
+        }
+        fail();
+    "#;
+    let dir = temp_dir("parity");
+    let compiled = lox(&[Path::new("run"), &compile(&dir, source)]);
+    let interpreted = run("run", source);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(stdout(&compiled), "2\nfinally\ninner\n[3]\né\n0.5\né\nboxed");
+    assert_eq!(stdout(&compiled), stdout(&interpreted));
+    assert_eq!(stderr(&compiled), stderr(&interpreted));
+    assert_eq!(compiled.status.code(), Some(70));
+}
+
+#[test]
+fn compiled_files_import_relative_to_themselves() {
+    let dir = temp_dir("imports");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("lib/greet.lox"), "fun greet(name) { return \"hello \" + name; }").unwrap();
+    let compiled = compile(&dir, "import { greet } from \"lib/greet.lox\";\nprint greet(\"loxc\");");
+    let output = lox(&[Path::new("run"), &compiled]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(stdout(&output), "hello loxc");
+}
+
+#[test]
+fn compile_defaults_to_the_source_name_and_reports_compile_errors() {
+    let dir = temp_dir("default");
+    fs::write(dir.join("script.lox"), "print 1;").unwrap();
+    let output = lox(&[Path::new("compile"), &dir.join("script.lox")]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&lox(&[Path::new("run"), &dir.join("script.loxc")])), "1");
+
+    fs::write(dir.join("broken.lox"), "print (1;").unwrap();
+    let output = lox(&[Path::new("compile"), &dir.join("broken.lox")]);
+    let written = dir.join("broken.loxc").exists();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    assert!(stderr(&output).starts_with("[line 1]"), "{}", stderr(&output));
+    assert!(!written);
+}
+
+#[test]
+fn damaged_files_are_rejected() {
+    let dir = temp_dir("damaged");
+    let bytes = fs::read(compile(&dir, "var x = \"some text\";\nprint x;")).unwrap();
+    let mut cases = vec![
+        ("truncated", bytes[..bytes.len() - 10].to_vec(), "the file is truncated"),
+        ("header", bytes[..7].to_vec(), "the file is truncated"),
+        ("empty", Vec::new(), "not a compiled Lox file"),
+        ("source", b"print 1;".to_vec(), "not a compiled Lox file"),
+    ];
+    let mut version = bytes.clone();
+    version[4] = 3;
+    cases.push(("version", version, "compiled for format version 3, but this interpreter reads version 2"));
+    let mut corrupted = bytes.clone();
+    corrupted[20] ^= 0x40;
+    cases.push(("corrupted", corrupted, "checksum mismatch; the file is corrupted"));
+    let mut extended = bytes.clone();
+    extended.push(0);
+    cases.push(("extended", extended, "unexpected data after the end of the file"));
+
+    for (name, contents, message) in cases {
+        let path = dir.join(format!("{}.loxc", name));
+        fs::write(&path, contents).unwrap();
+        let output = lox(&[Path::new("run"), &path]);
+        assert_eq!(output.status.code(), Some(65), "{}", name);
+        assert_eq!(stderr(&output), format!("Invalid bytecode file {}: {}.", path.display(), message));
+        assert_eq!(stdout(&output), "");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn function(name: &str, arity: usize, code: Vec<Op>) -> FunctionProto {
+    let lines = vec![1; code.len()];
+    FunctionProto { name: Symbol::new(name), arity, method: false, upvalues: Vec::new(), chunk: Chunk { code, lines, ..Chunk::default() } }
+}
+
+// Encodes `script` and reads it back, as loading a file with that code would.
+fn load(script: &FunctionProto) -> Result<(), String> {
+    bytecode::read(&bytecode::write(script), &Interner::default()).map(|_| ())
+}
+
+#[test]
+fn the_verifier_rejects_unsafe_code() {
+    let cases = [
+        (vec![Op::Add, Op::ReturnPending], "in script at 0000: ADD needs 2 values but the stack holds 0"),
+        (vec![Op::Nil, Op::PopScope(2), Op::ReturnPending], "in script at 0001: POP_SCOPE needs 2 values but the stack holds 1"),
+        (vec![Op::Jump(7)], "in script at 0000: execution continues at 0007, past the end of the code"),
+        (vec![Op::True, Op::PushCatch(9), Op::ReturnPending], "in script at 0001: execution continues at 0009, past the end of the code"),
+        (vec![Op::Nil, Op::Pop], "in script at 0001: execution continues at 0002, past the end of the code"),
+        (vec![Op::Constant(0), Op::ReturnPending], "in script at 0000: CONSTANT refers to constant 0, but there are only 0"),
+        (vec![Op::GetGlobal(3), Op::ReturnPending], "in script at 0000: GET_GLOBAL refers to name 3, but there are only 0"),
+        (vec![Op::Nil, Op::GetLocal(1), Op::ReturnPending], "in script at 0001: GET_LOCAL refers to stack slot 1, but there are only 1"),
+        (vec![Op::Closure(0), Op::ReturnPending], "in script at 0000: CLOSURE refers to function 0, but there are only 0"),
+        (vec![Op::True, Op::JumpIfFalse(3), Op::Nil, Op::ReturnPending], "in script at 0003: the stack holds 2 values on one path here and 1 on another"),
+        (vec![Op::Nil, Op::Jump(0)], "in script at 0000: the stack holds 0 values on one path here and 1 on another"),
+        (vec![Op::Nil, Op::Rethrow], "in script at 0001: RETHROW outside a finally handler"),
+        (vec![], "in script: the function has no code"),
+    ];
+    for (code, message) in cases {
+        assert_eq!(load(&function("script", 0, code)), Err(message.to_string()));
+    }
+
+    let mut script = function("script", 0, vec![Op::Import(0), Op::ReturnPending]);
+    script.chunk.constants.push(Value::Number(1.0));
+    assert_eq!(load(&script), Err("in script at 0000: IMPORT needs a string constant".to_string()));
+
+    let script = function("script", 1, vec![Op::ReturnPending]);
+    assert_eq!(load(&script), Err("the script must take no parameters and capture no variables".to_string()));
+}
+
+#[test]
+fn nested_functions_are_verified_against_their_enclosing_function() {
+    // Parameters are the function's first locals.
+    let mut script = function("script", 0, vec![Op::Closure(0), Op::ReturnPending]);
+    script.chunk.functions.push(Rc::new(function("f", 2, vec![Op::GetLocal(1), Op::Return])));
+    assert_eq!(load(&script), Ok(()));
+
+    let mut script = function("script", 0, vec![Op::Closure(0), Op::ReturnPending]);
+    script.chunk.functions.push(Rc::new(function("f", 1, vec![Op::GetLocal(1), Op::Return])));
+    assert_eq!(load(&script), Err("in f at 0000: GET_LOCAL refers to stack slot 1, but there are only 1".to_string()));
+
+    let mut script = function("script", 0, vec![Op::Closure(0), Op::ReturnPending]);
+    let mut nested = function("f", 0, vec![Op::GetUpvalue(0), Op::Return]);
+    nested.upvalues.push(UpvalueDesc { is_local: true, index: 0, name: Symbol::new("x") });
+    script.chunk.functions.push(Rc::new(nested));
+    assert_eq!(load(&script), Err("in script at 0000: CLOSURE refers to stack slot 0, but there are only 0".to_string()));
+}
+
+#[test]
+fn the_api_compiles_and_runs_bytecode() {
+    let dir = temp_dir("api");
+    let path = dir.join("prog.loxc");
+    let program = Interpreter::compile("var answer = 6 * 7;\nprint answer;\nnil.x;").unwrap();
+    fs::write(&path, Interpreter::compile_bytecode(&program)).unwrap();
+    let mut interp = Interpreter::new();
+    let out = interp.capture_output();
+    let err = interp.run_bytecode_file(&path).unwrap_err();
+    assert_eq!(out.take(), "42\n");
+    // Line tables survive the round trip.
+    assert_eq!(err.to_string(), "Only instances have properties.\n[line 3] in script");
+    assert_eq!(interp.get_global("answer"), Some(42.0.into()));
+
+    fs::write(&path, b"LOXC").unwrap();
+    let err = interp.run_bytecode_file(&path).unwrap_err();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(err, Error::Bytecode { .. }));
+    assert_eq!(err.exit_code(), 65);
+}
+
+// Runs `script` from a bytecode file, which it must pass verification to get to.
+fn run_loaded(dir: &Path, script: &FunctionProto) -> Result<(), Error> {
+    let path = dir.join("tampered.loxc");
+    fs::write(&path, bytecode::write(script)).unwrap();
+    Interpreter::new().run_bytecode_file(&path).map(|_| ())
+}
+
+#[test]
+fn loaded_code_that_skips_the_compiler_s_checks_fails_without_panicking() {
+    let dir = temp_dir("tampered");
+    let program = Interpreter::compile("match ([1]) { case [a] => print a; }").unwrap();
+    let mut script = Rc::try_unwrap(compiler::compile(&program, Symbol::new("script"))).ok().unwrap();
+    let element = script.chunk.code.iter().position(|op| matches!(op, Op::ListElement(0))).unwrap();
+    script.chunk.code[element] = Op::ListElement(7);
+    let err = run_loaded(&dir, &script).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid bytecode: LIST_ELEMENT without a matching IS_LIST.\n[line 1] in script");
+    assert_eq!(err.exit_code(), 70);
+
+    let cases = [
+        (vec![Op::Nil, Op::ListRest(0), Op::ReturnPending], "LIST_REST without a matching IS_LIST"),
+        (vec![Op::Map(0), Op::Nil, Op::MapEntry, Op::ReturnPending], "MAP_ENTRY without a matching HAS_ENTRY"),
+        (vec![Op::PopHandler, Op::ReturnPending], "POP_HANDLER without a handler in this function"),
+        // The placeholder's slot is right, but nothing raised an error to rethrow.
+        (vec![Op::PushFinally(3), Op::Nil, Op::Rethrow, Op::ReturnPending], "RETHROW without a pending error"),
+        (
+            vec![Op::Nil, Op::PushCatch(5), Op::Pop, Op::Nil, Op::Throw, Op::ReturnPending],
+            "the stack shrank below an exception handler",
+        ),
+    ];
+    for (code, message) in cases {
+        let err = run_loaded(&dir, &function("script", 0, code)).unwrap_err();
+        assert_eq!(err.to_string(), format!("Invalid bytecode: {}.\n[line 1] in script", message));
+    }
+
+    // Only methods make classes, and a method only runs bound to an instance.
+    let mut method = function("m", 0, vec![Op::GetLocal(0), Op::Return]);
+    method.method = true;
+    let functions = vec![Rc::new(method), Rc::new(function("f", 0, vec![Op::Nil, Op::Return]))];
+    let cases = [
+        (vec![Op::Closure(1), Op::Class(0, 1), Op::ReturnPending], "CLASS takes method closures"),
+        (vec![Op::Closure(0), Op::Call(0), Op::ReturnPending], "a method was called without an instance"),
+    ];
+    for (code, message) in cases {
+        let mut script = function("script", 0, code);
+        script.chunk.names.push(Symbol::new("A"));
+        script.chunk.functions = functions.clone();
+        let err = run_loaded(&dir, &script).unwrap_err();
+        assert_eq!(err.to_string(), format!("Invalid bytecode: {}.\n[line 1] in script", message));
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::{run_err, run_ok};
+
+#[test]
+fn methods_see_the_instance_as_this() {
+    let out = run_ok(r#"
+        class Point {
+            init(x, y) {
+                this.x = x;
+                this.y = y;
+            }
+            sum() { return this.x + this.y; }
+            scaled(k) { return Point(this.x * k, this.y * k); }
+            adder() {
+                fun add(n) { return this.x + n; }
+                return add;
+            }
+        }
+        var p = Point(1, 2);
+        print p.sum();
+        print p.scaled(3).sum();
+        print p.adder()(10);
+        var sum = p.sum;
+        p.x = 5;
+        print sum();
+        print p.sum;
+    "#);
+    assert_eq!(out, "3\n9\n11\n7\n<fn sum>");
+}
+
+#[test]
+fn init_runs_on_new_instances_and_returns_them() {
+    let out = run_ok(r#"
+        class Counter {
+            init() {
+                this.n = 0;
+                return;
+                this.n = 100;
+            }
+            inc() {
+                this.n = this.n + 1;
+                return this;
+            }
+        }
+        var c = Counter();
+        print c.inc().inc().n;
+        print c.init() == c;
+        print c.n;
+    "#);
+    assert_eq!(out, "2\ntrue\n0");
+}
+
+#[test]
+fn fields_shadow_methods() {
+    let out = run_ok(r#"
+        class Greeter {
+            greet() { return "method"; }
+        }
+        var g = Greeter();
+        print g.greet();
+        g.greet = "field";
+        print g.greet;
+        print Greeter().greet();
+    "#);
+    assert_eq!(out, "method\nfield\nmethod");
+}
+
+#[test]
+fn calls_to_classes_pass_their_arguments_to_init() {
+    assert_eq!(run_err("class A { init(a, b) {} }\nA(1);", 70), "Expected 2 arguments but got 1.\n[line 2] in script");
+    assert_eq!(run_err("class A {}\nA(1);", 70), "Expected 0 arguments but got 1.\n[line 2] in script");
+}
+
+#[test]
+fn this_and_initializer_returns_are_checked_before_running() {
+    assert_eq!(run_err("print this;", 65), "[line 1] Can't use 'this' outside of a class.");
+    assert_eq!(run_err("fun f() { return this; }", 65), "[line 1] Can't use 'this' outside of a class.");
+    assert_eq!(run_err("class A { init() { return 1; } }", 65), "[line 1] Can't return a value from an initializer.");
+    assert_eq!(run_err("class A { m() { this = 1; } }", 65), "[line 1] Invalid assignment target.");
+    // Functions nested in an initializer return values as usual.
+    assert_eq!(run_ok("class A { init() { fun f() { return 1; } print f(); } }\nA();"), "1");
+}
@@ -0,0 +1,120 @@
+#![allow(dead_code)]
+
+use std::cell::Cell;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use codecrafters_interpreter::HostObject;
+
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+// Setting LOX_BACKEND=vm runs every test program on the bytecode VM instead of
+// the tree walker.
+fn backend_args() -> Vec<String> {
+    match std::env::var("LOX_BACKEND") {
+        Ok(backend) if !backend.is_empty() => vec![format!("--backend={}", backend)],
+        _ => Vec::new(),
+    }
+}
+
+// Writes `source` to a fresh temp file and runs the interpreter binary on it.
+pub fn run(command: &str, source: &str) -> Output {
+    run_with(command, source, &[], "")
+}
+
+// Like `run`, with extra command-line arguments and text for standard input.
+pub fn run_with(command: &str, source: &str, args: &[&str], stdin: &str) -> Output {
+    let n = NEXT_FILE.fetch_add(1, Ordering::SeqCst);
+    let path = std::env::temp_dir().join(format!("lox-test-{}-{}.lox", std::process::id(), n));
+    fs::write(&path, source).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .arg(command)
+        .arg(&path)
+        .args(args)
+        .args(if args.iter().any(|arg| arg.starts_with("--backend")) { Vec::new() } else { backend_args() })
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).trim_end().to_string()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).trim_end().to_string()
+}
+
+// Runs a program that must succeed and returns what it printed.
+pub fn run_ok(source: &str) -> String {
+    let output = run("run", source);
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output)
+}
+
+// Runs a program that must fail with `code` and returns its diagnostics.
+pub fn run_err(source: &str, code: i32) -> String {
+    let output = run("run", source);
+    assert_eq!(output.status.code(), Some(code), "stdout: {}\nstderr: {}", stdout(&output), stderr(&output));
+    stderr(&output)
+}
+
+// Writes each `(relative path, source)` into a fresh temp directory and runs the
+// first file, so programs can import the others.
+pub fn run_project(files: &[(&str, &str)]) -> Output {
+    let n = NEXT_FILE.fetch_add(1, Ordering::SeqCst);
+    let dir = std::env::temp_dir().join(format!("lox-test-{}-{}", std::process::id(), n));
+    for (name, source) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, source).unwrap();
+    }
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .arg("run")
+        .arg(dir.join(files[0].0))
+        .args(backend_args())
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    output
+}
+
+// Starts the interpreter with `args` and no file, types `input` into it and waits
+// for it to finish.
+pub fn run_repl(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-interpreter"))
+        .args(args)
+        .args(backend_args())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+// A host object that counts how many of its kind the interpreter has dropped.
+pub struct Tracer {
+    pub drops: Rc<Cell<usize>>,
+}
+
+impl HostObject for Tracer {
+    fn class_name(&self) -> &str {
+        "Tracer"
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        self.drops.set(self.drops.get() + 1);
+    }
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::{run_with, stderr, stdout};
+
+#[test]
+fn listings_show_offsets_lines_operands_and_jump_targets() {
+    let output = run_with("disassemble", "var greeting = \"hi\";\nif (greeting) print 1.5;\nprint greeting;\n", &[], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "\
+== script (0 parameters) ==
+0000    1 CONSTANT           0 \"hi\"
+0001    | DEFINE_GLOBAL      0 'greeting'
+0002    | NIL
+0003    | SET_RETURN
+0004    2 GET_GLOBAL         0 'greeting'
+0005    | POP_JUMP_IF_FALSE  -> 0011
+0006    | CONSTANT           1 1.5
+0007    | PRINT
+0008    | NIL
+0009    | SET_RETURN
+0010    | JUMP               -> 0013
+0011    | NIL
+0012    | SET_RETURN
+0013    3 GET_GLOBAL         0 'greeting'
+0014    | PRINT
+0015    | NIL
+0016    | SET_RETURN
+0017    | RETURN_PENDING");
+}
+
+#[test]
+fn nested_functions_are_listed_with_their_captures() {
+    let output = run_with("disassemble", r#"
+fun outer(n) {
+  var total = 0;
+  fun add(x) { total = total + x; return total; }
+  return add;
+}
+"#, &[], "");
+    let listing = stdout(&output);
+    assert!(listing.contains("CLOSURE            0 <fn outer>"), "{}", listing);
+    assert!(listing.contains("\n\n== outer (1 parameter) ==\n"), "{}", listing);
+    assert!(listing.contains("CLOSURE            0 <fn add>\n0004    |                    local 1 'total'"), "{}", listing);
+    assert!(listing.contains("\n\n== add (1 parameter) ==\n0000    4 GET_UPVALUE        0 'total'"), "{}", listing);
+    assert!(listing.find("== outer").unwrap() < listing.find("== add").unwrap());
+}
+
+#[test]
+fn json_listings_describe_every_instruction() {
+    let output = run_with("disassemble", "var s = \"a\\b\";\nfun f() {}\nmatch (1) { case 0..5 => print s; }\n", &["--format=json"], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    let json = stdout(&output);
+    assert!(!json.contains('\n'));
+    assert!(json.starts_with(r#"{"name":"script","arity":0,"method":false,"upvalues":[],"constants":["a\\b",1],"names":["s","f"],"code":["#), "{}", json);
+    assert!(json.contains(r#"{"offset":0,"line":1,"op":"CONSTANT","operands":[0],"constant":"a\\b"}"#), "{}", json);
+    assert!(json.contains(r#""op":"CLOSURE","operands":[0],"function":"f"}"#), "{}", json);
+    assert!(json.contains(r#""range":{"start":0,"end":5,"inclusive":false}"#), "{}", json);
+    assert!(json.contains(r#""op":"POP_JUMP_IF_FALSE","operands":["#) && json.contains(r#""target":"#), "{}", json);
+    assert!(json.ends_with(r#""functions":[{"name":"f","arity":0,"method":false,"upvalues":[],"constants":[],"names":[],"code":[{"offset":0,"line":2,"op":"NIL","operands":[]},{"offset":1,"line":2,"op":"RETURN","operands":[]}],"functions":[]}]}"#), "{}", json);
+}
+
+#[test]
+fn dump_bytecode_lists_to_stderr_and_still_runs() {
+    let output = run_with("run", "print 1 + 2;", &["--dump-bytecode"], "");
+    assert_eq!(stdout(&output), "3");
+    assert!(stderr(&output).starts_with("== script (0 parameters) ==\n0000    1 CONSTANT           0 1\n"), "{}", stderr(&output));
+
+    let output = run_with("evaluate", "1 + 2;", &["--dump-bytecode=json", "--backend=tree"], "");
+    assert_eq!(stdout(&output), "3");
+    assert!(stderr(&output).starts_with(r#"{"name":"script""#), "{}", stderr(&output));
+
+    let output = run_with("run", "print 1;", &["--dump-bytecode=xml"], "");
+    assert_eq!(output.status.code(), Some(64));
+}
+
+#[test]
+fn programs_that_do_not_compile_are_not_listed() {
+    let output = run_with("disassemble", "print (1;", &[], "");
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).starts_with("[line 1]"), "{}", stderr(&output));
+}
@@ -0,0 +1,60 @@
+use codecrafters_interpreter::{Error, Interpreter, LoxValue};
+
+#[test]
+fn globals_persist_between_calls() {
+    let mut interp = Interpreter::new();
+    interp.set_global("limit", 3.0).unwrap();
+    interp.set_global("names", vec!["a", "b"]).unwrap();
+    interp.eval_str("var total = 0; for (var i = 0; i < limit; i = i + 1) total = total + i;").unwrap();
+    assert_eq!(interp.get_global("total"), Some(LoxValue::Number(3.0)));
+    assert_eq!(interp.eval_str("names[1] + \"!\"").unwrap(), LoxValue::String("b!".to_string()));
+    assert_eq!(interp.get_global("missing"), None);
+}
+
+#[test]
+fn converts_values_both_ways() {
+    let mut interp = Interpreter::new();
+    let map = LoxValue::Map(vec![(LoxValue::from("k"), LoxValue::List(vec![LoxValue::Nil, true.into()]))]);
+    interp.set_global("m", map.clone()).unwrap();
+    assert_eq!(interp.eval_str("m").unwrap(), map);
+    assert_eq!(interp.eval_str("m").unwrap().to_string(), "{k: [nil, true]}");
+
+    let f = interp.eval_str("fun f() {} f").unwrap();
+    assert_eq!(f.to_string(), "<fn f>");
+    interp.set_global("g", f.clone()).unwrap();
+    assert_eq!(interp.eval_str("f == g").unwrap(), LoxValue::Bool(true));
+    assert_eq!(interp.get_global("g"), Some(f));
+
+    let bad = LoxValue::Map(vec![(LoxValue::List(vec![]), LoxValue::Nil)]);
+    assert!(matches!(interp.set_global("bad", bad), Err(Error::Conversion(_))));
+}
+
+#[test]
+fn reports_errors_with_exit_codes() {
+    let mut interp = Interpreter::new();
+    let err = interp.eval_str("var = 1;").unwrap_err();
+    assert!(matches!(err, Error::Compile(_)));
+    assert_eq!(err.exit_code(), 65);
+
+    let err = interp.eval_str("-\"a\";").unwrap_err();
+    assert_eq!(err.to_string(), "Operand must be a number.\n[line 1] in script");
+    assert_eq!(err.exit_code(), 70);
+
+    let err = interp.run_file("/nonexistent/script.lox").unwrap_err();
+    assert!(matches!(err, Error::Io { .. }));
+    // The interpreter stays usable after errors.
+    assert_eq!(interp.eval_str("1 + 2").unwrap(), LoxValue::Number(3.0));
+}
+
+#[test]
+fn run_file_resolves_imports_relative_to_the_script() {
+    let dir = std::env::temp_dir().join(format!("lox-embed-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("main.lox"), "import { greet } from \"lib/greet.lox\"; var message = greet(\"host\");").unwrap();
+    std::fs::write(dir.join("lib/greet.lox"), "fun greet(name) { return \"hello \" + name; }").unwrap();
+    let mut interp = Interpreter::new();
+    let result = interp.run_file(dir.join("main.lox"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    result.unwrap();
+    assert_eq!(interp.get_global("message"), Some(LoxValue::from("hello host")));
+}
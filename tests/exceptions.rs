@@ -0,0 +1,122 @@
+mod common;
+
+use common::{run_err, run_ok};
+
+#[test]
+fn throw_and_catch_values() {
+    let out = run_ok(r#"
+        try {
+            throw "boom";
+            print "unreachable";
+        } catch (e) {
+            print e;
+        }
+        fun fail(n) {
+            if (n > 0) throw [n];
+            return n;
+        }
+        try { print fail(0); fail(2); } catch (e) { print e[0]; }
+    "#);
+    assert_eq!(out, "boom\n0\n2");
+}
+
+#[test]
+fn builtin_errors_are_catchable() {
+    let out = run_ok(r#"
+        fun inner() { return -"a"; }
+        fun outer() { inner(); }
+        try {
+            outer();
+        } catch (e) {
+            print e.kind;
+            print e.message;
+            print e.line;
+            print e.stack[0];
+            print e.stack[1];
+            print e.stack[2];
+        }
+        try { print missing; } catch (e) { print e.kind + ": " + e.message; }
+        try { [1][5]; } catch (e) { print e.kind; }
+    "#);
+    assert_eq!(
+        out,
+        "TypeError\nOperand must be a number.\n2\n[line 2] in inner()\n[line 3] in outer()\n[line 5] in script\n\
+         NameError: Undefined variable 'missing'.\nIndexError"
+    );
+}
+
+#[test]
+fn finally_runs_on_every_exit() {
+    let out = run_ok(r#"
+        fun f() {
+            try { return "try"; } finally { print "finally 1"; }
+        }
+        print f();
+        for (var i = 0; i < 3; i = i + 1) {
+            try {
+                if (i == 0) continue;
+                if (i == 2) break;
+                print i;
+            } finally {
+                print "finally " + "loop";
+            }
+        }
+        try {
+            try { throw "inner"; } finally { print "finally 2"; }
+        } catch (e) {
+            print "caught " + e;
+        }
+    "#);
+    assert_eq!(
+        out,
+        "finally 1\ntry\nfinally loop\n1\nfinally loop\nfinally loop\nfinally 2\ncaught inner"
+    );
+}
+
+#[test]
+fn abrupt_finally_overrides_pending_outcome() {
+    let out = run_ok(r#"
+        fun f() {
+            try { throw "lost"; } finally { return "finally"; }
+        }
+        print f();
+        fun g() {
+            try { return 1; } finally { return 2; }
+        }
+        print g();
+        try {
+            try { throw "first"; } catch (e) { throw "second"; } finally { print "cleanup"; }
+        } catch (e) {
+            print e;
+        }
+    "#);
+    assert_eq!(out, "finally\n2\ncleanup\nsecond");
+}
+
+#[test]
+fn rethrow_keeps_error_object() {
+    let out = run_ok(r#"
+        var first;
+        try {
+            try { nil + 1; } catch (e) { first = e; throw e; }
+        } catch (e) {
+            print e == first;
+            print e.kind;
+        }
+    "#);
+    assert_eq!(out, "true\nTypeError");
+}
+
+#[test]
+fn uncaught_errors_exit_70() {
+    let err = run_err("print 1;\nthrow \"oops\";", 70);
+    assert_eq!(err, "Uncaught exception: oops\n[line 2] in script");
+    let err = run_err("try { nil + 1; } catch (e) { throw e; }", 70);
+    assert_eq!(err, "Operands must be two numbers or two strings.\n[line 1] in script");
+}
+
+#[test]
+fn return_outside_function_is_a_resolver_error() {
+    let err = run_err("return 1;", 65);
+    assert!(err.contains("Can't return from top-level code."), "{err}");
+}
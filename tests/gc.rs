@@ -0,0 +1,112 @@
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+use codecrafters_interpreter::{GcConfig, Interpreter, LoxValue};
+use common::{run_with, stderr, stdout, Tracer};
+
+// Each call leaves behind an instance that holds a closure capturing the instance.
+const CYCLES: &str = r#"
+    class Node {}
+    fun make(i) {
+        var n = Node();
+        fun get() { return n; }
+        n.get = get;
+        n.tracer = Tracer();
+        return i;
+    }
+"#;
+
+#[test]
+fn collector_frees_unreachable_cycles() {
+    let drops = Rc::new(Cell::new(0));
+    let mut interp = Interpreter::new();
+    // A threshold this high keeps the collector from running on its own.
+    interp.set_gc_config(GcConfig { initial_threshold: 1_000_000, ..GcConfig::default() });
+    let counter = Rc::clone(&drops);
+    interp.register_class("Tracer", 0, move |_| Ok(Tracer { drops: Rc::clone(&counter) }));
+    interp.eval_str(CYCLES).unwrap();
+    interp.eval_str("var kept = Node(); kept.self = kept; for (var i = 0; i < 100; i = i + 1) make(i);").unwrap();
+    assert_eq!(drops.get(), 0);
+
+    assert!(interp.collect_garbage() >= 300);
+    assert_eq!(drops.get(), 100);
+    let stats = interp.gc_stats();
+    assert_eq!(stats.collections, 1);
+    assert!(stats.freed_by_collector >= 300, "{:?}", stats);
+
+    // Reachable cycles survive.
+    assert_eq!(interp.eval_str("kept.self == kept").unwrap(), LoxValue::Bool(true));
+}
+
+#[test]
+fn collector_runs_as_the_heap_grows() {
+    let drops = Rc::new(Cell::new(0));
+    let mut interp = Interpreter::new();
+    interp.set_gc_config(GcConfig { initial_threshold: 64, ..GcConfig::default() });
+    let counter = Rc::clone(&drops);
+    interp.register_class("Tracer", 0, move |_| Ok(Tracer { drops: Rc::clone(&counter) }));
+    interp.eval_str(CYCLES).unwrap();
+    let result = interp.eval_str("var sum = 0; for (var i = 0; i < 2000; i = i + 1) sum = sum + make(i); sum").unwrap();
+    assert_eq!(result, LoxValue::Number(1999000.0));
+
+    let stats = interp.gc_stats();
+    assert!(stats.collections > 1, "{:?}", stats);
+    assert!(stats.live < 200, "{:?}", stats);
+    assert!(drops.get() > 1900);
+}
+
+#[test]
+fn stress_mode_does_not_change_results() {
+    let source = r#"
+        fun counter() {
+            var n = 0;
+            fun inc() { n = n + 1; return n; }
+            return inc;
+        }
+        var c = counter();
+        c(); c();
+        print c();
+
+        var nested = [];
+        for (var i = 0; i < 50; i = i + 1) { nested = [nested, i]; }
+        print nested[1];
+
+        var m = {"a": [1, 2], "b": {}};
+        m["b"]["back"] = m;
+        fun back() { return m; }
+        m["a"][0] = back;
+        print m["b"]["back"]["a"][1];
+
+        class Box {}
+        var b = Box();
+        b.self = b;
+        b.items = [b, 1, "two"];
+        print b.items[2];
+    "#;
+    let normal = run_with("run", source, &[], "");
+    let stressed = run_with("run", source, &["--gc-stress"], "");
+    assert!(normal.status.success(), "{}", stderr(&normal));
+    assert!(stressed.status.success(), "{}", stderr(&stressed));
+    assert_eq!(stdout(&stressed), stdout(&normal));
+    assert_eq!(stdout(&normal), "3\n49\n2\ntwo");
+}
+
+#[test]
+fn gc_stats_flag_reports_collections() {
+    let source = "class A {} for (var i = 0; i < 500; i = i + 1) { var a = A(); a.me = a; } print \"ok\";";
+    let output = run_with("run", source, &["--gc-stats", "--gc-threshold=100"], "");
+    assert_eq!(stdout(&output), "ok");
+    let report = stderr(&output);
+    assert!(report.starts_with("GC statistics:"), "{}", report);
+    assert!(report.contains("freed by collector:"), "{}", report);
+    assert!(!report.contains("collections:        0\n"), "{}", report);
+
+    // Statistics are printed even when the script fails.
+    let output = run_with("run", "print 1 + nil;", &["--gc-stats"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).starts_with("GC statistics:"), "{}", stderr(&output));
+
+    let output = run_with("run", "print 1;", &["--gc-growth=0.5"], "");
+    assert_eq!(output.status.code(), Some(64));
+}
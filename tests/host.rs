@@ -0,0 +1,146 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use codecrafters_interpreter::{Args, ErrorKind, HostObject, Interpreter, LoxValue, NativeError};
+
+struct Counter {
+    count: f64,
+    label: String,
+}
+
+impl HostObject for Counter {
+    fn class_name(&self) -> &str {
+        "Counter"
+    }
+
+    fn method_arity(&self, name: &str) -> Option<usize> {
+        match name {
+            "increment" => Some(1),
+            "reset" => Some(0),
+            _ => None,
+        }
+    }
+
+    fn call_method(&mut self, name: &str, args: &Args) -> Result<LoxValue, NativeError> {
+        match name {
+            "increment" => self.count += args.get::<f64>(0)?,
+            _ => self.count = 0.0,
+        }
+        Ok(self.count.into())
+    }
+
+    fn get_property(&self, name: &str) -> Option<LoxValue> {
+        match name {
+            "count" => Some(self.count.into()),
+            "label" => Some(self.label.as_str().into()),
+            _ => None,
+        }
+    }
+
+    fn set_property(&mut self, name: &str, value: LoxValue) -> Result<(), NativeError> {
+        match (name, value) {
+            ("label", LoxValue::String(label)) => {
+                self.label = label;
+                Ok(())
+            }
+            ("label", value) => Err(NativeError::new(ErrorKind::TypeError, format!("label must be a string, not {}.", value.type_name()))),
+            _ => Err(NativeError::new(ErrorKind::PropertyError, format!("Counter has no settable property '{}'.", name))),
+        }
+    }
+}
+
+struct Logger {
+    lines: Rc<RefCell<Vec<String>>>,
+}
+
+impl HostObject for Logger {
+    fn class_name(&self) -> &str {
+        "Logger"
+    }
+
+    fn method_arity(&self, name: &str) -> Option<usize> {
+        (name == "log").then_some(1)
+    }
+
+    fn call_method(&mut self, _name: &str, args: &Args) -> Result<LoxValue, NativeError> {
+        self.lines.borrow_mut().push(args.arg(0).to_string());
+        Ok(LoxValue::Nil)
+    }
+
+    fn inspect(&self) -> String {
+        format!("<logger with {} lines>", self.lines.borrow().len())
+    }
+}
+
+fn interpreter() -> Interpreter {
+    let mut interp = Interpreter::new();
+    interp.register_class("Counter", 1, |args| Ok(Counter { count: 0.0, label: args.get(0)? }));
+    interp
+}
+
+#[test]
+fn methods_and_properties() {
+    let mut interp = interpreter();
+    let result = interp.eval_str(r#"
+        var c = Counter("clicks");
+        c.increment(2);
+        var bump = c.increment;
+        bump(3);
+        c.label = c.label + "!";
+        [c.count, c.label, c.increment(1), c.reset(), c.count]
+    "#).unwrap();
+    assert_eq!(result.to_string(), "[5, clicks!, 6, 0, 0]");
+}
+
+#[test]
+fn printing_equality_and_type() {
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let mut interp = interpreter();
+    interp.set_global("logger", LoxValue::host(Logger { lines: Rc::clone(&lines) })).unwrap();
+    interp.eval_str(r#"
+        var a = Counter("a");
+        var b = Counter("a");
+        var alias = a;
+        logger.log(a);
+        logger.log(a == alias);
+        logger.log(a == b);
+        logger.log(logger);
+    "#).unwrap();
+    assert_eq!(*lines.borrow(), vec!["Counter instance", "true", "false", "<host object>"]);
+    assert_eq!(interp.eval_str("logger").unwrap().to_string(), "<logger with 4 lines>");
+}
+
+#[test]
+fn host_state_is_shared_with_rust() {
+    let mut interp = interpreter();
+    interp.eval_str("var c = Counter(\"x\"); c.increment(41);").unwrap();
+    let c = interp.get_global("c").unwrap();
+    let LoxValue::Object(handle) = c else { panic!("expected an object") };
+    assert_eq!(handle.with_host(|counter: &mut Counter| counter.count), Some(41.0));
+    handle.with_host(|counter: &mut Counter| counter.count += 1.0);
+    assert_eq!(interp.eval_str("c.count").unwrap(), LoxValue::Number(42.0));
+    assert_eq!(handle.with_host(|_: &mut Logger| ()), None);
+}
+
+#[test]
+fn errors_are_catchable_runtime_errors() {
+    let mut interp = interpreter();
+    let result = interp.eval_str(r#"
+        var c = Counter("x");
+        var errors = [nil, nil, nil, nil, nil];
+        try { c.missing; } catch (e) { errors[0] = e.kind + ": " + e.message; }
+        try { c.count = 3; } catch (e) { errors[1] = e.message; }
+        try { c.label = 3; } catch (e) { errors[2] = e.kind + ": " + e.message; }
+        try { c.increment("a"); } catch (e) { errors[3] = e.message; }
+        try { c.reset(1); } catch (e) { errors[4] = e.message; }
+        errors
+    "#);
+    let LoxValue::List(errors) = result.unwrap() else { panic!("expected a list") };
+    let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(errors, vec![
+        "PropertyError: Undefined property 'missing'.",
+        "Counter has no settable property 'count'.",
+        "TypeError: label must be a string, not number.",
+        "Argument 1 to 'increment' must be a number but got string.",
+        "Expected 0 arguments but got 1.",
+    ]);
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use codecrafters_interpreter::common::symbol::{Interner, Known, Symbol};
+use codecrafters_interpreter::lexer::lexer::Lexer;
+use codecrafters_interpreter::{Interpreter, LoxValue};
+use common::run_ok;
+
+#[test]
+fn symbols_are_shared_per_string() {
+    let interner = Interner::default();
+    let known = interner.len();
+    let a = interner.intern("counter");
+    let b = interner.intern(&String::from("counter"));
+    assert_eq!(a, b);
+    assert_eq!(a.as_str().as_ptr(), b.as_str().as_ptr());
+    assert_ne!(a, interner.intern("Counter"));
+    assert_eq!(a, "counter");
+    assert_eq!(a.to_string(), "counter");
+    assert_eq!(interner.len(), known + 2);
+
+    // Equality is identity, so symbols from elsewhere don't equal interned ones.
+    assert_ne!(Symbol::new("counter"), a);
+    assert_ne!(Interner::default().intern("counter"), a);
+    assert_eq!(interner.lookup("counter"), Some(a));
+    assert_eq!(interner.lookup("missing"), None);
+    assert_eq!(interner.len(), known + 2);
+
+    // Names the interpreter looks up are the same in every interner.
+    assert_eq!(interner.intern("this"), Symbol::known(Known::This));
+    assert_eq!(Interner::default().intern("init"), Symbol::known(Known::Init));
+    assert_eq!(interner.len(), known + 2);
+}
+
+#[test]
+fn only_identifiers_and_keywords_are_interned() {
+    let interner = Interner::default();
+    let known = interner.len();
+    let mut lexer = Lexer::with_interner(r#"var count = 0; count = count + 1; print "a" + "b"; fun f(n) { return n; }"#.to_string(), interner.clone());
+    lexer.tokenize();
+    assert!(lexer.errors.is_empty());
+    // count, f and n, and var, print, fun and return.
+    assert_eq!(interner.len(), known + 7);
+
+    let mut lexer = Lexer::with_interner("var count = 2; print count;".to_string(), interner.clone());
+    lexer.tokenize();
+    assert_eq!(interner.len(), known + 7);
+}
+
+#[test]
+fn strings_compare_by_content_whatever_their_origin() {
+    let output = run_ok(r#"
+        var a = "lox";
+        var b = "lox";
+        var c = "l" + "ox";
+        print a == b;
+        print a == c;
+        print c == "lox";
+        print "lox" != "Lox";
+        var m = {};
+        m[c] = 1;
+        print m["lox"];
+    "#);
+    assert_eq!(output, "true\ntrue\ntrue\ntrue\n1");
+}
+
+#[test]
+fn names_resolve_through_the_api() {
+    let mut interp = Interpreter::new();
+    interp.set_global("answer", 42.0).unwrap();
+    interp.eval_str("class P {} var p = P(); p.field = answer; var copy = p.field;").unwrap();
+    assert_eq!(interp.get_global("copy"), Some(LoxValue::Number(42.0)));
+    assert_eq!(interp.get_global("never defined anywhere"), None);
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use codecrafters_interpreter::{Backend, Error, Interpreter, LoxValue, Permissions};
+use common::{run_ok, run_with, stderr, stdout};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lox-io-{}-{}", name, std::process::id()));
+    fs::create_dir_all(dir.join("data")).unwrap();
+    dir
+}
+
+#[test]
+fn everything_is_denied_by_default() {
+    let dir = temp_dir("denied");
+    fs::write(dir.join("data/a.txt"), "secret").unwrap();
+    let path = dir.join("data/a.txt").display().to_string();
+    let source = format!(r#"
+        fun attempt(f) {{
+            try {{ f(); }} catch (e) {{ print e.kind + ": " + e.message; }}
+        }}
+        fun read() {{ readFile("{path}"); }}
+        fun write() {{ writeFile("{path}", "x"); }}
+        fun list() {{ listDir("{dir}"); }}
+        fun home() {{ env("HOME"); }}
+        attempt(read);
+        attempt(write);
+        attempt(list);
+        attempt(home);
+    "#, path = path, dir = dir.display());
+    let out = run_ok(&source);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(out, format!(
+        "PermissionError: readFile: read access to '{path}' is not allowed.\n\
+         PermissionError: writeFile: write access to '{path}' is not allowed.\n\
+         PermissionError: listDir: read access to '{dir}' is not allowed.\n\
+         PermissionError: env: reading environment variables is not allowed.",
+        path = path, dir = dir.display()
+    ));
+}
+
+#[test]
+fn cli_capabilities_grant_access_to_directories() {
+    let dir = temp_dir("cli");
+    fs::write(dir.join("data/in.txt"), "hello").unwrap();
+    let data = dir.join("data").display().to_string();
+    let source = format!(r#"
+        var text = readFile("{data}/in.txt");
+        writeFile("{data}/out.txt", upper(text));
+        print listDir("{data}");
+        print env("LOX_IO_TEST");
+        try {{ readFile("{data}/../escape.txt"); }} catch (e) {{ print e.kind; }}
+        try {{ writeFile("{data}/missing/x.txt", ""); }} catch (e) {{ print e.kind; }}
+        try {{ readFile("{data}/nope.txt"); }} catch (e) {{ print e.kind; }}
+    "#, data = data);
+    let read = format!("--allow-read={}", data);
+    let write = format!("--allow-write={}", data);
+    std::env::set_var("LOX_IO_TEST", "set");
+    let output = run_with("run", &source, &[&read, &write, "--allow-env"], "");
+    let written = fs::read_to_string(dir.join("data/out.txt"));
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "[in.txt, out.txt]\nset\nPermissionError\nPermissionError\nIOError");
+    assert_eq!(written.unwrap(), "HELLO");
+}
+
+#[test]
+fn input_reads_lines_until_eof() {
+    let source = r#"
+        var line = input();
+        while (line != nil) {
+            print "[" + line + "]";
+            line = input();
+        }
+    "#;
+    let output = run_with("run", source, &[], "one\r\ntwo\n\nlast");
+    assert_eq!(stdout(&output), "[one]\n[two]\n[]\n[last]");
+}
+
+#[test]
+fn exit_stops_the_script_with_a_status() {
+    let source = r#"
+        print "before";
+        try { exit(3); } catch (e) { print "caught"; } finally { print "finally"; }
+        print "after";
+    "#;
+    let output = run_with("run", source, &[], "");
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(stdout(&output), "before");
+    assert_eq!(stderr(&output), "");
+
+    let output = run_with("run", "exit();", &[], "");
+    assert_eq!(output.status.code(), Some(0));
+    let output = run_with("run", "exit(1.5);", &[], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "Argument 1 to 'exit' must be an integer but got 1.5.\n[line 1] in exit()\n[line 1] in script");
+    let output = run_with("run", "exit(4294967297);", &[], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "exit: status 4294967297 is not between 0 and 255.\n[line 1] in exit()\n[line 1] in script");
+    let output = run_with("run", "exit(-1);", &[], "");
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[test]
+fn invalid_capability_flags_are_usage_errors() {
+    let output = run_with("run", "", &["--allow-read"], "");
+    assert_eq!(output.status.code(), Some(64));
+    assert!(stderr(&output).starts_with("--allow-read needs a directory, e.g. --allow-read=."));
+    let output = run_with("run", "", &["--allow-everything"], "");
+    assert_eq!(output.status.code(), Some(64));
+}
+
+#[test]
+fn embedding_permissions() {
+    let dir = temp_dir("api");
+    fs::write(dir.join("data/config.txt"), "debug=true").unwrap();
+    let path = dir.join("data/config.txt").display().to_string();
+    let source = format!("readFile(\"{}\")", path);
+
+    let mut interp = Interpreter::new();
+    let denied = interp.eval_str(&source).unwrap_err();
+    interp.set_permissions(Permissions { read: vec![dir.join("data")], ..Permissions::default() });
+    let allowed = interp.eval_str(&source);
+    let exited = interp.eval_str("exit(4);");
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(denied.to_string().starts_with("readFile: read access to"), "{denied}");
+    assert_eq!(allowed.unwrap(), LoxValue::from("debug=true"));
+    let exited = exited.unwrap_err();
+    assert!(matches!(exited, Error::Exit(4)));
+    assert_eq!(exited.exit_code(), 4);
+    assert!(interp.eval_str("input()").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn dangling_symlinks_do_not_lead_out_of_allowed_directories() {
+    let dir = temp_dir("symlink");
+    let data = dir.join("data");
+    std::os::unix::fs::symlink(dir.join("outside.txt"), data.join("link.txt")).unwrap();
+    let source = format!(r#"try {{ writeFile("{}/link.txt", "x"); }} catch (e) {{ print e.kind; }}"#, data.display());
+    let write = format!("--allow-write={}", data.display());
+    let output = run_with("run", &source, &[&write], "");
+    let escaped = dir.join("outside.txt").exists();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(stdout(&output), "PermissionError", "{}", stderr(&output));
+    assert!(!escaped);
+}
+
+#[test]
+fn imports_from_outside_the_script_directory_need_read_access() {
+    let dir = temp_dir("import");
+    fs::create_dir_all(dir.join("project")).unwrap();
+    fs::write(dir.join("project/main.lox"), "import \"../data/lib.lox\" as lib; var out = lib.answer;").unwrap();
+    fs::write(dir.join("data/lib.lox"), "var answer = 42;").unwrap();
+    let lib = dir.join("data/lib.lox").display().to_string();
+
+    let mut results = Vec::new();
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+        let mut interp = Interpreter::new();
+        interp.set_backend(backend);
+        let denied = interp.run_file(dir.join("project/main.lox")).unwrap_err().to_string();
+        let bare = interp.eval_str(&format!("import \"{}\" as lib;", lib)).unwrap_err().to_string();
+        interp.set_permissions(Permissions { read: vec![dir.join("data")], ..Permissions::default() });
+        interp.run_file(dir.join("project/main.lox")).unwrap();
+        results.push((denied, bare, interp.get_global("out")));
+    }
+    fs::remove_dir_all(&dir).unwrap();
+    for (denied, bare, out) in results {
+        assert_eq!(denied, "Can't import '../data/lib.lox': read access to it is not allowed.\n[line 1] in script");
+        assert_eq!(bare, format!("Can't import '{}': read access to it is not allowed.\n[line 1] in script", lib));
+        assert_eq!(out, Some(LoxValue::Number(42.0)));
+    }
+}
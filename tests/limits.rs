@@ -0,0 +1,119 @@
+mod common;
+
+use std::thread;
+use std::time::{Duration, Instant};
+use codecrafters_interpreter::{Error, ErrorKind, Interpreter, Limits, LoxValue};
+use common::{run_ok, run_with, stderr, stdout};
+
+fn runtime_kind(err: Error) -> ErrorKind {
+    match err {
+        Error::Runtime(err) => err.kind,
+        err => panic!("expected a runtime error, got {:?}", err),
+    }
+}
+
+#[test]
+fn deep_recursion_is_a_catchable_stack_overflow() {
+    let output = run_ok(r#"
+        fun depth(n) { if (n == 0) return 0; return 1 + depth(n - 1); }
+        print depth(900);
+        try { depth(100000); } catch (e) { print e.kind + ": " + e.message; }
+        print depth(10);
+    "#);
+    assert_eq!(output, "900\nStackOverflow: Stack overflow: maximum call depth of 1000 exceeded.\n10");
+
+    let output = run_with("run", "fun f() { f(); }\nf();", &["--max-call-depth=50"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).starts_with("Stack overflow: maximum call depth of 50 exceeded.\n[line 1]"), "{}", stderr(&output));
+}
+
+#[test]
+fn step_limit_stops_infinite_loops_and_cannot_be_caught() {
+    let source = r#"
+        try {
+            while (true) {}
+        } catch (e) {
+            print "caught";
+        } finally {
+            print "finally";
+        }
+    "#;
+    let output = run_with("run", source, &["--max-steps=1000"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stdout(&output), "");
+    assert!(stderr(&output).starts_with("Step limit of 1000 exceeded."), "{}", stderr(&output));
+
+    let output = run_with("run", "print 1 + 2;", &["--max-steps=1000"], "");
+    assert_eq!(stdout(&output), "3");
+}
+
+#[test]
+fn timeout_ends_long_runs() {
+    let start = Instant::now();
+    let output = run_with("run", "while (true) {}", &["--timeout=0.2"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "Execution timed out after 0.2s.\n[line 1] in script");
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    let output = run_with("run", "print 1;", &["--timeout=abc"], "");
+    assert_eq!(output.status.code(), Some(64));
+}
+
+#[test]
+fn heap_limits_count_live_objects_and_bytes() {
+    // Garbage from earlier iterations doesn't count against the limit.
+    let output = run_with("run", "for (var i = 0; i < 5000; i = i + 1) { var x = [i]; } print \"done\";", &["--max-heap-objects=100"], "");
+    assert_eq!(stdout(&output), "done", "{}", stderr(&output));
+
+    let output = run_with("run", "var l = nil; while (true) { l = [l]; }", &["--max-heap-objects=100"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "Heap limit of 100 objects exceeded.\n[line 1] in script");
+
+    let output = run_with("run", "var s = \"ab\"; while (true) { s = s + s; }", &["--max-heap-bytes=10000"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "Heap limit of 10000 bytes exceeded.\n[line 1] in script");
+}
+
+#[test]
+fn byte_limits_measure_a_container_that_keeps_growing() {
+    // One map, allocated once, grows far past the limit.
+    let source = "var m = {};\nfor (var i = 0; i < 100000; i = i + 1) m[str(i)] = \"yyyyyyyyyyyyyyyyyyyyyyyy\" + str(i);\nprint len(m);";
+    let output = run_with("run", source, &["--max-heap-bytes=100000"], "");
+    assert_eq!(output.status.code(), Some(70), "{}", stdout(&output));
+    assert_eq!(stderr(&output), "Heap limit of 100000 bytes exceeded.\n[line 2] in script");
+
+    let source = "class Bag {}\nvar b = Bag();\nvar l = [nil];\nfor (var i = 0; i < 100000; i = i + 1) { b.s = str(i) + \"yyyyyyyy\"; l[0] = b.s; }\nprint \"done\";";
+    let output = run_with("run", source, &["--max-heap-bytes=100000"], "");
+    assert_eq!(stdout(&output), "done", "{}", stderr(&output));
+}
+
+#[test]
+fn interrupt_handle_stops_a_running_program() {
+    let mut interp = Interpreter::new();
+    let handle = interp.interrupt_handle();
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        handle.interrupt();
+    });
+    let err = interp.eval_str("var n = 0; while (true) { n = n + 1; }").unwrap_err();
+    interrupter.join().unwrap();
+    assert_eq!(err.exit_code(), 70);
+    assert_eq!(runtime_kind(err), ErrorKind::Interrupted);
+
+    // The interrupt only applies to the run it arrived during.
+    assert_eq!(interp.eval_str("1 + 1").unwrap(), LoxValue::Number(2.0));
+}
+
+#[test]
+fn limits_apply_through_the_api() {
+    let mut interp = Interpreter::new();
+    interp.set_limits(Limits { max_steps: Some(500), ..Limits::default() });
+    let err = interp.eval_str("for (var i = 0; i < 1000; i = i + 1) {}").unwrap_err();
+    assert_eq!(runtime_kind(err), ErrorKind::StepLimitExceeded);
+    // Each run gets a fresh budget.
+    assert_eq!(interp.eval_str("2 * 3").unwrap(), LoxValue::Number(6.0));
+
+    interp.set_limits(Limits { timeout: Some(Duration::from_millis(50)), ..Limits::default() });
+    let err = interp.eval_str("while (true) {}").unwrap_err();
+    assert_eq!(runtime_kind(err), ErrorKind::Timeout);
+}
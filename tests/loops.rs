@@ -0,0 +1,90 @@
+mod common;
+
+use common::{run_err, run_ok};
+
+#[test]
+fn break_exits_innermost_loop() {
+    let out = run_ok(r#"
+        for (var i = 0; i < 3; i = i + 1) {
+            var j = 0;
+            while (true) {
+                if (j == 2) break;
+                print i * 10 + j;
+                j = j + 1;
+            }
+        }
+    "#);
+    assert_eq!(out, "0\n1\n10\n11\n20\n21");
+}
+
+#[test]
+fn continue_runs_for_increment() {
+    let out = run_ok(r#"
+        for (var i = 0; i < 6; i = i + 1) {
+            if (i % 2 == 0) continue;
+            print i;
+        }
+    "#);
+    assert_eq!(out, "1\n3\n5");
+}
+
+#[test]
+fn continue_in_while_rechecks_condition() {
+    let out = run_ok(r#"
+        var i = 0;
+        while (i < 5) {
+            i = i + 1;
+            if (i == 3) continue;
+            print i;
+        }
+    "#);
+    assert_eq!(out, "1\n2\n4\n5");
+}
+
+#[test]
+fn labeled_break_and_continue() {
+    let out = run_ok(r#"
+        outer: for (var i = 0; i < 4; i = i + 1) {
+            inner: for (var j = 0; j < 4; j = j + 1) {
+                if (j == 1) continue inner;
+                if (i == 1) continue outer;
+                if (i == 3) break outer;
+                if (j == 3) break inner;
+                print i * 10 + j;
+            }
+        }
+        print "done";
+    "#);
+    assert_eq!(out, "0\n2\n20\n22\ndone");
+}
+
+#[test]
+fn labeled_while_loops() {
+    let out = run_ok(r#"
+        var i = 0;
+        outer: while (true) {
+            i = i + 1;
+            while (true) {
+                if (i < 3) continue outer;
+                break outer;
+            }
+        }
+        print i;
+    "#);
+    assert_eq!(out, "3");
+}
+
+#[test]
+fn loop_control_outside_loop_is_a_resolver_error() {
+    assert_eq!(run_err("break;", 65), "[line 1] Can't use 'break' outside of a loop.");
+    assert_eq!(run_err("{\n  continue;\n}", 65), "[line 2] Can't use 'continue' outside of a loop.");
+    assert_eq!(run_err("if (true) break;", 65), "[line 1] Can't use 'break' outside of a loop.");
+}
+
+#[test]
+fn unknown_or_duplicate_labels_are_resolver_errors() {
+    assert_eq!(run_err("while (true) break missing;", 65), "[line 1] No enclosing loop labeled 'missing'.");
+    assert_eq!(run_err("a: while (true) { }\nwhile (true) continue a;", 65), "[line 2] No enclosing loop labeled 'a'.");
+    assert_eq!(run_err("a: while (true) a: while (true) break a;", 65), "[line 1] Label 'a' is already used by an enclosing loop.");
+    assert_eq!(run_err("a: print 1;", 65), "[line 1] Expect 'while' or 'for' after label 'a'.");
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use common::{run_err, run_ok};
+
+#[test]
+fn literal_range_and_wildcard_patterns() {
+    let out = run_ok(r#"
+        for (var i = -2; i < 13; i = i + 4) {
+            match (i) {
+                case 2 => print "two";
+                case -5..0 => print "negative";
+                case 0..=6 => print "small";
+                case _ => print "other";
+            }
+        }
+        match ("b") { case "a" => print 1; case "b" => print 2; }
+        match (nil) { case false => print "false"; case nil => print "nil"; }
+    "#);
+    assert_eq!(out, "negative\ntwo\nsmall\nother\n2\nnil");
+}
+
+#[test]
+fn binding_patterns_and_guards() {
+    let out = run_ok(r#"
+        var values = [5, -5, 0];
+        for (var i = 0; i < 3; i = i + 1) {
+            match (values[i]) {
+                case x if x > 0 => print "positive " + str(x);
+                case x if x < 0 => print -x;
+                case x => print x;
+            }
+        }
+    "#);
+    assert_eq!(out, "positive 5\n5\n0");
+}
+
+#[test]
+fn list_patterns() {
+    let out = run_ok(r#"
+        var lists = [[], [1], [1, 2], [1, 2, 3, 4]];
+        for (var i = 0; i < 4; i = i + 1) {
+            match (lists[i]) {
+                case [] => print "empty";
+                case [x] => print x;
+                case [1, y] => print y * 10;
+                case [head, _, ..tail] => { print head; print tail; }
+            }
+        }
+        match ([1, 2]) { case [_, ..] => print "prefix"; }
+    "#);
+    assert_eq!(out, "empty\n1\n20\n1\n[3, 4]\nprefix");
+}
+
+#[test]
+fn map_patterns() {
+    let out = run_ok(r#"
+        var request = {"method": "GET", "path": "/", "headers": {"host": "lox"}};
+        match (request) {
+            case {"method": "POST"} => print "post";
+            case {"method": "GET", "headers": {"host": h}} => print h;
+        }
+        match ({}) { case {"missing": _} => print "no"; case {} => print "any map"; }
+    "#);
+    assert_eq!(out, "lox\nany map");
+}
+
+#[test]
+fn instance_patterns() {
+    let out = run_ok(r#"
+        class Point {}
+        class Other {}
+        var p = Point();
+        p.x = 0;
+        p.y = 7;
+        var o = Other();
+        o.x = 0;
+        var things = [o, p];
+        for (var i = 0; i < 2; i = i + 1) {
+            match (things[i]) {
+                case Point { x: 1 } => print "x is one";
+                case Point { x: 0, y } => print y;
+                case Point { z } => print z;
+                case _ => print "not a point";
+            }
+        }
+    "#);
+    assert_eq!(out, "not a point\n7");
+}
+
+#[test]
+fn arms_share_loop_control() {
+    let out = run_ok(r#"
+        for (var i = 0; i < 10; i = i + 1) {
+            match (i) {
+                case 1 => continue;
+                case 3 => break;
+                case _ => print i;
+            }
+        }
+    "#);
+    assert_eq!(out, "0\n2");
+}
+
+#[test]
+fn match_expressions_evaluate_to_the_matching_arm() {
+    let out = run_ok(r#"
+        fun describe(x) {
+            return match (x) {
+                case 0 => "zero";
+                case [a, b] => "pair " + str(a + b);
+                case n if n > 0 => "positive " + str(n);
+                case _ => "other";
+            };
+        }
+        var kind = match (describe(5)) { case "positive 5" => "ok"; case _ => "wrong"; };
+        print kind;
+        print describe(0) + ", " + describe([1, 2]) + ", " + describe(-1);
+        print 1 + match (2) { case 2 => 10; case _ => 0; };
+    "#);
+    assert_eq!(out, "ok
+zero, pair 3, other
+11");
+}
+
+#[test]
+fn non_exhaustive_match_is_a_runtime_error() {
+    assert_eq!(
+        run_err("print 1;\nmatch (3) {\n  case 1 => print 1;\n}", 70),
+        "No match case for value 3.\n[line 2] in script"
+    );
+    assert_eq!(
+        run_err("var x = 1 +\n  match (3) { case 1 => 1; };", 70),
+        "No match case for value 3.\n[line 2] in script"
+    );
+}
+
+#[test]
+fn instance_pattern_requires_a_class() {
+    assert_eq!(
+        run_err("var NotAClass = 1;\nmatch (1) { case NotAClass { x } => print x; }", 70),
+        "'NotAClass' is not a class.\n[line 2] in script"
+    );
+}
+
+#[test]
+fn duplicate_bindings_are_resolver_errors() {
+    assert_eq!(
+        run_err("match ([1, 2]) { case [a, a] => print a; }", 65),
+        "[line 1] Already a variable with this name in this scope."
+    );
+}
+
+#[test]
+fn lists_and_maps_that_contain_themselves_print() {
+    let out = run_ok(r#"
+        var a = [1];
+        a[0] = a;
+        print a;
+        var m = {"self": nil, "n": 1};
+        m["self"] = m;
+        print m;
+        var shared = [2];
+        var b = [shared, shared, [a]];
+        print b;
+        m["list"] = a;
+        a[0] = m;
+        print a;
+    "#);
+    assert_eq!(out, "[[...]]\n{self: {...}, n: 1}\n[[2], [2], [[[...]]]]\n[{self: {...}, n: 1, list: [...]}]");
+}
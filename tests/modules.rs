@@ -0,0 +1,84 @@
+mod common;
+
+use common::{run_err, run_project, stderr, stdout};
+
+#[test]
+fn namespaced_and_selective_imports() {
+    let output = run_project(&[
+        ("main.lox", r#"
+            import "lib/util.lox" as util;
+            import { square, counter as count } from "lib/util.lox";
+            print util.square(3);
+            print square(4);
+            print count;
+            util.bump();
+            print util.counter;
+            print util;
+        "#),
+        ("lib/util.lox", r#"
+            print "loading util";
+            import { double } from "math.lox";
+            var counter = 1;
+            fun square(x) { return double(x) * x / 2; }
+            fun bump() { counter = counter + 1; }
+        "#),
+        ("lib/math.lox", "fun double(x) { return x * 2; }"),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "loading util\n9\n16\n1\n2\n<module util>");
+}
+
+#[test]
+fn modules_have_their_own_globals() {
+    let output = run_project(&[
+        ("main.lox", r#"
+            var name = "main";
+            import "other.lox" as other;
+            print name;
+            print other.name;
+            print other.describe();
+            try { print other.helper; } catch (e) { print e.kind + ": " + e.message; }
+            try { other.name = "changed"; } catch (e) { print e.message; }
+        "#),
+        ("other.lox", r#"
+            var name = "other";
+            fun describe() { return "in " + name; }
+            if (true) { var helper = 1; }
+        "#),
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "main\nother\nin other\nImportError: Module 'other' has no export 'helper'.\nCan't assign to export 'name' of module 'other'."
+    );
+}
+
+#[test]
+fn circular_imports_report_the_cycle() {
+    let output = run_project(&[
+        ("a.lox", "import \"b.lox\" as b;"),
+        ("b.lox", "import \"dir/c.lox\" as c;"),
+        ("dir/c.lox", "import \"../a.lox\" as a;"),
+    ]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "Circular import: a.lox -> b.lox -> dir/c.lox -> a.lox.\n[line 1] in <module dir/c.lox>\n[line 1] in <module b.lox>\n[line 1] in script");
+}
+
+#[test]
+fn import_errors() {
+    let output = run_project(&[
+        ("main.lox", "import \"broken.lox\" as broken;"),
+        ("broken.lox", "var x = ;"),
+    ]);
+    assert_eq!(output.status.code(), Some(70));
+    let err = stderr(&output);
+    assert!(err.starts_with("Could not compile module 'broken.lox':\n[line 1] "), "{err}");
+    assert!(err.contains("Expect ';' after variable declaration."), "{err}");
+
+    let err = run_err("import \"missing.lox\" as m;", 70);
+    assert_eq!(err, "Could not find module 'missing.lox'.\n[line 1] in script");
+    let err = run_err("fun f() {\n  import \"x.lox\" as x;\n}", 65);
+    assert!(err.contains("[line 2] Can only import at top level."), "{err}");
+    let err = run_err("import { } from \"x.lox\";", 65);
+    assert!(err.contains("Expect name to import."), "{err}");
+}
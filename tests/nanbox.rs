@@ -0,0 +1,89 @@
+#![cfg(feature = "nan-boxing")]
+
+mod common;
+
+use std::cell::RefCell;
+use std::mem::size_of;
+use std::rc::Rc;
+use codecrafters_interpreter::object::nanbox::NanBox;
+use codecrafters_interpreter::object::object::Value;
+use common::{run_with, stderr, stdout};
+
+fn round_trip(value: Value) -> Value {
+    let boxed = NanBox::from(value);
+    let copy = boxed.clone();
+    assert_eq!(Value::from(&copy).inspect(), Value::from(&boxed).inspect());
+    Value::from(boxed)
+}
+
+#[test]
+fn values_fit_in_eight_bytes_and_come_back_unchanged() {
+    assert_eq!(size_of::<NanBox>(), 8);
+    for n in [0.0, -0.0, 1.5, -2.0, f64::MAX, f64::MIN_POSITIVE, f64::INFINITY, f64::NEG_INFINITY] {
+        match round_trip(Value::Number(n)) {
+            Value::Number(m) => assert_eq!(m.to_bits(), n.to_bits()),
+            other => panic!("{} came back as {}", n, other.inspect()),
+        }
+    }
+    // Any NaN, including ones whose bits look like a tagged value, stays a number.
+    for bits in [f64::NAN.to_bits(), 0x7ffc_0000_0000_0001, 0xfffc_0000_0000_0008] {
+        assert!(matches!(round_trip(Value::Number(f64::from_bits(bits))), Value::Number(n) if n.is_nan()));
+    }
+    assert!(matches!(round_trip(Value::Nil), Value::Nil));
+    assert!(matches!(round_trip(Value::Boolean(true)), Value::Boolean(true)));
+    assert!(matches!(round_trip(Value::Boolean(false)), Value::Boolean(false)));
+    assert!(NanBox::from(Value::Nil).is_nil());
+    assert!(!NanBox::from(Value::Boolean(false)).is_truthy());
+    assert!(NanBox::from(Value::Number(0.0)).is_truthy());
+    assert_eq!(NanBox::from(Value::Number(2.5)).as_number(), Some(2.5));
+    assert_eq!(NanBox::from(Value::string("hi")).as_number(), None);
+}
+
+#[test]
+fn boxes_hold_exactly_one_reference_to_their_object() {
+    let list = Rc::new(RefCell::new(vec![Value::Number(1.0)]));
+    let boxed = NanBox::from(Value::List(Rc::clone(&list)));
+    assert_eq!(Rc::strong_count(&list), 2);
+    let copy = boxed.clone();
+    assert_eq!(Rc::strong_count(&list), 3);
+    match Value::from(&copy) {
+        Value::List(elements) => assert!(Rc::ptr_eq(&elements, &list)),
+        other => panic!("got {}", other.inspect()),
+    }
+    assert_eq!(Rc::strong_count(&list), 3);
+    drop(copy);
+    let value = Value::from(boxed);
+    assert_eq!(Rc::strong_count(&list), 2);
+    drop(value);
+    assert_eq!(Rc::strong_count(&list), 1);
+
+    // Strings are pointed at directly, like lists, not boxed again.
+    let text: Rc<Box<str>> = Rc::new(Box::from("boxed"));
+    let boxed = NanBox::from(Value::String(Rc::clone(&text)));
+    let copy = boxed.clone();
+    assert_eq!(Rc::strong_count(&text), 3);
+    assert!(matches!(Value::from(copy), Value::String(s) if Rc::ptr_eq(&s, &text)));
+    assert_eq!(Rc::strong_count(&text), 2);
+    assert!(matches!(Value::from(&boxed), Value::String(s) if &**s == "boxed"));
+    drop(boxed);
+    assert_eq!(Rc::strong_count(&text), 1);
+}
+
+#[test]
+fn programs_run_on_the_boxed_stack() {
+    let output = run_with("run", r#"
+        class Point {}
+        var p = Point();
+        p.x = 0 / 0;
+        var values = [nil, true, false, -0, 1.5, "text", p, {"k": [1, 2]}, clock == clock];
+        var shown = "";
+        for (var i = 0; i < len(values); i = i + 1) shown = shown + str(values[i]) + " ";
+        print shown;
+        print p.x == p.x;
+        fun counter() { var n = 0; fun inc() { n = n + 1; return n; } return inc; }
+        var c = counter();
+        c();
+        print c();
+    "#, &["--backend=vm"], "");
+    assert_eq!(stdout(&output), "nil true false -0 1.5 text Point instance {k: [1, 2]} true \nfalse\n2", "{}", stderr(&output));
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use codecrafters_interpreter::{ErrorKind, Interpreter, LoxValue, NativeError};
+use common::run_ok;
+
+#[test]
+fn registered_functions_convert_arguments_and_results() {
+    let mut interp = Interpreter::new();
+    interp.register_fn("hypot", 2, |args| {
+        let (a, b): (f64, f64) = (args.get(0)?, args.get(1)?);
+        Ok((a * a + b * b).sqrt())
+    });
+    interp.register_fn("shout", 1, |args| Ok(args.get::<String>(0)?.to_uppercase() + "!"));
+    interp.register_fn("total", 1, |args| Ok(args.get::<Vec<f64>>(0)?.iter().sum::<f64>()));
+    interp.register_fn("describe", 1, |args| {
+        Ok(match args.get::<Option<bool>>(0)? {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "unknown",
+        })
+    });
+    interp.register_fn("pair", 0, |_| Ok(vec![LoxValue::Nil, 1.0.into()]));
+
+    assert_eq!(interp.eval_str("hypot(3, 4)").unwrap(), LoxValue::Number(5.0));
+    assert_eq!(interp.eval_str("shout(\"hi\")").unwrap(), LoxValue::from("HI!"));
+    assert_eq!(interp.eval_str("total([1, 2, 3.5])").unwrap(), LoxValue::Number(6.5));
+    assert_eq!(interp.eval_str("describe(nil) + describe(true)").unwrap(), LoxValue::from("unknownyes"));
+    assert_eq!(interp.eval_str("pair()[1] + 1").unwrap(), LoxValue::Number(2.0));
+    assert_eq!(interp.eval_str("hypot").unwrap().to_string(), "<native fn>");
+}
+
+#[test]
+fn native_functions_keep_host_state() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut interp = Interpreter::new();
+    let sink = Rc::clone(&log);
+    interp.register_fn("log", 1, move |args| {
+        sink.borrow_mut().push(args.arg(0).to_string());
+        Ok(())
+    });
+    interp.eval_str("for (var i = 0; i < 3; i = i + 1) log(i * 2);").unwrap();
+    assert_eq!(*log.borrow(), vec!["0", "2", "4"]);
+}
+
+#[test]
+fn arity_and_type_errors_are_runtime_errors() {
+    let mut interp = Interpreter::new();
+    interp.register_fn("half", 1, |args| Ok(args.get::<f64>(0)? / 2.0));
+    interp.register_fn("fail", 0, |_| Err::<(), _>(NativeError::from("disk on fire")));
+
+    let err = interp.eval_str("half(1, 2);").unwrap_err();
+    assert_eq!(err.to_string(), "Expected 1 arguments but got 2.\n[line 1] in script");
+    let err = interp.eval_str("\nhalf(\"x\");").unwrap_err();
+    assert_eq!(err.to_string(), "Argument 1 to 'half' must be a number but got string.\n[line 2] in half()\n[line 2] in script");
+    assert_eq!(err.exit_code(), 70);
+
+    let caught = interp.eval_str(r#"
+        var result;
+        try { half([]); } catch (e) { result = e.kind; }
+        try { fail(); } catch (e) { result = result + " " + e.kind + ": " + e.message + " " + e.stack[0]; }
+        result
+    "#).unwrap();
+    assert_eq!(caught, LoxValue::from("TypeError HostError: disk on fire [line 4] in fail()"));
+    assert_eq!(NativeError::from("x").kind, ErrorKind::HostError);
+}
+
+#[test]
+fn list_and_map_arguments_are_only_copied_on_request() {
+    let mut interp = Interpreter::new();
+    interp.register_fn("copy", 1, |args| args.get::<LoxValue>(0));
+    // Copying `shared` in full would take 2^40 elements.
+    let out = interp.eval_str(r#"
+        var a = [1];
+        a[0] = a;
+        var m = {};
+        m["m"] = m;
+        var shared = [0];
+        for (var i = 0; i < 40; i = i + 1) shared = [shared, shared];
+        var b = copy(a);
+        str(len(a)) + type(a) + str(len(m)) + type(m) + str(len(shared)) + " " + str(b == a) + str(b[0] == a)
+    "#).unwrap();
+    assert_eq!(out, LoxValue::from("1list1map2 falsetrue"));
+}
+
+#[test]
+fn natives_are_visible_in_modules() {
+    let dir = std::env::temp_dir().join(format!("lox-native-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.lox"), "import { twice } from \"lib.lox\"; var out = twice();").unwrap();
+    std::fs::write(dir.join("lib.lox"), "fun twice() { return answer() * 2; }").unwrap();
+    let mut interp = Interpreter::new();
+    interp.register_fn("answer", 0, |_| Ok(21.0));
+    let result = interp.run_file(dir.join("main.lox"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    result.unwrap();
+    assert_eq!(interp.get_global("out"), Some(LoxValue::Number(42.0)));
+}
+
+#[test]
+fn clock_builtin() {
+    let out = run_ok(r#"
+        var start = clock();
+        print start > 1000000000;
+        print clock() >= start;
+        print clock;
+    "#);
+    assert_eq!(out, "true\ntrue\n<native fn>");
+}
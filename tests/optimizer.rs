@@ -0,0 +1,148 @@
+mod common;
+
+use codecrafters_interpreter::{Interpreter, LoxValue};
+use common::{run_with, stderr, stdout};
+
+fn optimized_tree(source: &str) -> String {
+    let output = run_with("parse", source, &["--optimize"], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output)
+}
+
+#[test]
+fn constant_expressions_are_folded() {
+    assert_eq!(optimized_tree("print 2 * 60 * 60;"), "(print 7200.0)");
+    assert_eq!(optimized_tree("print \"a\" + \"b\" + \"c\";"), "(print abc)");
+    assert_eq!(optimized_tree("print (1 + 2) * (3 - 1) ** 2;"), "(print 12.0)");
+    assert_eq!(optimized_tree("print 1 < 2 == !false;"), "(print true)");
+    assert_eq!(optimized_tree("print \"a\" == \"a\" != (nil == false);"), "(print true)");
+    assert_eq!(optimized_tree("print -(4 % 3);"), "(print -1.0)");
+    assert_eq!(optimized_tree("print nil ? 1 : \"no\";"), "(print no)");
+    // `and` and `or` keep whichever operand they would produce.
+    assert_eq!(optimized_tree("print nil or x;"), "(print x)");
+    assert_eq!(optimized_tree("print 0 and x;"), "(print x)");
+    assert_eq!(optimized_tree("print false and x;"), "(print false)");
+    assert_eq!(optimized_tree("print x and 1 + 1;"), "(print (and x 2.0))");
+    // Only the constant parts of an expression fold.
+    assert_eq!(optimized_tree("print (1 + 2) * x + 4 / 2;"), "(print (+ (* 3.0 x) 2.0))");
+    assert_eq!(optimized_tree("fun f(a) { return a[1 + 1] = [2 * 3]; }"), "(fun f (a) (block (return (= ([] a 2.0) (list 6.0)))))");
+    // Without --optimize the tree is printed as parsed.
+    assert_eq!(stdout(&run_with("parse", "print 2 * 3;", &[], "")), "(print (* 2.0 3.0))");
+}
+
+#[test]
+fn folding_follows_float_semantics() {
+    let source = "print 0.1 + 0.2;\nprint 1 / 0;\nprint -1 / 0;\nprint 0 / 0 == 0 / 0;\nprint -7 % 3;\nprint 2 ** 0.5;\nprint -0 == 0;";
+    assert_eq!(
+        optimized_tree(source),
+        "(print 0.30000000000000004)\n(print inf)\n(print -inf)\n(print false)\n(print -1.0)\n(print 1.4142135623730951)\n(print true)",
+    );
+    let plain = run_with("run", source, &[], "");
+    let optimized = run_with("run", source, &["--optimize"], "");
+    assert_eq!(stdout(&optimized), stdout(&plain));
+}
+
+#[test]
+fn operations_that_fail_are_left_to_fail_at_runtime() {
+    assert_eq!(optimized_tree("print -\"a\";"), "(print (- a))");
+    assert_eq!(optimized_tree("print 1 + \"a\";"), "(print (+ 1.0 a))");
+    assert_eq!(optimized_tree("print \"a\" < \"b\";"), "(print (< a b))");
+    assert_eq!(optimized_tree("print -nil + 1;"), "(print (+ (- nil) 1.0))");
+    for source in ["print 1;\nprint -\"a\";", "print 1;\nprint 2 *\n  (\"a\" + 1);", "fun f() {\n  return true < 1;\n}\nf();"] {
+        let plain = run_with("run", source, &[], "");
+        let optimized = run_with("run", source, &["--optimize"], "");
+        assert_eq!(stdout(&optimized), stdout(&plain), "{}", source);
+        assert_eq!(stderr(&optimized), stderr(&plain), "{}", source);
+        assert_eq!(optimized.status.code(), Some(70));
+    }
+}
+
+#[test]
+fn unreachable_code_is_removed() {
+    assert_eq!(optimized_tree("if (false) { print 1; }\nprint 2;"), "(print 2.0)");
+    assert_eq!(optimized_tree("if (1 > 2) print 1; else print 3;"), "(print 3.0)");
+    assert_eq!(optimized_tree("if (\"yes\") { print 1; } else { print 3; }"), "(block (print 1.0))");
+    assert_eq!(optimized_tree("while (x) if (nil) print 1;"), "(while x (block ))");
+    assert_eq!(optimized_tree("while (1 == 2) print 1;\nprint 2;"), "(print 2.0)");
+    assert_eq!(optimized_tree("for (var i = 0; false; i++) print i;\nfor (; false;) print 1;"), "(block (var i 0.0))\n(block )");
+    assert_eq!(
+        optimized_tree("fun f(x) {\n  if (x) { return 1; print \"a\"; }\n  throw \"b\";\n  print \"c\";\n}"),
+        "(fun f (x) (block (if x (block (return 1.0))) (throw b)))",
+    );
+    assert_eq!(
+        optimized_tree("while (true) { if (x) { continue; x = 1; } break; print 2; }"),
+        "(while true (block (if x (block (continue))) (break)))",
+    );
+    assert_eq!(optimized_tree("var y = 2 > 1 ? \"big\" : \"small\";"), "(var y big)");
+}
+
+#[test]
+fn optimized_programs_behave_the_same() {
+    let source = r#"
+        var i = "outer";
+        for (var i = 0; false;) print "never";
+        print i;
+        var hour = 60 * 60;
+        fun describe(n) {
+            if (n < 0) return "negative";
+            if (false) return "impossible";
+            match (n) {
+                case 0 => return "zero";
+                case _ if 1 + 1 == 2 => return "positive " + str(n * hour);
+            }
+            return "unreachable";
+        }
+        print describe(-1);
+        print describe(0);
+        print describe(2);
+        var total = 0;
+        while (true) {
+            total = total + 1;
+            if (total >= 3) break;
+            continue;
+            total = 100;
+        }
+        print total;
+        print "con" + "cat" == "concat" and 1 < 2;
+    "#;
+    for backend in ["--backend=tree", "--backend=vm"] {
+        let plain = run_with("run", source, &[backend], "");
+        let optimized = run_with("run", source, &[backend, "--optimize"], "");
+        assert_eq!(stdout(&plain), "outer\nnegative\nzero\npositive 7200\n3\ntrue");
+        assert_eq!(stdout(&optimized), stdout(&plain), "{}", backend);
+    }
+}
+
+#[test]
+fn a_dropped_last_statement_leaves_the_program_value_nil() {
+    for (source, value) in [("1;\nif (false) 2;", "nil"), ("1;\nif (true) 2 + 3;", "5"), ("if (false) 1; 2;", "2"), ("1;\nwhile (false) {}", "nil")] {
+        let plain = run_with("evaluate", source, &[], "");
+        let optimized = run_with("evaluate", source, &["--optimize"], "");
+        assert_eq!(stdout(&plain), value, "{}", source);
+        assert_eq!(stdout(&optimized), value, "{}", source);
+    }
+}
+
+#[test]
+fn the_api_optimizes_programs_and_their_imports() {
+    let dir = std::env::temp_dir().join(format!("lox-optimizer-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("main.lox"), "import { twice } from \"lib.lox\";\nvar result = twice(3 * 7);").unwrap();
+    std::fs::write(dir.join("lib.lox"), "fun twice(n) { return n * (1 + 1); print \"unreachable\"; }").unwrap();
+    let mut interp = Interpreter::new();
+    interp.set_optimize(true);
+    let result = interp.run_file(dir.join("main.lox"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    result.unwrap();
+    assert_eq!(interp.get_global("result"), Some(LoxValue::Number(42.0)));
+    assert_eq!(interp.eval_str("-(2 ** 3);").unwrap(), LoxValue::Number(-8.0));
+    assert!(interp.eval_str("-\"a\";").is_err());
+}
+
+#[test]
+fn bytecode_is_compiled_from_the_optimized_tree() {
+    let listing = stdout(&run_with("disassemble", "print 2 * 3;", &["--optimize"], ""));
+    assert!(listing.contains("CONSTANT           0 6\n0001    | PRINT"), "{}", listing);
+    let listing = stdout(&run_with("disassemble", "print 2 * 3;", &[], ""));
+    assert!(listing.contains("MULTIPLY"), "{}", listing);
+}
@@ -0,0 +1,58 @@
+use codecrafters_interpreter::lexer::lexer::Lexer;
+use codecrafters_interpreter::parser::parser::Parser;
+use codecrafters_interpreter::common::common::PrjString;
+use codecrafters_interpreter::{Interpreter, Output};
+
+#[test]
+fn print_writes_to_the_interpreter_output() {
+    let mut interp = Interpreter::new();
+    let captured = interp.capture_output();
+    interp.eval_str("print 1 + 2; print \"hi\"; print [nil, true];").unwrap();
+    assert_eq!(captured.take(), "3\nhi\n[nil, true]\n");
+
+    interp.eval_str("fun f(x) { print x * 2; } f(21);").unwrap();
+    assert_eq!(captured.contents(), "42\n");
+}
+
+#[test]
+fn interpreters_have_separate_outputs() {
+    let mut a = Interpreter::new();
+    let mut b = Interpreter::new();
+    let out_a = a.capture_output();
+    let out_b = b.capture_output();
+    a.eval_str("print \"a\";").unwrap();
+    b.eval_str("print \"b\";").unwrap();
+    assert_eq!(out_a.contents(), "a\n");
+    assert_eq!(out_b.contents(), "b\n");
+
+    // Outputs can also be shared, keeping lines in order.
+    let (shared, buffer) = Output::capture();
+    a.set_output(shared.clone());
+    b.set_output(shared);
+    a.eval_str("print 1;").unwrap();
+    b.eval_str("print 2;").unwrap();
+    a.eval_str("print 3;").unwrap();
+    assert_eq!(buffer.contents(), "1\n2\n3\n");
+}
+
+#[test]
+fn tokens_programs_and_diagnostics_write_to_outputs() {
+    let (out, tokens) = Output::capture();
+    let mut l = Lexer::new("var x = 1;".to_string());
+    l.tokenize();
+    for token in l.tokens.iter() {
+        token.print(&out).unwrap();
+    }
+    assert_eq!(tokens.contents(), "VAR var null\nIDENTIFIER x null\nEQUAL = null\nNUMBER 1 1.0\nSEMICOLON ; null\nEOF  null\n");
+
+    let (out, ast) = Output::capture();
+    let mut l = Lexer::new("(1 + 2) * 3".to_string());
+    l.tokenize();
+    let mut p = Parser::new(l);
+    p.parse_program().print(&out).unwrap();
+    assert_eq!(ast.contents(), "(* (group (+ 1.0 2.0)) 3.0)\n");
+
+    let (diagnostics, errors) = Output::capture();
+    "[line 1] Error: Unexpected character: $".to_string().print_error(&diagnostics).unwrap();
+    assert_eq!(errors.contents(), "[line 1] Error: Unexpected character: $\n");
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use common::{run, stderr, stdout};
+
+#[test]
+fn precedence_table() {
+    let cases = [
+        ("1 + 2 * 3", "(+ 1.0 (* 2.0 3.0))"),
+        ("1 - 2 - 3", "(- (- 1.0 2.0) 3.0)"),
+        ("7 % 3 * 2", "(* (% 7.0 3.0) 2.0)"),
+        ("1 + 7 % 3", "(+ 1.0 (% 7.0 3.0))"),
+        ("2 ** 3 ** 2", "(** 2.0 (** 3.0 2.0))"),
+        ("2 * 3 ** 2", "(* 2.0 (** 3.0 2.0))"),
+        ("-2 ** 2", "(- (** 2.0 2.0))"),
+        ("-2 * 3", "(* (- 2.0) 3.0)"),
+        ("2 ** -1", "(** 2.0 (- 1.0))"),
+        ("!a ** b", "(! (** a b))"),
+        ("a < b == c", "(== (< a b) c)"),
+        ("a ? b : c", "(? a b c)"),
+        ("a ? b : c ? d : e", "(? a b (? c d e))"),
+        ("a ? b ? c : d : e", "(? a (? b c d) e)"),
+        ("a == b ? c + 1 : d * 2", "(? (== a b) (+ c 1.0) (* d 2.0))"),
+        ("a = b = c", "(= a (= b c))"),
+        ("a = b ? c : d", "(= a (? b c d))"),
+        ("a += b -= 1", "(+= a (-= b 1.0))"),
+        ("a *= b + c", "(*= a (+ b c))"),
+        ("a /= 2 ** 3", "(/= a (** 2.0 3.0))"),
+        ("a %= 2", "(%= a 2.0)"),
+        ("a.b = c", "(= (. a b) c)"),
+        ("a[1] += 2", "(+= ([] a 1.0) 2.0)"),
+        ("a.b[c + 1].d", "(. ([] (. a b) (+ c 1.0)) d)"),
+        ("a++", "(a ++)"),
+        ("--a", "(-- a)"),
+        ("a.b++ * 2", "(* ((. a b) ++) 2.0)"),
+        ("-a[0]--", "(- (([] a 0.0) --))"),
+        ("++a.b ** 2", "(** (++ (. a b)) 2.0)"),
+    ];
+
+    for (source, expected) in cases.iter() {
+        let output = run("parse", source);
+        assert!(output.status.success(), "{source}: {}", stderr(&output));
+        assert_eq!(stdout(&output), *expected, "{source}");
+    }
+}
+
+#[test]
+fn invalid_assignment_targets() {
+    let cases = [
+        ("a + b = c", "[line 1] Invalid assignment target."),
+        ("a ? b : c = d", "[line 1] Invalid assignment target."),
+        ("1 += 2", "[line 1] Invalid assignment target."),
+        ("(a)++", "[line 1] Invalid ++ target."),
+        ("--1", "[line 1] Invalid -- target."),
+        ("a ? b", "[line 1] Expect ':' after then branch of conditional expression."),
+    ];
+
+    for (source, expected) in cases.iter() {
+        let output = run("parse", source);
+        assert_eq!(output.status.code(), Some(65), "{source}");
+        assert_eq!(stderr(&output), *expected, "{source}");
+    }
+}
+
+#[test]
+fn input_that_ends_part_way_is_an_error() {
+    let cases = [
+        ("{\"a\":", "[line 1] Expect ';' after expression.\n[line 1] Expect '}' after block."),
+        ("var m = {\"a\":", "[line 1] Expect expression.\n[line 1] Expect ';' after variable declaration."),
+        ("print {\"a\": 1,\n\"b\":", "[line 2] Expect expression.\n[line 2] Expect ';' after value."),
+    ];
+
+    for (source, expected) in cases.iter() {
+        let output = run("parse", source);
+        assert_eq!(output.status.code(), Some(65), "{source}");
+        assert_eq!(stderr(&output), *expected, "{source}");
+    }
+}
+
+#[test]
+fn evaluates_new_operators() {
+    let cases = [
+        ("7 % 3", "1"),
+        ("-7 % 3", "-1"),
+        ("7.5 % 2", "1.5"),
+        ("2 ** 10", "1024"),
+        ("2 ** 3 ** 2", "512"),
+        ("-2 ** 2", "-4"),
+        ("(-2) ** 2", "4"),
+        ("2 ** -1", "0.5"),
+        ("true ? 1 : 2", "1"),
+        ("nil ? 1 : false ? 2 : 3", "3"),
+    ];
+
+    for (source, expected) in cases.iter() {
+        let output = run("evaluate", source);
+        assert!(output.status.success(), "{source}: {}", stderr(&output));
+        assert_eq!(stdout(&output), *expected, "{source}");
+    }
+}
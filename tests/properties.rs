@@ -0,0 +1,98 @@
+mod common;
+
+use common::{run, stderr, stdout};
+
+#[test]
+fn fields_read_the_same_whatever_order_they_were_added_in() {
+    // `describe` sees instances of three shapes at each property expression,
+    // more than once, so its caches hit as well as miss.
+    let output = run("run", r#"
+        class Point {}
+        fun make(x, y, flip) {
+            var p = Point();
+            if (flip) { p.y = y; p.x = x; } else { p.x = x; p.y = y; }
+            return p;
+        }
+        fun describe(p) { return str(p.x) + "," + str(p.y); }
+        var a = make(1, 2, false);
+        var b = make(3, 4, true);
+        var c = make(5, 6, false);
+        c.z = 7;
+        for (var i = 0; i < 2; i = i + 1) {
+            print describe(a);
+            print describe(b);
+            print describe(c);
+        }
+        b.x += 10;
+        b.y++;
+        print describe(b);
+        print c.z;
+    "#);
+    assert_eq!(stdout(&output), "1,2\n3,4\n5,6\n1,2\n3,4\n5,6\n13,5\n7", "{}", stderr(&output));
+}
+
+#[test]
+fn a_cached_expression_still_reports_missing_fields() {
+    let output = run("run", r#"
+        class Point {}
+        fun getX(p) { return p.x; }
+        var p = Point();
+        p.x = 1;
+        print getX(p);
+        var q = Point();
+        q.y = 2;
+        print getX(q);
+    "#);
+    assert_eq!(stdout(&output), "1");
+    assert_eq!(stderr(&output), "Undefined property 'x'.\n[line 3] in getX()\n[line 9] in script");
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[test]
+fn classes_with_the_same_fields_stay_distinct() {
+    let output = run("run", r#"
+        class A {}
+        class B {}
+        fun tag(v) {
+            match (v) {
+                case A { n: n } => return "A" + str(n);
+                case B { n: n } => return "B" + str(n);
+            }
+        }
+        var a = A();
+        a.n = 1;
+        var b = B();
+        b.n = 2;
+        print tag(a);
+        print tag(b);
+        try { nil.x; } catch (e) { print e.kind; print e.message; }
+    "#);
+    assert_eq!(stdout(&output), "A1\nB2\nTypeError\nOnly instances have properties.", "{}", stderr(&output));
+}
+
+#[test]
+fn cached_methods_give_way_to_fields_and_other_classes() {
+    // `speak` is cached as a method for A's instances, then has to miss for an
+    // instance that shadows it with a field and for a class with its own `speak`.
+    let output = run("run", r#"
+        class A { speak() { return "A " + str(this.n); } }
+        class B { speak() { return "B " + str(this.n); } }
+        fun say(v) { return v.speak(); }
+        fun shout() { return "field"; }
+        var a = A();
+        a.n = 1;
+        var b = B();
+        b.n = 2;
+        var shadowed = A();
+        shadowed.n = 3;
+        for (var i = 0; i < 2; i = i + 1) {
+            print say(a);
+            print say(b);
+        }
+        print say(shadowed);
+        shadowed.speak = shout;
+        print say(shadowed);
+        print say(a);
+    "#);
+    assert_eq!(stdout(&output), "A 1\nB 2\nA 1\nB 2\nA 3\nfield\nA 1", "{}", stderr(&output));
+}
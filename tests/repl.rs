@@ -0,0 +1,79 @@
+mod common;
+
+use std::fs;
+use common::{run, run_repl, stderr, stdout};
+
+#[test]
+fn entries_share_globals_and_echo_expressions() {
+    let output = run_repl(&["repl"], r#"var x = 41;
+x + 1
+fun add(a,
+        b) {
+  return a + b;
+}
+add(x, 1);
+print [1,
+  2];
+var s = "two
+lines";
+s
+x = 1
+"#);
+    assert_eq!(stdout(&output), "42\n42\n[1, 2]\ntwo\nlines\n1", "{}", stderr(&output));
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn errors_are_reported_and_the_session_goes_on() {
+    let output = run_repl(&[], r#"print missing;
+1 +;
+fun broken() { return nil.x; }
+broken();
+print "still here";
+{ print "open";
+"#);
+    assert_eq!(stdout(&output), "still here");
+    assert_eq!(
+        stderr(&output),
+        "Undefined variable 'missing'.\n[line 1] in script\n\
+         [line 1] Invalid token type Semicolon\n\
+         Only instances have properties.\n[line 1] in broken()\n[line 1] in script\n\
+         [line 1] Expect '}' after block.",
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    // An entry still open at the end of input is reported, not run.
+    let output = run_repl(&[], "var m = {\"a\":");
+    assert_eq!(stderr(&output), "[line 1] Expect expression.\n[line 1] Expect ';' after variable declaration.");
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = run_repl(&["repl"], "print 1;\nexit(3);\nprint 2;\n");
+    assert_eq!(stdout(&output), "1");
+    assert_eq!(output.status.code(), Some(3));
+    let output = run_repl(&["repl"], "print 1;\n:quit\nprint 2;\n");
+    assert_eq!(stdout(&output), "1");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn commands() {
+    let path = std::env::temp_dir().join(format!("lox-repl-{}.lox", std::process::id()));
+    fs::write(&path, "fun twice(n) { return n * 2; }\nvar loaded = true;\n").unwrap();
+    let input = format!(":load {}\ntwice(21)\nloaded\n:reset\nloaded\n:ast (1 + 2) * x\n:tokens x >= \"s\"\n:help\n:load\n:nope\n", path.display());
+    let output = run_repl(&["repl"], &input);
+    fs::remove_file(&path).unwrap();
+    let out = stdout(&output);
+    assert!(out.starts_with("42\ntrue\n(* (group (+ 1.0 2.0)) x)\nIDENTIFIER x null\nGREATER_EQUAL >= null\nSTRING \"s\" s\nEOF  null\n"), "{}", out);
+    assert!(out.contains(":load <file>") && out.contains(":reset") && out.contains(":ast <code>") && out.contains(":tokens <code>"), "{}", out);
+    assert_eq!(
+        stderr(&output),
+        "Undefined variable 'loaded'.\n[line 1] in script\nUsage: :load <file>\nUnknown command ':nope'. Type :help for the list.",
+    );
+}
+
+#[test]
+fn strings_may_span_lines() {
+    let output = run("run", "var s = \"a\nb\";\nprint s;\nprint missing;\n");
+    assert_eq!(stdout(&output), "a\nb");
+    assert_eq!(stderr(&output), "Undefined variable 'missing'.\n[line 4] in script");
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::{run, stderr, stdout};
+
+#[test]
+fn locals_in_every_kind_of_scope() {
+    let output = run("run", r#"
+        fun sum(a, b) { var total = a + b; return total; }
+        print sum(1, 2);
+        var closures = {};
+        for (var i = 0; i < 3; i = i + 1) {
+            var j = i * 10;
+            fun get() { return j; }
+            closures[i] = get;
+        }
+        print closures[0]() + closures[2]();
+        fun counter() {
+            var n = 0;
+            fun inc() { n += 1; n++; return n; }
+            inc();
+            return inc;
+        }
+        print counter()();
+        try { throw "boom"; } catch (e) { var message = e + "!"; print message; }
+        class Point {}
+        var p = Point();
+        p.x = 3;
+        fun describe(v) {
+            match (v) {
+                case [first, ..rest] => { var count = 1 + len(rest); return str(first) + "/" + str(count); }
+                case Point { x: x } if x > 2 => return "point " + str(x);
+            }
+        }
+        print describe([7, 8, 9]);
+        print describe(p);
+    "#);
+    assert_eq!(stdout(&output), "3\n20\n4\nboom!\n7/3\npoint 3", "{}", stderr(&output));
+}
+
+#[test]
+fn a_name_refers_to_one_variable_wherever_it_runs() {
+    // `f` is resolved where it is declared, before the inner `a` is, so it keeps
+    // reading the outer one. A reference made before any local of its name is
+    // declared reads a global declared before it.
+    let output = run("run", r#"
+        var a = "global";
+        {
+            var a = "outer";
+            {
+                fun f() { return a; }
+                print f();
+                var a = "inner";
+                print f();
+            }
+        }
+        {
+            print a;
+            var a = "shadow";
+            print a;
+        }
+    "#);
+    assert_eq!(stdout(&output), "outer\nouter\nglobal\nshadow", "{}", stderr(&output));
+}
+
+#[test]
+fn a_local_whose_declaration_has_not_run_is_undefined() {
+    let output = run("run", r#"
+        var x = "global";
+        fun f(c) {
+            if (c) var x = "local";
+            x = "assigned";
+            return x;
+        }
+        print f(true);
+        print x;
+        print f(false);
+    "#);
+    assert_eq!(stdout(&output), "assigned\nglobal");
+    assert_eq!(stderr(&output), "Undefined variable 'x'.\n[line 5] in f()\n[line 10] in script");
+    assert_eq!(output.status.code(), Some(70));
+
+    let output = run("run", r#"
+        {
+            fun later() { return early; }
+            print later();
+            var early = "declared";
+        }
+    "#);
+    assert_eq!(stderr(&output), "Undefined variable 'early'.\n[line 3] in later()\n[line 4] in script");
+
+    let output = run("run", "{\n  missing = 1;\n}");
+    assert_eq!(stderr(&output), "Undefined variable 'missing'.\n[line 2] in script");
+}
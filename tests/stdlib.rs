@@ -0,0 +1,92 @@
+mod common;
+
+use common::{run_err, run_ok};
+
+// Runs `call` inside try/catch and prints either its result or "Kind: message".
+fn outcome(call: &str) -> String {
+    run_ok(&format!("try {{ print {}; }} catch (e) {{ print e.kind + \": \" + e.message; }}", call))
+}
+
+#[test]
+fn string_functions() {
+    let out = run_ok(r#"
+        print len("héllo");
+        print len([1, 2, 3]);
+        print len({"a": 1});
+        print substr("hello world", 6, 5);
+        print substr("héllo", 1, 0) == "";
+        print split("a,b,,c", ",");
+        print split("abc", "");
+        print join([1, "b", nil, true], "-");
+        print "[" + trim("  padded  ") + "]";
+        print upper("MiXeD") + lower("MiXeD");
+        print find("héllo", "llo");
+        print find("hello", "z");
+        print replace("a-b-c", "-", "+");
+        print format("{} + {} = {}", 1, 2.5, "3.5");
+        print format("{{literal}} {}", [1]);
+    "#);
+    assert_eq!(
+        out,
+        "5\n3\n1\nworld\ntrue\n[a, b, , c]\n[a, b, c]\n1-b-nil-true\n[padded]\nMIXEDmixed\n2\n-1\na+b+c\n1 + 2.5 = 3.5\n{literal} [1]"
+    );
+}
+
+#[test]
+fn string_function_errors() {
+    assert_eq!(outcome("len(3)"), "TypeError: Argument 1 to 'len' must be a string, list or map but got number.");
+    assert_eq!(outcome("upper(nil)"), "TypeError: Argument 1 to 'upper' must be a string but got nil.");
+    assert_eq!(outcome("substr(\"abc\", 1.5, 1)"), "TypeError: Argument 2 to 'substr' must be an integer but got 1.5.");
+    assert_eq!(outcome("substr(\"abc\", 2, 2)"), "IndexError: substr: range 2..4 is out of bounds for a string of length 3.");
+    assert_eq!(outcome("substr(\"abc\", -1, 1)"), "IndexError: substr: range -1..0 is out of bounds for a string of length 3.");
+    assert_eq!(outcome("substr(\"abc\", 1, 100000000000000000000000)"), "IndexError: substr: range 1..9223372036854775807 is out of bounds for a string of length 3.");
+    assert_eq!(outcome("substr(\"abc\", 100000000000000000000000, 100000000000000000000000)"), "IndexError: substr: range 9223372036854775807..9223372036854775807 is out of bounds for a string of length 3.");
+    assert_eq!(outcome("join([1], 2)"), "TypeError: Argument 2 to 'join' must be a string but got number.");
+    assert_eq!(outcome("replace(\"abc\", \"\", \"x\")"), "ValueError: replace: the string to replace must not be empty.");
+    assert_eq!(outcome("format(\"{} {}\", 1)"), "ValueError: format: template has 2 placeholders but got 1 values.");
+    assert_eq!(outcome("format(\"{\", 1)"), "ValueError: format: unmatched '{' in template.");
+    assert_eq!(outcome("format()"), "TypeError: Expected at least 1 arguments but got 0.");
+}
+
+#[test]
+fn math_functions() {
+    let out = run_ok(r#"
+        print floor(2.7) + ceil(2.1);
+        print sqrt(16) + abs(-2);
+        print pow(2, 10);
+        print sin(0) + cos(0) + tan(0);
+        print asin(1) == acos(0);
+        print atan(1) * 4 == atan2(1, -1) * 4 / 3;
+        print min(3, -1, 2) + max(3, -1, 2);
+        print min(7);
+        print sqrt(-1);
+    "#);
+    assert_eq!(out, "5\n6\n1024\n1\ntrue\ntrue\n2\n7\nNaN");
+    assert_eq!(outcome("max(1, \"2\")"), "TypeError: Argument 2 to 'max' must be a number but got string.");
+    assert_eq!(outcome("floor(true)"), "TypeError: Argument 1 to 'floor' must be a number but got boolean.");
+    assert_eq!(outcome("pow(2)"), "TypeError: Expected 2 arguments but got 1.");
+}
+
+#[test]
+fn conversions() {
+    let out = run_ok(r#"
+        print num("3.5") + num(" -2 ") + num("1e2") + num(4);
+        print str(12) + str(nil) + str([1, "a"]) + str(true);
+        print type(nil) + " " + type(true) + " " + type(1) + " " + type("s");
+        print type([]) + " " + type({}) + " " + type(clock) + " " + type(type);
+        class C {}
+        fun f() {}
+        print type(C) + " " + type(C()) + " " + type(f);
+    "#);
+    assert_eq!(out, "105.5\n12nil[1, a]true\nnil boolean number string\nlist map function function\nclass instance function");
+    assert_eq!(outcome("num(\"abc\")"), "ValueError: num: can't convert \"abc\" to a number.");
+    assert_eq!(outcome("num(\"inf\")"), "ValueError: num: can't convert \"inf\" to a number.");
+    assert_eq!(outcome("num(\"\")"), "ValueError: num: can't convert \"\" to a number.");
+    assert_eq!(outcome("num([])"), "TypeError: Argument 1 to 'num' must be a number or string but got list.");
+}
+
+#[test]
+fn uncaught_stdlib_errors_exit_70() {
+    let err = run_err("print 1;\nprint num(\"x\");", 70);
+    assert_eq!(err, "num: can't convert \"x\" to a number.\n[line 2] in num()\n[line 2] in script");
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{run_err, run_ok, run_with, stderr, stdout};
+
+#[test]
+fn tail_recursion_runs_in_constant_stack() {
+    // A call depth limit far below the number of calls shows that tail calls
+    // don't nest.
+    let output = run_with("run", r#"
+        fun loop(n) { if (n == 0) return 0; return loop(n - 1); }
+        print loop(1000000);
+        fun sum(n, acc) {
+            if (n == 0) return acc;
+            { var next = n - 1; return sum(next, acc + n); }
+        }
+        print sum(100000, 0);
+        fun count(n) {
+            while (true) {
+                if (n % 2 == 0) return count(n - 1);
+                if (n > 0) return count(n - 1);
+                return "done";
+            }
+        }
+        print count(100000);
+    "#, &["--max-call-depth=50"], "");
+    assert_eq!(stdout(&output), "0\n5000050000\ndone", "{}", stderr(&output));
+}
+
+#[test]
+fn mutual_recursion_runs_in_constant_stack() {
+    let output = run_with("run", r#"
+        fun isEven(n) { if (n == 0) return true; return isOdd(n - 1); }
+        fun isOdd(n) { if (n == 0) return false; return isEven(n - 1); }
+        print isEven(1000000);
+        print isOdd(100001);
+        fun counter() {
+            var calls = 0;
+            fun ping(n) { calls = calls + 1; if (n == 0) return calls; return pong(n - 1); }
+            fun pong(n) { calls = calls + 1; return ping(n); }
+            return ping;
+        }
+        print counter()(100000);
+        class Box {}
+        fun wrap(n) { if (n == 0) return Box(); return wrap(n - 1); }
+        print wrap(10000);
+        fun timed(n) { if (n == 0) return clock() > 0; return timed(n - 1); }
+        print timed(10000);
+    "#, &["--max-call-depth=50"], "");
+    assert_eq!(stdout(&output), "true\ntrue\n200001\nBox instance\ntrue", "{}", stderr(&output));
+}
+
+#[test]
+fn calls_that_are_not_in_tail_position_still_nest() {
+    // The call in `deep` is an operand, and the one in `guarded` has a catch
+    // clause around it, so both keep their frames and hit the depth limit. A
+    // tail call replaces its caller in stack traces.
+    let output = run_with("run", r#"
+        fun deep(n) { if (n == 0) return 0; return 1 + deep(n - 1); }
+        try { deep(100); } catch (e) { print e.kind; }
+        fun guarded(n) {
+            if (n == 0) return 0;
+            try { return guarded(n - 1); } catch (e) { throw e; }
+        }
+        try { guarded(100); } catch (e) { print e.kind; }
+        fun cleanup(n) {
+            if (n == 0) return "clean";
+            try { print n; } finally { return cleanup(n - 1); }
+        }
+        print cleanup(2);
+        fun fail() { return -"a"; }
+        fun caller() { return fail(); }
+        caller();
+    "#, &["--max-call-depth=50"], "");
+    assert_eq!(stdout(&output), "StackOverflow\nStackOverflow\n2\n1\nclean");
+    assert_eq!(stderr(&output), "Operand must be a number.\n[line 14] in fail()\n[line 16] in script");
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[test]
+fn tail_calls_check_their_arguments() {
+    let err = run_err(r#"
+        fun one(a) { return a; }
+        fun two() { return one(1, 2); }
+        two();
+    "#, 70);
+    assert_eq!(err, "Expected 1 arguments but got 2.\n[line 3] in two()\n[line 4] in script");
+    let out = run_ok(r#"
+        fun make() { var n = 10; fun get() { return n; } return get; }
+        fun pass(f) { return f(); }
+        print pass(make());
+        fun notFunction() { return "text"(); }
+        try { notFunction(); } catch (e) { print e.message; }
+    "#);
+    assert_eq!(out, "10\nCan only call functions and classes.");
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use codecrafters_interpreter::{Frame, Interpreter};
+use common::{run_err, run_project, stderr};
+
+fn frame(function: &str, line: i32) -> Frame {
+    Frame { function: function.to_string(), line }
+}
+
+#[test]
+fn uncaught_errors_print_the_call_stack() {
+    let err = run_err(r#"fun fib(n) {
+  if (n < 2) return n + nil;
+  return fib(n - 1) + fib(n - 2);
+}
+
+fun main() {
+  print fib(2);
+}
+
+main();"#, 70);
+    assert_eq!(err, "Operands must be two numbers or two strings.\n[line 2] in fib()\n[line 3] in fib()\n[line 7] in main()\n[line 10] in script");
+}
+
+#[test]
+fn traces_include_module_code() {
+    let output = run_project(&[
+        ("main.lox", "import \"lib.lox\" as lib;"),
+        ("lib.lox", "fun fail() {\n  return -\"x\";\n}\nfail();"),
+    ]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "Operand must be a number.\n[line 2] in fail()\n[line 4] in <module lib.lox>\n[line 1] in script");
+}
+
+#[test]
+fn traces_are_available_through_the_api() {
+    let mut interp = Interpreter::new();
+    let err = interp.eval_str("fun inner() { return nil.x; }\nfun outer() {\n  inner();\n}\nouter();").unwrap_err();
+    assert_eq!(err.trace().unwrap(), &[frame("inner", 1), frame("outer", 3), frame("script", 5)]);
+
+    let err = interp.eval_str("var = 1;").unwrap_err();
+    assert!(err.trace().is_none());
+}
+
+#[test]
+fn caught_errors_carry_the_stack_at_the_raise_point() {
+    let mut interp = Interpreter::new();
+    let captured = interp.capture_output();
+    interp.eval_str(r#"fun check(x) {
+  if (x > 1) len(x);
+}
+fun run() {
+  try { check(2); } catch (e) { for (var i = 0; i < len(e.stack); i = i + 1) print e.stack[i]; }
+}
+run();"#).unwrap();
+    assert_eq!(captured.contents(), "[line 2] in len()\n[line 2] in check()\n[line 5] in run()\n[line 7] in script\n");
+}
+
+#[test]
+fn recursion_in_traces_is_collapsed() {
+    let err = run_err("fun f(n) {\n  return 1 + f(n + 1);\n}\nf(0);", 70);
+    assert_eq!(
+        err,
+        "Stack overflow: maximum call depth of 1000 exceeded.\n\
+         [line 2] in f()\n[line 2] in f()\n[line 2] in f()\n... 997 more\n[line 4] in script"
+    );
+
+    // Mutual recursion doesn't repeat a frame twice in a row, so only the ends
+    // of the trace are printed.
+    let err = run_err("fun f(n) {\n  return 1 + g(n);\n}\nfun g(n) {\n  return 1 + f(n);\n}\nf(0);", 70);
+    let lines: Vec<&str> = err.lines().collect();
+    assert_eq!(lines.len(), 42);
+    assert_eq!(lines[1..3], ["[line 5] in g()", "[line 2] in f()"]);
+    assert_eq!(lines[21], "... 961 more lines");
+    assert_eq!(lines[41], "[line 7] in script");
+}
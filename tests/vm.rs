@@ -0,0 +1,335 @@
+mod common;
+
+use std::cell::Cell;
+use std::rc::Rc;
+use codecrafters_interpreter::{Backend, Frame, GcConfig, Interpreter, Limits, LoxValue};
+use common::{run_with, stderr, stdout, Tracer};
+
+// Runs `source` on both backends and checks that they print, fail and exit alike.
+fn assert_same(source: &str) -> String {
+    let tree = run_with("run", source, &["--backend=tree"], "");
+    let vm = run_with("run", source, &["--backend=vm"], "");
+    assert_eq!(stdout(&vm), stdout(&tree), "stdout differs for:\n{}", source);
+    assert_eq!(stderr(&vm), stderr(&tree), "stderr differs for:\n{}", source);
+    assert_eq!(vm.status.code(), tree.status.code(), "exit code differs for:\n{}", source);
+    stdout(&vm)
+}
+
+#[test]
+fn closures_capture_variables_not_values() {
+    let out = assert_same(r#"
+        fun counter() {
+            var n = 0;
+            fun inc() { n = n + 1; return n; }
+            return inc;
+        }
+        var a = counter();
+        var b = counter();
+        a(); a();
+        print a();
+        print b();
+
+        var fs = [nil, nil, nil];
+        for (var i = 0; i < 3; i = i + 1) {
+            var j = i;
+            fun f() { return j; }
+            fs[i] = f;
+        }
+        print fs[0]() + fs[1]() + fs[2]();
+    "#);
+    assert_eq!(out, "3\n1\n3");
+}
+
+#[test]
+fn declarations_in_branches_keep_the_stack_balanced() {
+    let out = assert_same(r#"
+        var y = "global";
+        fun f(c) {
+            if (c) var x = "then"; else var y = "else";
+            var i = 0;
+            while (i < 2) var z = i++;
+            return [c ? x : y, z];
+        }
+        print f(true);
+        print f(false);
+        {
+            fun later() { return early; }
+            var early = "declared";
+            print later();
+        }
+        {
+            fun early_call() { return y; }
+            print early_call();
+            var y = "local";
+            print early_call();
+        }
+        fun g(c) {
+            if (c) var x = 1;
+            return x;
+        }
+        g(false);
+    "#);
+    assert_eq!(out, "[then, 1]\n[else, 1]\ndeclared\nglobal\nglobal");
+}
+
+#[test]
+fn finally_runs_on_break_continue_and_return() {
+    let out = assert_same(r#"
+        fun f() {
+            try {
+                for (var i = 0; i < 5; i = i + 1) {
+                    try {
+                        if (i == 1) continue;
+                        if (i == 3) break;
+                        print i;
+                    } finally {
+                        print "inner " + str(i);
+                    }
+                }
+                return "returned";
+            } finally {
+                print "outer";
+            }
+        }
+        print f();
+        outer: while (true) {
+            try {
+                while (true) { try { break outer; } finally { print "a"; } }
+            } finally {
+                print "b";
+            }
+        }
+    "#);
+    assert_eq!(out, "0\ninner 0\ninner 1\n2\ninner 2\ninner 3\nouter\nreturned\na\nb");
+}
+
+#[test]
+fn errors_pass_through_finally_and_unwind_calls() {
+    assert_same(r#"
+        fun thrower(n) {
+            if (n == 0) throw "deep";
+            try { thrower(n - 1); } finally { print "unwinding " + str(n); }
+        }
+        try {
+            thrower(3);
+        } catch (e) {
+            print "caught " + e;
+        }
+        try {
+            try { nil.x; } catch (e) { print e.message; throw "again"; } finally { print "cleanup"; }
+        } catch (e) {
+            print e;
+        }
+        fun replaced() {
+            try { throw "lost"; } finally { return "finally wins"; }
+        }
+        print replaced();
+        thrower(1);
+    "#);
+}
+
+#[test]
+fn match_arms_that_fail_part_way_leave_no_values_behind() {
+    let out = assert_same(r#"
+        class Point {}
+        var p = Point();
+        p.x = 1;
+        p.y = 2;
+        var subjects = [[1, [2, 3]], {"a": [1, 9]}, p, 7, "s"];
+        for (var i = 0; i < 5; i = i + 1) {
+            var before = "kept";
+            match (subjects[i]) {
+                case [1, [2, 4]] => print "wrong";
+                case [a, [b, c]] if c > 5 => print "guarded";
+                case [a, [b, c]] => print a + b + c;
+                case {"a": [1, 2]} => print "wrong";
+                case {"a": [x, y]} => print y;
+                case Point { x: 1, z: _ } => print "wrong";
+                case Point { y: y } => print y;
+                case 0..=7 => print "range";
+                case _ => print before;
+            }
+        }
+        match (1) { case 2 => print "no"; }
+    "#);
+    assert_eq!(out, "6\n9\n2\nrange\nkept");
+}
+
+#[test]
+fn match_expressions_find_their_bindings_above_pending_operands() {
+    let out = assert_same(r#"
+        fun add(a, b, c) { return a + b + c; }
+        var outer = "outer";
+        {
+            var local = 100;
+            var list = [1, match ([2, 3]) { case [x, y] => x * y; }, local];
+            print list;
+            print add(1, match (local) { case n if n > 50 => n; case _ => 0; }, 3);
+            var m = {"k": match ("v") { case s => s + outer; }};
+            print m;
+            list[match (0) { case i => i + 1; }] = match (7) { case n => n * local; };
+            print list;
+            print match (1) { case a => a + match (2) { case b => a * 10 + b; }; };
+            print str(match (list) { case [first, ..rest] => rest; });
+        }
+    "#);
+    assert_eq!(out, "[1, 6, 100]
+104
+{k: vouter}
+[1, 700, 100]
+13
+[700, 100]");
+}
+
+#[test]
+fn methods_bind_this_alike() {
+    let out = assert_same(r#"
+        class Counter {
+            init(start) { this.n = start; }
+            add(k) { this.n = this.n + k; return this; }
+            getter() { fun get() { return this.n; } return get; }
+            down(k) { if (k == 0) return this.n; return this.down(k - 1); }
+        }
+        var c = Counter(1).add(2).add(3);
+        var get = c.getter();
+        c.add(4);
+        print get();
+        var add = c.add;
+        add(10);
+        print c.n;
+        print c.down(100000);
+        print c.init(7) == c;
+        print c.n;
+        c.n += 1;
+        c.add("s");
+    "#);
+    assert_eq!(out, "10\n20\n20\ntrue\n7");
+}
+
+#[test]
+fn runtime_errors_and_traces_match_the_tree_walker() {
+    let cases = [
+        "fun f(a) { return a; }\nf(1, 2);",
+        "var l = [1];\nl[1] = 2;",
+        "var m = {};\nm[[1]] = 1;",
+        "var x = 1;\nx.y = undefined;",
+        "fun deep(n) {\n  return 1 + deep(n + 1);\n}\ndeep(0);",
+        "print undefined;",
+        "undefined = 1;",
+        "var s = \"a\";\ns++;",
+        "class C {}\nvar c = C();\nprint c.missing;",
+        "fun f() {\n  return clock(1);\n}\nf();",
+        "match (1) { case Nope {} => print 1; }",
+        "var n = nil;\nn();",
+        "print 1 +\n  \"a\";",
+        "class C { m(a) { return a; } }\nC().m();",
+        "class C { init(a) {} }\nC();",
+        "class C {}\nC(1);",
+        "class C { m() {} }\nvar c = C();\nc.m += 1;",
+    ];
+    for source in cases {
+        assert_same(source);
+    }
+}
+
+#[test]
+fn program_results_match_for_evaluate() {
+    for source in ["1 + 2;", "if (true) 3; else 4;", "var x = 1;", "match (2) { case 2 => \"two\"; }", ""] {
+        let tree = run_with("evaluate", &format!("{}\n", source), &["--backend=tree"], "");
+        let vm = run_with("evaluate", &format!("{}\n", source), &["--backend=vm"], "");
+        assert_eq!(stdout(&vm), stdout(&tree), "{}", source);
+    }
+}
+
+#[test]
+fn limits_apply_to_the_vm() {
+    let output = run_with("run", "while (true) {}", &["--backend=vm", "--max-steps=1000"], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).starts_with("Step limit of 1000 exceeded."), "{}", stderr(&output));
+
+    let output = run_with("run", "var l = [];\nwhile (true) l = [l];", &["--backend=vm", "--max-heap-objects=100"], "");
+    assert!(stderr(&output).starts_with("Heap limit of 100 objects exceeded."), "{}", stderr(&output));
+
+    let output = run_with("run", "fun f() {\n  try { f(); } catch (e) { print \"caught\"; }\n}\nf();", &["--backend=vm", "--max-call-depth=50"], "");
+    assert_eq!(stdout(&output), "caught");
+}
+
+#[test]
+fn unknown_backends_are_rejected() {
+    let output = run_with("run", "print 1;", &["--backend=jit"], "");
+    assert_eq!(output.status.code(), Some(64));
+    assert!(stderr(&output).starts_with("--backend must be tree or vm."));
+}
+
+#[test]
+fn the_api_runs_programs_on_the_vm() {
+    let mut interp = Interpreter::new();
+    interp.set_backend(Backend::Vm);
+    let out = interp.capture_output();
+    interp.register_fn("twice", 1, |args| Ok(args.get::<f64>(0)? * 2.0));
+    interp.set_global("base", 20.0).unwrap();
+    assert_eq!(interp.eval_str("var total = twice(base) + 2; total;").unwrap(), LoxValue::Number(42.0));
+    interp.eval_str("print total;").unwrap();
+    assert_eq!(out.take(), "42\n");
+
+    // Globals carry over between backends.
+    interp.set_backend(Backend::TreeWalker);
+    assert_eq!(interp.get_global("total"), Some(LoxValue::Number(42.0)));
+
+    interp.set_backend(Backend::Vm);
+    let err = interp.eval_str("fun inner() { return nil.x; }\nfun outer() {\n  inner();\n}\nouter();").unwrap_err();
+    let frame = |function: &str, line| Frame { function: function.to_string(), line };
+    assert_eq!(err.trace().unwrap(), &[frame("inner", 1), frame("outer", 3), frame("script", 5)]);
+
+    interp.set_limits(Limits { max_steps: Some(100), ..Limits::default() });
+    assert!(interp.eval_str("while (true) {}").is_err());
+    interp.set_limits(Limits::default());
+    assert_eq!(interp.eval_str("1;").unwrap(), LoxValue::Number(1.0));
+}
+
+#[test]
+fn the_collector_frees_cycles_through_closures() {
+    let drops = Rc::new(Cell::new(0));
+    let mut interp = Interpreter::new();
+    interp.set_backend(Backend::Vm);
+    interp.set_gc_config(GcConfig { initial_threshold: 1_000_000, ..GcConfig::default() });
+    let counter = Rc::clone(&drops);
+    interp.register_class("Tracer", 0, move |_| Ok(Tracer { drops: Rc::clone(&counter) }));
+    interp.eval_str(r#"
+        class Node {}
+        fun make() {
+            var n = Node();
+            fun get() { return n; }
+            n.get = get;
+            n.tracer = Tracer();
+        }
+        for (var i = 0; i < 50; i = i + 1) make();
+    "#).unwrap();
+    assert_eq!(drops.get(), 0);
+    assert!(interp.collect_garbage() >= 150);
+    assert_eq!(drops.get(), 50);
+    assert_eq!(interp.gc_stats().collections, 1);
+}
+
+#[test]
+fn the_collector_frees_cycles_through_bound_methods() {
+    let drops = Rc::new(Cell::new(0));
+    let mut interp = Interpreter::new();
+    interp.set_backend(Backend::Vm);
+    interp.set_gc_config(GcConfig { initial_threshold: 1_000_000, ..GcConfig::default() });
+    let counter = Rc::clone(&drops);
+    interp.register_class("Tracer", 0, move |_| Ok(Tracer { drops: Rc::clone(&counter) }));
+    interp.eval_str(r#"
+        class Node {
+            init() { this.tracer = Tracer(); }
+            get() { return this; }
+        }
+        for (var i = 0; i < 50; i = i + 1) {
+            var n = Node();
+            n.get = n.get;
+        }
+    "#).unwrap();
+    assert_eq!(drops.get(), 0);
+    interp.collect_garbage();
+    assert_eq!(drops.get(), 50);
+}